@@ -0,0 +1,61 @@
+//! Build metadata for the health endpoint, as `VERGEN_*` environment
+//! variables. Any already set win, so builds without git (or wanting
+//! reproducible output) can supply their own.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    // Watching paths that don't exist would rerun this on every build, as in
+    // a source tarball without `.git` or before refs are first packed.
+    let git_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("../../.git");
+    if git_dir.is_dir() {
+        for watched in ["HEAD", "refs", "packed-refs"] {
+            let path = git_dir.join(watched);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+    for var in ["VERGEN_GIT_SHA", "VERGEN_BUILD_TIMESTAMP", "VERGEN_RUSTC_SEMVER", "SOURCE_DATE_EPOCH"] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+
+    let git_sha = command_output("git", &["rev-parse", "HEAD"]);
+    set("VERGEN_GIT_SHA", git_sha);
+
+    let built_at = match env::var("SOURCE_DATE_EPOCH").ok().and_then(|s| s.parse().ok()) {
+        Some(epoch) => chrono::DateTime::from_timestamp(epoch, 0),
+        None => Some(chrono::Utc::now()),
+    };
+    set("VERGEN_BUILD_TIMESTAMP", built_at.map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+
+    // `rustc 1.86.0 (05f9846f8 2025-03-31)` -> `1.86.0`
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"])
+        .and_then(|v| v.split_whitespace().nth(1).map(String::from));
+    set("VERGEN_RUSTC_SEMVER", rustc_version);
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=OPENCAT_FEATURES={}", features.join(","));
+}
+
+/// Pass `name` through to the crate, preferring a value already in the environment.
+fn set(name: &str, value: Option<String>) {
+    if let Some(value) = env::var(name).ok().filter(|v| !v.is_empty()).or(value) {
+        println!("cargo:rustc-env={name}={value}");
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|s| !s.is_empty())
+}
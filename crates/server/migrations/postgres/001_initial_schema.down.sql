@@ -0,0 +1,10 @@
+DROP TABLE IF EXISTS api_keys;
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS webhook_endpoints;
+DROP TABLE IF EXISTS events;
+DROP TABLE IF EXISTS transactions;
+DROP TABLE IF EXISTS subscribers;
+DROP TABLE IF EXISTS product_entitlements;
+DROP TABLE IF EXISTS products;
+DROP TABLE IF EXISTS entitlements;
+DROP TABLE IF EXISTS apps;
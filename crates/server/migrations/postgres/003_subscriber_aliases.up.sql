@@ -0,0 +1,10 @@
+-- Previous app_user_ids that were merged into another subscriber
+CREATE TABLE IF NOT EXISTS subscriber_aliases (
+    app_id TEXT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+    alias TEXT NOT NULL,
+    subscriber_id TEXT NOT NULL REFERENCES subscribers(id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL DEFAULT (to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"')),
+    PRIMARY KEY (app_id, alias)
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscriber_aliases_subscriber ON subscriber_aliases(subscriber_id);
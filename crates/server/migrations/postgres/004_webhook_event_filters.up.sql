@@ -0,0 +1,2 @@
+-- JSON array of event types an endpoint subscribes to; empty means all events
+ALTER TABLE webhook_endpoints ADD COLUMN event_types TEXT NOT NULL DEFAULT '[]';
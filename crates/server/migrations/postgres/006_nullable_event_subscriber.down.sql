@@ -0,0 +1,3 @@
+-- Events without a subscriber can't be kept
+DELETE FROM events WHERE subscriber_id IS NULL;
+ALTER TABLE events ALTER COLUMN subscriber_id SET NOT NULL;
@@ -0,0 +1,2 @@
+-- Store notifications that don't match a known transaction with no subscriber
+ALTER TABLE events ALTER COLUMN subscriber_id DROP NOT NULL;
@@ -0,0 +1,4 @@
+-- Record the store environment by name so sandbox purchases can be filtered out of revenue
+ALTER TABLE transactions ADD COLUMN environment TEXT NOT NULL DEFAULT 'production' CHECK (environment IN ('production', 'sandbox'));
+UPDATE transactions SET environment = 'sandbox' WHERE is_sandbox <> 0;
+ALTER TABLE transactions DROP COLUMN is_sandbox;
@@ -0,0 +1,8 @@
+-- Store prices per App Store territory, so offerings can be localized
+CREATE TABLE IF NOT EXISTS product_prices (
+    product_id TEXT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    territory TEXT NOT NULL,
+    price_micros BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    PRIMARY KEY (product_id, territory)
+);
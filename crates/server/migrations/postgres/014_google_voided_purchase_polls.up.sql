@@ -0,0 +1,6 @@
+-- How far each app's Google Play voided purchases have been polled, so refunds are only processed once
+CREATE TABLE IF NOT EXISTS google_voided_purchase_polls (
+    app_id TEXT PRIMARY KEY REFERENCES apps(id) ON DELETE CASCADE,
+    last_voided_time_millis BIGINT NOT NULL,
+    polled_at TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Fails while any Stripe transactions remain
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_store_check;
+ALTER TABLE transactions ADD CONSTRAINT transactions_store_check CHECK (store IN ('apple', 'google', 'amazon'));
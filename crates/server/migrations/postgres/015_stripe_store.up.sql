@@ -0,0 +1,3 @@
+-- Allow Stripe web purchases
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_store_check;
+ALTER TABLE transactions ADD CONSTRAINT transactions_store_check CHECK (store IN ('apple', 'google', 'amazon', 'stripe'));
@@ -0,0 +1,5 @@
+-- Renewal state behind the subscriber's subscription_status rollup. auto_renew
+-- is 1 or 0 once a store has reported it, NULL until then.
+ALTER TABLE transactions ADD COLUMN period_type TEXT NOT NULL DEFAULT 'normal' CHECK (period_type IN ('normal', 'trial', 'intro'));
+ALTER TABLE transactions ADD COLUMN auto_renew INTEGER;
+ALTER TABLE transactions ADD COLUMN grace_period_expires_date TEXT;
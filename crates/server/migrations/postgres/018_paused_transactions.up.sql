@@ -0,0 +1,3 @@
+-- Allow paused Google Play subscriptions
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_status_check;
+ALTER TABLE transactions ADD CONSTRAINT transactions_status_check CHECK (status IN ('active', 'expired', 'refunded', 'grace_period', 'billing_retry', 'paused'));
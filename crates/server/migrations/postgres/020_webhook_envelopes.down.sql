@@ -0,0 +1,2 @@
+ALTER TABLE webhook_deliveries DROP COLUMN payload;
+ALTER TABLE webhook_deliveries DROP COLUMN api_version;
@@ -0,0 +1,10 @@
+-- The app a delivery's event belongs to, so the worker only sends it to that
+-- app's endpoints. Deliveries whose event can't be traced to an app stay
+-- NULL and are never sent.
+ALTER TABLE webhook_deliveries ADD COLUMN app_id TEXT REFERENCES apps(id) ON DELETE CASCADE;
+
+UPDATE webhook_deliveries SET app_id = (
+    SELECT s.app_id FROM events e
+    JOIN subscribers s ON s.id = e.subscriber_id
+    WHERE e.id = webhook_deliveries.event_id
+);
@@ -0,0 +1,14 @@
+-- Offerings shown instead of the current one to users in a country and/or on
+-- an app version at or above min_app_version. A NULL column matches anyone.
+CREATE TABLE IF NOT EXISTS offering_overrides (
+    id TEXT PRIMARY KEY,
+    app_id TEXT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+    offering_id TEXT NOT NULL REFERENCES offerings(id) ON DELETE CASCADE,
+    country TEXT,
+    min_app_version TEXT,
+    created_at TEXT NOT NULL DEFAULT (to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'))
+);
+
+-- NULLs never collide in a UNIQUE constraint, so compare them as ''
+CREATE UNIQUE INDEX IF NOT EXISTS idx_offering_overrides_targeting
+    ON offering_overrides (app_id, COALESCE(country, ''), COALESCE(min_app_version, ''));
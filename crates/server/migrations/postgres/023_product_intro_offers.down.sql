@@ -0,0 +1,3 @@
+ALTER TABLE products DROP COLUMN offer_mode;
+ALTER TABLE products DROP COLUMN intro_currency;
+ALTER TABLE products DROP COLUMN intro_price_micros;
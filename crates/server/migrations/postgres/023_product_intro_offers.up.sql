@@ -0,0 +1,5 @@
+-- Price and kind of a subscription's introductory offer, whose length is
+-- trial_period. offer_mode is free_trial, pay_as_you_go or pay_up_front.
+ALTER TABLE products ADD COLUMN intro_price_micros BIGINT;
+ALTER TABLE products ADD COLUMN intro_currency TEXT;
+ALTER TABLE products ADD COLUMN offer_mode TEXT;
@@ -0,0 +1,19 @@
+-- Offering A/B tests. Users are split between an experiment's variants in
+-- proportion to their weights; only the app's newest experiment runs.
+CREATE TABLE IF NOT EXISTS experiments (
+    id TEXT PRIMARY KEY,
+    app_id TEXT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"')),
+    UNIQUE(app_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS experiment_variants (
+    id TEXT PRIMARY KEY,
+    experiment_id TEXT NOT NULL REFERENCES experiments(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    offering_id TEXT NOT NULL REFERENCES offerings(id) ON DELETE CASCADE,
+    weight INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    UNIQUE(experiment_id, name)
+);
@@ -0,0 +1,3 @@
+-- Fails while any Amazon, web or macOS apps remain.
+ALTER TABLE apps DROP CONSTRAINT IF EXISTS apps_platform_check;
+ALTER TABLE apps ADD CONSTRAINT apps_platform_check CHECK (platform IN ('ios', 'android'));
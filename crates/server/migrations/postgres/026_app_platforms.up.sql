@@ -0,0 +1,3 @@
+-- Allow Amazon, web and macOS apps
+ALTER TABLE apps DROP CONSTRAINT IF EXISTS apps_platform_check;
+ALTER TABLE apps ADD CONSTRAINT apps_platform_check CHECK (platform IN ('ios', 'android', 'amazon', 'web', 'macos'));
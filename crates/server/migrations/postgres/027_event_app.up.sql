@@ -0,0 +1,11 @@
+-- The app an event belongs to, so listing events can be scoped to the caller's
+-- app. Backfilled through the subscriber, or failing that a delivery, since
+-- events for unknown transactions have no subscriber.
+ALTER TABLE events ADD COLUMN app_id TEXT REFERENCES apps(id) ON DELETE CASCADE;
+
+UPDATE events SET app_id = COALESCE(
+    (SELECT s.app_id FROM subscribers s WHERE s.id = events.subscriber_id),
+    (SELECT MIN(d.app_id) FROM webhook_deliveries d WHERE d.event_id = events.id)
+);
+
+CREATE INDEX idx_events_app ON events (app_id, created_at);
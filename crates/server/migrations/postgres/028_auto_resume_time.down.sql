@@ -0,0 +1 @@
+ALTER TABLE transactions DROP COLUMN auto_resume_time;
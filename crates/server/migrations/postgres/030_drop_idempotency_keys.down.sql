@@ -0,0 +1,11 @@
+-- Remember receipt submissions by client-supplied Idempotency-Key so retries replay the first response
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    app_id TEXT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+    idempotency_key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    status_code INTEGER NOT NULL,
+    response_body TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (app_id, idempotency_key)
+);
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS idx_raw_notifications_received_at;
@@ -0,0 +1,7 @@
+ALTER TABLE products DROP COLUMN last_synced_at;
+ALTER TABLE products DROP COLUMN trial_period;
+ALTER TABLE products DROP COLUMN subscription_period;
+ALTER TABLE products DROP COLUMN currency;
+ALTER TABLE products DROP COLUMN price_micros;
+ALTER TABLE products DROP COLUMN description;
+ALTER TABLE products DROP COLUMN display_name;
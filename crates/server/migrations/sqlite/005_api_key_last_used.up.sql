@@ -0,0 +1,2 @@
+-- Track when each API key last authenticated a request
+ALTER TABLE api_keys ADD COLUMN last_used_at TEXT;
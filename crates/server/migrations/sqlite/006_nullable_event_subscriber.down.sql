@@ -0,0 +1,25 @@
+-- Events without a subscriber can't be kept. As in the up migration, rebuild
+-- the table and restore the webhook deliveries the drop cascades into.
+DELETE FROM events WHERE subscriber_id IS NULL;
+
+CREATE TEMP TABLE webhook_deliveries_backup AS SELECT * FROM webhook_deliveries;
+
+CREATE TABLE events_old (
+    id TEXT PRIMARY KEY,
+    subscriber_id TEXT NOT NULL REFERENCES subscribers(id) ON DELETE CASCADE,
+    event_type TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+);
+
+INSERT INTO events_old (id, subscriber_id, event_type, payload, created_at)
+SELECT id, subscriber_id, event_type, payload, created_at FROM events;
+
+DROP TABLE events;
+ALTER TABLE events_old RENAME TO events;
+
+CREATE INDEX IF NOT EXISTS idx_events_subscriber ON events(subscriber_id);
+CREATE INDEX IF NOT EXISTS idx_events_created ON events(created_at);
+
+INSERT INTO webhook_deliveries SELECT * FROM webhook_deliveries_backup;
+DROP TABLE webhook_deliveries_backup;
@@ -0,0 +1,24 @@
+-- Store notifications that don't match a known transaction with no subscriber.
+-- SQLite can't drop NOT NULL in place, so rebuild the table. Dropping the old
+-- one cascades into webhook_deliveries, so set those rows aside and restore them.
+CREATE TEMP TABLE webhook_deliveries_backup AS SELECT * FROM webhook_deliveries;
+
+CREATE TABLE events_new (
+    id TEXT PRIMARY KEY,
+    subscriber_id TEXT REFERENCES subscribers(id) ON DELETE CASCADE,
+    event_type TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+);
+
+INSERT INTO events_new (id, subscriber_id, event_type, payload, created_at)
+SELECT id, subscriber_id, event_type, payload, created_at FROM events;
+
+DROP TABLE events;
+ALTER TABLE events_new RENAME TO events;
+
+CREATE INDEX IF NOT EXISTS idx_events_subscriber ON events(subscriber_id);
+CREATE INDEX IF NOT EXISTS idx_events_created ON events(created_at);
+
+INSERT INTO webhook_deliveries SELECT * FROM webhook_deliveries_backup;
+DROP TABLE webhook_deliveries_backup;
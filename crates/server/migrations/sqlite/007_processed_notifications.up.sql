@@ -0,0 +1,7 @@
+-- Provider notification IDs already applied, so redelivered notifications are skipped
+CREATE TABLE IF NOT EXISTS processed_notifications (
+    store TEXT NOT NULL,
+    notification_id TEXT NOT NULL,
+    processed_at TEXT NOT NULL,
+    PRIMARY KEY (store, notification_id)
+);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS offerings (
+    id TEXT PRIMARY KEY,
+    app_id TEXT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+    identifier TEXT NOT NULL,
+    description TEXT,
+    is_current INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    UNIQUE(app_id, identifier)
+);
+
+CREATE TABLE IF NOT EXISTS packages (
+    id TEXT PRIMARY KEY,
+    offering_id TEXT NOT NULL REFERENCES offerings(id) ON DELETE CASCADE,
+    identifier TEXT NOT NULL,
+    package_type TEXT NOT NULL CHECK (package_type IN ('weekly', 'monthly', 'two_month', 'three_month', 'six_month', 'annual', 'lifetime', 'custom')),
+    product_id TEXT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL DEFAULT 0,
+    UNIQUE(offering_id, identifier)
+);
+
+CREATE INDEX IF NOT EXISTS idx_packages_offering ON packages(offering_id);
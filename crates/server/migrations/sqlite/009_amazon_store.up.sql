@@ -0,0 +1,23 @@
+-- Allow Amazon Appstore transactions. SQLite can't alter a CHECK constraint in
+-- place, so rebuild the table; nothing references it, so no rows need restoring.
+CREATE TABLE transactions_new (
+    id TEXT PRIMARY KEY,
+    subscriber_id TEXT NOT NULL REFERENCES subscribers(id) ON DELETE CASCADE,
+    product_id TEXT NOT NULL REFERENCES products(id),
+    store TEXT NOT NULL CHECK (store IN ('apple', 'google', 'amazon')),
+    store_transaction_id TEXT NOT NULL,
+    purchase_date TEXT NOT NULL,
+    expiration_date TEXT,
+    status TEXT NOT NULL CHECK (status IN ('active', 'expired', 'refunded', 'grace_period', 'billing_retry')),
+    raw_receipt TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+);
+
+INSERT INTO transactions_new SELECT * FROM transactions;
+
+DROP TABLE transactions;
+ALTER TABLE transactions_new RENAME TO transactions;
+
+CREATE INDEX IF NOT EXISTS idx_transactions_subscriber ON transactions(subscriber_id);
+CREATE INDEX IF NOT EXISTS idx_transactions_store_tx ON transactions(store_transaction_id);
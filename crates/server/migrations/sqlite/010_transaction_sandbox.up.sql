@@ -0,0 +1,2 @@
+-- Whether the purchase was made in a store test environment
+ALTER TABLE transactions ADD COLUMN is_sandbox INTEGER NOT NULL DEFAULT 0;
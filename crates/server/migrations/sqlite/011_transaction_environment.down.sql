@@ -0,0 +1,3 @@
+ALTER TABLE transactions ADD COLUMN is_sandbox INTEGER NOT NULL DEFAULT 0;
+UPDATE transactions SET is_sandbox = 1 WHERE environment = 'sandbox';
+ALTER TABLE transactions DROP COLUMN environment;
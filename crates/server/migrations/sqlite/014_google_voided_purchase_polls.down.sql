@@ -0,0 +1 @@
+DROP TABLE IF EXISTS google_voided_purchase_polls;
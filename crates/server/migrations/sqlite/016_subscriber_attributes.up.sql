@@ -0,0 +1,9 @@
+-- Custom key-value attributes on subscribers. A NULL value records that the
+-- attribute was cleared, so an older write arriving late can't bring it back.
+CREATE TABLE IF NOT EXISTS subscriber_attributes (
+    subscriber_id TEXT NOT NULL REFERENCES subscribers(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT,
+    updated_at_ms INTEGER NOT NULL,
+    PRIMARY KEY (subscriber_id, key)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE transactions DROP COLUMN grace_period_expires_date;
+ALTER TABLE transactions DROP COLUMN auto_renew;
+ALTER TABLE transactions DROP COLUMN period_type;
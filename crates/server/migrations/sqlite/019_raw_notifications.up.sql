@@ -0,0 +1,14 @@
+-- Every store notification exactly as received, kept for audits and so it can
+-- be replayed. app_id is filled in once the notification is matched to an app.
+CREATE TABLE IF NOT EXISTS raw_notifications (
+    id TEXT PRIMARY KEY,
+    store TEXT NOT NULL,
+    app_id TEXT REFERENCES apps(id) ON DELETE CASCADE,
+    payload BLOB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'received' CHECK (status IN ('received', 'processed', 'failed')),
+    error TEXT,
+    received_at TEXT NOT NULL,
+    processed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_raw_notifications_app ON raw_notifications(app_id, received_at);
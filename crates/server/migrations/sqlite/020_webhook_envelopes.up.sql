@@ -0,0 +1,5 @@
+-- Each delivery's body, serialized in its envelope version when it was
+-- queued. Both are NULL for deliveries queued before envelopes, which are
+-- sent the bare event payload.
+ALTER TABLE webhook_deliveries ADD COLUMN api_version INTEGER;
+ALTER TABLE webhook_deliveries ADD COLUMN payload TEXT;
@@ -0,0 +1,2 @@
+DROP INDEX idx_transactions_original;
+ALTER TABLE transactions DROP COLUMN original_transaction_id;
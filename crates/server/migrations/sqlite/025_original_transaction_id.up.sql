@@ -0,0 +1,4 @@
+-- The transaction a subscription started with, shared by all its renewals
+-- (Apple's originalTransactionId). NULL for stores without renewal chains.
+ALTER TABLE transactions ADD COLUMN original_transaction_id TEXT;
+CREATE INDEX idx_transactions_original ON transactions (store, original_transaction_id);
@@ -0,0 +1,16 @@
+-- Fails while any Amazon, web or macOS apps remain.
+CREATE TABLE apps_old (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    platform TEXT NOT NULL CHECK (platform IN ('ios', 'android')),
+    bundle_id TEXT NOT NULL,
+    store_credentials_encrypted TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    UNIQUE(bundle_id, platform)
+);
+
+INSERT INTO apps_old SELECT * FROM apps;
+
+DROP TABLE apps;
+ALTER TABLE apps_old RENAME TO apps;
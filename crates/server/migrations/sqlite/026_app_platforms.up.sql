@@ -0,0 +1,18 @@
+-- Allow Amazon, web and macOS apps. As in 009, rebuild the table to change the
+-- CHECK constraint. Everything references apps, so this relies on migrations
+-- running with foreign keys off (see db::connect_with).
+CREATE TABLE apps_new (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    platform TEXT NOT NULL CHECK (platform IN ('ios', 'android', 'amazon', 'web', 'macos')),
+    bundle_id TEXT NOT NULL,
+    store_credentials_encrypted TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    UNIQUE(bundle_id, platform)
+);
+
+INSERT INTO apps_new SELECT * FROM apps;
+
+DROP TABLE apps;
+ALTER TABLE apps_new RENAME TO apps;
@@ -0,0 +1,2 @@
+DROP INDEX idx_events_app;
+ALTER TABLE events DROP COLUMN app_id;
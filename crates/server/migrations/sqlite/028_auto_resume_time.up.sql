@@ -0,0 +1,2 @@
+-- When a paused Google Play subscription resumes on its own, while it's paused.
+ALTER TABLE transactions ADD COLUMN auto_resume_time TEXT;
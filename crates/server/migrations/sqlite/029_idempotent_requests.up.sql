@@ -0,0 +1,14 @@
+-- Responses to POSTs sent with an Idempotency-Key, replayed when the request is
+-- retried. scope hashes the caller's Authorization header with the path, since
+-- some routes (app creation) have no app yet. status_code and response_body
+-- stay NULL while the first request is still running.
+CREATE TABLE IF NOT EXISTS idempotent_requests (
+    scope TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    status_code INTEGER,
+    response_body TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (scope, idempotency_key)
+);
+CREATE INDEX IF NOT EXISTS idx_idempotent_requests_created_at ON idempotent_requests(created_at);
@@ -0,0 +1,2 @@
+-- Receipt submissions now go through the shared idempotent_requests table
+DROP TABLE IF EXISTS idempotency_keys;
@@ -0,0 +1,2 @@
+-- Lets raw notification pruning find old rows without a full scan
+CREATE INDEX IF NOT EXISTS idx_raw_notifications_received_at ON raw_notifications(received_at);
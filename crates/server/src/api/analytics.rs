@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashSet};
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Serialize;
+use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+
+/// Average weeks and days in a month, for normalizing shorter periods.
+const WEEKS_PER_MONTH: f64 = 4.33;
+const DAYS_PER_MONTH: f64 = 30.44;
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct MetricsOverview {
+    /// Subscribers with at least one active subscription.
+    pub active_subscribers: i64,
+    pub active_subscriptions: i64,
+    /// Active subscription count keyed by store product ID.
+    pub active_subscriptions_by_product: BTreeMap<String, i64>,
+    /// Active subscriptions still in a free trial.
+    pub active_trials: i64,
+    /// Approximate monthly recurring revenue in micros, per currency. Uses
+    /// each product's synced list price, so discounts and regional pricing
+    /// aren't reflected, and trials count for nothing.
+    pub mrr_micros: BTreeMap<String, i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ActiveSubscription {
+    subscriber_id: String,
+    store_product_id: String,
+    price_micros: Option<i64>,
+    currency: Option<String>,
+    subscription_period: Option<String>,
+    period_type: String,
+}
+
+/// How many times per month a subscription with this ISO 8601 period
+/// renews, e.g. `P1Y` is 1/12 and `P1W` about 4.33. `None` for periods we
+/// can't read.
+fn monthly_factor(period: &str) -> Option<f64> {
+    let rest = period.strip_prefix('P')?;
+    let unit = rest.chars().last()?;
+    let count: f64 = rest[..rest.len() - unit.len_utf8()].parse().ok()?;
+    if count <= 0.0 {
+        return None;
+    }
+    let per_month = match unit {
+        'D' => DAYS_PER_MONTH,
+        'W' => WEEKS_PER_MONTH,
+        'M' => 1.0,
+        'Y' => 1.0 / 12.0,
+        _ => return None,
+    };
+    Some(per_month / count)
+}
+
+/// Headline subscription numbers for an app, counting production purchases only.
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/metrics/overview",
+    tag = "metrics",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    responses(
+        (status = 200, body = MetricsOverview),
+    ),
+)]
+pub async fn overview(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+) -> Result<Json<MetricsOverview>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let subscriptions = sqlx::query_as::<_, ActiveSubscription>(
+        "SELECT t.subscriber_id, p.store_product_id, p.price_micros, p.currency, p.subscription_period, t.period_type
+         FROM transactions t
+         JOIN products p ON p.id = t.product_id
+         WHERE p.app_id = $1 AND p.product_type = 'subscription'
+         AND t.status = 'active' AND t.environment = 'production'
+         AND (t.expiration_date IS NULL OR t.expiration_date > $2)"
+    )
+    .bind(&app_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut subscribers = HashSet::new();
+    let mut by_product = BTreeMap::new();
+    let mut trials = 0;
+    let mut mrr = BTreeMap::<String, f64>::new();
+    for subscription in &subscriptions {
+        subscribers.insert(subscription.subscriber_id.as_str());
+        *by_product.entry(subscription.store_product_id.clone()).or_insert(0) += 1;
+
+        if subscription.period_type == "trial" {
+            trials += 1;
+            continue;
+        }
+        let (Some(price), Some(currency), Some(factor)) = (
+            subscription.price_micros,
+            subscription.currency.as_ref(),
+            subscription.subscription_period.as_deref().and_then(monthly_factor),
+        ) else {
+            continue;
+        };
+        *mrr.entry(currency.clone()).or_insert(0.0) += price as f64 * factor;
+    }
+
+    Ok(Json(MetricsOverview {
+        active_subscribers: subscribers.len() as i64,
+        active_subscriptions: subscriptions.len() as i64,
+        active_subscriptions_by_product: by_product,
+        active_trials: trials,
+        mrr_micros: mrr.into_iter().map(|(currency, micros)| (currency, micros.round() as i64)).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_test_app, test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn seed_product(state: &AppState, app_id: &str, id: &str, price_micros: i64, period: &str) {
+        sqlx::query(
+            "INSERT INTO products (id, app_id, store_product_id, product_type, price_micros, currency, subscription_period)
+             VALUES ($1, $2, $1, 'subscription', $3, 'USD', $4)"
+        )
+        .bind(id)
+        .bind(app_id)
+        .bind(price_micros)
+        .bind(period)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    }
+
+    async fn seed_transaction(state: &AppState, subscriber_id: &str, product_id: &str, period_type: &str, environment: &str) {
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, expiration_date, status, period_type, environment)
+             VALUES ($1, $2, $3, 'apple', $1, '2026-01-01T00:00:00Z', '2099-01-01T00:00:00Z', 'active', $4, $5)"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(subscriber_id)
+        .bind(product_id)
+        .bind(period_type)
+        .bind(environment)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_monthly_factor() {
+        assert_eq!(monthly_factor("P1M"), Some(1.0));
+        assert_eq!(monthly_factor("P3M"), Some(1.0 / 3.0));
+        assert_eq!(monthly_factor("P1Y"), Some(1.0 / 12.0));
+        assert_eq!(monthly_factor("P1W"), Some(4.33));
+        assert_eq!(monthly_factor("P2W"), Some(4.33 / 2.0));
+        assert_eq!(monthly_factor("ONE_MONTH"), None);
+        assert_eq!(monthly_factor("P0M"), None);
+    }
+
+    #[tokio::test]
+    async fn test_overview_normalizes_mrr() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        seed_product(&state, &app_id, "com.test.monthly", 9_990_000, "P1M").await;
+        seed_product(&state, &app_id, "com.test.yearly", 59_990_000, "P1Y").await;
+        for subscriber in ["alice", "bob", "carol", "dave"] {
+            sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ($1, $2, $1)")
+                .bind(subscriber)
+                .bind(&app_id)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+        seed_transaction(&state, "alice", "com.test.monthly", "normal", "production").await;
+        seed_transaction(&state, "bob", "com.test.yearly", "normal", "production").await;
+        seed_transaction(&state, "carol", "com.test.yearly", "trial", "production").await;
+        seed_transaction(&state, "dave", "com.test.monthly", "normal", "sandbox").await;
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/apps/{app_id}/metrics/overview"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let overview: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(overview["active_subscribers"], 3);
+        assert_eq!(overview["active_subscriptions_by_product"]["com.test.monthly"], 1);
+        assert_eq!(overview["active_subscriptions_by_product"]["com.test.yearly"], 2);
+        assert_eq!(overview["active_trials"], 1);
+        // 9.99 monthly plus 59.99 / 12 yearly; the trial and sandbox purchases count for nothing
+        assert_eq!(overview["mrr_micros"]["USD"], 9_990_000 + 4_999_167);
+    }
+}
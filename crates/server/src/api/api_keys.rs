@@ -0,0 +1,213 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use crate::api::AppState;
+use crate::api::auth::{hash_api_key, AuthenticatedApp};
+use crate::db::DbPool;
+use crate::models::api_key::{ApiKey, CreatedApiKey};
+
+const API_KEY_COLUMNS: &str = "id, app_id, key_prefix, permissions, created_at, last_used_at, revoked_at";
+
+/// Length of the plaintext prefix kept to help identify a key (`ocat_` plus 7 characters).
+const KEY_PREFIX_LEN: usize = 12;
+
+/// Generate a new key for `app_id`, storing only its hash.
+pub(crate) async fn issue_api_key(pool: &DbPool, app_id: &str) -> Result<CreatedApiKey, sqlx::Error> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let key = format!("ocat_{}", uuid::Uuid::new_v4().simple());
+    let now = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query("INSERT INTO api_keys (id, app_id, key_hash, key_prefix, created_at) VALUES ($1, $2, $3, $4, $5)")
+        .bind(&id)
+        .bind(app_id)
+        .bind(hash_api_key(&key))
+        .bind(&key[..KEY_PREFIX_LEN])
+        .bind(&now)
+        .execute(pool)
+        .await?;
+
+    let api_key = sqlx::query_as::<_, ApiKey>(&format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE id = $1"))
+        .bind(&id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(CreatedApiKey { api_key, key })
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/api-keys",
+    tag = "api-keys",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    responses(
+        (status = 201, body = CreatedApiKey),
+    ),
+)]
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let created = issue_api_key(&state.pool, &app_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/api-keys",
+    tag = "api-keys",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    responses(
+        (status = 200, body = Vec<ApiKey>),
+    ),
+)]
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+) -> Result<Json<Vec<ApiKey>>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let keys = sqlx::query_as::<_, ApiKey>(
+        &format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE app_id = $1 ORDER BY created_at DESC")
+    )
+    .bind(&app_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(keys))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/apps/{app_id}/api-keys/{key_id}",
+    tag = "api-keys",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("key_id" = String, Path, description = "API key ID"),
+    ),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 404, description = "Key not found"),
+    ),
+)]
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, key_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let result = sqlx::query(
+        "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, $1) WHERE id = $2 AND app_id = $3"
+    )
+    .bind(&now)
+    .bind(&key_id)
+    .bind(&app_id)
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "API key not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::test_support::{create_test_app, test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn get_subscriber_status(state: &AppState, key: &str) -> StatusCode {
+        crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/subscribers/user123")
+                    .header("authorization", format!("Bearer {key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_create_list_and_revoke_api_key() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let app = crate::api::router(state.clone());
+
+        let response = app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/api-keys"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let created: Value = serde_json::from_slice(&body).unwrap();
+        let key = created["key"].as_str().unwrap().to_string();
+        let key_id = created["id"].as_str().unwrap().to_string();
+        assert!(key.starts_with("ocat_"));
+        assert!(key.starts_with(created["key_prefix"].as_str().unwrap()));
+
+        // Authenticates (subscriber doesn't exist, so 404 rather than 401) and records usage
+        assert_eq!(get_subscriber_status(&state, &key).await, StatusCode::NOT_FOUND);
+
+        let response = app.clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/apps/{app_id}/api-keys"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let keys: Value = serde_json::from_slice(&body).unwrap();
+        // The app's initial key plus the one created above
+        let keys = keys.as_array().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.iter().all(|k| k.get("key").is_none() && k.get("key_hash").is_none()));
+        let listed = keys.iter().find(|k| k["id"] == key_id.as_str()).unwrap();
+        assert!(listed["last_used_at"].is_string());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/v1/apps/{app_id}/api-keys/{key_id}"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        assert_eq!(get_subscriber_status(&state, &key).await, StatusCode::UNAUTHORIZED);
+    }
+}
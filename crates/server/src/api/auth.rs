@@ -5,10 +5,26 @@ use axum::{
 use sha2::{Sha256, Digest};
 use crate::api::AppState;
 
+/// Hex-encoded SHA-256 of an API key, as stored in `api_keys.key_hash`.
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
 pub struct AuthenticatedApp {
     pub app_id: String,
 }
 
+impl AuthenticatedApp {
+    /// Reject requests for any app other than the one the key was issued for.
+    pub fn authorize(&self, app_id: &str) -> Result<(), (StatusCode, String)> {
+        if self.app_id == app_id {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, "API key does not belong to this app".to_string()))
+        }
+    }
+}
+
 impl FromRequestParts<AppState> for AuthenticatedApp {
     type Rejection = (StatusCode, String);
 
@@ -23,27 +39,30 @@ impl FromRequestParts<AppState> for AuthenticatedApp {
             .strip_prefix("Bearer ")
             .ok_or((StatusCode::UNAUTHORIZED, "Invalid Authorization format".to_string()))?;
 
-        let mut hasher = Sha256::new();
-        hasher.update(token.as_bytes());
-        let key_hash = format!("{:x}", hasher.finalize());
-
-        let result = sqlx::query_as::<_, (String,)>(
-            "SELECT app_id FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL"
+        let result = sqlx::query_as::<_, (String, String)>(
+            "SELECT id, app_id FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL"
         )
-        .bind(&key_hash)
+        .bind(hash_api_key(token))
         .fetch_optional(&state.pool)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()))?;
 
-        Ok(AuthenticatedApp { app_id: result.0 })
+        sqlx::query("UPDATE api_keys SET last_used_at = $1 WHERE id = $2")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(&result.0)
+            .execute(&state.pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        tracing::Span::current().record("app_id", result.1.as_str());
+        Ok(AuthenticatedApp { app_id: result.1 })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::api::AppState;
-    use crate::db;
+    use crate::test_support::{create_test_app, test_pool, test_state};
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
     use sha2::{Sha256, Digest};
@@ -51,8 +70,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_unauthenticated_request_returns_401() {
-        let pool = db::connect("sqlite::memory:").await.unwrap();
-        let state = AppState { pool };
+        let state = test_state(test_pool().await);
         let app = crate::api::router(state);
 
         let response = app
@@ -64,8 +82,37 @@ mod tests {
             )
             .await
             .unwrap();
-        // Currently list_apps doesn't require auth, so this returns 200
-        // Auth will be applied selectively to protected routes
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_key_is_limited_to_its_own_app() {
+        let state = test_state(test_pool().await);
+        let (app_a, key_a) = create_test_app(&state, "com.test.a").await;
+        let (app_b, _) = create_test_app(&state, "com.test.b").await;
+        let app = crate::api::router(state);
+
+        for (app_id, key, expected) in [
+            (&app_a, Some(&key_a), StatusCode::OK),
+            (&app_b, Some(&key_a), StatusCode::FORBIDDEN),
+            (&app_a, None, StatusCode::UNAUTHORIZED),
+        ] {
+            let mut request = Request::builder().uri(format!("/v1/apps/{app_id}/products"));
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {key}"));
+            }
+            let response = app.clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected);
+        }
+
+        // Open routes stay reachable without a key
+        let response = app
+            .oneshot(Request::builder().uri("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
     }
 
@@ -0,0 +1,103 @@
+use axum::http::{HeaderValue, request::Parts};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// An entry of `server.cors_origins`: an exact origin such as
+/// `https://dashboard.example.com`, or `https://*.example.com` for any subdomain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OriginPattern {
+    Exact(String),
+    Subdomains { scheme: String, suffix: String },
+}
+
+impl OriginPattern {
+    fn parse(origin: &str) -> Self {
+        let origin = origin.trim().trim_end_matches('/');
+        match origin.split_once("://*.") {
+            Some((scheme, domain)) => Self::Subdomains {
+                scheme: format!("{scheme}://"),
+                suffix: format!(".{domain}"),
+            },
+            None => Self::Exact(origin.to_string()),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(exact) => origin == exact,
+            Self::Subdomains { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|sub| !sub.is_empty() && sub.split('.').all(is_label)),
+        }
+    }
+}
+
+fn is_label(label: &str) -> bool {
+    !label.is_empty() && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// CORS for the configured origins. With none configured any origin is
+/// allowed, which is only safe for local development.
+pub fn layer(origins: &[String]) -> CorsLayer {
+    let layer = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+
+    let patterns: Vec<OriginPattern> = origins.iter()
+        .filter(|origin| !origin.trim().is_empty())
+        .map(|origin| OriginPattern::parse(origin))
+        .collect();
+    if patterns.is_empty() {
+        tracing::warn!("No server.cors_origins configured; allowing requests from any origin");
+        return layer.allow_origin(Any);
+    }
+
+    layer.allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _: &Parts| {
+        origin.to_str().is_ok_and(|origin| patterns.iter().any(|p| p.matches(origin)))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_wildcard_matches_subdomains_only() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(pattern.matches("https://app.example.com"));
+        assert!(pattern.matches("https://eu.app.example.com"));
+        assert!(!pattern.matches("https://example.com"));
+        assert!(!pattern.matches("https://evilexample.com"));
+        assert!(!pattern.matches("https://app.example.com.evil.io"));
+        assert!(!pattern.matches("http://app.example.com"));
+    }
+
+    async fn allowed_origin(router: &Router, origin: &str) -> Option<String> {
+        let response = router.clone()
+            .oneshot(Request::builder().uri("/").header(header::ORIGIN, origin).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_layer_allows_only_configured_origins() {
+        let origins = vec!["https://dashboard.example.com".to_string(), "https://*.opencat.dev".to_string()];
+        let router = Router::new().route("/", get(|| async { "ok" })).layer(layer(&origins));
+
+        assert_eq!(
+            allowed_origin(&router, "https://dashboard.example.com").await.as_deref(),
+            Some("https://dashboard.example.com")
+        );
+        assert_eq!(
+            allowed_origin(&router, "https://staging.opencat.dev").await.as_deref(),
+            Some("https://staging.opencat.dev")
+        );
+        assert_eq!(allowed_origin(&router, "https://evil.example.org").await, None);
+    }
+}
@@ -1,16 +1,37 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json};
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
 use crate::api::AppState;
-use crate::models::entitlement::{CreateEntitlement, Entitlement};
+use crate::api::auth::AuthenticatedApp;
+use crate::api::events::{decode_cursor, encode_cursor};
+use crate::models::entitlement::{CreateEntitlement, Entitlement, UpdateEntitlement};
 
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/entitlements",
+    tag = "entitlements",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response to a retried request"),
+    ),
+    request_body = CreateEntitlement,
+    responses(
+        (status = 201, body = Entitlement),
+        (status = 409, description = "A request with the same Idempotency-Key is still in progress"),
+        (status = 422, description = "Idempotency key reused for a different request"),
+    ),
+)]
 pub async fn create_entitlement(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Path(app_id): Path<String>,
     Json(input): Json<CreateEntitlement>,
 ) -> Result<(StatusCode, Json<Entitlement>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
     let id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
 
-    sqlx::query("INSERT INTO entitlements (id, app_id, name, description, created_at) VALUES (?, ?, ?, ?, ?)")
+    sqlx::query("INSERT INTO entitlements (id, app_id, name, description, created_at) VALUES ($1, $2, $3, $4, $5)")
         .bind(&id)
         .bind(&app_id)
         .bind(&input.name)
@@ -20,73 +41,273 @@ pub async fn create_entitlement(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let entitlement = sqlx::query_as::<_, Entitlement>("SELECT * FROM entitlements WHERE id = ?")
+    let entitlement = sqlx::query_as::<_, Entitlement>("SELECT * FROM entitlements WHERE id = $1")
         .bind(&id)
         .fetch_one(&state.pool)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.offerings_cache.invalidate(&app_id);
     Ok((StatusCode::CREATED, Json(entitlement)))
 }
 
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EntitlementsQuery {
+    /// Opaque `next_cursor` from a previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct EntitlementsPage {
+    pub entitlements: Vec<Entitlement>,
+    /// Pass back as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// List the app's entitlements oldest-first, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/entitlements",
+    tag = "entitlements",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        EntitlementsQuery,
+    ),
+    responses(
+        (status = 200, body = EntitlementsPage),
+        (status = 400, description = "Invalid cursor"),
+    ),
+)]
 pub async fn list_entitlements(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Path(app_id): Path<String>,
-) -> Result<Json<Vec<Entitlement>>, (StatusCode, String)> {
-    let entitlements = sqlx::query_as::<_, Entitlement>(
-        "SELECT * FROM entitlements WHERE app_id = ? ORDER BY created_at DESC"
+    Query(query): Query<EntitlementsQuery>,
+) -> Result<Json<EntitlementsPage>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let after = query.cursor.as_deref()
+        .map(|cursor| decode_cursor(cursor).ok_or((StatusCode::BAD_REQUEST, "Invalid cursor".to_string())))
+        .transpose()?;
+
+    // Fetch one extra row to tell whether there is another page
+    let position = if after.is_some() { "AND (created_at, id) > ($2, $3)" } else { "" };
+    let sql = format!(
+        "SELECT * FROM entitlements WHERE app_id = $1 {position} ORDER BY created_at ASC, id ASC LIMIT {}",
+        limit + 1
+    );
+    let mut query = sqlx::query_as::<_, Entitlement>(&sql).bind(&app_id);
+    if let Some((created_at, id)) = after {
+        query = query.bind(created_at).bind(id);
+    }
+    let mut entitlements = query
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let next_cursor = if entitlements.len() as i64 > limit {
+        entitlements.truncate(limit as usize);
+        entitlements.last().map(|e| encode_cursor(&e.created_at, &e.id))
+    } else {
+        None
+    };
+
+    Ok(Json(EntitlementsPage { entitlements, next_cursor }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/entitlements/{entitlement_id}",
+    tag = "entitlements",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("entitlement_id" = String, Path, description = "Entitlement ID"),
+    ),
+    responses(
+        (status = 200, body = Entitlement),
+        (status = 404, description = "Entitlement not found"),
+    ),
+)]
+pub async fn get_entitlement(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, entitlement_id)): Path<(String, String)>,
+) -> Result<Json<Entitlement>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let entitlement = sqlx::query_as::<_, Entitlement>("SELECT * FROM entitlements WHERE id = $1 AND app_id = $2")
+        .bind(&entitlement_id)
+        .bind(&app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Entitlement not found".to_string()))?;
+
+    Ok(Json(entitlement))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/apps/{app_id}/entitlements/{entitlement_id}",
+    tag = "entitlements",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("entitlement_id" = String, Path, description = "Entitlement ID"),
+    ),
+    request_body = UpdateEntitlement,
+    responses(
+        (status = 200, body = Entitlement),
+        (status = 404, description = "Entitlement not found"),
+        (status = 409, description = "Another entitlement has that name"),
+    ),
+)]
+pub async fn update_entitlement(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, entitlement_id)): Path<(String, String)>,
+    Json(input): Json<UpdateEntitlement>,
+) -> Result<Json<Entitlement>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let result = sqlx::query(
+        "UPDATE entitlements SET name = COALESCE($1, name), description = COALESCE($2, description) \
+         WHERE id = $3 AND app_id = $4"
     )
+    .bind(&input.name)
+    .bind(&input.description)
+    .bind(&entitlement_id)
     .bind(&app_id)
-    .fetch_all(&state.pool)
+    .execute(&state.pool)
     .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    .map_err(|e| match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => {
+            (StatusCode::CONFLICT, "An entitlement with that name already exists".to_string())
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Entitlement not found".to_string()));
+    }
+
+    let entitlement = sqlx::query_as::<_, Entitlement>("SELECT * FROM entitlements WHERE id = $1")
+        .bind(&entitlement_id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok(Json(entitlement))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteEntitlementQuery {
+    /// Delete even if active subscribers still hold the entitlement.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Delete an entitlement and its product mappings. Refused with 409 while it
+/// still grants access to any subscriber, unless `force` is set.
+#[utoipa::path(
+    delete,
+    path = "/v1/apps/{app_id}/entitlements/{entitlement_id}",
+    tag = "entitlements",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("entitlement_id" = String, Path, description = "Entitlement ID"),
+        DeleteEntitlementQuery,
+    ),
+    responses(
+        (status = 204, description = "Entitlement deleted"),
+        (status = 404, description = "Entitlement not found"),
+        (status = 409, description = "Active subscribers hold the entitlement"),
+    ),
+)]
+pub async fn delete_entitlement(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, entitlement_id)): Path<(String, String)>,
+    Query(query): Query<DeleteEntitlementQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let exists = sqlx::query_scalar::<_, String>("SELECT id FROM entitlements WHERE id = $1 AND app_id = $2")
+        .bind(&entitlement_id)
+        .bind(&app_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if exists.is_none() {
+        return Err((StatusCode::NOT_FOUND, "Entitlement not found".to_string()));
+    }
+
+    if !query.force {
+        let active_grants: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM transactions t \
+             JOIN product_entitlements pe ON pe.product_id = t.product_id \
+             WHERE pe.entitlement_id = $1 AND t.status IN ('active', 'grace_period')"
+        )
+        .bind(&entitlement_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        if active_grants > 0 {
+            return Err((
+                StatusCode::CONFLICT,
+                "Entitlement still grants active access; pass force=true to delete it anyway".to_string(),
+            ));
+        }
+    }
+
+    sqlx::query("DELETE FROM product_entitlements WHERE entitlement_id = $1")
+        .bind(&entitlement_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("DELETE FROM entitlements WHERE id = $1")
+        .bind(&entitlement_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(entitlements))
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::api::AppState;
-    use crate::db;
+    use crate::test_support::{create_test_app, test_pool, test_state};
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
     use serde_json::Value;
     use tower::ServiceExt;
 
-    async fn test_state() -> AppState {
-        let pool = db::connect("sqlite::memory:").await.unwrap();
-        AppState { pool }
-    }
-
-    async fn create_test_app(state: &AppState) -> String {
-        let app = crate::api::router(state.clone());
-        let resp = app
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri("/v1/apps")
-                    .header("content-type", "application/json")
-                    .body(Body::from(r#"{"name":"Test","platform":"ios","bundle_id":"com.test"}"#))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let v: Value = serde_json::from_slice(&body).unwrap();
-        v["id"].as_str().unwrap().to_string()
-    }
-
     #[tokio::test]
     async fn test_create_and_list_entitlements() {
-        let state = test_state().await;
-        let app_id = create_test_app(&state).await;
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
         let app = crate::api::router(state);
 
         let response = app.clone()
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri(&format!("/v1/apps/{app_id}/entitlements"))
+                    .uri(format!("/v1/apps/{app_id}/entitlements"))
+                    .header("authorization", format!("Bearer {api_key}"))
                     .header("content-type", "application/json")
                     .body(Body::from(r#"{"name":"pro","description":"Pro access"}"#))
                     .unwrap(),
@@ -98,7 +319,8 @@ mod tests {
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri(&format!("/v1/apps/{app_id}/entitlements"))
+                    .uri(format!("/v1/apps/{app_id}/entitlements"))
+                    .header("authorization", format!("Bearer {api_key}"))
                     .body(Body::empty())
                     .unwrap(),
             )
@@ -106,4 +328,132 @@ mod tests {
             .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    async fn send(state: &AppState, method: &str, uri: &str, api_key: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", format!("Bearer {api_key}"))
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+        let response = crate::api::router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    async fn create_entitlement(state: &AppState, app_id: &str, api_key: &str, name: &str) -> String {
+        let (status, body) = send(
+            state, "POST", &format!("/v1/apps/{app_id}/entitlements"), api_key,
+            Some(serde_json::json!({ "name": name, "description": "Pro access" })),
+        ).await;
+        assert_eq!(status, StatusCode::CREATED);
+        body["id"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_update_entitlement() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let id = create_entitlement(&state, &app_id, &api_key, "prp").await;
+        create_entitlement(&state, &app_id, &api_key, "premium").await;
+        let uri = format!("/v1/apps/{app_id}/entitlements/{id}");
+
+        let (status, body) = send(&state, "PUT", &uri, &api_key, Some(serde_json::json!({ "name": "pro" }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "pro");
+        assert_eq!(body["description"], "Pro access");
+
+        let (status, _) = send(&state, "PUT", &uri, &api_key, Some(serde_json::json!({ "name": "premium" }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) = send(
+            &state, "PUT", &format!("/v1/apps/{app_id}/entitlements/missing"), &api_key,
+            Some(serde_json::json!({ "name": "gold" })),
+        ).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_entitlement_removes_product_mappings() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let id = create_entitlement(&state, &app_id, &api_key, "pro").await;
+        let (status, _) = send(
+            &state, "POST", &format!("/v1/apps/{app_id}/products"), &api_key,
+            Some(serde_json::json!({ "store_product_id": "com.test.pro", "product_type": "subscription", "entitlement_ids": [id] })),
+        ).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _) = send(&state, "DELETE", &format!("/v1/apps/{app_id}/entitlements/{id}"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let mappings: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM product_entitlements")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(mappings, 0);
+
+        let (_, body) = send(&state, "GET", &format!("/v1/apps/{app_id}/entitlements"), &api_key, None).await;
+        assert_eq!(body["entitlements"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_entitlement_in_use_requires_force() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let id = create_entitlement(&state, &app_id, &api_key, "pro").await;
+        let (_, product) = send(
+            &state, "POST", &format!("/v1/apps/{app_id}/products"), &api_key,
+            Some(serde_json::json!({ "store_product_id": "com.test.pro", "product_type": "subscription", "entitlement_ids": [id] })),
+        ).await;
+
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status)
+             VALUES ('tx', 'sub', $1, 'apple', '1000', '2026-01-01T00:00:00Z', 'active')"
+        )
+        .bind(product["id"].as_str().unwrap())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+        let uri = format!("/v1/apps/{app_id}/entitlements/{id}");
+        let (status, _) = send(&state, "DELETE", &uri, &api_key, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) = send(&state, "DELETE", &format!("{uri}?force=true"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_get_entitlement() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let id = create_entitlement(&state, &app_id, &api_key, "pro").await;
+
+        let (status, body) = send(&state, "GET", &format!("/v1/apps/{app_id}/entitlements/{id}"), &api_key, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "pro");
+
+        let (status, _) = send(&state, "GET", &format!("/v1/apps/{app_id}/entitlements/missing"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // An entitlement belonging to another app is reported as missing
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('other', 'Other', 'ios', 'com.other')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO entitlements (id, app_id, name) VALUES ('elsewhere', 'other', 'pro')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let (status, _) = send(&state, "GET", &format!("/v1/apps/{app_id}/entitlements/elsewhere"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 }
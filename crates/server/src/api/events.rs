@@ -1,37 +1,562 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::time::Duration;
 use axum::{extract::{Query, State}, http::StatusCode, Json};
-use serde::Deserialize;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use tokio_stream::{Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::wrappers::BroadcastStream;
 use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
 use crate::models::event::Event;
 
-#[derive(Deserialize)]
+/// Most events replayed from `since` when a stream is opened.
+const STREAM_REPLAY_LIMIT: i64 = 100;
+
+/// How often an idle socket is pinged, so proxies keep it open.
+const SOCKET_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct EventsQuery {
+    /// Opaque `next_cursor` from a previous page.
+    pub cursor: Option<String>,
     pub since: Option<String>,
     pub limit: Option<i64>,
+    /// Defaults to the API key's app, the only one it may list.
+    pub app_id: Option<String>,
+    pub event_type: Option<String>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct EventsPage {
+    pub events: Vec<Event>,
+    /// Pass back as `cursor` to get the events after this page; `None` when
+    /// there are no events yet.
+    pub next_cursor: Option<String>,
+}
+
+/// Encode a row's position in `(created_at, id)` order as an opaque cursor.
+pub(crate) fn encode_cursor(created_at: &str, id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{created_at}|{id}"))
 }
 
+pub(crate) fn decode_cursor(cursor: &str) -> Option<(String, String)> {
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (created_at, id) = decoded.split_once('|')?;
+    Some((created_at.to_string(), id.to_string()))
+}
+
+/// List the app's events oldest-first after `cursor` (or the `since`
+/// timestamp), or the newest events when neither is given, optionally only
+/// those of `event_type`.
+///
+/// Events are ordered by `(created_at, id)` so ones sharing a timestamp are
+/// never skipped or repeated across pages. `next_cursor` always points at the
+/// newest event returned, so following it walks forward through new events.
+#[utoipa::path(
+    get,
+    path = "/v1/events",
+    tag = "events",
+    params(
+        EventsQuery,
+    ),
+    responses(
+        (status = 200, body = EventsPage),
+        (status = 400, description = "Invalid cursor or timestamp"),
+        (status = 403, description = "`app_id` is another app"),
+    ),
+)]
 pub async fn list_events(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Query(query): Query<EventsQuery>,
-) -> Result<Json<Vec<Event>>, (StatusCode, String)> {
+) -> Result<Json<EventsPage>, (StatusCode, String)> {
+    let app_id = query.app_id.as_deref().unwrap_or(&auth.app_id);
+    auth.authorize(app_id)?;
     let limit = query.limit.unwrap_or(50).min(100);
 
-    let events = if let Some(since) = &query.since {
-        sqlx::query_as::<_, Event>(
-            "SELECT * FROM events WHERE created_at > ? ORDER BY created_at ASC LIMIT ?"
-        )
-        .bind(since)
+    // Each filter adds its own placeholders, bound below in the same order
+    let mut conditions = vec!["app_id = $1".to_string()];
+    let mut params = vec![app_id.to_string()];
+    if let Some(event_type) = &query.event_type {
+        params.push(event_type.clone());
+        conditions.push(format!("event_type = ${}", params.len()));
+    }
+    let order = if let Some(cursor) = &query.cursor {
+        let (created_at, id) = decode_cursor(cursor)
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+        params.extend([created_at, id]);
+        conditions.push(format!("(created_at, id) > (${}, ${})", params.len() - 1, params.len()));
+        "ASC"
+    } else if let Some(since) = &query.since {
+        params.push(since.clone());
+        conditions.push(format!("created_at > ${}", params.len()));
+        "ASC"
+    } else {
+        "DESC"
+    };
+
+    let sql = format!(
+        "SELECT * FROM events WHERE {} ORDER BY created_at {order}, id {order} LIMIT ${}",
+        conditions.join(" AND "),
+        params.len() + 1,
+    );
+    let mut events_query = sqlx::query_as::<_, Event>(&sql);
+    for param in params {
+        events_query = events_query.bind(param);
+    }
+    let events = events_query
         .bind(limit)
         .fetch_all(&state.pool)
         .await
-    } else {
-        sqlx::query_as::<_, Event>(
-            "SELECT * FROM events ORDER BY created_at DESC LIMIT ?"
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // An empty page keeps the caller where it was
+    let next_cursor = events.iter()
+        .max_by(|a, b| (&a.created_at, &a.id).cmp(&(&b.created_at, &b.id)))
+        .map(|e| encode_cursor(&e.created_at, &e.id))
+        .or(query.cursor);
+
+    Ok(Json(EventsPage { events, next_cursor }))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamQuery {
+    /// Replay events created after this timestamp before streaming live ones.
+    pub since: Option<String>,
+    pub event_type: Option<String>,
+}
+
+/// Server-Sent Events stream of the API key's app's newly created events.
+#[utoipa::path(
+    get,
+    path = "/v1/events/stream",
+    tag = "events",
+    params(
+        StreamQuery,
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events, one per event", content_type = "text/event-stream", body = Event),
+    ),
+)]
+pub async fn stream_events(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Query(query): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, (StatusCode, String)> {
+    // Subscribe before replaying so events created in between aren't lost
+    let live = BroadcastStream::new(state.events.subscribe());
+
+    let replay = match &query.since {
+        Some(since) => sqlx::query_as::<_, Event>(
+            "SELECT * FROM events WHERE app_id = $1 AND created_at > $2 ORDER BY created_at ASC LIMIT $3"
         )
-        .bind(limit)
+        .bind(&auth.app_id)
+        .bind(since)
+        .bind(STREAM_REPLAY_LIMIT)
         .fetch_all(&state.pool)
         .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        None => Vec::new(),
+    };
+    let replayed: HashSet<String> = replay.iter().map(|e| e.id.clone()).collect();
+
+    // A lagging subscriber skips the events it missed rather than disconnecting
+    let app_id = auth.app_id;
+    let live = live.filter_map(move |event| {
+        event.ok().filter(|e| e.app_id.as_deref() == Some(app_id.as_str()) && !replayed.contains(&e.id))
+    });
+
+    let event_type = query.event_type;
+    let stream = tokio_stream::iter(replay)
+        .chain(live)
+        .filter(move |event| event_type.as_ref().is_none_or(|t| *t == event.event_type))
+        .map(|event| {
+            Ok(SseEvent::default()
+                .id(event.id.clone())
+                .event(event.event_type.clone())
+                .json_data(&event)
+                .unwrap_or_default())
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// The frame a socket client sends to start, or change, what it receives.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct SocketSubscription {
+    pub app_id: String,
+    /// Empty means every event type.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+}
+
+impl SocketSubscription {
+    fn matches(&self, event: &Event) -> bool {
+        event.app_id.as_deref() == Some(self.app_id.as_str())
+            && (self.event_types.is_empty() || self.event_types.contains(&event.event_type))
+    }
+}
+
+/// WebSocket stream of newly created events. Nothing is sent until the
+/// client subscribes with a [`SocketSubscription`] frame for the API key's
+/// app, which is acknowledged with `{"subscribed": ...}`; sending another
+/// replaces it. Bad frames are answered with `{"error": ...}`.
+#[utoipa::path(
+    get,
+    path = "/v1/events/ws",
+    tag = "events",
+    responses(
+        (status = 101, description = "Switching to a WebSocket; subscribe with a `SocketSubscription` frame"),
+    ),
+)]
+pub async fn socket_events(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| serve_socket(socket, state, auth))
+}
+
+async fn serve_socket(mut socket: WebSocket, state: AppState, auth: AuthenticatedApp) {
+    let mut live = state.events.subscribe();
+    let mut subscription: Option<SocketSubscription> = None;
+    let mut ping = tokio::time::interval(SOCKET_PING_INTERVAL);
+    ping.tick().await;
+
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match serde_json::from_str::<SocketSubscription>(&text) {
+                        Ok(requested) => match auth.authorize(&requested.app_id) {
+                            Ok(()) => {
+                                let ack = serde_json::json!({ "subscribed": &requested });
+                                subscription = Some(requested);
+                                ack
+                            }
+                            Err((_, error)) => serde_json::json!({ "error": error }),
+                        },
+                        Err(e) => serde_json::json!({ "error": format!("Invalid subscription: {e}") }),
+                    };
+                    Some(reply.to_string())
+                }
+                // Pings are answered by the socket itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Binary(_))) => None,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            },
+            event = live.recv() => match event {
+                Ok(event) if subscription.as_ref().is_some_and(|s| s.matches(&event)) => {
+                    serde_json::to_string(&event).ok()
+                }
+                Ok(_) => None,
+                // A lagging subscriber skips the events it missed rather than disconnecting
+                Err(RecvError::Lagged(_)) => None,
+                Err(RecvError::Closed) => break,
+            },
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break;
+                }
+                None
+            }
+        };
+
+        if let Some(reply) = reply {
+            if socket.send(Message::Text(reply.into())).await.is_err() {
+                break;
+            }
+        }
     }
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+}
 
-    Ok(Json(events))
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tokio_stream::StreamExt;
+    use tower::ServiceExt;
+
+    async fn insert_event(state: &AppState, app_id: &str, event_type: &str) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO events (id, app_id, event_type, payload, created_at) VALUES ($1, $2, $3, '{}', $4)")
+            .bind(&id)
+            .bind(app_id)
+            .bind(event_type)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        state.events.publish_stored(&state.pool, &id).await.unwrap();
+        id
+    }
+
+    async fn open_stream(state: &AppState, api_key: Option<&str>, query: &str) -> axum::response::Response {
+        let mut request = Request::builder().uri(format!("/v1/events/stream?{query}"));
+        if let Some(api_key) = api_key {
+            request = request.header("authorization", format!("Bearer {api_key}"));
+        }
+        crate::api::router(state.clone())
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stream_receives_new_events() {
+        let state = test_state(test_pool().await);
+        let (key, _) = seed_two_apps(&state).await;
+
+        let response = open_stream(&state, Some(&key), "event_type=RENEWAL").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let mut body = response.into_body().into_data_stream();
+
+        insert_event(&state, "app", "CANCELLATION").await;
+        let renewal_id = insert_event(&state, "app", "RENEWAL").await;
+
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next())
+            .await
+            .expect("no event received")
+            .unwrap()
+            .unwrap();
+        let text = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(text.contains("event: RENEWAL"));
+        assert!(text.contains(&format!("id: {renewal_id}")));
+        assert!(!text.contains("CANCELLATION"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_only_carries_the_apps_events() {
+        let state = test_state(test_pool().await);
+        let (key, _) = seed_two_apps(&state).await;
+
+        assert_eq!(open_stream(&state, None, "").await.status(), StatusCode::UNAUTHORIZED);
+
+        let response = open_stream(&state, Some(&key), "since=2025-01-01").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+
+        let elsewhere = insert_event(&state, "other", "RENEWAL").await;
+        let own = insert_event(&state, "app", "RENEWAL").await;
+
+        let mut text = String::new();
+        while !text.contains(&format!("id: {own}")) {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next())
+                .await
+                .expect("no event received")
+                .unwrap()
+                .unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        assert!(text.contains("id: evt-1") && text.contains("id: evt-2"));
+        assert!(!text.contains("id: evt-3"));
+        assert!(!text.contains(&elsewhere));
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pages_through_events_with_identical_timestamps() {
+        let state = test_state(test_pool().await);
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id, created_at, updated_at) VALUES ('app', 'Test', 'ios', 'com.test', '', '')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', 'app', 'user123')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let api_key = crate::api::api_keys::issue_api_key(&state.pool, "app").await.unwrap().key;
+        sqlx::query("INSERT INTO events (id, app_id, subscriber_id, event_type, payload, created_at) VALUES ('evt-0', 'app', 'sub', 'RENEWAL', '{}', '2026-01-01T00:00:00+00:00')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let start = "2026-01-01T00:00:00+00:00|evt-0";
+        let mut cursor = base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, start);
+        let mut expected = Vec::new();
+        for i in 1..=5 {
+            let id = format!("evt-{i}");
+            sqlx::query("INSERT INTO events (id, app_id, subscriber_id, event_type, payload, created_at) VALUES ($1, 'app', 'sub', 'RENEWAL', '{}', '2026-01-01T00:00:01+00:00')")
+                .bind(&id)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+            expected.push(id);
+        }
+
+        let mut seen = Vec::new();
+        loop {
+            let response = crate::api::router(state.clone())
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/v1/events?cursor={cursor}&limit=2"))
+                        .header("authorization", format!("Bearer {api_key}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let events = page["events"].as_array().unwrap();
+            if events.is_empty() {
+                // Nothing new: the cursor stays put
+                assert_eq!(page["next_cursor"], cursor.as_str());
+                break;
+            }
+            seen.extend(events.iter().map(|e| e["id"].as_str().unwrap().to_string()));
+            cursor = page["next_cursor"].as_str().unwrap().to_string();
+        }
+        assert_eq!(seen, expected);
+    }
+
+    async fn list(state: &AppState, api_key: &str, query: &str) -> (StatusCode, Vec<String>) {
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/events?{query}"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+        let mut ids: Vec<String> = page["events"].as_array().into_iter().flatten()
+            .map(|e| e["id"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        (status, ids)
+    }
+
+    async fn seed_two_apps(state: &AppState) -> (String, String) {
+        for (app, bundle_id) in [("app", "com.test"), ("other", "com.other")] {
+            sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ($1, 'Test', 'ios', $2)")
+                .bind(app)
+                .bind(bundle_id)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+        for (id, app, event_type) in [("evt-1", "app", "RENEWAL"), ("evt-2", "app", "CANCELLATION"), ("evt-3", "other", "RENEWAL")] {
+            sqlx::query("INSERT INTO events (id, app_id, event_type, payload, created_at) VALUES ($1, $2, $3, '{}', '2026-01-01T00:00:00+00:00')")
+                .bind(id)
+                .bind(app)
+                .bind(event_type)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+        let key = crate::api::api_keys::issue_api_key(&state.pool, "app").await.unwrap().key;
+        let other_key = crate::api::api_keys::issue_api_key(&state.pool, "other").await.unwrap().key;
+        (key, other_key)
+    }
+
+    #[tokio::test]
+    async fn test_list_events_is_scoped_to_the_app() {
+        let state = test_state(test_pool().await);
+        let (key, other_key) = seed_two_apps(&state).await;
+
+        assert_eq!(list(&state, &key, "").await, (StatusCode::OK, vec!["evt-1".to_string(), "evt-2".to_string()]));
+        assert_eq!(list(&state, &other_key, "").await, (StatusCode::OK, vec!["evt-3".to_string()]));
+        assert_eq!(list(&state, &key, "app_id=app&since=2025-01-01").await.1.len(), 2);
+        assert_eq!(list(&state, &key, "app_id=other").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(list(&state, "bogus", "").await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_list_events_filters_by_type() {
+        let state = test_state(test_pool().await);
+        let (key, _) = seed_two_apps(&state).await;
+
+        assert_eq!(list(&state, &key, "event_type=RENEWAL").await, (StatusCode::OK, vec!["evt-1".to_string()]));
+        assert_eq!(list(&state, &key, "event_type=CANCELLATION&since=2025-01-01").await.1, vec!["evt-2".to_string()]);
+        let cursor = crate::api::events::encode_cursor("2025-01-01", "");
+        assert_eq!(list(&state, &key, &format!("event_type=RENEWAL&cursor={cursor}")).await.1, vec!["evt-1".to_string()]);
+        assert!(list(&state, &key, "event_type=EXPIRATION").await.1.is_empty());
+    }
+
+    /// The next text frame from a socket, as JSON.
+    async fn next_text<S, E>(socket: &mut S) -> serde_json::Value
+    where
+        S: tokio_stream::Stream<Item = Result<tokio_tungstenite::tungstenite::Message, E>> + Unpin,
+        E: std::fmt::Debug,
+    {
+        loop {
+            let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+                .await
+                .expect("no message received")
+                .unwrap()
+                .unwrap();
+            if let tokio_tungstenite::tungstenite::Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_socket_only_receives_subscribed_events() {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+
+        let state = test_state(test_pool().await);
+        let (key, _) = seed_two_apps(&state).await;
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', 'app', 'user123')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::api::router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut request = format!("ws://{addr}/v1/events/ws").into_client_request().unwrap();
+        request.headers_mut().insert("authorization", format!("Bearer {key}").parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        // Another app's events can't be subscribed to
+        socket.send(Message::text(r#"{"app_id":"other"}"#)).await.unwrap();
+        assert!(next_text(&mut socket).await["error"].is_string());
+        socket.send(Message::text("not json")).await.unwrap();
+        assert!(next_text(&mut socket).await["error"].is_string());
+
+        socket.send(Message::text(r#"{"app_id":"app","event_types":["RENEWAL"]}"#)).await.unwrap();
+        assert_eq!(next_text(&mut socket).await["subscribed"]["event_types"], serde_json::json!(["RENEWAL"]));
+
+        for (app, event_type) in [("other", "RENEWAL"), ("app", "CANCELLATION"), ("app", "RENEWAL")] {
+            let id = uuid::Uuid::new_v4().to_string();
+            sqlx::query("INSERT INTO events (id, app_id, event_type, payload, created_at) VALUES ($1, $2, $3, '{}', $4)")
+                .bind(&id)
+                .bind(app)
+                .bind(event_type)
+                .bind(chrono::Utc::now().to_rfc3339())
+                .execute(&state.pool)
+                .await
+                .unwrap();
+            state.events.publish_stored(&state.pool, &id).await.unwrap();
+        }
+        let event = next_text(&mut socket).await;
+        assert_eq!((event["app_id"].as_str(), event["event_type"].as_str()), (Some("app"), Some("RENEWAL")));
+
+        socket.send(Message::Ping(b"hi".to_vec().into())).await.unwrap();
+        let pong = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(pong, Message::Pong(b"hi".to_vec().into()));
+
+        socket.close(None).await.unwrap();
+        while let Some(Ok(message)) = socket.next().await {
+            assert!(matches!(message, Message::Close(_)), "{message:?}");
+        }
+    }
 }
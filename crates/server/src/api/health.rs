@@ -1,18 +1,37 @@
 use axum::{http::StatusCode, Json};
 use serde::Serialize;
 
-#[derive(Serialize)]
+#[derive(Serialize, utoipa::ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub version: String,
+    /// Commit the server was built from, when git was available to the build.
+    pub git_sha: Option<String>,
+    pub built_at: Option<String>,
+    pub rustc_version: Option<String>,
+    /// Cargo features compiled in.
+    pub features: Vec<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, body = HealthResponse),
+    ),
+    security(()),
+)]
 pub async fn health_check() -> (StatusCode, Json<HealthResponse>) {
     (
         StatusCode::OK,
         Json(HealthResponse {
             status: "ok".to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: option_env!("VERGEN_GIT_SHA").map(String::from),
+            built_at: option_env!("VERGEN_BUILD_TIMESTAMP").map(String::from),
+            rustc_version: option_env!("VERGEN_RUSTC_SEMVER").map(String::from),
+            features: env!("OPENCAT_FEATURES").split(',').filter(|f| !f.is_empty()).map(String::from).collect(),
         }),
     )
 }
@@ -35,5 +54,9 @@ mod tests {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(!health["version"].as_str().unwrap().is_empty());
+        assert!(health["features"].is_array());
     }
 }
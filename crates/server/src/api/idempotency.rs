@@ -0,0 +1,306 @@
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use crate::api::AppState;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Set on a response replayed for a repeated key rather than produced afresh.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// How long a stored response is replayed for a repeated `Idempotency-Key`.
+const IDEMPOTENCY_TTL_HOURS: i64 = 24;
+
+/// How long a request may hold its key before another with the same key is
+/// let through in its place, in case the first never finished and its claim
+/// couldn't be released (say the process died).
+const IN_FLIGHT_TTL_MINUTES: i64 = 5;
+
+/// Replay the first successful response to a POST retried with the same
+/// `Idempotency-Key` within 24 hours, so double-clicks and client retries
+/// don't create the same resource twice. Opt a route in with
+/// `post(handler).layer(from_fn_with_state(state, idempotency::replay))`.
+///
+/// Keys are scoped to the caller's `Authorization` header and the path. A key
+/// reused for a different query or body is rejected with 422, and one whose first
+/// request is still running with 409. Failed requests aren't remembered, so
+/// they can be retried under the same key. Stored responses are encrypted, as
+/// some are secrets.
+///
+/// Responses to requests without an `Authorization` header aren't stored,
+/// since anyone with the same key and body could read them back: a new app's
+/// API key, say. A retry of one that succeeded is refused with 409 instead.
+pub async fn replay(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if request.method() != Method::POST {
+        return next.run(request).await;
+    }
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let Some(key) = key.to_str().ok().filter(|k| !k.is_empty()).map(str::to_string) else {
+        return (StatusCode::BAD_REQUEST, "Invalid Idempotency-Key header").into_response();
+    };
+
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response();
+    };
+    let authorization = parts.headers.get(header::AUTHORIZATION).map(HeaderValue::as_bytes).unwrap_or_default();
+    let replayable = !authorization.is_empty();
+    let scope = format!("{:x}", Sha256::digest([authorization, b"\n", parts.uri.path().as_bytes()].concat()));
+    let query = parts.uri.query().unwrap_or_default().as_bytes();
+    let request_hash = format!("{:x}", Sha256::digest([query, b"\n", &body].concat()));
+
+    match claim(&state, &scope, &key, &request_hash).await {
+        Ok(None) => {}
+        Ok(Some(response)) => return response,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+    let mut claim = InFlightClaim { state: Some(state.clone()), scope: scope.clone(), key: key.clone() };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let remembered = remember(&state, &scope, &key, replayable, response).await;
+    claim.state = None;
+    match remembered {
+        Ok(response) => response,
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Releases a claimed key if the request is dropped before its response is
+/// remembered, e.g. when the client disconnects or the request times out, so
+/// a retry isn't refused as in progress until the claim goes stale.
+struct InFlightClaim {
+    /// `None` once the response has been remembered.
+    state: Option<AppState>,
+    scope: String,
+    key: String,
+}
+
+impl Drop for InFlightClaim {
+    fn drop(&mut self) {
+        let Some(state) = self.state.take() else { return };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else { return };
+        let (scope, key) = (std::mem::take(&mut self.scope), std::mem::take(&mut self.key));
+        runtime.spawn(async move {
+            let released = sqlx::query(
+                "DELETE FROM idempotent_requests WHERE scope = $1 AND idempotency_key = $2 AND status_code IS NULL"
+            )
+            .bind(&scope)
+            .bind(&key)
+            .execute(&state.pool)
+            .await;
+            if let Err(e) = released {
+                tracing::warn!("Failed to release abandoned Idempotency-Key: {e}");
+            }
+        });
+    }
+}
+
+/// Take `key` for this request, or the response to give instead: the stored
+/// one for a repeat, or an error when the key can't be used.
+async fn claim(state: &AppState, scope: &str, key: &str, request_hash: &str) -> anyhow::Result<Option<Response>> {
+    let now = chrono::Utc::now();
+    sqlx::query("DELETE FROM idempotent_requests WHERE created_at < $1 OR (status_code IS NULL AND created_at < $2)")
+        .bind((now - chrono::Duration::hours(IDEMPOTENCY_TTL_HOURS)).to_rfc3339())
+        .bind((now - chrono::Duration::minutes(IN_FLIGHT_TTL_MINUTES)).to_rfc3339())
+        .execute(&state.pool)
+        .await?;
+
+    let claimed = sqlx::query(
+        "INSERT INTO idempotent_requests (scope, idempotency_key, request_hash, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(request_hash)
+    .bind(now.to_rfc3339())
+    .execute(&state.pool)
+    .await?
+    .rows_affected();
+    if claimed == 1 {
+        return Ok(None);
+    }
+
+    let (stored_hash, status_code, body) = sqlx::query_as::<_, (String, Option<i32>, Option<String>)>(
+        "SELECT request_hash, status_code, response_body FROM idempotent_requests WHERE scope = $1 AND idempotency_key = $2"
+    )
+    .bind(scope)
+    .bind(key)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if stored_hash != request_hash {
+        return Ok(Some((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Idempotency-Key was already used for a different request",
+        ).into_response()));
+    }
+    let (status_code, body) = match (status_code, body) {
+        (Some(status_code), Some(body)) => (status_code, body),
+        (Some(_), None) => return Ok(Some((
+            StatusCode::CONFLICT,
+            "A request with this Idempotency-Key already succeeded",
+        ).into_response())),
+        (None, _) => return Ok(Some((
+            StatusCode::CONFLICT,
+            "A request with this Idempotency-Key is still in progress",
+        ).into_response())),
+    };
+
+    let status = StatusCode::from_u16(status_code as u16)?;
+    let body = state.cipher.decrypt(&body)?;
+    Ok(Some((
+        status,
+        [(header::CONTENT_TYPE.as_str(), "application/json"), (IDEMPOTENT_REPLAYED_HEADER, "true")],
+        body,
+    ).into_response()))
+}
+
+/// Store a successful `response` against `key` and pass it on, or only that
+/// it succeeded unless `replayable`; release the key after a failure so the
+/// request can be retried.
+async fn remember(state: &AppState, scope: &str, key: &str, replayable: bool, response: Response) -> anyhow::Result<Response> {
+    if !response.status().is_success() {
+        sqlx::query("DELETE FROM idempotent_requests WHERE scope = $1 AND idempotency_key = $2")
+            .bind(scope)
+            .bind(key)
+            .execute(&state.pool)
+            .await?;
+        return Ok(response);
+    }
+    if !replayable {
+        sqlx::query("UPDATE idempotent_requests SET status_code = $1 WHERE scope = $2 AND idempotency_key = $3")
+            .bind(response.status().as_u16() as i32)
+            .bind(scope)
+            .bind(key)
+            .execute(&state.pool)
+            .await?;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await?;
+    sqlx::query(
+        "UPDATE idempotent_requests SET status_code = $1, response_body = $2 WHERE scope = $3 AND idempotency_key = $4"
+    )
+    .bind(parts.status.as_u16() as i32)
+    .bind(state.cipher.encrypt(std::str::from_utf8(&body)?)?)
+    .bind(scope)
+    .bind(key)
+    .execute(&state.pool)
+    .await?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn post(state: &AppState, uri: &str, key: Option<&str>, api_key: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut request = Request::builder().method("POST").uri(uri).header("content-type", "application/json");
+        if let Some(key) = key {
+            request = request.header("idempotency-key", key);
+        }
+        if let Some(api_key) = api_key {
+            request = request.header("authorization", format!("Bearer {api_key}"));
+        }
+        let response = crate::api::router(state.clone())
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_repeated_create_with_same_key_returns_same_resource() {
+        let state = test_state(test_pool().await);
+        let app = serde_json::json!({ "name": "Test", "platform": "ios", "bundle_id": "com.test" });
+
+        let (status, first) = post(&state, "/v1/apps", Some("key-1"), None, app.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        // Unauthenticated, so the new API key isn't replayed to whoever repeats the request
+        let (status, again) = post(&state, "/v1/apps", Some("key-1"), None, app.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(again.get("api_key").is_none());
+
+        let (status, other) = post(&state, "/v1/apps", Some("key-2"), None, serde_json::json!({ "name": "Other", "platform": "ios", "bundle_id": "com.other" })).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(other["id"], first["id"]);
+        let apps: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM apps").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(apps, 2);
+
+        let (status, _) = post(&state, "/v1/apps", Some("key-1"), None, serde_json::json!({ "name": "Other", "platform": "ios", "bundle_id": "com.other" })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Other routes and callers have keys of their own
+        let api_key = first["api_key"].as_str().unwrap();
+        let entitlement = serde_json::json!({ "name": "pro" });
+        let uri = format!("/v1/apps/{}/entitlements", first["id"].as_str().unwrap());
+        let (status, created) = post(&state, &uri, Some("key-1"), Some(api_key), entitlement.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, again) = post(&state, &uri, Some("key-1"), Some(api_key), entitlement).await;
+        assert_eq!(again["id"], created["id"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_releases_its_key() {
+        let state = test_state(test_pool().await);
+        let started = std::sync::Arc::new(tokio::sync::Notify::new());
+        let app = axum::Router::new()
+            .route("/slow", axum::routing::post({
+                let started = started.clone();
+                move || async move {
+                    started.notify_one();
+                    std::future::pending::<()>().await
+                }
+            }))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), super::replay));
+        let request = || Request::builder().method("POST").uri("/slow").header("idempotency-key", "key-1").body(Body::empty()).unwrap();
+
+        let handling = tokio::spawn(app.clone().oneshot(request()));
+        started.notified().await;
+        let in_flight: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM idempotent_requests").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(in_flight, 1);
+
+        // The client goes away, dropping the request mid-handler
+        handling.abort();
+        let _ = handling.await;
+        let mut in_flight = 1;
+        for _ in 0..100 {
+            in_flight = sqlx::query_scalar("SELECT COUNT(*) FROM idempotent_requests").fetch_one(&state.pool).await.unwrap();
+            if in_flight == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(in_flight, 0);
+
+        // A retry reaches the handler rather than getting 409 for a request nobody is running
+        let retry = tokio::spawn(app.oneshot(request()));
+        let reached = tokio::time::timeout(std::time::Duration::from_secs(5), started.notified()).await;
+        retry.abort();
+        assert!(reached.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_releases_its_key() {
+        let state = test_state(test_pool().await);
+
+        let (status, _) = post(&state, "/v1/apps", Some("key-1"), None, serde_json::json!({ "name": "Test", "platform": "toaster", "bundle_id": "com.test" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = post(&state, "/v1/apps", Some("key-1"), None, serde_json::json!({ "name": "Test", "platform": "ios", "bundle_id": "com.test" })).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+}
@@ -1,45 +1,157 @@
+pub mod analytics;
+pub mod api_keys;
 pub mod apps;
 pub mod auth;
+pub mod cors;
 pub mod entitlements;
 pub mod events;
 pub mod health;
+pub mod idempotency;
 pub mod notifications;
 pub mod offerings;
+pub mod openapi;
 pub mod products;
+pub mod promotional_offers;
 pub mod receipts;
+pub mod request_id;
 pub mod subscribers;
+pub mod subscriptions;
+pub mod transactions;
 pub mod webhooks;
 
+use std::time::Duration;
 use axum::Router;
-use axum::routing::{get, post, put};
-use tower_http::cors::{CorsLayer, Any};
+use axum::extract::DefaultBodyLimit;
+use axum::http::StatusCode;
+use axum::routing::{get, post, put, delete};
+use crate::crypto::CredentialCipher;
+use crate::currency::FxRates;
 use crate::db::DbPool;
+use crate::events::EventBus;
+use self::offerings::OfferingsCache;
+use crate::store::apple::AppleEnvironmentCache;
+use crate::store::apple_jws::AppleJwsVerifier;
+use crate::store::google_push::GooglePushVerifier;
+use crate::store::StoreError;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: DbPool,
+    pub cipher: CredentialCipher,
+    /// Shared HTTP client for outbound store API calls.
+    pub http: reqwest::Client,
+    pub events: EventBus,
+    /// Trust anchor for App Store signed payloads.
+    pub apple_verifier: AppleJwsVerifier,
+    /// Which App Store environment each transaction was last found in.
+    pub apple_environments: AppleEnvironmentCache,
+    /// Authenticates Google Pub/Sub push requests.
+    pub google_verifier: GooglePushVerifier,
+    /// Computed offerings, per app.
+    pub offerings_cache: OfferingsCache,
+    /// Rates for showing offering prices in another currency.
+    pub fx_rates: FxRates,
 }
 
+/// Status to answer with when a store call fails: a bad receipt is the
+/// client's fault, while an unreachable store or rejected credentials aren't.
+pub(crate) fn store_error_status(error: &StoreError) -> StatusCode {
+    match error {
+        StoreError::NotFound(_) => StatusCode::NOT_FOUND,
+        StoreError::Invalid(_) => StatusCode::BAD_REQUEST,
+        StoreError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        StoreError::Auth(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        StoreError::RateLimited(_) => StatusCode::SERVICE_UNAVAILABLE,
+        StoreError::WrongApp(_) => StatusCode::UNAUTHORIZED,
+    }
+}
+
+/// Bounds on a single request, from `server.max_body_bytes` and
+/// `server.request_timeout_seconds`.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    pub max_body_bytes: usize,
+    pub timeout: Duration,
+}
+
+impl RequestLimits {
+    pub fn from_config(config: &crate::config::ServerConfig) -> Self {
+        Self {
+            max_body_bytes: config.max_body_bytes,
+            timeout: Duration::from_secs(config.request_timeout_seconds),
+        }
+    }
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self { max_body_bytes: 256 * 1024, timeout: Duration::from_secs(30) }
+    }
+}
+
+/// API routes with the default [`RequestLimits`]. CORS is left to the
+/// caller; see [`cors::layer`].
 pub fn router(state: AppState) -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    router_with_limits(state, RequestLimits::default())
+}
+
+/// API routes. Oversized bodies are answered with 413 and requests still
+/// running after `limits.timeout` with 408.
+pub fn router_with_limits(state: AppState, limits: RequestLimits) -> Router {
+    let idempotent = || axum::middleware::from_fn_with_state(state.clone(), idempotency::replay);
 
     Router::new()
         .route("/health", get(health::health_check))
-        .route("/v1/apps", post(apps::create_app).get(apps::list_apps))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/v1/apps", post(apps::create_app).layer(idempotent()).get(apps::list_apps))
+        .route("/v1/apps/{app_id}", delete(apps::delete_app))
         .route("/v1/apps/{app_id}/credentials", put(apps::update_credentials).get(apps::get_credentials))
-        .route("/v1/apps/{app_id}/offerings", get(offerings::get_offerings))
+        .route("/v1/apps/{app_id}/api-keys", post(api_keys::create_api_key).get(api_keys::list_api_keys))
+        .route("/v1/apps/{app_id}/api-keys/{key_id}", delete(api_keys::revoke_api_key))
+        .route("/v1/apps/{app_id}/offerings", post(offerings::create_offering).get(offerings::get_offerings))
+        .route("/v1/apps/{app_id}/current-offering", put(offerings::set_current_offering))
+        .route("/v1/apps/{app_id}/offerings/overrides", post(offerings::create_offering_override))
+        .route("/v1/apps/{app_id}/experiments", post(offerings::create_experiment))
+        .route("/v1/apps/{app_id}/experiments/{experiment_id}", delete(offerings::delete_experiment))
         .route("/v1/apps/{app_id}/sync-products", post(apps::sync_products))
-        .route("/v1/apps/{app_id}/entitlements", post(entitlements::create_entitlement).get(entitlements::list_entitlements))
-        .route("/v1/apps/{app_id}/products", post(products::create_product).get(products::list_products))
+        .route("/v1/apps/{app_id}/promotional-offers/sign", post(promotional_offers::sign_offer))
+        .route("/v1/apps/{app_id}/entitlements", post(entitlements::create_entitlement).layer(idempotent()).get(entitlements::list_entitlements))
+        .route("/v1/apps/{app_id}/entitlements/{entitlement_id}", get(entitlements::get_entitlement).put(entitlements::update_entitlement).delete(entitlements::delete_entitlement))
+        .route("/v1/apps/{app_id}/products", post(products::create_product).layer(idempotent()).get(products::list_products))
+        .route("/v1/apps/{app_id}/products/import", post(products::import_products))
+        .route("/v1/apps/{app_id}/products/{product_id}", get(products::get_product).put(products::update_product).delete(products::delete_product))
+        .route("/v1/apps/{app_id}/metrics/overview", get(analytics::overview))
+        .route("/v1/apps/{app_id}/subscribers", post(subscribers::create_subscriber).get(subscribers::list_subscribers))
+        .route("/v1/apps/{app_id}/subscribers/export.csv", get(subscribers::export_subscribers))
+        .route("/v1/apps/{app_id}/subscribers/{app_user_id}", delete(subscribers::delete_subscriber))
+        .route("/v1/apps/{app_id}/subscribers/{app_user_id}/attributes", post(subscribers::set_attributes))
+        .route("/v1/apps/{app_id}/subscribers/{app_user_id}/restore", post(receipts::restore_purchases))
+        .route("/v1/apps/{app_id}/subscribers/{app_user_id}/backfill", post(receipts::backfill_subscriber))
+        .route("/v1/apps/{app_id}/subscriptions/{original_transaction_id}", get(subscriptions::get_subscription))
+        .route("/v1/apps/{app_id}/transactions", get(transactions::list_transactions))
         .route("/v1/subscribers/{app_user_id}", get(subscribers::get_subscriber))
-        .route("/v1/receipts", post(receipts::submit_receipt))
+        .route("/v1/subscribers/{app_user_id}/alias", post(subscribers::alias_subscriber))
+        .route("/v1/receipts", post(receipts::submit_receipt).layer(idempotent()))
         .route("/v1/notifications/apple", post(notifications::apple_notification))
+        .route("/v1/notifications/apple/{app_id}", post(notifications::apple_app_notification))
         .route("/v1/notifications/google", post(notifications::google_notification))
-        .route("/v1/webhooks", post(webhooks::create_webhook).get(webhooks::list_webhooks))
+        .route("/v1/notifications/{id}/replay", post(notifications::replay_notification))
+        .route("/v1/webhooks", post(webhooks::create_webhook).layer(idempotent()).get(webhooks::list_webhooks))
+        .route("/v1/webhooks/{endpoint_id}", put(webhooks::update_webhook).delete(webhooks::delete_webhook))
+        .route("/v1/webhooks/{endpoint_id}/rotate-secret", post(webhooks::rotate_webhook_secret))
+        .route("/v1/webhooks/{endpoint_id}/deliveries", get(webhooks::list_deliveries))
+        .route("/v1/webhooks/{endpoint_id}/redrive", post(webhooks::redrive_dead_letters))
+        .route("/v1/webhook-deliveries/{delivery_id}/retry", post(webhooks::retry_delivery))
         .route("/v1/events", get(events::list_events))
-        .layer(cors)
+        .route("/v1/events/stream", get(events::stream_events))
+        .route("/v1/events/ws", get(events::socket_events))
+        .route_layer(axum::middleware::from_fn(crate::metrics::track_requests))
+        // Replaces axum's own 2MB default so the configured limit applies
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(limits.max_body_bytes))
+        .layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, limits.timeout))
+        .layer(axum::middleware::from_fn(request_id::propagate))
         .with_state(state)
 }
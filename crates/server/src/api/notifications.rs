@@ -1,72 +1,1008 @@
-use axum::{extract::State, http::StatusCode, Json};
-use serde::Deserialize;
+use axum::{extract::{Path, State}, http::{header, HeaderMap, StatusCode}, Json};
+use serde::{Deserialize, Serialize};
 use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+use crate::events::record_event;
+use crate::models::app::{App, Platform};
+use crate::models::subscriber::Subscriber;
+use crate::store::apple::{AppleStoreAdapter, ConsumptionRequest, ConsumptionUsage};
+use crate::store::google::DeveloperNotification;
+use crate::store::{StoreAdapter, StoreError};
+use crate::store::types::TransactionEvent;
+use crate::transactions::{apply_transaction_event, event_payload, resumes_pause};
+use tracing::Instrument;
 
+/// App Store notifications for whichever app the payload's bundle ID belongs to.
+#[utoipa::path(
+    post,
+    path = "/v1/notifications/apple",
+    tag = "notifications",
+    request_body = serde_json::Value,
+    responses(
+        (status = 200, description = "Notification applied"),
+        (status = 400, description = "Invalid or untrusted payload"),
+        (status = 413, description = "Payload too large"),
+    ),
+    security(()),
+)]
 pub async fn apple_notification(
     State(state): State<AppState>,
     body: axum::body::Bytes,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    // Parse the notification to extract event data
-    let payload: serde_json::Value = serde_json::from_slice(&body)
+    let raw_id = record_raw_notification(&state, "apple", &body).await?;
+    let result = process_apple_notification(&state, &raw_id, &body, None, false).await;
+    finish_raw_notification(&state, &raw_id, &result).await?;
+    result
+}
+
+/// App Store notifications for one app, rejected with 401 when Apple signed
+/// them for a different one.
+#[utoipa::path(
+    post,
+    path = "/v1/notifications/apple/{app_id}",
+    tag = "notifications",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = serde_json::Value,
+    responses(
+        (status = 200, description = "Notification applied"),
+        (status = 401, description = "Signed for a different app"),
+        (status = 404, description = "App not found"),
+        (status = 413, description = "Payload too large"),
+    ),
+    security(()),
+)]
+pub async fn apple_app_notification(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    body: axum::body::Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let raw_id = record_raw_notification(&state, "apple", &body).await?;
+    let result = process_apple_notification(&state, &raw_id, &body, Some(&app_id), false).await;
+    finish_raw_notification(&state, &raw_id, &result).await?;
+    result
+}
+
+/// Decode an App Store notification and apply its events, for `app_id` if
+/// given and otherwise for the app with the payload's bundle ID. A replay
+/// skips duplicate detection and doesn't answer consumption requests again.
+#[tracing::instrument(skip_all, fields(store = "apple", raw_notification_id = %raw_id, app_id = tracing::field::Empty))]
+async fn process_apple_notification(
+    state: &AppState,
+    raw_id: &str,
+    body: &[u8],
+    app_id: Option<&str>,
+    replay: bool,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let peeked = crate::store::apple::peek_notification(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let bundle_id = peeked["data"]["bundleId"]
+        .as_str()
+        .ok_or((StatusCode::BAD_REQUEST, "Missing bundleId".to_string()))?;
+    let notification_id = peeked["notificationUUID"].as_str().filter(|_| !replay);
 
-    // Store raw event
-    let event_id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
-
-    // We need a subscriber_id to store the event. In a full implementation,
-    // we'd decode the JWS to find the transaction, look up the subscriber,
-    // then store the event. For now, store with a placeholder if we can't resolve.
-    sqlx::query(
-        "INSERT INTO events (id, subscriber_id, event_type, payload, created_at)
-         SELECT ?, s.id, 'APPLE_NOTIFICATION', ?, ?
-         FROM subscribers s LIMIT 1"
-    )
-    .bind(&event_id)
-    .bind(serde_json::to_string(&payload).unwrap_or_default())
-    .bind(&now)
-    .execute(&state.pool)
-    .await
-    .ok(); // Best-effort event storage
+    // Either way the adapter checks the verified payload was meant for the app
+    let app = match app_id {
+        Some(app_id) => find_app(state, app_id).await?,
+        None => find_app_by_bundle_id(state, bundle_id, &[Platform::Ios, Platform::Macos]).await?,
+    };
+    tracing::Span::current().record("app_id", app.id.as_str());
+    let adapter = crate::store::apple_adapter_for_app(&app, &state.cipher, &state.http, &state.apple_verifier, &state.apple_environments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let events = adapter.process_notification(body).await
+        .map_err(|e| {
+            crate::metrics::record_notification("apple", notification_failure(&e));
+            (crate::api::store_error_status(&e), format!("Notification not processed: {e}"))
+        })?;
+    attach_raw_notification(state, raw_id, &app).await?;
+
+    // Apple wants consumption data within 12 hours of a refund request
+    let consumption_transaction = (peeked["notificationType"] == "CONSUMPTION_REQUEST" && !replay)
+        .then(|| events.first().map(|e| e.transaction.store_transaction_id.clone()))
+        .flatten();
+
+    store_transaction_events(state, &app, "apple", notification_id, events).await?;
+
+    if let Some(transaction_id) = consumption_transaction {
+        if !adapter.has_consumption_consent() {
+            tracing::info!("Not answering consumption request for {transaction_id}: app {} has no customer consent", app.id);
+        } else if let Err(e) = answer_consumption_request(state, &adapter, &transaction_id).await {
+            tracing::warn!("Failed to send consumption info for {transaction_id}: {e}");
+        }
+    }
 
     Ok(StatusCode::OK)
 }
 
-#[derive(Deserialize)]
+/// Send Apple what we know about the customer behind `transaction_id`.
+async fn answer_consumption_request(
+    state: &AppState,
+    adapter: &AppleStoreAdapter,
+    transaction_id: &str,
+) -> anyhow::Result<()> {
+    let subscriber = sqlx::query_as::<_, Subscriber>(
+        "SELECT s.* FROM subscribers s JOIN transactions t ON t.subscriber_id = s.id \
+         WHERE t.store = 'apple' AND t.store_transaction_id = $1"
+    )
+    .bind(transaction_id)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("No subscriber for transaction {transaction_id}"))?;
+
+    let history = sqlx::query_as::<_, (String, Option<i64>)>(
+        "SELECT t.status, p.price_micros FROM transactions t JOIN products p ON t.product_id = p.id \
+         WHERE t.subscriber_id = $1"
+    )
+    .bind(&subscriber.id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    let mut usage = ConsumptionUsage::default();
+    for (status, price_micros) in history {
+        let price = price_micros.unwrap_or(0);
+        usage.purchased_micros += price;
+        if status == "refunded" {
+            usage.refunded_micros += price;
+        }
+        usage.has_active_subscription |= status == "active";
+    }
+
+    let request = ConsumptionRequest::for_subscriber(&subscriber, &usage, chrono::Utc::now());
+    adapter.send_consumption_info(transaction_id, &request).await
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
 pub struct PubSubMessage {
     pub message: PubSubData,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::ToSchema)]
 pub struct PubSubData {
     pub data: String,
+    #[serde(rename = "messageId", alias = "message_id")]
+    pub message_id: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/notifications/google",
+    tag = "notifications",
+    request_body = PubSubMessage,
+    responses(
+        (status = 200, description = "Notification applied"),
+        (status = 401, description = "Push request not authenticated by Pub/Sub"),
+        (status = 413, description = "Payload too large"),
+    ),
+    security(()),
+)]
 pub async fn google_notification(
     State(state): State<AppState>,
-    Json(pubsub_message): Json<PubSubMessage>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let authorization = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+    if let Err(e) = state.google_verifier.verify(&state.http, authorization).await {
+        tracing::warn!("Rejected Google push notification: {e}");
+        return Err((StatusCode::UNAUTHORIZED, "Invalid push authentication".to_string()));
+    }
+
+    let raw_id = record_raw_notification(&state, "google", &body).await?;
+    let result = process_google_notification(&state, &raw_id, &body, false).await;
+    finish_raw_notification(&state, &raw_id, &result).await?;
+    result
+}
+
+/// Unwrap a Pub/Sub push body and apply the developer notification inside.
+/// A replay skips duplicate detection.
+#[tracing::instrument(skip_all, fields(store = "google", raw_notification_id = %raw_id, app_id = tracing::field::Empty))]
+async fn process_google_notification(
+    state: &AppState,
+    raw_id: &str,
+    body: &[u8],
+    replay: bool,
 ) -> Result<StatusCode, (StatusCode, String)> {
+    let pubsub_message: PubSubMessage = serde_json::from_slice(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
     use base64::Engine;
     let data = base64::engine::general_purpose::STANDARD.decode(&pubsub_message.message.data)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
     let payload: serde_json::Value = serde_json::from_slice(&data)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let package_name = payload["packageName"]
+        .as_str()
+        .ok_or((StatusCode::BAD_REQUEST, "Missing packageName".to_string()))?;
+
+    let app = find_app_by_bundle_id(state, package_name, &[Platform::Android]).await?;
+    tracing::Span::current().record("app_id", app.id.as_str());
+    let adapter = crate::store::adapter_for_app(&app, "google", &state.cipher, &state.http, &state.apple_verifier, &state.apple_environments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let events = adapter.process_notification(&data).await
+        .map_err(|e| {
+            crate::metrics::record_notification("google", notification_failure(&e));
+            (crate::api::store_error_status(&e), format!("Notification not processed: {e}"))
+        })?;
+    attach_raw_notification(state, raw_id, &app).await?;
+
+    let notification_id = pubsub_message.message.message_id.as_deref().filter(|_| !replay);
+    store_transaction_events(state, &app, "google", notification_id, events).await?;
 
-    let event_id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
+    if let Ok(DeveloperNotification::VoidedPurchase { purchase_token }) = DeveloperNotification::parse(&payload) {
+        crate::voided::refund_google_purchase(&state.pool, &state.events, &app.id, &purchase_token)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
 
-    sqlx::query(
-        "INSERT INTO events (id, subscriber_id, event_type, payload, created_at)
-         SELECT ?, s.id, 'GOOGLE_NOTIFICATION', ?, ?
-         FROM subscribers s LIMIT 1"
+    Ok(StatusCode::OK)
+}
+
+/// A stored notification, without its payload.
+#[derive(Debug, Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct RawNotification {
+    pub id: String,
+    pub store: String,
+    pub app_id: Option<String>,
+    /// `received`, `processed` or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+    pub received_at: String,
+    pub processed_at: Option<String>,
+}
+
+/// Run a stored notification through processing again, e.g. after fixing a
+/// bug that mishandled it. Its events are applied and emitted again even if
+/// it was processed before. Only notifications matched to the caller's app
+/// can be replayed.
+#[utoipa::path(
+    post,
+    path = "/v1/notifications/{id}/replay",
+    tag = "notifications",
+    params(
+        ("id" = String, Path, description = "Raw notification ID"),
+    ),
+    responses(
+        (status = 200, body = RawNotification),
+        (status = 404, description = "Notification not found"),
+    ),
+)]
+pub async fn replay_notification(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(id): Path<String>,
+) -> Result<Json<RawNotification>, (StatusCode, String)> {
+    let (store, payload) = sqlx::query_as::<_, (String, Vec<u8>)>(
+        "SELECT store, payload FROM raw_notifications WHERE id = $1 AND app_id = $2"
     )
-    .bind(&event_id)
-    .bind(serde_json::to_string(&payload).unwrap_or_default())
-    .bind(&now)
-    .execute(&state.pool)
+    .bind(&id)
+    .bind(&auth.app_id)
+    .fetch_optional(&state.pool)
     .await
-    .ok();
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Notification not found".to_string()))?;
 
-    Ok(StatusCode::OK)
+    let result = match store.as_str() {
+        "apple" => process_apple_notification(&state, &id, &payload, Some(&auth.app_id), true).await,
+        "google" => process_google_notification(&state, &id, &payload, true).await,
+        other => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Can't replay {other} notifications"))),
+    };
+    finish_raw_notification(&state, &id, &result).await?;
+    result?;
+
+    sqlx::query_as::<_, RawNotification>(
+        "SELECT id, store, app_id, status, error, received_at, processed_at FROM raw_notifications WHERE id = $1"
+    )
+    .bind(&id)
+    .fetch_one(&state.pool)
+    .await
+    .map(Json)
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Largest notification body kept; real ones are a few signed JWS at most.
+const MAX_RAW_NOTIFICATION_BYTES: usize = 128 * 1024;
+
+/// How long notifications matched to an app are kept for replays.
+const RAW_NOTIFICATION_RETENTION_DAYS: i64 = 30;
+
+/// How long notifications that never passed verification are kept, long
+/// enough to look into why.
+const UNVERIFIED_RAW_NOTIFICATION_RETENTION_HOURS: i64 = 24;
+
+/// Keep the notification as received before anything can go wrong with it.
+/// It isn't trusted yet, so it belongs to no app until it has been verified.
+async fn record_raw_notification(state: &AppState, store: &str, payload: &[u8]) -> Result<String, (StatusCode, String)> {
+    if payload.len() > MAX_RAW_NOTIFICATION_BYTES {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Notification too large".to_string()));
+    }
+
+    let now = chrono::Utc::now();
+    sqlx::query("DELETE FROM raw_notifications WHERE received_at < $1 OR (app_id IS NULL AND received_at < $2)")
+        .bind((now - chrono::Duration::days(RAW_NOTIFICATION_RETENTION_DAYS)).to_rfc3339())
+        .bind((now - chrono::Duration::hours(UNVERIFIED_RAW_NOTIFICATION_RETENTION_HOURS)).to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO raw_notifications (id, store, payload, received_at) VALUES ($1, $2, $3, $4)")
+        .bind(&id)
+        .bind(store)
+        .bind(payload.to_vec())
+        .bind(now.to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(id)
+}
+
+/// Hand a verified notification to its app, which can then replay it.
+async fn attach_raw_notification(state: &AppState, raw_id: &str, app: &App) -> Result<(), (StatusCode, String)> {
+    sqlx::query("UPDATE raw_notifications SET app_id = $1 WHERE id = $2")
+        .bind(&app.id)
+        .bind(raw_id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(())
+}
+
+/// Record how processing went, keeping the error for failures.
+async fn finish_raw_notification(
+    state: &AppState,
+    raw_id: &str,
+    result: &Result<StatusCode, (StatusCode, String)>,
+) -> Result<(), (StatusCode, String)> {
+    let (status, error) = match result {
+        Ok(_) => ("processed", None),
+        Err((_, message)) => ("failed", Some(message.as_str())),
+    };
+    sqlx::query("UPDATE raw_notifications SET status = $1, error = $2, processed_at = $3 WHERE id = $4")
+        .bind(status)
+        .bind(error)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(raw_id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(())
+}
+
+/// Metrics result for a notification the store adapter couldn't process.
+fn notification_failure(error: &StoreError) -> &'static str {
+    if matches!(error, StoreError::Invalid(_)) { "invalid" } else { "failed" }
+}
+
+async fn find_app(state: &AppState, app_id: &str) -> Result<App, (StatusCode, String)> {
+    sqlx::query_as::<_, App>("SELECT * FROM apps WHERE id = $1")
+        .bind(app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "App not found".to_string()))
+}
+
+/// The app with `bundle_id` on one of the store's `platforms`; an app's iOS
+/// and Android builds often share a bundle ID.
+async fn find_app_by_bundle_id(state: &AppState, bundle_id: &str, platforms: &[Platform]) -> Result<App, (StatusCode, String)> {
+    let placeholders: Vec<String> = (2..platforms.len() + 2).map(|n| format!("${n}")).collect();
+    let sql = format!(
+        "SELECT * FROM apps WHERE bundle_id = $1 AND platform IN ({}) ORDER BY created_at LIMIT 1",
+        placeholders.join(", "),
+    );
+    let mut query = sqlx::query_as::<_, App>(&sql).bind(bundle_id);
+    for platform in platforms {
+        query = query.bind(platform.as_str());
+    }
+    query
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, format!("No app registered for {bundle_id}")))
+}
+
+/// Apply each decoded event to its transaction and record it against the
+/// owning subscriber, or against no subscriber when the transaction isn't known to us.
+///
+/// Stores redeliver notifications they didn't see acknowledged, so a
+/// `notification_id` already recorded for `store` is skipped. Claiming the ID
+/// shares a database transaction with the writes, so a failed attempt can be retried.
+async fn store_transaction_events(
+    state: &AppState,
+    app: &App,
+    store: &str,
+    notification_id: Option<&str>,
+    events: Vec<TransactionEvent>,
+) -> Result<(), (StatusCode, String)> {
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(notification_id) = notification_id {
+        let claimed = sqlx::query(
+            "INSERT INTO processed_notifications (store, notification_id, processed_at) VALUES ($1, $2, $3) \
+             ON CONFLICT DO NOTHING"
+        )
+        .bind(store)
+        .bind(notification_id)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .rows_affected();
+
+        if claimed == 0 {
+            tracing::info!("Skipping already processed {} notification {}", store, notification_id);
+            crate::metrics::record_notification(store, "duplicate");
+            return Ok(());
+        }
+    }
+
+    let mut event_ids = Vec::with_capacity(events.len());
+    for mut event in events {
+        if resumes_pause(&mut tx, &app.id, &event).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        {
+            event.event_type = "RESUME".to_string();
+        }
+
+        let span = tracing::info_span!(
+            "transaction_event",
+            event_type = %event.event_type,
+            store_transaction_id = %event.transaction.store_transaction_id,
+        );
+        let subscriber_id = apply_transaction_event(&mut tx, &app.id, &event)
+            .instrument(span.clone())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        if subscriber_id.is_none() {
+            span.in_scope(|| tracing::warn!(
+                "No transaction {} for {} notification on app {}",
+                event.transaction.store_transaction_id, store, app.id
+            ));
+        }
+
+        let payload = event_payload(&event)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let event_id = record_event(&mut tx, &app.id, subscriber_id.as_deref(), &event.event_type, &payload)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        event_ids.push(event_id);
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    crate::metrics::record_notification(store, "processed");
+
+    for event_id in event_ids {
+        state.events.publish_stored(&state.pool, &event_id).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::store::google_push::GooglePushVerifier;
+    use crate::store::apple_jws::tests::{sign_test_jws, test_verifier};
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /// Shared test state, trusting the test certificates App Store payloads are signed with.
+    async fn trusting_test_certs() -> AppState {
+        AppState { apple_verifier: test_verifier(), ..test_state(test_pool().await) }
+    }
+
+    /// An app with Apple credentials and one subscriber owning transaction `1000`.
+    /// Returns the app's API key.
+    async fn setup(state: &AppState) -> String {
+        let app = crate::api::router(state.clone());
+        let resp = app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/apps")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"name":"Test","platform":"ios","bundle_id":"com.test"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        let app_id = v["id"].as_str().unwrap().to_string();
+        let api_key = v["api_key"].as_str().unwrap().to_string();
+
+        app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/v1/apps/{app_id}/credentials"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"apple":{"issuer_id":"issuer","key_id":"KEY","private_key":"unused"}}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ('prod', $1, 'com.test.pro', 'subscription')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status)
+             VALUES ('tx', 'sub', 'prod', 'apple', '1000', '2026-01-01T00:00:00Z', 'active')"
+        )
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+        api_key
+    }
+
+    async fn send_apple_notification(state: &AppState, transaction_id: &str) -> StatusCode {
+        send_apple_notification_with_id(state, transaction_id, &uuid::Uuid::new_v4().to_string()).await
+    }
+
+    async fn send_apple_notification_with_id(state: &AppState, transaction_id: &str, notification_uuid: &str) -> StatusCode {
+        send_apple_transaction(state, serde_json::json!({
+            "transactionId": transaction_id,
+            "productId": "com.test.pro",
+            "expiresDate": 4_102_444_800_000_i64,
+            "environment": "Production",
+        }), notification_uuid).await
+    }
+
+    async fn send_apple_transaction(state: &AppState, transaction: Value, notification_uuid: &str) -> StatusCode {
+        let signed_tx = sign_test_jws(&transaction);
+        let signed_payload = sign_test_jws(&serde_json::json!({
+            "notificationType": "DID_RENEW",
+            "notificationUUID": notification_uuid,
+            "data": { "bundleId": "com.test", "signedTransactionInfo": signed_tx },
+        }));
+
+        crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/notifications/apple")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::json!({ "signedPayload": signed_payload }).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_notification_is_attached_to_owning_subscriber() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+
+        assert_eq!(send_apple_notification(&state, "1000").await, StatusCode::OK);
+
+        let (subscriber_id, event_type): (Option<String>, String) =
+            sqlx::query_as("SELECT subscriber_id, event_type FROM events")
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+        assert_eq!(subscriber_id.as_deref(), Some("sub"));
+        assert_eq!(event_type, "RENEWAL");
+
+        let (status, expiration): (String, Option<String>) =
+            sqlx::query_as("SELECT status, expiration_date FROM transactions WHERE id = 'tx'")
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "active");
+        assert!(expiration.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_notification_has_no_subscriber() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+
+        assert_eq!(send_apple_notification(&state, "9999").await, StatusCode::OK);
+
+        let subscriber_id: Option<String> = sqlx::query_scalar("SELECT subscriber_id FROM events")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(subscriber_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_notification_is_stored_once() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+
+        let notification_uuid = "8b4d7e2a-1c3f-4e5d-9a6b-0c1d2e3f4a5b";
+        assert_eq!(send_apple_notification_with_id(&state, "1000", notification_uuid).await, StatusCode::OK);
+        assert_eq!(send_apple_notification_with_id(&state, "1000", notification_uuid).await, StatusCode::OK);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_google_push_with_bogus_token_is_rejected() {
+        let mut state = trusting_test_certs().await;
+        state.google_verifier = GooglePushVerifier::new(Some("https://opencat.example/v1/notifications/google".to_string()), None);
+
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/notifications/google")
+                    .header("authorization", "Bearer not-a-real-token")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"message":{"data":"e30=","messageId":"1"}}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_transaction_is_recorded_as_sandbox() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+
+        let status = send_apple_transaction(&state, serde_json::json!({
+            "transactionId": "1000",
+            "productId": "com.test.pro",
+            "expiresDate": 4_102_444_800_000_i64,
+            "environment": "Sandbox",
+        }), "2f1e0d9c-8b7a-4f6e-9d5c-4b3a2f1e0d9c").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let environment: String = sqlx::query_scalar("SELECT environment FROM transactions WHERE id = 'tx'")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(environment, "sandbox");
+
+        let payload: String = sqlx::query_scalar("SELECT payload FROM events")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["environment"], "sandbox");
+    }
+
+    #[tokio::test]
+    async fn test_stored_notification_is_replayed() {
+        let state = trusting_test_certs().await;
+        let api_key = setup(&state).await;
+
+        let notification_uuid = "5c6d7e8f-9a0b-4c1d-8e2f-3a4b5c6d7e8f";
+        assert_eq!(send_apple_notification_with_id(&state, "1000", notification_uuid).await, StatusCode::OK);
+
+        let (id, status): (String, String) = sqlx::query_as("SELECT id, status FROM raw_notifications")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "processed");
+
+        // Replays need an API key
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/notifications/{id}/replay"))
+                    .header("authorization", "Bearer ocat_wrong")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Replaying re-emits the event despite the notification having been processed
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/notifications/{id}/replay"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let replayed: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(replayed["status"], "processed");
+        assert_eq!(replayed["store"], "apple");
+
+        let event_types: Vec<String> = sqlx::query_scalar("SELECT event_type FROM events")
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(event_types, vec!["RENEWAL", "RENEWAL"]);
+    }
+
+    #[tokio::test]
+    async fn test_voided_purchase_notification_refunds_the_transaction() {
+        let state = trusting_test_certs().await;
+        let api_key = setup(&state).await;
+        let app_id: String = sqlx::query_scalar("SELECT id FROM apps").fetch_one(&state.pool).await.unwrap();
+        // Google notifications only go to Android apps
+        sqlx::query("UPDATE apps SET platform = 'android' WHERE id = $1")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let key = serde_json::json!({
+            "client_email": "test@example.iam.gserviceaccount.com",
+            "private_key": crate::store::google::tests::TEST_RSA_KEY,
+            "token_uri": "http://127.0.0.1:1/token",
+        });
+        let credentials = serde_json::json!({ "google": { "service_account_key": key.to_string() } });
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/v1/apps/{app_id}/credentials"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(credentials.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status)
+             VALUES ('gtx', 'sub', 'prod', 'google', 'voided-token', '2026-01-01T00:00:00Z', 'active')"
+        )
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+        use base64::Engine;
+        let data = base64::engine::general_purpose::STANDARD.encode(serde_json::json!({
+            "version": "1.0",
+            "packageName": "com.test",
+            "eventTimeMillis": "1767225600000",
+            "voidedPurchaseNotification": { "purchaseToken": "voided-token", "orderId": "GPA.1", "productType": 2, "refundType": 1 },
+        }).to_string());
+
+        // Pushes need a signed Google token, so the notification goes in as a stored one to replay
+        let body = serde_json::json!({ "message": { "data": data, "messageId": "m1" } }).to_string();
+        sqlx::query("INSERT INTO raw_notifications (id, store, app_id, payload, received_at) VALUES ('raw', 'google', $1, $2, $3)")
+            .bind(&app_id)
+            .bind(body.into_bytes())
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/notifications/raw/replay")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let status: String = sqlx::query_scalar("SELECT status FROM transactions WHERE id = 'gtx'")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "refunded");
+        let event_types: Vec<String> = sqlx::query_scalar("SELECT event_type FROM events")
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(event_types, vec!["REFUND"]);
+    }
+
+    #[tokio::test]
+    async fn test_renewals_are_linked_by_original_transaction_id() {
+        let state = trusting_test_certs().await;
+        let api_key = setup(&state).await;
+        let app_id: String = sqlx::query_scalar("SELECT id FROM apps").fetch_one(&state.pool).await.unwrap();
+
+        // Apple gives each renewal a new transaction id, tied to the original purchase
+        for (transaction_id, purchase_date) in [("1001", 1_769_904_000_000_i64), ("1002", 1_772_323_200_000)] {
+            let status = send_apple_transaction(&state, serde_json::json!({
+                "transactionId": transaction_id,
+                "originalTransactionId": "1000",
+                "productId": "com.test.pro",
+                "purchaseDate": purchase_date,
+                "expiresDate": 4_102_444_800_000_i64,
+                "environment": "Production",
+            }), &uuid::Uuid::new_v4().to_string()).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let subscribers: Vec<Option<String>> = sqlx::query_scalar("SELECT subscriber_id FROM events ORDER BY created_at")
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(subscribers, vec![Some("sub".to_string()), Some("sub".to_string())]);
+
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/apps/{app_id}/subscriptions/1000"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let history: Value = serde_json::from_slice(&body).unwrap();
+        let chain: Vec<(&str, &str)> = history["transactions"].as_array().unwrap().iter()
+            .map(|t| (t["store_transaction_id"].as_str().unwrap(), t["status"].as_str().unwrap()))
+            .collect();
+        // Only the newest period is still active
+        assert_eq!(chain, vec![("1000", "expired"), ("1001", "expired"), ("1002", "active")]);
+        assert!(history["transactions"].as_array().unwrap().iter().all(|t| t["subscriber_id"] == "sub"));
+
+        // Redelivering a renewal doesn't add it again
+        assert_eq!(send_apple_transaction(&state, serde_json::json!({
+            "transactionId": "1002",
+            "originalTransactionId": "1000",
+            "productId": "com.test.pro",
+            "purchaseDate": 1_772_323_200_000_i64,
+            "expiresDate": 4_102_444_800_000_i64,
+            "environment": "Production",
+        }), &uuid::Uuid::new_v4().to_string()).await, StatusCode::OK);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_notification_is_not_stored() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/notifications/apple")
+                    .header("content-type", "application/json")
+                    .body(Body::from(vec![b'x'; super::MAX_RAW_NOTIFICATION_BYTES + 1]))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM raw_notifications").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    #[tokio::test]
+    async fn test_old_raw_notifications_are_pruned() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+        let app_id: String = sqlx::query_scalar("SELECT id FROM apps").fetch_one(&state.pool).await.unwrap();
+        let days_ago = |days: i64| (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+        for (id, app_id, received_at) in [
+            ("stale", Some(app_id.as_str()), days_ago(31)),
+            ("recent", Some(app_id.as_str()), days_ago(2)),
+            ("unverified", None, days_ago(2)),
+        ] {
+            sqlx::query("INSERT INTO raw_notifications (id, store, app_id, payload, received_at) VALUES ($1, 'apple', $2, $3, $4)")
+                .bind(id)
+                .bind(app_id)
+                .bind(b"{}".to_vec())
+                .bind(received_at)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(send_apple_notification(&state, "1000").await, StatusCode::OK);
+
+        let kept: Vec<String> = sqlx::query_scalar("SELECT id FROM raw_notifications WHERE id IN ('stale', 'recent', 'unverified')")
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(kept, vec!["recent"]);
+    }
+
+    #[tokio::test]
+    async fn test_notification_signed_for_another_bundle_is_rejected() {
+        let state = trusting_test_certs().await;
+        setup(&state).await;
+        let app_id: String = sqlx::query_scalar("SELECT id FROM apps").fetch_one(&state.pool).await.unwrap();
+
+        let send = |notification_bundle: &str, transaction_bundle: &str| {
+            let signed_tx = sign_test_jws(&serde_json::json!({
+                "transactionId": "1000",
+                "productId": "com.test.pro",
+                "bundleId": transaction_bundle,
+            }));
+            let signed_payload = sign_test_jws(&serde_json::json!({
+                "notificationType": "DID_RENEW",
+                "notificationUUID": uuid::Uuid::new_v4().to_string(),
+                "data": { "bundleId": notification_bundle, "signedTransactionInfo": signed_tx },
+            }));
+            let request = Request::builder()
+                .method("POST")
+                .uri(format!("/v1/notifications/apple/{app_id}"))
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "signedPayload": signed_payload }).to_string()))
+                .unwrap();
+            let app = crate::api::router(state.clone());
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(send("com.other", "com.other").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send("com.test", "com.other").await, StatusCode::UNAUTHORIZED);
+        let events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(events, 0);
+        // Kept for inspection, but the app can't replay what it never verified
+        let attached: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM raw_notifications WHERE app_id IS NOT NULL")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(attached, 0);
+
+        assert_eq!(send("com.test", "com.test").await, StatusCode::OK);
+        let events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(events, 1);
+    }
+
+    #[tokio::test]
+    async fn test_notification_goes_to_the_app_on_the_stores_platform() {
+        let state = trusting_test_certs().await;
+        // An older Android build sharing the bundle ID
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id, created_at, updated_at) VALUES ('android', 'Test', 'android', 'com.test', '2000-01-01T00:00:00Z', '2000-01-01T00:00:00Z')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        setup(&state).await;
+
+        assert_eq!(send_apple_notification(&state, "1000").await, StatusCode::OK);
+
+        let (app_id, subscriber_id): (String, Option<String>) = sqlx::query_as("SELECT app_id, subscriber_id FROM events")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_ne!(app_id, "android");
+        assert_eq!(subscriber_id.as_deref(), Some("sub"));
+    }
 }
@@ -1,8 +1,24 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json};
-use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+use crate::currency::{format_price, FxRates};
+use crate::db::DbPool;
+use crate::models::offering::{
+    CreateExperiment, CreateOffering, CreateOfferingOverride, CurrentOffering, Experiment, Offering, OfferingOverride,
+    Package,
+};
+use crate::models::product::Product;
 
-#[derive(Debug, Serialize)]
+const PACKAGE_TYPES: &[&str] = &[
+    "weekly", "monthly", "two_month", "three_month", "six_month", "annual", "lifetime", "custom",
+];
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
 pub struct OfferingProduct {
     pub store_product_id: String,
     pub product_type: String,
@@ -10,53 +26,1275 @@ pub struct OfferingProduct {
     pub description: Option<String>,
     pub price_micros: i64,
     pub currency: String,
+    /// The price as shown to English-speaking users, e.g. `$9.99`.
+    pub price_formatted: String,
     pub subscription_period: Option<String>,
     pub trial_period: Option<String>,
+    pub intro_price_micros: Option<i64>,
+    pub intro_currency: Option<String>,
+    pub offer_mode: Option<String>,
     pub entitlements: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, utoipa::ToSchema)]
 pub struct OfferingsResponse {
     pub offerings: Vec<OfferingProduct>,
 }
 
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct PackageResponse {
+    pub identifier: String,
+    pub package_type: String,
+    pub product: OfferingProduct,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct OfferingResponse {
+    pub identifier: String,
+    pub description: Option<String>,
+    pub packages: Vec<PackageResponse>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct CurrentOfferingsResponse {
+    pub current_offering_id: Option<String>,
+    pub offerings: Vec<OfferingResponse>,
+    /// Set when `current_offering_id` comes from an experiment, so the client
+    /// can report the exposure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<ExperimentAssignment>,
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct ExperimentAssignment {
+    pub name: String,
+    pub variant: String,
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OfferingsQuery {
+    /// Return every product as a flat list, as before offerings existed.
+    #[serde(default)]
+    pub flat: bool,
+    /// App Store territory code (e.g. `GBR`) to price products for, when
+    /// synced prices exist, and to pick a country's override offering.
+    pub country: Option<String>,
+    /// The client's app version, to pick an override offering by.
+    pub app_version: Option<String>,
+    /// The user asking, to assign an experiment variant to.
+    pub app_user_id: Option<String>,
+    /// ISO currency to convert prices to with `offerings.fx_rates`. Prices
+    /// without a rate stay in their own currency.
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct OfferingOverrideResponse {
+    pub id: String,
+    pub offering: String,
+    pub country: Option<String>,
+    pub min_app_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct ExperimentResponse {
+    pub id: String,
+    pub name: String,
+    pub variants: Vec<ExperimentVariantResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct ExperimentVariantResponse {
+    pub name: String,
+    pub offering: String,
+    pub weight: u32,
+}
+
+/// The experiment a user may be assigned a variant of, with each variant's
+/// offering identifier.
+#[derive(Debug, Clone)]
+struct RunningExperiment {
+    id: String,
+    name: String,
+    variants: Vec<ExperimentVariantResponse>,
+}
+
+impl RunningExperiment {
+    /// The variant `app_user_id` always gets: its hash with the experiment's
+    /// id, spread over the variants' weights.
+    fn assign(&self, app_user_id: &str) -> Option<&ExperimentVariantResponse> {
+        let total: u64 = self.variants.iter().map(|v| u64::from(v.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let digest = Sha256::digest(format!("{}:{app_user_id}", self.id).as_bytes());
+        let mut bucket = u64::from_be_bytes(digest[..8].try_into().ok()?) % total;
+        self.variants.iter().find(|v| {
+            let weight = u64::from(v.weight);
+            if bucket < weight {
+                return true;
+            }
+            bucket -= weight;
+            false
+        })
+    }
+}
+
+/// A built offerings response, before any experiment assignment, which
+/// depends on the user.
+#[derive(Debug, Clone)]
+struct OfferingsSnapshot {
+    body: serde_json::Value,
+    /// Experiment to assign users of this response to, unless an override
+    /// already picked their offering.
+    experiment: Option<RunningExperiment>,
+}
+
+/// Most responses cached per app. Queries include the client's country, app
+/// version and currency, so an app's entries are capped rather than left to
+/// grow with every new combination.
+const MAX_CACHED_QUERIES_PER_APP: usize = 256;
+
+/// Responses by query, with when they were built.
+type CachedOfferings = HashMap<String, (Instant, OfferingsSnapshot)>;
+
+/// Offerings responses per app and query, so the read-heavy offerings
+/// endpoint doesn't rebuild them on every request. Handlers that change an
+/// app's catalog call [`OfferingsCache::invalidate`]; changes made elsewhere,
+/// like the CLI, show up once the entry is older than the TTL.
+#[derive(Debug, Clone)]
+pub struct OfferingsCache {
+    ttl: Duration,
+    apps: Arc<Mutex<HashMap<String, CachedOfferings>>>,
+}
+
+impl OfferingsCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, apps: Arc::default() }
+    }
+
+    fn get(&self, app_id: &str, key: &str) -> Option<OfferingsSnapshot> {
+        let apps = self.apps.lock().unwrap_or_else(|e| e.into_inner());
+        let (cached_at, snapshot) = apps.get(app_id)?.get(key)?;
+        (cached_at.elapsed() < self.ttl).then(|| snapshot.clone())
+    }
+
+    /// Cache `snapshot`, first dropping the app's expired entries and, when
+    /// it's still at [`MAX_CACHED_QUERIES_PER_APP`], its oldest one.
+    fn insert(&self, app_id: &str, key: String, snapshot: OfferingsSnapshot) {
+        let mut apps = self.apps.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = apps.entry(app_id.to_string()).or_default();
+        cached.retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+        if cached.len() >= MAX_CACHED_QUERIES_PER_APP && !cached.contains_key(&key) {
+            let oldest = cached.iter().min_by_key(|(_, (cached_at, _))| *cached_at).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                cached.remove(&oldest);
+            }
+        }
+        cached.insert(key, (Instant::now(), snapshot));
+    }
+
+    /// Forget everything cached for `app_id`.
+    pub fn invalidate(&self, app_id: &str) {
+        let mut apps = self.apps.lock().unwrap_or_else(|e| e.into_inner());
+        apps.remove(app_id);
+    }
+}
+
+impl Default for OfferingsCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/offerings",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = CreateOffering,
+    responses(
+        (status = 201, body = OfferingResponse),
+        (status = 400, description = "Invalid package or unknown product"),
+    ),
+)]
+pub async fn create_offering(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<CreateOffering>,
+) -> Result<(StatusCode, Json<OfferingResponse>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    for package in &input.packages {
+        if !PACKAGE_TYPES.contains(&package.package_type.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown package type: {}", package.package_type)));
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if input.is_current {
+        sqlx::query("UPDATE offerings SET is_current = 0 WHERE app_id = $1")
+            .bind(&app_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    sqlx::query("INSERT INTO offerings (id, app_id, identifier, description, is_current, created_at) VALUES ($1, $2, $3, $4, $5, $6)")
+        .bind(&id)
+        .bind(&app_id)
+        .bind(&input.identifier)
+        .bind(&input.description)
+        .bind(input.is_current as i32)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for (position, package) in input.packages.iter().enumerate() {
+        let owned: Option<String> = sqlx::query_scalar("SELECT id FROM products WHERE id = $1 AND app_id = $2")
+            .bind(&package.product_id)
+            .bind(&app_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if owned.is_none() {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown product: {}", package.product_id)));
+        }
+
+        sqlx::query("INSERT INTO packages (id, offering_id, identifier, package_type, product_id, position) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(uuid::Uuid::new_v4().to_string())
+            .bind(&id)
+            .bind(&package.identifier)
+            .bind(&package.package_type)
+            .bind(&package.product_id)
+            .bind(position as i32)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let offering = sqlx::query_as::<_, Offering>("SELECT * FROM offerings WHERE id = $1")
+        .bind(&id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    let catalog = Catalog::load(&state.pool, &app_id, None).await?;
+    Ok((StatusCode::CREATED, Json(offering_response(&state.pool, &catalog, offering).await?)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/apps/{app_id}/current-offering",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = CurrentOffering,
+    responses(
+        (status = 200, body = CurrentOffering),
+        (status = 400, description = "Unknown offering"),
+        (status = 422, description = "Missing `current_offering_id` or unknown field"),
+    ),
+)]
+pub async fn set_current_offering(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<CurrentOffering>,
+) -> Result<Json<CurrentOffering>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("UPDATE offerings SET is_current = 0 WHERE app_id = $1")
+        .bind(&app_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(identifier) = &input.current_offering_id {
+        let updated = sqlx::query("UPDATE offerings SET is_current = 1 WHERE app_id = $1 AND identifier = $2")
+            .bind(&app_id)
+            .bind(identifier)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .rows_affected();
+        if updated == 0 {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown offering: {identifier}")));
+        }
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok(Json(input))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/offerings",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        OfferingsQuery,
+    ),
+    responses(
+        (status = 200, description = "The app's offerings, or with `flat=true` an `OfferingsResponse`", body = CurrentOfferingsResponse),
+    ),
+)]
 pub async fn get_offerings(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Path(app_id): Path<String>,
-) -> Result<Json<OfferingsResponse>, (StatusCode, String)> {
-    let products = sqlx::query_as::<_, crate::models::product::Product>(
-        "SELECT * FROM products WHERE app_id = ? ORDER BY created_at"
+    Query(query): Query<OfferingsQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let cache_key = format!(
+        "{}|{}|{}|{}",
+        query.flat,
+        query.country.as_deref().unwrap_or_default().to_uppercase(),
+        query.app_version.as_deref().unwrap_or_default(),
+        query.currency.as_deref().unwrap_or_default().to_uppercase(),
+    );
+    let snapshot = match state.offerings_cache.get(&app_id, &cache_key) {
+        Some(snapshot) => snapshot,
+        None => {
+            let snapshot = if query.flat {
+                let body = serde_json::to_value(flat_offerings(&state, &app_id, &query).await?);
+                OfferingsSnapshot {
+                    body: body.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+                    experiment: None,
+                }
+            } else {
+                let (response, experiment) = current_offerings(&state, &app_id, &query).await?;
+                let body = serde_json::to_value(response);
+                OfferingsSnapshot {
+                    body: body.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+                    experiment,
+                }
+            };
+            state.offerings_cache.insert(&app_id, cache_key, snapshot.clone());
+            snapshot
+        }
+    };
+
+    let mut body = snapshot.body;
+    if let (Some(experiment), Some(app_user_id)) = (&snapshot.experiment, &query.app_user_id) {
+        if let Some(variant) = experiment.assign(app_user_id) {
+            body["current_offering_id"] = serde_json::json!(variant.offering);
+            body["experiment"] = serde_json::json!(ExperimentAssignment {
+                name: experiment.name.clone(),
+                variant: variant.name.clone(),
+            });
+        }
+    }
+    Ok(Json(body))
+}
+
+/// The app's offerings, and the experiment to assign users to if no
+/// override targets them.
+async fn current_offerings(
+    state: &AppState,
+    app_id: &str,
+    query: &OfferingsQuery,
+) -> Result<(CurrentOfferingsResponse, Option<RunningExperiment>), (StatusCode, String)> {
+    let offerings = sqlx::query_as::<_, Offering>(
+        "SELECT * FROM offerings WHERE app_id = $1 ORDER BY created_at"
     )
-    .bind(&app_id)
+    .bind(app_id)
     .fetch_all(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let mut offerings = Vec::new();
+    let overrides = sqlx::query_as::<_, OfferingOverride>("SELECT * FROM offering_overrides WHERE app_id = $1")
+        .bind(app_id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let targeted = targeted_offering(&overrides, query.country.as_deref(), query.app_version.as_deref());
+    let experiment = match targeted {
+        Some(_) => None,
+        None => running_experiment(&state.pool, app_id).await?,
+    };
+    let current_offering_id = match targeted {
+        Some(offering_id) => offerings.iter().find(|o| o.id == offering_id),
+        None => offerings.iter().find(|o| o.is_current != 0),
+    }
+    .map(|o| o.identifier.clone());
+
+    let catalog = Catalog::load(&state.pool, app_id, query.country.as_deref())
+        .await?
+        .priced_in(&state.fx_rates, query.currency.as_deref());
+    let mut responses = Vec::with_capacity(offerings.len());
+    for offering in offerings {
+        responses.push(offering_response(&state.pool, &catalog, offering).await?);
+    }
+
+    Ok((CurrentOfferingsResponse { current_offering_id, offerings: responses, experiment: None }, experiment))
+}
+
+/// The app's newest experiment, if it has any.
+async fn running_experiment(pool: &DbPool, app_id: &str) -> Result<Option<RunningExperiment>, (StatusCode, String)> {
+    let experiment = sqlx::query_as::<_, Experiment>(
+        "SELECT * FROM experiments WHERE app_id = $1 ORDER BY created_at DESC, id DESC LIMIT 1"
+    )
+    .bind(app_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let Some(experiment) = experiment else {
+        return Ok(None);
+    };
+
+    let variants = experiment_variants(pool, &experiment.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Some(RunningExperiment { id: experiment.id, name: experiment.name, variants }))
+}
+
+async fn experiment_variants(pool: &DbPool, experiment_id: &str) -> Result<Vec<ExperimentVariantResponse>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, String, i32)>(
+        "SELECT v.name, o.identifier, v.weight FROM experiment_variants v \
+         JOIN offerings o ON o.id = v.offering_id \
+         WHERE v.experiment_id = $1 \
+         ORDER BY v.position"
+    )
+    .bind(experiment_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(name, offering, weight)| ExperimentVariantResponse {
+            name,
+            offering,
+            weight: u32::try_from(weight).unwrap_or(0),
+        })
+        .collect())
+}
+
+/// Show users matching `input` another offering than the current one.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/offerings/overrides",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = CreateOfferingOverride,
+    responses(
+        (status = 201, body = OfferingOverrideResponse),
+        (status = 400, description = "Unknown offering or invalid rule"),
+        (status = 409, description = "An override with the same rule exists"),
+    ),
+)]
+pub async fn create_offering_override(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<CreateOfferingOverride>,
+) -> Result<(StatusCode, Json<OfferingOverrideResponse>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    if input.country.is_none() && input.min_app_version.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "An override needs a country or min_app_version".to_string()));
+    }
+    if let Some(version) = &input.min_app_version {
+        if parse_app_version(version).is_none() {
+            return Err((StatusCode::BAD_REQUEST, format!("Invalid app version: {version}")));
+        }
+    }
+
+    let offering_id: String = sqlx::query_scalar("SELECT id FROM offerings WHERE app_id = $1 AND identifier = $2")
+        .bind(&app_id)
+        .bind(&input.offering)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::BAD_REQUEST, format!("Unknown offering: {}", input.offering)))?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let country = input.country.map(|c| c.to_uppercase());
+
+    sqlx::query(
+        "INSERT INTO offering_overrides (id, app_id, offering_id, country, min_app_version, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(&id)
+    .bind(&app_id)
+    .bind(&offering_id)
+    .bind(&country)
+    .bind(&input.min_app_version)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(&state.pool)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => {
+            (StatusCode::CONFLICT, "An override for that country and version already exists".to_string())
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok((StatusCode::CREATED, Json(OfferingOverrideResponse {
+        id,
+        offering: input.offering,
+        country,
+        min_app_version: input.min_app_version,
+    })))
+}
+
+/// Start an A/B test between offerings. It replaces any earlier experiment
+/// for users who pass `app_user_id` to the offerings endpoint.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/experiments",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = CreateExperiment,
+    responses(
+        (status = 201, body = ExperimentResponse),
+        (status = 400, description = "Invalid variants"),
+        (status = 409, description = "An experiment with the same name exists"),
+    ),
+)]
+pub async fn create_experiment(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<CreateExperiment>,
+) -> Result<(StatusCode, Json<ExperimentResponse>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    if input.variants.len() < 2 {
+        return Err((StatusCode::BAD_REQUEST, "An experiment needs at least two variants".to_string()));
+    }
+    if input.variants.iter().any(|v| v.weight == 0 || i32::try_from(v.weight).is_err()) {
+        return Err((StatusCode::BAD_REQUEST, "Variant weights must be positive".to_string()));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("INSERT INTO experiments (id, app_id, name, created_at) VALUES ($1, $2, $3, $4)")
+        .bind(&id)
+        .bind(&app_id)
+        .bind(&input.name)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db) if db.is_unique_violation() => (StatusCode::CONFLICT, "Experiment name already exists".to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+
+    for (position, variant) in input.variants.iter().enumerate() {
+        let offering_id: String = sqlx::query_scalar("SELECT id FROM offerings WHERE app_id = $1 AND identifier = $2")
+            .bind(&app_id)
+            .bind(&variant.offering)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::BAD_REQUEST, format!("Unknown offering: {}", variant.offering)))?;
 
-    for product in products {
-        let entitlements: Vec<String> = sqlx::query_scalar(
-            "SELECT e.name FROM entitlements e \
-             JOIN product_entitlements pe ON pe.entitlement_id = e.id \
-             WHERE pe.product_id = ?"
+        sqlx::query(
+            "INSERT INTO experiment_variants (id, experiment_id, name, offering_id, weight, position) \
+             VALUES ($1, $2, $3, $4, $5, $6)"
         )
-        .bind(&product.id)
-        .fetch_all(&state.pool)
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&id)
+        .bind(&variant.name)
+        .bind(&offering_id)
+        .bind(variant.weight as i32)
+        .bind(position as i32)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db) if db.is_unique_violation() => {
+                (StatusCode::BAD_REQUEST, format!("Duplicate variant name: {}", variant.name))
+            }
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let variants = experiment_variants(&state.pool, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok((StatusCode::CREATED, Json(ExperimentResponse { id, name: input.name, variants })))
+}
+
+/// End an experiment; its users go back to the current offering.
+#[utoipa::path(
+    delete,
+    path = "/v1/apps/{app_id}/experiments/{experiment_id}",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("experiment_id" = String, Path, description = "Experiment ID"),
+    ),
+    responses(
+        (status = 204, description = "Experiment deleted"),
+        (status = 404, description = "Experiment not found"),
+    ),
+)]
+pub async fn delete_experiment(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, experiment_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("DELETE FROM experiment_variants WHERE experiment_id IN (SELECT id FROM experiments WHERE id = $1 AND app_id = $2)")
+        .bind(&experiment_id)
+        .bind(&app_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let deleted = sqlx::query("DELETE FROM experiments WHERE id = $1 AND app_id = $2")
+        .bind(&experiment_id)
+        .bind(&app_id)
+        .execute(&mut *tx)
         .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .rows_affected();
+    if deleted == 0 {
+        return Err((StatusCode::NOT_FOUND, "Experiment not found".to_string()));
+    }
+
+    tx.commit().await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-        offerings.push(OfferingProduct {
-            store_product_id: product.store_product_id,
-            product_type: product.product_type,
-            display_name: product.display_name.unwrap_or_default(),
-            description: product.description,
-            price_micros: product.price_micros.unwrap_or(0),
-            currency: product.currency.unwrap_or_else(|| "USD".to_string()),
-            subscription_period: product.subscription_period,
-            trial_period: product.trial_period,
-            entitlements,
+    state.offerings_cache.invalidate(&app_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The offering of the most specific override matching the client: one for
+/// its country beats one for any country, then the highest `min_app_version`
+/// wins. Overrides naming a country or version only match clients that sent one.
+fn targeted_offering(overrides: &[OfferingOverride], country: Option<&str>, app_version: Option<&str>) -> Option<String> {
+    let country = country.map(str::to_uppercase);
+    let app_version = app_version.and_then(parse_app_version);
+
+    overrides
+        .iter()
+        .filter_map(|o| {
+            if o.country.is_some() && o.country != country {
+                return None;
+            }
+            let min_version = match &o.min_app_version {
+                Some(min) => {
+                    let min = parse_app_version(min)?;
+                    if app_version.as_ref()? < &min {
+                        return None;
+                    }
+                    Some(min)
+                }
+                None => None,
+            };
+            Some(((o.country.is_some(), min_version), &o.offering_id))
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, offering_id)| offering_id.clone())
+}
+
+/// `2.10.1` as `[2, 10, 1]`, without trailing zeros so `2.0` equals `2`.
+fn parse_app_version(version: &str) -> Option<Vec<u64>> {
+    let mut parts = version
+        .trim()
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+/// An app's products with their entitlement names and, when a country was
+/// asked for, synced prices there. Loaded in three queries however many
+/// products the app has.
+struct Catalog {
+    products: Vec<Product>,
+    entitlements: HashMap<String, Vec<String>>,
+    local_prices: HashMap<String, (i64, String)>,
+    /// Currency to show prices in, where `fx_rates` allow it.
+    display_currency: Option<String>,
+    fx_rates: FxRates,
+}
+
+impl Catalog {
+    async fn load(pool: &DbPool, app_id: &str, country: Option<&str>) -> Result<Self, (StatusCode, String)> {
+        let products = sqlx::query_as::<_, Product>(
+            "SELECT * FROM products WHERE app_id = $1 ORDER BY created_at"
+        )
+        .bind(app_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        let rows = sqlx::query_as::<_, (String, String)>(
+            "SELECT pe.product_id, e.name FROM product_entitlements pe \
+             JOIN entitlements e ON e.id = pe.entitlement_id \
+             JOIN products p ON p.id = pe.product_id \
+             WHERE p.app_id = $1 \
+             ORDER BY e.name"
+        )
+        .bind(app_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let mut entitlements: HashMap<String, Vec<String>> = HashMap::new();
+        for (product_id, name) in rows {
+            entitlements.entry(product_id).or_default().push(name);
+        }
+
+        let local_prices = match country {
+            Some(country) => sqlx::query_as::<_, (String, i64, String)>(
+                "SELECT pp.product_id, pp.price_micros, pp.currency FROM product_prices pp \
+                 JOIN products p ON p.id = pp.product_id \
+                 WHERE p.app_id = $1 AND pp.territory = $2"
+            )
+            .bind(app_id)
+            .bind(country.to_uppercase())
+            .fetch_all(pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .into_iter()
+            .map(|(product_id, price_micros, currency)| (product_id, (price_micros, currency)))
+            .collect(),
+            None => HashMap::new(),
+        };
+
+        Ok(Self { products, entitlements, local_prices, display_currency: None, fx_rates: FxRates::default() })
+    }
+
+    fn priced_in(self, fx_rates: &FxRates, currency: Option<&str>) -> Self {
+        Self { display_currency: currency.map(str::to_uppercase), fx_rates: fx_rates.clone(), ..self }
+    }
+
+    /// `price` in the display currency, or as it is without a rate for it.
+    fn displayed(&self, price: (i64, String)) -> (i64, String) {
+        let Some(target) = &self.display_currency else {
+            return price;
+        };
+        match self.fx_rates.convert(price.0, &price.1, target) {
+            Some(converted) => (converted, target.clone()),
+            None => price,
+        }
+    }
+
+    /// Describe `product` for clients, priced for the catalog's country if we
+    /// have a synced price there, and converted to the display currency.
+    fn offering_product(&self, product: &Product) -> OfferingProduct {
+        let (price_micros, currency) = self.displayed(self.local_prices.get(&product.id).cloned().unwrap_or_else(|| (
+            product.price_micros.unwrap_or(0),
+            product.currency.clone().unwrap_or_else(|| "USD".to_string()),
+        )));
+        let (intro_price_micros, intro_currency) = match (product.intro_price_micros, &product.intro_currency) {
+            (Some(micros), Some(currency)) => {
+                let (micros, currency) = self.displayed((micros, currency.clone()));
+                (Some(micros), Some(currency))
+            }
+            (micros, currency) => (micros, currency.clone()),
+        };
+
+        OfferingProduct {
+            store_product_id: product.store_product_id.clone(),
+            product_type: product.product_type.clone(),
+            display_name: product.display_name.clone().unwrap_or_default(),
+            description: product.description.clone(),
+            price_formatted: format_price(price_micros, &currency),
+            price_micros,
+            currency,
+            subscription_period: product.subscription_period.clone(),
+            trial_period: product.trial_period.clone(),
+            intro_price_micros,
+            intro_currency,
+            offer_mode: product.offer_mode.clone(),
+            entitlements: self.entitlements.get(&product.id).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+async fn offering_response(
+    pool: &DbPool,
+    catalog: &Catalog,
+    offering: Offering,
+) -> Result<OfferingResponse, (StatusCode, String)> {
+    let packages = sqlx::query_as::<_, Package>(
+        "SELECT * FROM packages WHERE offering_id = $1 ORDER BY position"
+    )
+    .bind(&offering.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut responses = Vec::with_capacity(packages.len());
+    for package in packages {
+        let product = catalog.products.iter()
+            .find(|p| p.id == package.product_id)
+            .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, format!("Package product {} not found", package.product_id)))?;
+
+        responses.push(PackageResponse {
+            identifier: package.identifier,
+            package_type: package.package_type,
+            product: catalog.offering_product(product),
         });
     }
 
-    Ok(Json(OfferingsResponse { offerings }))
+    Ok(OfferingResponse {
+        identifier: offering.identifier,
+        description: offering.description,
+        packages: responses,
+    })
+}
+
+async fn flat_offerings(state: &AppState, app_id: &str, query: &OfferingsQuery) -> Result<OfferingsResponse, (StatusCode, String)> {
+    let catalog = Catalog::load(&state.pool, app_id, query.country.as_deref())
+        .await?
+        .priced_in(&state.fx_rates, query.currency.as_deref());
+    let offerings = catalog.products.iter().map(|p| catalog.offering_product(p)).collect();
+    Ok(OfferingsResponse { offerings })
+}
+
+/// Names of the entitlements a product grants.
+pub(crate) async fn product_entitlement_names(pool: &DbPool, product_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT e.name FROM entitlements e \
+         JOIN product_entitlements pe ON pe.entitlement_id = e.id \
+         WHERE pe.product_id = $1 \
+         ORDER BY e.name"
+    )
+    .bind(product_id)
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::api::offerings::OfferingsCache;
+    use crate::currency::FxRates;
+    use crate::test_support::{create_test_app, test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn create_test_product(state: &AppState, app_id: &str, api_key: &str, store_product_id: &str) -> String {
+        let app = crate::api::router(state.clone());
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/products"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"store_product_id":"{store_product_id}","product_type":"subscription","entitlement_ids":[]}}"#
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        v["id"].as_str().unwrap().to_string()
+    }
+
+    async fn get_json(state: &AppState, uri: &str, api_key: &str) -> Value {
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_current_offering_groups_packages() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let monthly = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        let annual = create_test_product(&state, &app_id, &api_key, "com.test.annual").await;
+
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/offerings"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"identifier":"default","is_current":true,"packages":[
+                            {{"identifier":"$rc_monthly","package_type":"monthly","product_id":"{monthly}"}},
+                            {{"identifier":"$rc_annual","package_type":"annual","product_id":"{annual}"}}
+                        ]}}"#
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings"), &api_key).await;
+        assert_eq!(v["current_offering_id"], "default");
+        let packages = v["offerings"][0]["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0]["package_type"], "monthly");
+        assert_eq!(packages[0]["product"]["store_product_id"], "com.test.monthly");
+        assert_eq!(packages[1]["product"]["store_product_id"], "com.test.annual");
+
+        // The flat product list is still available
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true"), &api_key).await;
+        assert_eq!(v["offerings"].as_array().unwrap().len(), 2);
+        assert!(v["offerings"][0]["store_product_id"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_flat_offerings_use_country_price() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        sqlx::query("INSERT INTO product_prices (product_id, territory, price_micros, currency) VALUES ($1, 'GBR', 8990000, 'GBP')")
+            .bind(&product_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true&country=gbr"), &api_key).await;
+        assert_eq!(v["offerings"][0]["price_micros"], 8_990_000);
+        assert_eq!(v["offerings"][0]["currency"], "GBP");
+
+        // Territories without a synced price fall back to the product's default
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true&country=FRA"), &api_key).await;
+        assert_eq!(v["offerings"][0]["currency"], "USD");
+    }
+
+    #[tokio::test]
+    async fn test_prices_are_formatted_and_converted() {
+        let mut state = test_state(test_pool().await);
+        state.fx_rates = FxRates::new(std::collections::HashMap::from([("JPY".to_string(), 150.0)]));
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        sqlx::query("UPDATE products SET price_micros = 9990000, currency = 'USD' WHERE id = $1")
+            .bind(&product_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true"), &api_key).await;
+        assert_eq!(v["offerings"][0]["price_formatted"], "$9.99");
+
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true&currency=jpy"), &api_key).await;
+        assert_eq!(v["offerings"][0]["price_micros"], 1_499_000_000_i64);
+        assert_eq!(v["offerings"][0]["currency"], "JPY");
+        assert_eq!(v["offerings"][0]["price_formatted"], "¥1,499");
+
+        // Without a rate the price stays as it is
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true&currency=EUR"), &api_key).await;
+        assert_eq!(v["offerings"][0]["currency"], "USD");
+        assert_eq!(v["offerings"][0]["price_formatted"], "$9.99");
+    }
+
+    async fn post_json(state: &AppState, uri: &str, api_key: &str, body: String) -> StatusCode {
+        crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_set_current_offering() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let monthly = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        for (identifier, is_current) in [("default", true), ("sale", false)] {
+            let status = post_json(&state, &format!("/v1/apps/{app_id}/offerings"), &api_key, format!(
+                r#"{{"identifier":"{identifier}","is_current":{is_current},"packages":[
+                    {{"identifier":"$rc_monthly","package_type":"monthly","product_id":"{monthly}"}}
+                ]}}"#
+            )).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        let offerings_uri = format!("/v1/apps/{app_id}/offerings");
+        assert_eq!(get_json(&state, &offerings_uri, &api_key).await["current_offering_id"], "default");
+
+        let put = |body: &'static str| {
+            crate::api::router(state.clone()).oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/v1/apps/{app_id}/current-offering"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+
+        let response = put(r#"{"current_offering_id":"sale"}"#).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(get_json(&state, &offerings_uri, &api_key).await["current_offering_id"], "sale");
+
+        // Only the app's own offerings can be made current
+        let response = put(r#"{"current_offering_id":"missing"}"#).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get_json(&state, &offerings_uri, &api_key).await["current_offering_id"], "sale");
+
+        // Clearing it takes an explicit null, not a missing or misspelled field
+        for body in ["{}", r#"{"current_offering":null}"#, r#"{"current_offering_id":null,"extra":1}"#] {
+            let response = put(body).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        assert_eq!(get_json(&state, &offerings_uri, &api_key).await["current_offering_id"], "sale");
+
+        let response = put(r#"{"current_offering_id":null}"#).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(get_json(&state, &offerings_uri, &api_key).await["current_offering_id"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_override_targets_country_and_app_version() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let monthly = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        let offerings_uri = format!("/v1/apps/{app_id}/offerings");
+        for (identifier, is_current) in [("default", true), ("us_promo", false), ("us_v2", false)] {
+            let status = post_json(&state, &offerings_uri, &api_key, format!(
+                r#"{{"identifier":"{identifier}","is_current":{is_current},"packages":[
+                    {{"identifier":"$rc_monthly","package_type":"monthly","product_id":"{monthly}"}}
+                ]}}"#
+            )).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let overrides_uri = format!("/v1/apps/{app_id}/offerings/overrides");
+        let status = post_json(&state, &overrides_uri, &api_key, r#"{"offering":"us_promo","country":"usa"}"#.to_string()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let status = post_json(&state, &overrides_uri, &api_key,
+            r#"{"offering":"us_v2","country":"USA","min_app_version":"2.0"}"#.to_string()).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Same targeting twice, an unknown offering, or no targeting at all are rejected
+        let status = post_json(&state, &overrides_uri, &api_key, r#"{"offering":"default","country":"USA"}"#.to_string()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let status = post_json(&state, &overrides_uri, &api_key, r#"{"offering":"missing","country":"FRA"}"#.to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let status = post_json(&state, &overrides_uri, &api_key, r#"{"offering":"us_promo"}"#.to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let v = get_json(&state, &format!("{offerings_uri}?country=USA"), &api_key).await;
+        assert_eq!(v["current_offering_id"], "us_promo");
+        let v = get_json(&state, &format!("{offerings_uri}?country=USA&app_version=2.3.1"), &api_key).await;
+        assert_eq!(v["current_offering_id"], "us_v2");
+        let v = get_json(&state, &format!("{offerings_uri}?country=USA&app_version=1.9"), &api_key).await;
+        assert_eq!(v["current_offering_id"], "us_promo");
+
+        // Clients outside every override get the current offering
+        let v = get_json(&state, &format!("{offerings_uri}?country=GBR&app_version=3.0"), &api_key).await;
+        assert_eq!(v["current_offering_id"], "default");
+        let v = get_json(&state, &offerings_uri, &api_key).await;
+        assert_eq!(v["current_offering_id"], "default");
+    }
+
+    #[tokio::test]
+    async fn test_offerings_are_cached_until_the_catalog_changes() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        let uri = format!("/v1/apps/{app_id}/offerings?flat=true");
+
+        let v = get_json(&state, &uri, &api_key).await;
+        assert_eq!(v["offerings"].as_array().unwrap().len(), 1);
+
+        // Written behind the API's back, so only a fresh query would see it
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type, created_at) VALUES ('direct', $1, 'com.test.direct', 'subscription', $2)")
+            .bind(&app_id)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let v = get_json(&state, &uri, &api_key).await;
+        assert_eq!(v["offerings"].as_array().unwrap().len(), 1);
+
+        create_test_product(&state, &app_id, &api_key, "com.test.annual").await;
+        let v = get_json(&state, &uri, &api_key).await;
+        assert_eq!(v["offerings"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flat_offerings_match_per_product_lookups() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let monthly = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        let annual = create_test_product(&state, &app_id, &api_key, "com.test.annual").await;
+        create_test_product(&state, &app_id, &api_key, "com.test.coins").await;
+
+        for (entitlement, products) in [("pro", vec![&monthly, &annual]), ("ad_free", vec![&annual])] {
+            sqlx::query("INSERT INTO entitlements (id, app_id, name, created_at) VALUES ($1, $2, $1, $3)")
+                .bind(entitlement)
+                .bind(&app_id)
+                .bind(chrono::Utc::now().to_rfc3339())
+                .execute(&state.pool)
+                .await
+                .unwrap();
+            for product_id in products {
+                sqlx::query("INSERT INTO product_entitlements (product_id, entitlement_id) VALUES ($1, $2)")
+                    .bind(product_id)
+                    .bind(entitlement)
+                    .execute(&state.pool)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let v = get_json(&state, &format!("/v1/apps/{app_id}/offerings?flat=true"), &api_key).await;
+        let offerings = v["offerings"].as_array().unwrap();
+        let ids: Vec<_> = offerings.iter().map(|o| o["store_product_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["com.test.monthly", "com.test.annual", "com.test.coins"]);
+
+        let products: Vec<String> = sqlx::query_scalar("SELECT id FROM products WHERE app_id = $1 ORDER BY created_at")
+            .bind(&app_id)
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        for (offering, product_id) in offerings.iter().zip(&products) {
+            let expected = super::product_entitlement_names(&state.pool, product_id).await.unwrap();
+            assert_eq!(offering["entitlements"], serde_json::json!(expected));
+        }
+        assert_eq!(offerings[1]["entitlements"], serde_json::json!(["ad_free", "pro"]));
+        assert_eq!(offerings[2]["entitlements"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_experiment_assignment_is_stable_per_user() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let monthly = create_test_product(&state, &app_id, &api_key, "com.test.monthly").await;
+        let offerings_uri = format!("/v1/apps/{app_id}/offerings");
+        for (identifier, is_current) in [("default", true), ("paywall_a", false), ("paywall_b", false)] {
+            let status = post_json(&state, &offerings_uri, &api_key, format!(
+                r#"{{"identifier":"{identifier}","is_current":{is_current},"packages":[
+                    {{"identifier":"$rc_monthly","package_type":"monthly","product_id":"{monthly}"}}
+                ]}}"#
+            )).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let experiments_uri = format!("/v1/apps/{app_id}/experiments");
+        let status = post_json(&state, &experiments_uri, &api_key,
+            r#"{"name":"paywall_test","variants":[{"name":"a","offering":"paywall_a","weight":1}]}"#.to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let status = post_json(&state, &experiments_uri, &api_key, r#"{"name":"paywall_test","variants":[
+            {"name":"a","offering":"paywall_a","weight":50},
+            {"name":"b","offering":"paywall_b","weight":50}
+        ]}"#.to_string()).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        for user in ["user-1", "user-2", "user-3"] {
+            let first = get_json(&state, &format!("{offerings_uri}?app_user_id={user}"), &api_key).await;
+            let variant = first["experiment"]["variant"].as_str().unwrap().to_string();
+            assert_eq!(first["experiment"]["name"], "paywall_test");
+            assert_eq!(first["current_offering_id"], format!("paywall_{variant}"));
+
+            let again = get_json(&state, &format!("{offerings_uri}?app_user_id={user}"), &api_key).await;
+            assert_eq!(again["experiment"]["variant"], variant.as_str());
+        }
+
+        // Without a user there is nothing to assign
+        let v = get_json(&state, &offerings_uri, &api_key).await;
+        assert_eq!(v["current_offering_id"], "default");
+        assert!(v.get("experiment").is_none());
+    }
+
+    #[test]
+    fn test_experiment_assignment_follows_weights() {
+        let variant = |name: &str, weight| super::ExperimentVariantResponse {
+            name: name.to_string(),
+            offering: name.to_string(),
+            weight,
+        };
+        let experiment = super::RunningExperiment {
+            id: "exp".to_string(),
+            name: "paywall_test".to_string(),
+            variants: vec![variant("a", 70), variant("b", 30)],
+        };
+
+        let users = 10_000;
+        let a = (0..users)
+            .filter(|i| experiment.assign(&format!("user-{i}")).unwrap().name == "a")
+            .count();
+        let share = a as f64 / users as f64;
+        assert!((0.67..0.73).contains(&share), "variant a got {share}");
+    }
+
+    #[test]
+    fn test_offerings_cache_drops_expired_entries_and_caps_each_app() {
+        let snapshot = || super::OfferingsSnapshot { body: serde_json::json!({}), experiment: None };
+        let cached = |cache: &OfferingsCache, app_id: &str| cache.apps.lock().unwrap().get(app_id).map_or(0, |c| c.len());
+
+        let expired = OfferingsCache::new(std::time::Duration::ZERO);
+        for i in 0..10 {
+            expired.insert("app", format!("country-{i}"), snapshot());
+        }
+        assert_eq!(cached(&expired, "app"), 1);
+
+        let cache = OfferingsCache::default();
+        for i in 0..super::MAX_CACHED_QUERIES_PER_APP + 10 {
+            cache.insert("app", format!("country-{i}"), snapshot());
+        }
+        cache.insert("other", "country-0".to_string(), snapshot());
+        assert_eq!(cached(&cache, "app"), super::MAX_CACHED_QUERIES_PER_APP);
+        assert_eq!(cached(&cache, "other"), 1);
+        assert!(cache.get("app", &format!("country-{}", super::MAX_CACHED_QUERIES_PER_APP + 9)).is_some());
+    }
 }
@@ -0,0 +1,146 @@
+use axum::Json;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use super::{
+    analytics, api_keys, apps, entitlements, events, health, notifications, offerings, products,
+    promotional_offers, receipts, subscribers, subscriptions, transactions, webhooks,
+};
+
+/// The API's OpenAPI document, built from the handlers' `#[utoipa::path]`
+/// annotations. A route missing from `paths` is missing from the document.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "OpenCat API"),
+    paths(
+        health::health_check,
+        apps::create_app,
+        apps::list_apps,
+        apps::delete_app,
+        apps::update_credentials,
+        apps::get_credentials,
+        apps::sync_products,
+        api_keys::create_api_key,
+        api_keys::list_api_keys,
+        api_keys::revoke_api_key,
+        offerings::create_offering,
+        offerings::get_offerings,
+        offerings::set_current_offering,
+        offerings::create_offering_override,
+        offerings::create_experiment,
+        offerings::delete_experiment,
+        promotional_offers::sign_offer,
+        entitlements::create_entitlement,
+        entitlements::list_entitlements,
+        entitlements::get_entitlement,
+        entitlements::update_entitlement,
+        entitlements::delete_entitlement,
+        products::create_product,
+        products::list_products,
+        products::import_products,
+        products::get_product,
+        products::update_product,
+        products::delete_product,
+        analytics::overview,
+        subscribers::create_subscriber,
+        subscribers::list_subscribers,
+        subscribers::export_subscribers,
+        subscribers::delete_subscriber,
+        subscribers::set_attributes,
+        subscribers::get_subscriber,
+        subscribers::alias_subscriber,
+        subscriptions::get_subscription,
+        transactions::list_transactions,
+        receipts::submit_receipt,
+        receipts::restore_purchases,
+        receipts::backfill_subscriber,
+        notifications::apple_notification,
+        notifications::apple_app_notification,
+        notifications::google_notification,
+        notifications::replay_notification,
+        webhooks::create_webhook,
+        webhooks::list_webhooks,
+        webhooks::update_webhook,
+        webhooks::delete_webhook,
+        webhooks::rotate_webhook_secret,
+        webhooks::list_deliveries,
+        webhooks::redrive_dead_letters,
+        webhooks::retry_delivery,
+        events::list_events,
+        events::stream_events,
+        events::socket_events,
+    ),
+    components(schemas(offerings::OfferingsResponse, events::SocketSubscription)),
+    modifiers(&ApiKeyAuth),
+    security(("api_key" = [])),
+)]
+pub struct ApiDoc;
+
+/// Declares the `Authorization: Bearer <API key>` scheme most routes require.
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("An API key issued for the app, e.g. `ocat_...`"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /// Every `$ref` in `value`.
+    fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    found.push(target);
+                }
+                map.values().for_each(|v| refs(v, found));
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, found)),
+            _ => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_describes_the_api() {
+        let response = crate::api::router(test_state(test_pool().await))
+            .oneshot(Request::builder().uri("/openapi.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let doc: Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        let apps = &doc["paths"]["/v1/apps"];
+        assert_eq!(apps["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/CreateApp");
+        assert_eq!(apps["post"]["security"], serde_json::json!([{}]));
+        assert!(apps["get"].is_object());
+        assert_eq!(doc["components"]["securitySchemes"]["api_key"]["scheme"], "bearer");
+
+        let mut found = Vec::new();
+        refs(&doc, &mut found);
+        for target in found {
+            let name = target.strip_prefix("#/components/schemas/").unwrap();
+            assert!(doc["components"]["schemas"][name].is_object(), "{target} is not defined");
+        }
+    }
+}
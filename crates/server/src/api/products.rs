@@ -1,19 +1,43 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json};
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
 use crate::api::AppState;
-use crate::models::product::{CreateProduct, Product};
+use crate::api::auth::AuthenticatedApp;
+use crate::api::events::{decode_cursor, encode_cursor};
+use crate::api::offerings::product_entitlement_names;
+use crate::models::product::{CreateProduct, ImportProduct, ImportSummary, Product, UpdateProduct};
 
+const PRODUCT_TYPES: &[&str] = &["subscription", "consumable", "non_consumable"];
+
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/products",
+    tag = "products",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response to a retried request"),
+    ),
+    request_body = CreateProduct,
+    responses(
+        (status = 201, body = Product),
+        (status = 409, description = "A request with the same Idempotency-Key is still in progress"),
+        (status = 422, description = "Idempotency key reused for a different request"),
+    ),
+)]
 pub async fn create_product(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Path(app_id): Path<String>,
     Json(input): Json<CreateProduct>,
 ) -> Result<(StatusCode, Json<Product>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
     let id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
 
     let mut tx = state.pool.begin().await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type, created_at) VALUES (?, ?, ?, ?, ?)")
+    sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type, created_at) VALUES ($1, $2, $3, $4, $5)")
         .bind(&id)
         .bind(&app_id)
         .bind(&input.store_product_id)
@@ -24,7 +48,7 @@ pub async fn create_product(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     for entitlement_id in &input.entitlement_ids {
-        sqlx::query("INSERT INTO product_entitlements (product_id, entitlement_id) VALUES (?, ?)")
+        sqlx::query("INSERT INTO product_entitlements (product_id, entitlement_id) VALUES ($1, $2)")
             .bind(&id)
             .bind(entitlement_id)
             .execute(&mut *tx)
@@ -35,69 +59,423 @@ pub async fn create_product(
     tx.commit().await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let product = sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = ?")
+    let product = sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = $1")
         .bind(&id)
         .fetch_one(&state.pool)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.offerings_cache.invalidate(&app_id);
     Ok((StatusCode::CREATED, Json(product)))
 }
 
+/// Create or update many products at once, creating any entitlements they
+/// name that don't exist yet. All-or-nothing: a bad row rolls back the lot.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/products/import",
+    tag = "products",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = Vec<ImportProduct>,
+    responses(
+        (status = 200, body = ImportSummary),
+        (status = 400, description = "A row is invalid; nothing was imported"),
+    ),
+)]
+pub async fn import_products(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<Vec<ImportProduct>>,
+) -> Result<Json<ImportSummary>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut summary = ImportSummary { created: 0, updated: 0 };
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for (row, product) in input.iter().enumerate() {
+        let row_error = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("Row {row}: {e}"));
+        if !PRODUCT_TYPES.contains(&product.product_type.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Row {row}: unknown product type: {}", product.product_type)));
+        }
+
+        let existing = sqlx::query_scalar::<_, String>("SELECT id FROM products WHERE app_id = $1 AND store_product_id = $2")
+            .bind(&app_id)
+            .bind(&product.store_product_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(row_error)?;
+
+        let product_id = if let Some(product_id) = existing {
+            sqlx::query("UPDATE products SET product_type = $1 WHERE id = $2")
+                .bind(&product.product_type)
+                .bind(&product_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(row_error)?;
+            sqlx::query("DELETE FROM product_entitlements WHERE product_id = $1")
+                .bind(&product_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(row_error)?;
+            summary.updated += 1;
+            product_id
+        } else {
+            let id = uuid::Uuid::new_v4().to_string();
+            sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type, created_at) VALUES ($1, $2, $3, $4, $5)")
+                .bind(&id)
+                .bind(&app_id)
+                .bind(&product.store_product_id)
+                .bind(&product.product_type)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await
+                .map_err(row_error)?;
+            summary.created += 1;
+            id
+        };
+
+        for name in &product.entitlements {
+            let entitlement_id = sqlx::query_scalar::<_, String>("SELECT id FROM entitlements WHERE app_id = $1 AND name = $2")
+                .bind(&app_id)
+                .bind(name)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(row_error)?;
+            let entitlement_id = match entitlement_id {
+                Some(id) => id,
+                None => {
+                    let id = uuid::Uuid::new_v4().to_string();
+                    sqlx::query("INSERT INTO entitlements (id, app_id, name, created_at) VALUES ($1, $2, $3, $4)")
+                        .bind(&id)
+                        .bind(&app_id)
+                        .bind(name)
+                        .bind(&now)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(row_error)?;
+                    id
+                }
+            };
+
+            sqlx::query("INSERT INTO product_entitlements (product_id, entitlement_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(&product_id)
+                .bind(&entitlement_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(row_error)?;
+        }
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok(Json(summary))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ProductsQuery {
+    /// Opaque `next_cursor` from a previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct ProductsPage {
+    pub products: Vec<Product>,
+    /// Pass back as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// List the app's products oldest-first, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/products",
+    tag = "products",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ProductsQuery,
+    ),
+    responses(
+        (status = 200, body = ProductsPage),
+        (status = 400, description = "Invalid cursor"),
+    ),
+)]
 pub async fn list_products(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Path(app_id): Path<String>,
-) -> Result<Json<Vec<Product>>, (StatusCode, String)> {
-    let products = sqlx::query_as::<_, Product>(
-        "SELECT * FROM products WHERE app_id = ? ORDER BY created_at DESC"
-    )
-    .bind(&app_id)
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    Ok(Json(products))
+    Query(query): Query<ProductsQuery>,
+) -> Result<Json<ProductsPage>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let after = query.cursor.as_deref()
+        .map(|cursor| decode_cursor(cursor).ok_or((StatusCode::BAD_REQUEST, "Invalid cursor".to_string())))
+        .transpose()?;
+
+    // Fetch one extra row to tell whether there is another page
+    let position = if after.is_some() { "AND (created_at, id) > ($2, $3)" } else { "" };
+    let sql = format!(
+        "SELECT * FROM products WHERE app_id = $1 {position} ORDER BY created_at ASC, id ASC LIMIT {}",
+        limit + 1
+    );
+    let mut query = sqlx::query_as::<_, Product>(&sql).bind(&app_id);
+    if let Some((created_at, id)) = after {
+        query = query.bind(created_at).bind(id);
+    }
+    let mut products = query
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let next_cursor = if products.len() as i64 > limit {
+        products.truncate(limit as usize);
+        products.last().map(|p| encode_cursor(&p.created_at, &p.id))
+    } else {
+        None
+    };
+
+    Ok(Json(ProductsPage { products, next_cursor }))
+}
+
+/// A product with the names of the entitlements it grants.
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct ProductDetail {
+    #[serde(flatten)]
+    pub product: Product,
+    pub entitlements: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/products/{product_id}",
+    tag = "products",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("product_id" = String, Path, description = "Product ID"),
+    ),
+    responses(
+        (status = 200, body = ProductDetail),
+        (status = 404, description = "Product not found"),
+    ),
+)]
+pub async fn get_product(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, product_id)): Path<(String, String)>,
+) -> Result<Json<ProductDetail>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let product = sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = $1 AND app_id = $2")
+        .bind(&product_id)
+        .bind(&app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Product not found".to_string()))?;
+
+    let entitlements = product_entitlement_names(&state.pool, &product.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(ProductDetail { product, entitlements }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/apps/{app_id}/products/{product_id}",
+    tag = "products",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("product_id" = String, Path, description = "Product ID"),
+    ),
+    request_body = UpdateProduct,
+    responses(
+        (status = 200, body = Product),
+        (status = 400, description = "Unknown product type or entitlement"),
+        (status = 404, description = "Product not found"),
+    ),
+)]
+pub async fn update_product(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, product_id)): Path<(String, String)>,
+    Json(input): Json<UpdateProduct>,
+) -> Result<Json<Product>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    if let Some(product_type) = &input.product_type {
+        if !PRODUCT_TYPES.contains(&product_type.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown product type: {product_type}")));
+        }
+    }
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let updated = sqlx::query("UPDATE products SET product_type = COALESCE($1, product_type) WHERE id = $2 AND app_id = $3")
+        .bind(&input.product_type)
+        .bind(&product_id)
+        .bind(&app_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .rows_affected();
+    if updated == 0 {
+        return Err((StatusCode::NOT_FOUND, "Product not found".to_string()));
+    }
+
+    if let Some(entitlement_ids) = &input.entitlement_ids {
+        sqlx::query("DELETE FROM product_entitlements WHERE product_id = $1")
+            .bind(&product_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        for entitlement_id in entitlement_ids {
+            let owned = sqlx::query_scalar::<_, String>("SELECT id FROM entitlements WHERE id = $1 AND app_id = $2")
+                .bind(entitlement_id)
+                .bind(&app_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            if owned.is_none() {
+                return Err((StatusCode::BAD_REQUEST, format!("Unknown entitlement: {entitlement_id}")));
+            }
+
+            sqlx::query("INSERT INTO product_entitlements (product_id, entitlement_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(&product_id)
+                .bind(entitlement_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let product = sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = $1")
+        .bind(&product_id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok(Json(product))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteProductQuery {
+    /// Delete even if transactions reference the product, deleting them too.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Delete a product along with its entitlement links, prices and packages.
+/// Refused with 409 while transactions reference it, unless `force` is set.
+#[utoipa::path(
+    delete,
+    path = "/v1/apps/{app_id}/products/{product_id}",
+    tag = "products",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("product_id" = String, Path, description = "Product ID"),
+        DeleteProductQuery,
+    ),
+    responses(
+        (status = 204, description = "Product deleted"),
+        (status = 404, description = "Product not found"),
+        (status = 409, description = "Transactions reference the product"),
+    ),
+)]
+pub async fn delete_product(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, product_id)): Path<(String, String)>,
+    Query(query): Query<DeleteProductQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let exists = sqlx::query_scalar::<_, String>("SELECT id FROM products WHERE id = $1 AND app_id = $2")
+        .bind(&product_id)
+        .bind(&app_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if exists.is_none() {
+        return Err((StatusCode::NOT_FOUND, "Product not found".to_string()));
+    }
+
+    let transactions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions WHERE product_id = $1")
+        .bind(&product_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if transactions > 0 {
+        if !query.force {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{transactions} transactions reference this product; pass force=true to delete them with it"),
+            ));
+        }
+        sqlx::query("DELETE FROM transactions WHERE product_id = $1")
+            .bind(&product_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    sqlx::query("DELETE FROM product_entitlements WHERE product_id = $1")
+        .bind(&product_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Prices and offering packages cascade
+    sqlx::query("DELETE FROM products WHERE id = $1")
+        .bind(&product_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.offerings_cache.invalidate(&app_id);
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::api::AppState;
-    use crate::db;
+    use crate::test_support::{create_test_app, test_pool, test_state};
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
     use serde_json::Value;
     use tower::ServiceExt;
 
-    async fn test_state() -> AppState {
-        let pool = db::connect("sqlite::memory:").await.unwrap();
-        AppState { pool }
-    }
-
-    async fn create_test_app(state: &AppState) -> String {
+    async fn create_test_entitlement(state: &AppState, app_id: &str, api_key: &str) -> String {
         let app = crate::api::router(state.clone());
         let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/v1/apps")
-                    .header("content-type", "application/json")
-                    .body(Body::from(r#"{"name":"Test","platform":"ios","bundle_id":"com.test"}"#))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let v: Value = serde_json::from_slice(&body).unwrap();
-        v["id"].as_str().unwrap().to_string()
-    }
-
-    async fn create_test_entitlement(state: &AppState, app_id: &str) -> String {
-        let app = crate::api::router(state.clone());
-        let resp = app
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri(&format!("/v1/apps/{app_id}/entitlements"))
+                    .uri(format!("/v1/apps/{app_id}/entitlements"))
+                    .header("authorization", format!("Bearer {api_key}"))
                     .header("content-type", "application/json")
                     .body(Body::from(r#"{"name":"pro","description":"Pro access"}"#))
                     .unwrap(),
@@ -111,16 +489,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_and_list_products() {
-        let state = test_state().await;
-        let app_id = create_test_app(&state).await;
-        let ent_id = create_test_entitlement(&state, &app_id).await;
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let ent_id = create_test_entitlement(&state, &app_id, &api_key).await;
         let app = crate::api::router(state);
 
         let response = app.clone()
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri(&format!("/v1/apps/{app_id}/products"))
+                    .uri(format!("/v1/apps/{app_id}/products"))
+                    .header("authorization", format!("Bearer {api_key}"))
                     .header("content-type", "application/json")
                     .body(Body::from(format!(
                         r#"{{"store_product_id":"com.test.pro","product_type":"subscription","entitlement_ids":["{ent_id}"]}}"#
@@ -134,7 +513,8 @@ mod tests {
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri(&format!("/v1/apps/{app_id}/products"))
+                    .uri(format!("/v1/apps/{app_id}/products"))
+                    .header("authorization", format!("Bearer {api_key}"))
                     .body(Body::empty())
                     .unwrap(),
             )
@@ -142,4 +522,234 @@ mod tests {
             .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    async fn send(state: &AppState, method: &str, uri: &str, api_key: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", format!("Bearer {api_key}"))
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+        let response = crate::api::router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    async fn linked_entitlements(state: &AppState, product_id: &str) -> Vec<String> {
+        sqlx::query_scalar("SELECT entitlement_id FROM product_entitlements WHERE product_id = $1 ORDER BY entitlement_id")
+            .bind(product_id)
+            .fetch_all(&state.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_update_relinks_entitlements() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let (_, pro) = send(&state, "POST", &format!("/v1/apps/{app_id}/entitlements"), &api_key, Some(serde_json::json!({ "name": "pro" }))).await;
+        let (_, ads) = send(&state, "POST", &format!("/v1/apps/{app_id}/entitlements"), &api_key, Some(serde_json::json!({ "name": "no_ads" }))).await;
+        let (pro, ads) = (pro["id"].as_str().unwrap(), ads["id"].as_str().unwrap());
+
+        let (status, product) = send(
+            &state, "POST", &format!("/v1/apps/{app_id}/products"), &api_key,
+            Some(serde_json::json!({ "store_product_id": "com.test.pro", "product_type": "subscription", "entitlement_ids": [pro, ads] })),
+        ).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let product_id = product["id"].as_str().unwrap();
+        assert_eq!(linked_entitlements(&state, product_id).await.len(), 2);
+
+        let uri = format!("/v1/apps/{app_id}/products/{product_id}");
+        let (status, updated) = send(
+            &state, "PUT", &uri, &api_key,
+            Some(serde_json::json!({ "product_type": "non_consumable", "entitlement_ids": [pro] })),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["product_type"], "non_consumable");
+        assert_eq!(linked_entitlements(&state, product_id).await, vec![pro.to_string()]);
+
+        // A bad entitlement leaves the existing links alone
+        let (status, _) = send(&state, "PUT", &uri, &api_key, Some(serde_json::json!({ "entitlement_ids": ["missing"] }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(linked_entitlements(&state, product_id).await, vec![pro.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_product_with_transactions_requires_force() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let ent_id = create_test_entitlement(&state, &app_id, &api_key).await;
+        let (_, product) = send(
+            &state, "POST", &format!("/v1/apps/{app_id}/products"), &api_key,
+            Some(serde_json::json!({ "store_product_id": "com.test.pro", "product_type": "subscription", "entitlement_ids": [ent_id] })),
+        ).await;
+        let product_id = product["id"].as_str().unwrap();
+
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status)
+             VALUES ('tx', 'sub', $1, 'apple', '1000', '2026-01-01T00:00:00Z', 'expired')"
+        )
+        .bind(product_id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+        let uri = format!("/v1/apps/{app_id}/products/{product_id}");
+        let (status, _) = send(&state, "DELETE", &uri, &api_key, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) = send(&state, "DELETE", &format!("{uri}?force=true"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(linked_entitlements(&state, product_id).await.is_empty());
+
+        let (status, _) = send(&state, "DELETE", &uri, &api_key, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_products_pages_through_catalog() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('other', 'Other', 'ios', 'com.other')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        for i in 0..5 {
+            sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type, created_at) VALUES ($1, $2, $3, 'subscription', '2026-01-01T00:00:00Z')")
+                .bind(format!("prod-{i}"))
+                .bind(&app_id)
+                .bind(format!("com.test.product{i}"))
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ('elsewhere', 'other', 'com.other.pro', 'subscription')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let mut seen = Vec::new();
+        let mut uri = format!("/v1/apps/{app_id}/products?limit=2");
+        loop {
+            let (status, body) = send(&state, "GET", &uri, &api_key, None).await;
+            assert_eq!(status, StatusCode::OK);
+            let page: Vec<String> = body["products"].as_array().unwrap().iter()
+                .map(|p| p["id"].as_str().unwrap().to_string())
+                .collect();
+            assert!(page.len() <= 2);
+            seen.extend(page);
+            match body["next_cursor"].as_str() {
+                Some(cursor) => uri = format!("/v1/apps/{app_id}/products?limit=2&cursor={cursor}"),
+                None => break,
+            }
+        }
+        // Products sharing a timestamp are neither skipped nor repeated
+        assert_eq!(seen, (0..5).map(|i| format!("prod-{i}")).collect::<Vec<_>>());
+
+        let (status, _) = send(&state, "GET", &format!("/v1/apps/{app_id}/products?cursor=bogus"), &api_key, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_product() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let ent_id = create_test_entitlement(&state, &app_id, &api_key).await;
+        let (_, product) = send(
+            &state, "POST", &format!("/v1/apps/{app_id}/products"), &api_key,
+            Some(serde_json::json!({ "store_product_id": "com.test.pro", "product_type": "subscription", "entitlement_ids": [ent_id] })),
+        ).await;
+        let product_id = product["id"].as_str().unwrap();
+
+        let (status, body) = send(&state, "GET", &format!("/v1/apps/{app_id}/products/{product_id}"), &api_key, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["store_product_id"], "com.test.pro");
+        assert_eq!(body["entitlements"], serde_json::json!(["pro"]));
+
+        let (status, _) = send(&state, "GET", &format!("/v1/apps/{app_id}/products/missing"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // A product belonging to another app is reported as missing
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('other', 'Other', 'ios', 'com.other')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ('elsewhere', 'other', 'com.other.pro', 'subscription')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let (status, _) = send(&state, "GET", &format!("/v1/apps/{app_id}/products/elsewhere"), &api_key, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_import_products_with_shared_entitlement() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let existing = create_test_entitlement(&state, &app_id, &api_key).await;
+        let uri = format!("/v1/apps/{app_id}/products/import");
+
+        let (status, body) = send(
+            &state, "POST", &uri, &api_key,
+            Some(serde_json::json!([
+                { "store_product_id": "com.test.monthly", "product_type": "subscription", "entitlements": ["pro"] },
+                { "store_product_id": "com.test.annual", "product_type": "subscription", "entitlements": ["pro", "no_ads"] },
+                { "store_product_id": "com.test.lifetime", "product_type": "non_consumable", "entitlements": ["pro"] },
+            ])),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({ "created": 3, "updated": 0 }));
+
+        // `pro` already existed and is shared rather than duplicated
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM entitlements WHERE app_id = $1 ORDER BY name")
+            .bind(&app_id)
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(names, vec!["no_ads", "pro"]);
+        let linked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM product_entitlements WHERE entitlement_id = $1")
+            .bind(&existing)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(linked, 3);
+
+        // Re-importing updates in place
+        let (status, body) = send(
+            &state, "POST", &uri, &api_key,
+            Some(serde_json::json!([
+                { "store_product_id": "com.test.annual", "product_type": "subscription", "entitlements": ["pro"] },
+            ])),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({ "created": 0, "updated": 1 }));
+
+        // A bad row rolls back the whole import
+        let (status, _) = send(
+            &state, "POST", &uri, &api_key,
+            Some(serde_json::json!([
+                { "store_product_id": "com.test.weekly", "product_type": "subscription", "entitlements": ["weekly"] },
+                { "store_product_id": "com.test.coins", "product_type": "bogus", "entitlements": [] },
+            ])),
+        ).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let products: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM products WHERE app_id = $1")
+            .bind(&app_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(products, 3);
+        let weekly: Option<String> = sqlx::query_scalar("SELECT id FROM entitlements WHERE name = 'weekly'")
+            .fetch_optional(&state.pool)
+            .await
+            .unwrap();
+        assert!(weekly.is_none());
+    }
 }
@@ -0,0 +1,111 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+use crate::models::app::App;
+use crate::store::apple::SignedOffer;
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct SignOfferRequest {
+    pub product_id: String,
+    pub offer_id: String,
+    /// The `appAccountToken` the purchase will be made with, if any.
+    #[serde(default)]
+    pub app_account_token: Option<String>,
+}
+
+/// Sign an App Store promotional offer with the app's In-App Purchase key, for
+/// the client to pass to StoreKit.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/promotional-offers/sign",
+    tag = "offerings",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = SignOfferRequest,
+    responses(
+        (status = 200, body = SignedOffer),
+        (status = 400, description = "No Apple credentials"),
+        (status = 404, description = "App not found"),
+    ),
+)]
+pub async fn sign_offer(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<SignOfferRequest>,
+) -> Result<Json<SignedOffer>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let app = sqlx::query_as::<_, App>("SELECT * FROM apps WHERE id = $1")
+        .bind(&app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "App not found".to_string()))?;
+
+    let adapter = crate::store::apple_adapter_for_app(&app, &state.cipher, &state.http, &state.apple_verifier, &state.apple_environments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let signed = adapter
+        .sign_promotional_offer(&input.product_id, &input.offer_id, input.app_account_token.as_deref().unwrap_or_default())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to sign offer: {e}")))?;
+
+    Ok(Json(signed))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::store::apple_jws::tests::TEST_LEAF_KEY;
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn send(state: &AppState, method: &str, uri: &str, api_key: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(api_key) = api_key {
+            request = request.header("authorization", format!("Bearer {api_key}"));
+        }
+        let resp = crate::api::router(state.clone())
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_sign_offer_uses_app_key() {
+        let state = test_state(test_pool().await);
+        let (_, app) = send(&state, "POST", "/v1/apps", None, serde_json::json!({
+            "name": "Test", "platform": "ios", "bundle_id": "com.test"
+        })).await;
+        let app_id = app["id"].as_str().unwrap();
+        let api_key = app["api_key"].as_str().unwrap();
+        let uri = format!("/v1/apps/{app_id}/promotional-offers/sign");
+        let offer = serde_json::json!({ "product_id": "com.test.pro", "offer_id": "winback50" });
+
+        let (status, _) = send(&state, "POST", &uri, Some(api_key), offer.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(&state, "PUT", &format!("/v1/apps/{app_id}/credentials"), Some(api_key), serde_json::json!({
+            "apple": { "issuer_id": "issuer", "key_id": "KEY123", "private_key": TEST_LEAF_KEY }
+        })).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, signed) = send(&state, "POST", &uri, Some(api_key), offer).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(signed["keyIdentifier"], "KEY123");
+        assert!(uuid::Uuid::parse_str(signed["nonce"].as_str().unwrap()).is_ok());
+        assert!(signed["timestamp"].as_i64().unwrap() > 0);
+        assert!(!signed["signature"].as_str().unwrap().is_empty());
+    }
+}
@@ -1,99 +1,502 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde::Deserialize;
+use sqlx::AnyConnection;
 use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+use crate::api::subscribers::{find_or_create_subscriber, find_subscriber, subscriber_info, SubscriberInfo};
+use crate::models::app::App;
 use crate::models::subscriber::Subscriber;
 use crate::models::transaction::Transaction;
+use crate::store::types::{TransactionEvent, VerifiedTransaction};
+use crate::transactions::event_payload;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::ToSchema)]
 pub struct SubmitReceipt {
     pub app_id: String,
     pub app_user_id: String,
-    pub store: String,
+    /// Defaults to the store of the app's platform.
+    #[serde(default)]
+    pub store: Option<String>,
     pub receipt_data: String,
-    pub product_id: String,
 }
 
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubmitReceiptQuery {
+    /// Verify the receipt with the store and return what it says, without
+    /// recording anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Verify and record a receipt.
+///
+/// Clients may send an `Idempotency-Key` header so retries after a dropped
+/// connection don't verify the receipt again; see [`idempotency::replay`].
+///
+/// With `?dry_run=true` the verified transaction is returned as the store
+/// reported it, with no subscriber, transaction or event written, for
+/// checking an app's store credentials.
+///
+/// [`idempotency::replay`]: crate::api::idempotency::replay
+#[tracing::instrument(skip_all, fields(
+    app_id = %input.app_id,
+    app_user_id = %input.app_user_id,
+    store = tracing::field::Empty,
+    store_transaction_id = tracing::field::Empty,
+))]
+#[utoipa::path(
+    post,
+    path = "/v1/receipts",
+    tag = "receipts",
+    params(
+        SubmitReceiptQuery,
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response to a retried request"),
+    ),
+    request_body = SubmitReceipt,
+    responses(
+        (status = 201, description = "The recorded transaction", body = Transaction),
+        (status = 200, description = "With `dry_run`, the transaction as the store reported it", body = VerifiedTransaction),
+        (status = 400, description = "Invalid receipt or unknown product"),
+        (status = 409, description = "The transaction belongs to another subscriber, or a request with the same Idempotency-Key is still in progress"),
+        (status = 422, description = "Idempotency key reused for a different request"),
+    ),
+)]
 pub async fn submit_receipt(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Query(query): Query<SubmitReceiptQuery>,
     Json(input): Json<SubmitReceipt>,
+) -> Result<Response, (StatusCode, String)> {
+    auth.authorize(&input.app_id)?;
+
+    if query.dry_run {
+        let (_, _, verified) = verify_receipt(&state, &input).await?;
+        return Ok(Json(verified).into_response());
+    }
+
+    Ok(process_receipt(&state, &input).await?.into_response())
+}
+
+/// Verify the receipt with the store and find the product it's for, without
+/// writing anything. Returns the app, the product's ID and the transaction.
+async fn verify_receipt(
+    state: &AppState,
+    input: &SubmitReceipt,
+) -> Result<(App, String, VerifiedTransaction), (StatusCode, String)> {
+    let app = sqlx::query_as::<_, App>("SELECT * FROM apps WHERE id = $1")
+        .bind(&input.app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "App not found".to_string()))?;
+
+    let store = input.store.as_deref().unwrap_or(app.platform.store().as_str());
+    tracing::Span::current().record("store", store);
+    let adapter = crate::store::adapter_for_app(&app, store, &state.cipher, &state.http, &state.apple_verifier, &state.apple_environments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let verified = adapter.verify_purchase(&input.receipt_data).await;
+    crate::metrics::record_store_verification(store, verified.is_ok());
+    let verified = verified
+        .map_err(|e| (crate::api::store_error_status(&e), format!("Receipt verification failed: {e}")))?;
+    tracing::Span::current().record("store_transaction_id", verified.store_transaction_id.as_str());
+
+    let product_id = sqlx::query_scalar::<_, String>(
+        "SELECT id FROM products WHERE app_id = $1 AND store_product_id = $2"
+    )
+    .bind(&app.id)
+    .bind(&verified.product_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::BAD_REQUEST, format!("Unknown product: {}", verified.product_id)))?;
+
+    Ok((app, product_id, verified))
+}
+
+async fn process_receipt(
+    state: &AppState,
+    input: &SubmitReceipt,
 ) -> Result<(StatusCode, Json<Transaction>), (StatusCode, String)> {
+    let (app, product_id, verified) = verify_receipt(state, input).await?;
+
     let subscriber_id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
 
+    // The subscriber, transaction and event are recorded together or not at all
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     sqlx::query(
-        "INSERT OR IGNORE INTO subscribers (id, app_id, app_user_id, created_at) VALUES (?, ?, ?, ?)"
+        "INSERT INTO subscribers (id, app_id, app_user_id, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT DO NOTHING"
     )
     .bind(&subscriber_id)
     .bind(&input.app_id)
     .bind(&input.app_user_id)
     .bind(&now)
-    .execute(&state.pool)
+    .execute(&mut *tx)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let subscriber = sqlx::query_as::<_, Subscriber>(
-        "SELECT * FROM subscribers WHERE app_id = ? AND app_user_id = ?"
+        "SELECT * FROM subscribers WHERE app_id = $1 AND app_user_id = $2"
     )
     .bind(&input.app_id)
     .bind(&input.app_user_id)
-    .fetch_one(&state.pool)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // TODO: In Phase 3, this will call the store adapter to verify the receipt.
-    // For now, create a transaction with status 'active' (placeholder).
-    let tx_id = uuid::Uuid::new_v4().to_string();
-    let store_tx_id = format!("pending_verification_{tx_id}");
+    // A purchase starting a subscription chain we haven't seen is the initial
+    // one; anything else in a known chain renews it
+    let chain_id = verified.original_transaction_id.as_deref().unwrap_or(&verified.store_transaction_id);
+    let known_chain = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM transactions WHERE store = $1 AND (original_transaction_id = $2 OR store_transaction_id = $2)"
+    )
+    .bind(verified.store.as_str())
+    .bind(chain_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        > 0;
+
+    let upserted = upsert_transaction(&mut tx, &subscriber.id, &product_id, &verified, &input.receipt_data, &now).await?;
 
-    sqlx::query(
-        "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status, raw_receipt, created_at, updated_at)
-         VALUES (?, ?, ?, ?, ?, ?, 'active', ?, ?, ?)"
+    // Resubmitting a receipt that changes nothing isn't news to anyone
+    let mut event_id = None;
+    if upserted.changed {
+        let event = TransactionEvent {
+            event_type: if known_chain { "RENEWAL" } else { "INITIAL_PURCHASE" }.to_string(),
+            transaction: verified,
+            renewal_info: None,
+        };
+        let payload = event_payload(&event)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        event_id = Some(
+            crate::events::record_event(&mut tx, &app.id, Some(&subscriber.id), &event.event_type, &payload)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        );
+    }
+
+    let transaction = sqlx::query_as::<_, Transaction>("SELECT * FROM transactions WHERE id = $1")
+        .bind(&upserted.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(event_id) = event_id {
+        state.events.publish_stored(&state.pool, &event_id).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    Ok((StatusCode::CREATED, Json(transaction)))
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct RestorePurchases {
+    /// Defaults to the store of the app's platform.
+    #[serde(default)]
+    pub store: Option<String>,
+    /// An Apple app receipt, StoreKit 2 transaction IDs, Google purchase
+    /// tokens, or whatever else the store's receipts are.
+    pub receipts: Vec<String>,
+}
+
+/// Verify everything a reinstalled app can prove the user bought and record
+/// it for `app_user_id`. A transaction already on record is refreshed rather
+/// than duplicated, and products the app doesn't know are skipped. No events
+/// are emitted since nothing new was bought.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/subscribers/{app_user_id}/restore",
+    tag = "receipts",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("app_user_id" = String, Path, description = "The app's own ID for the user"),
+    ),
+    request_body = RestorePurchases,
+    responses(
+        (status = 200, body = SubscriberInfo),
+        (status = 400, description = "No receipts, or one failed verification"),
+        (status = 404, description = "App not found"),
+        (status = 409, description = "A purchase belongs to another subscriber"),
+    ),
+)]
+pub async fn restore_purchases(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, app_user_id)): Path<(String, String)>,
+    Json(input): Json<RestorePurchases>,
+) -> Result<Json<SubscriberInfo>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    if input.receipts.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No receipts to restore".to_string()));
+    }
+
+    let app = sqlx::query_as::<_, App>("SELECT * FROM apps WHERE id = $1")
+        .bind(&app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "App not found".to_string()))?;
+
+    let store = input.store.as_deref().unwrap_or(app.platform.store().as_str());
+    let adapter = crate::store::adapter_for_app(&app, store, &state.cipher, &state.http, &state.apple_verifier, &state.apple_environments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let mut restored: Vec<(VerifiedTransaction, &str)> = Vec::new();
+    for receipt in &input.receipts {
+        let verified = adapter.verify_all_purchases(receipt).await;
+        crate::metrics::record_store_verification(store, verified.is_ok());
+        let verified = verified
+            .map_err(|e| (crate::api::store_error_status(&e), format!("Receipt verification failed: {e}")))?;
+        for transaction in verified {
+            if !restored.iter().any(|(t, _)| t.store_transaction_id == transaction.store_transaction_id) {
+                restored.push((transaction, receipt));
+            }
+        }
+    }
+
+    let (subscriber, _) = find_or_create_subscriber(&state.pool, &app_id, &app_user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut conn = state.pool.acquire().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for (verified, receipt) in &restored {
+        let product_id = sqlx::query_scalar::<_, String>(
+            "SELECT id FROM products WHERE app_id = $1 AND store_product_id = $2"
+        )
+        .bind(&app_id)
+        .bind(&verified.product_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let Some(product_id) = product_id else {
+            tracing::warn!(product_id = %verified.product_id, "Skipping restored purchase of unknown product");
+            continue;
+        };
+
+        upsert_transaction(&mut conn, &subscriber.id, &product_id, verified, receipt, &now).await?;
+    }
+
+    Ok(Json(subscriber_info(&state.pool, subscriber).await?))
+}
+
+/// Fetch the full App Store history of every Apple purchase on record for
+/// `app_user_id` and record what's missing, such as renewals whose
+/// notifications never arrived. As with a restore, no events are emitted.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/subscribers/{app_user_id}/backfill",
+    tag = "receipts",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("app_user_id" = String, Path, description = "The app's own ID for the user"),
+    ),
+    responses(
+        (status = 200, body = SubscriberInfo),
+        (status = 400, description = "No App Store purchases or credentials"),
+        (status = 404, description = "App or subscriber not found"),
+    ),
+)]
+pub async fn backfill_subscriber(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, app_user_id)): Path<(String, String)>,
+) -> Result<Json<SubscriberInfo>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let app = sqlx::query_as::<_, App>("SELECT * FROM apps WHERE id = $1")
+        .bind(&app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "App not found".to_string()))?;
+    let subscriber = find_subscriber(&state.pool, &app_id, &app_user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Subscriber not found".to_string()))?;
+
+    let purchases = sqlx::query_scalar::<_, String>(
+        "SELECT DISTINCT COALESCE(original_transaction_id, store_transaction_id) FROM transactions \
+         WHERE subscriber_id = $1 AND store = 'apple'"
     )
-    .bind(&tx_id)
     .bind(&subscriber.id)
-    .bind(&input.product_id)
-    .bind(&input.store)
-    .bind(&store_tx_id)
-    .bind(&now)
-    .bind(&input.receipt_data)
-    .bind(&now)
-    .bind(&now)
-    .execute(&state.pool)
+    .fetch_all(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if purchases.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Subscriber has no App Store purchases to backfill".to_string()));
+    }
 
-    let transaction = sqlx::query_as::<_, Transaction>("SELECT * FROM transactions WHERE id = ?")
-        .bind(&tx_id)
-        .fetch_one(&state.pool)
-        .await
+    let adapter = crate::store::apple_adapter_for_app(&app, &state.cipher, &state.http, &state.apple_verifier, &state.apple_environments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut conn = state.pool.acquire().await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok((StatusCode::CREATED, Json(transaction)))
+    for original_transaction_id in &purchases {
+        let history = adapter.get_transaction_history(original_transaction_id, None).await;
+        crate::metrics::record_store_verification("apple", history.is_ok());
+        let history = history
+            .map_err(|e| (crate::api::store_error_status(&e), format!("Fetching transaction history failed: {e}")))?;
+
+        for verified in &history.transactions {
+            let product_id = sqlx::query_scalar::<_, String>(
+                "SELECT id FROM products WHERE app_id = $1 AND store_product_id = $2"
+            )
+            .bind(&app_id)
+            .bind(&verified.product_id)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let Some(product_id) = product_id else {
+                tracing::warn!(product_id = %verified.product_id, "Skipping backfilled purchase of unknown product");
+                continue;
+            };
+
+            upsert_transaction(&mut conn, &subscriber.id, &product_id, verified, &verified.store_transaction_id, &now).await?;
+        }
+    }
+
+    Ok(Json(subscriber_info(&state.pool, subscriber).await?))
+}
+
+/// What [`upsert_transaction`] did with a verified transaction.
+struct UpsertedTransaction {
+    id: String,
+    /// The row is new, or its product, status or expiration changed.
+    changed: bool,
+}
+
+/// Record `verified` for a subscriber, updating the existing row when the
+/// store transaction was seen before, and retire the periods it renews. A
+/// transaction already recorded for another subscriber, in this app or
+/// another, is refused with 409 rather than taken over.
+async fn upsert_transaction(
+    conn: &mut AnyConnection,
+    subscriber_id: &str,
+    product_id: &str,
+    verified: &VerifiedTransaction,
+    raw_receipt: &str,
+    now: &str,
+) -> Result<UpsertedTransaction, (StatusCode, String)> {
+    upsert_owned_transaction(conn, subscriber_id, product_id, verified, raw_receipt, now)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::CONFLICT, "Transaction belongs to another subscriber".to_string()))
+}
+
+/// [`upsert_transaction`], with `None` when the transaction is someone else's.
+async fn upsert_owned_transaction(
+    conn: &mut AnyConnection,
+    subscriber_id: &str,
+    product_id: &str,
+    verified: &VerifiedTransaction,
+    raw_receipt: &str,
+    now: &str,
+) -> Result<Option<UpsertedTransaction>, sqlx::Error> {
+    let existing = sqlx::query_as::<_, (String, String, String, String, Option<String>)>(
+        "SELECT id, subscriber_id, product_id, status, expiration_date FROM transactions WHERE store = $1 AND store_transaction_id = $2"
+    )
+    .bind(verified.store.as_str())
+    .bind(&verified.store_transaction_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let upserted = match existing {
+        Some((_, owner, ..)) if owner != subscriber_id => return Ok(None),
+        Some((tx_id, _, old_product_id, old_status, old_expiration)) => {
+            sqlx::query(
+                "UPDATE transactions SET product_id = $1, purchase_date = $2, expiration_date = $3, status = $4, \
+                 raw_receipt = $5, environment = $6, period_type = $7, auto_renew = COALESCE($8, auto_renew), updated_at = $9, \
+                 original_transaction_id = COALESCE($10, original_transaction_id), auto_resume_time = $11 WHERE id = $12"
+            )
+            .bind(product_id)
+            .bind(&verified.purchase_date)
+            .bind(&verified.expiration_date)
+            .bind(verified.status.as_str())
+            .bind(raw_receipt)
+            .bind(verified.environment())
+            .bind(verified.period_type.as_str())
+            .bind(verified.auto_renew.map(i32::from))
+            .bind(now)
+            .bind(&verified.original_transaction_id)
+            .bind(&verified.auto_resume_time)
+            .bind(&tx_id)
+            .execute(&mut *conn)
+            .await?;
+            let changed = old_product_id != product_id
+                || old_status != verified.status.as_str()
+                || old_expiration != verified.expiration_date;
+            UpsertedTransaction { id: tx_id, changed }
+        }
+        None => {
+            let tx_id = uuid::Uuid::new_v4().to_string();
+            sqlx::query(
+                "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, original_transaction_id, purchase_date, expiration_date, status, raw_receipt, environment, period_type, auto_renew, auto_resume_time, created_at, updated_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)"
+            )
+            .bind(&tx_id)
+            .bind(subscriber_id)
+            .bind(product_id)
+            .bind(verified.store.as_str())
+            .bind(&verified.store_transaction_id)
+            .bind(&verified.original_transaction_id)
+            .bind(&verified.purchase_date)
+            .bind(&verified.expiration_date)
+            .bind(verified.status.as_str())
+            .bind(raw_receipt)
+            .bind(verified.environment())
+            .bind(verified.period_type.as_str())
+            .bind(verified.auto_renew.map(i32::from))
+            .bind(&verified.auto_resume_time)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *conn)
+            .await?;
+            UpsertedTransaction { id: tx_id, changed: true }
+        }
+    };
+
+    crate::transactions::supersede_earlier_periods(conn, verified, now).await?;
+    Ok(Some(upserted))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::api::AppState;
-    use crate::db;
+    use crate::test_support::{create_test_app, test_pool, test_state};
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
     use serde_json::Value;
     use tower::ServiceExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    async fn test_state() -> AppState {
-        let pool = db::connect("sqlite::memory:").await.unwrap();
-        AppState { pool }
-    }
-
-    async fn create_test_app(state: &AppState) -> String {
+    async fn create_test_product(state: &AppState, app_id: &str, api_key: &str) -> String {
         let app = crate::api::router(state.clone());
         let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/v1/apps")
+                    .uri(format!("/v1/apps/{app_id}/products"))
+                    .header("authorization", format!("Bearer {api_key}"))
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"name":"Test","platform":"ios","bundle_id":"com.test"}"#))
+                    .body(Body::from(r#"{"store_product_id":"com.test.pro","product_type":"subscription","entitlement_ids":[]}"#))
                     .unwrap(),
             )
             .await
@@ -103,58 +506,612 @@ mod tests {
         v["id"].as_str().unwrap().to_string()
     }
 
-    async fn create_test_product(state: &AppState, app_id: &str) -> String {
+    #[tokio::test]
+    async fn test_unverified_receipt_is_rejected() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+
+        let app = crate::api::router(state.clone());
+
+        // Submit receipt for an app without store credentials
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/receipts")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"app_id":"{app_id}","app_user_id":"user123","store":"apple","receipt_data":"fake"}}"#
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // No placeholder transaction or subscriber is created
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        let subscribers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subscribers")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(subscribers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_rejected() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let body = format!(
+            r#"{{"app_id":"{app_id}","app_user_id":"user123","store":"apple","receipt_data":"{}"}}"#,
+            "a".repeat(300 * 1024)
+        );
+
+        let request = |body: String, content_length: bool| {
+            let mut builder = Request::builder()
+                .method("POST")
+                .uri("/v1/receipts")
+                .header("authorization", format!("Bearer {api_key}"))
+                .header("content-type", "application/json");
+            if content_length {
+                builder = builder.header("content-length", body.len());
+            }
+            builder.body(Body::from(body)).unwrap()
+        };
+
+        // Refused up front from the declared length, or once reading passes the limit
+        for content_length in [true, false] {
+            let response = crate::api::router(state.clone())
+                .oneshot(request(body.clone(), content_length))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        // The limit is configurable
+        let limits = crate::api::RequestLimits { max_body_bytes: 64, ..Default::default() };
+        let small = format!(r#"{{"app_id":"{app_id}","app_user_id":"user123","store":"apple","receipt_data":"fake"}}"#);
+        let response = crate::api::router_with_limits(state.clone(), limits)
+            .oneshot(request(small, true))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    /// Point the app's Amazon credentials at a mock RVS.
+    async fn configure_amazon(state: &AppState, app_id: &str, api_key: &str, rvs_base: &str) {
         let app = crate::api::router(state.clone());
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/v1/apps/{app_id}/credentials"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"amazon":{{"shared_secret":"secret","rvs_base_url":"{rvs_base}"}}}}"#
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+    }
+
+    async fn submit(state: &AppState, app_id: &str, api_key: &str, receipt_id: &str, key: &str) -> (StatusCode, Value) {
+        let app = crate::api::router(state.clone());
+        let receipt_data = serde_json::json!({"user_id": "amzn1", "receipt_id": receipt_id}).to_string();
+        let body = serde_json::json!({
+            "app_id": app_id,
+            "app_user_id": "user123",
+            "store": "amazon",
+            "receipt_data": receipt_data,
+        });
         let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri(&format!("/v1/apps/{app_id}/products"))
+                    .uri("/v1/receipts")
+                    .header("authorization", format!("Bearer {api_key}"))
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"store_product_id":"com.test.pro","product_type":"subscription","entitlement_ids":[]}"#))
+                    .header("idempotency-key", key)
+                    .body(Body::from(body.to_string()))
                     .unwrap(),
             )
             .await
             .unwrap();
+        let status = resp.status();
         let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let v: Value = serde_json::from_slice(&body).unwrap();
-        v["id"].as_str().unwrap().to_string()
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn rvs_receipt(receipt_id: &str) -> Value {
+        serde_json::json!({
+            "receiptId": receipt_id,
+            "productId": "com.test.pro",
+            "purchaseDate": 1767225600000_i64,
+            "renewalDate": 4102444800000_i64,
+            "cancelDate": null,
+            "testTransaction": false
+        })
     }
 
     #[tokio::test]
-    async fn test_submit_receipt_and_get_subscriber() {
-        let state = test_state().await;
-        let app_id = create_test_app(&state).await;
-        let product_id = create_test_product(&state, &app_id).await;
+    async fn test_idempotency_key_replays_first_response() {
+        let server = MockServer::start().await;
+        for receipt_id in ["r1", "r2"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/{receipt_id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt(receipt_id)))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
 
-        let app = crate::api::router(state);
+        let (status, first) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
 
-        // Submit receipt
-        let response = app.clone()
+        // A retry with the same key replays the stored response without hitting RVS again
+        let (status, replayed) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(replayed, first);
+
+        // Reusing the key for another receipt is refused
+        let (status, _) = submit(&state, &app_id, &api_key, "r2", "key-1").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, second) = submit(&state, &app_id, &api_key, "r2", "key-2").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(second["id"], first["id"]);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_receipt_submit_queues_webhook_deliveries() {
+        let server = MockServer::start().await;
+        for receipt_id in ["r1", "r2"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/{receipt_id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt(receipt_id)))
+                .mount(&server)
+                .await;
+        }
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+        sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ('wh', $1, 'https://example.com/hook', 'secret')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let (status, _) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = submit(&state, &app_id, &api_key, "r2", "key-2").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let deliveries = sqlx::query_as::<_, (String, String)>(
+            "SELECT e.event_type, wd.status FROM webhook_deliveries wd
+             JOIN events e ON e.id = wd.event_id
+             WHERE wd.webhook_endpoint_id = 'wh'
+             ORDER BY e.created_at, e.event_type"
+        )
+        .fetch_all(&state.pool)
+        .await
+        .unwrap();
+        // Unrelated receipts each start a subscription of their own
+        assert_eq!(deliveries, vec![
+            ("INITIAL_PURCHASE".to_string(), "pending".to_string()),
+            ("INITIAL_PURCHASE".to_string(), "pending".to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_resubmitted_receipt_emits_no_second_event() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt("r1")))
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+        sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ('wh', $1, 'https://example.com/hook', 'secret')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let (status, first) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, again) = submit(&state, &app_id, &api_key, "r1", "key-2").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(again["id"], first["id"]);
+
+        for (table, expected) in [("events", 1), ("webhook_deliveries", 1), ("transactions", 1)] {
+            let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+            assert_eq!(rows, expected, "{table}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_failures_map_to_status_codes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/bogus"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/outage"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+
+        // A receipt the store rejects is the client's problem
+        let (status, _) = submit(&state, &app_id, &api_key, "bogus", "key-1").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // The store being down isn't
+        let (status, _) = submit(&state, &app_id, &api_key, "outage", "key-2").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+
+    /// Collects the fields recorded on each span, by span name.
+    #[derive(Clone, Default)]
+    struct SpanFields(std::sync::Arc<std::sync::Mutex<Vec<(String, String, String)>>>);
+
+    impl SpanFields {
+        fn get(&self, span: &str, field: &str) -> Option<String> {
+            self.0.lock().unwrap().iter()
+                .find(|(s, f, _)| s == span && f == field)
+                .map(|(_, _, value)| value.clone())
+        }
+    }
+
+    struct FieldVisitor<'a>(&'a SpanFields, &'static str);
+
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.0.lock().unwrap().push((self.1.to_string(), field.name().to_string(), format!("{value:?}")));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.0.lock().unwrap().push((self.1.to_string(), field.name().to_string(), value.to_string()));
+        }
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for SpanFields
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, _: &tracing::span::Id, _: tracing_subscriber::layer::Context<'_, S>) {
+            attrs.record(&mut FieldVisitor(self, attrs.metadata().name()));
+        }
+
+        fn on_record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+            if let Some(span) = ctx.span(id) {
+                values.record(&mut FieldVisitor(self, span.name()));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_receipt_span_carries_app_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let fields = SpanFields::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(fields.clone()));
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt("r1")))
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+
+        let (status, _) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        assert_eq!(fields.get("submit_receipt", "app_id").as_deref(), Some(app_id.as_str()));
+        assert_eq!(fields.get("submit_receipt", "app_user_id").as_deref(), Some("user123"));
+        assert_eq!(fields.get("submit_receipt", "store").as_deref(), Some("amazon"));
+        assert_eq!(fields.get("submit_receipt", "store_transaction_id").as_deref(), Some("r1"));
+    }
+
+    #[tokio::test]
+    async fn test_store_defaults_to_the_app_platform() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt("r1")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+        sqlx::query("UPDATE apps SET platform = 'amazon' WHERE id = $1")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let body = serde_json::json!({
+            "app_id": app_id,
+            "app_user_id": "user123",
+            "receipt_data": serde_json::json!({"user_id": "amzn1", "receipt_id": "r1"}).to_string(),
+        });
+        let resp = crate::api::router(state.clone())
             .oneshot(
                 Request::builder()
                     .method("POST")
                     .uri("/v1/receipts")
+                    .header("authorization", format!("Bearer {api_key}"))
                     .header("content-type", "application/json")
-                    .body(Body::from(format!(
-                        r#"{{"app_id":"{app_id}","app_user_id":"user123","store":"apple","receipt_data":"fake","product_id":"{product_id}"}}"#
-                    )))
+                    .body(Body::from(body.to_string()))
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let transaction: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(transaction["store"], "amazon");
+    }
 
-        // Get subscriber
-        let response = app
+    #[tokio::test]
+    async fn test_dry_run_verifies_without_writing() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt("r1")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+
+        let body = serde_json::json!({
+            "app_id": app_id,
+            "app_user_id": "user123",
+            "store": "amazon",
+            "receipt_data": serde_json::json!({"user_id": "amzn1", "receipt_id": "r1"}).to_string(),
+        });
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/receipts?dry_run=true")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .header("idempotency-key", "key-1")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let verified: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(verified["store_transaction_id"], "r1");
+        assert_eq!(verified["product_id"], "com.test.pro");
+
+        for table in ["transactions", "subscribers", "events"] {
+            let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+            assert_eq!(rows, 0, "{table}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trial_purchase_is_recorded_as_trial() {
+        let server = MockServer::start().await;
+        let mut receipt = rvs_receipt("r1");
+        receipt["freeTrialEndDate"] = serde_json::json!(4102444800000_i64);
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(receipt))
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+
+        let (status, transaction) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(transaction["period_type"], "trial");
+
+        let payload: String = sqlx::query_scalar("SELECT payload FROM events WHERE event_type = 'INITIAL_PURCHASE'")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["transaction"]["period_type"], "trial");
+    }
+
+    #[tokio::test]
+    async fn test_restore_records_every_purchase_once() {
+        let server = MockServer::start().await;
+        for (receipt_id, product_id) in [("r1", "com.test.pro"), ("r2", "com.test.lifetime")] {
+            let mut receipt = rvs_receipt(receipt_id);
+            receipt["productId"] = serde_json::json!(product_id);
+            Mock::given(method("GET"))
+                .and(path(format!("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/{receipt_id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(receipt))
+                .mount(&server)
+                .await;
+        }
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type, created_at) VALUES ('lifetime', $1, 'com.test.lifetime', 'non_consumable', $2)")
+            .bind(&app_id)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+
+        // One of the purchases is already on record from before the reinstall
+        let (status, _) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let receipts: Vec<String> = ["r1", "r2", "r2"].iter()
+            .map(|id| serde_json::json!({"user_id": "amzn1", "receipt_id": id}).to_string())
+            .collect();
+        let response = crate::api::router(state.clone())
             .oneshot(
                 Request::builder()
-                    .uri("/v1/subscribers/user123")
-                    .body(Body::empty())
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/subscribers/user123/restore"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::json!({ "store": "amazon", "receipts": receipts }).to_string()))
                     .unwrap(),
             )
             .await
             .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+
+        let mut restored: Vec<_> = v["transactions"].as_array().unwrap().iter()
+            .map(|t| t["store_transaction_id"].as_str().unwrap())
+            .collect();
+        restored.sort();
+        assert_eq!(restored, vec!["r1", "r2"]);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_verified_receipt_is_recorded() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt("r1")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+
+        let (status, transaction) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(transaction["store"], "amazon");
+        assert_eq!(transaction["store_transaction_id"], "r1");
+        assert_eq!(transaction["product_id"], product_id.as_str());
+        assert_eq!(transaction["status"], "active");
+
+        let (subscriber_app, app_user_id): (String, String) = sqlx::query_as(
+            "SELECT s.app_id, s.app_user_id FROM transactions t JOIN subscribers s ON s.id = t.subscriber_id"
+        )
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+        assert_eq!((subscriber_app.as_str(), app_user_id.as_str()), (app_id.as_str(), "user123"));
+        let event_type: String = sqlx::query_scalar("SELECT event_type FROM events")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(event_type, "INITIAL_PURCHASE");
+    }
+
+    #[tokio::test]
+    async fn test_another_subscribers_transaction_is_not_taken_over() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(rvs_receipt("r1")))
+            .mount(&server)
+            .await;
+
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        create_test_product(&state, &app_id, &api_key).await;
+        configure_amazon(&state, &app_id, &api_key, &server.uri()).await;
+        let (other_app_id, other_api_key) = create_test_app(&state, "com.other").await;
+        create_test_product(&state, &other_app_id, &other_api_key).await;
+        configure_amazon(&state, &other_app_id, &other_api_key, &server.uri()).await;
+
+        let (status, first) = submit(&state, &app_id, &api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _) = submit(&state, &other_app_id, &other_api_key, "r1", "key-1").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (subscriber_id, product_id): (String, String) = sqlx::query_as("SELECT subscriber_id, product_id FROM transactions")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(product_id, first["product_id"].as_str().unwrap());
+        let owner: String = sqlx::query_scalar("SELECT app_id FROM subscribers WHERE id = $1")
+            .bind(&subscriber_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(owner, app_id);
+
+        // Nothing from the refused submission is left behind
+        for (table, expected) in [("events", 1), ("subscribers", 1)] {
+            let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+            assert_eq!(rows, expected, "{table}");
+        }
     }
 }
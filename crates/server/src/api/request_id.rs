@@ -0,0 +1,87 @@
+use std::time::Instant;
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied request id we pass through; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Tag each request with an id, taken from `X-Request-Id` when the caller (or
+/// a proxy in front of us) sent a usable one, and echo it on the response.
+///
+/// Everything logged while handling the request happens inside a span
+/// carrying the id, so the lines for one request can be pulled out of the
+/// logs. The span's `app_id` is filled in once the API key is checked.
+pub async fn propagate(request: Request, next: Next) -> Response {
+    let request_id = request.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        app_id = tracing::field::Empty,
+    );
+    let start = Instant::now();
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    span.in_scope(|| {
+        tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms = start.elapsed().as_millis() as u64,
+            "request completed"
+        );
+    });
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_request_id_is_echoed_or_generated() {
+        let state = test_state(test_pool().await);
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(Request::builder().uri("/health").header("x-request-id", "req-abc123").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()["x-request-id"], "req-abc123");
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(Request::builder().uri("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let generated = resp.headers()["x-request-id"].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+        // Ids that can't be logged safely are replaced
+        let resp = crate::api::router(state)
+            .oneshot(Request::builder().uri("/health").header("x-request-id", "a b").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_ne!(resp.headers()["x-request-id"], "a b");
+    }
+}
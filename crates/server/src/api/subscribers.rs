@@ -1,52 +1,1426 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json};
-use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use axum::{extract::{Path, Query, State}, http::{header, StatusCode}, response::IntoResponse, Json};
+use axum::body::Body;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::AnyConnection;
 use crate::api::AppState;
-use crate::models::subscriber::Subscriber;
-use crate::models::entitlement::Entitlement;
+use crate::api::auth::AuthenticatedApp;
+use crate::api::events::{decode_cursor, encode_cursor};
+use crate::db::DbPool;
+use crate::models::subscriber::{AttributeUpdate, Subscriber, SubscriberAttribute};
+use crate::models::entitlement::{ActiveEntitlement, EntitlementStatus};
 use crate::models::transaction::Transaction;
 
-#[derive(Serialize)]
+#[derive(Serialize, utoipa::ToSchema)]
 pub struct SubscriberInfo {
     pub subscriber: Subscriber,
-    pub active_entitlements: Vec<Entitlement>,
+    pub active_entitlements: Vec<ActiveEntitlement>,
     pub transactions: Vec<Transaction>,
+    /// Keyed by entitlement name.
+    pub subscription_status: BTreeMap<String, EntitlementStatus>,
+    pub attributes: BTreeMap<String, SubscriberAttribute>,
 }
 
-pub async fn get_subscriber(
-    State(state): State<AppState>,
-    Path(app_user_id): Path<String>,
-) -> Result<Json<SubscriberInfo>, (StatusCode, String)> {
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscribersQuery {
+    /// Opaque `next_cursor` from a previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct SubscriberSummary {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub subscriber: Subscriber,
+    pub active_entitlement_count: i64,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct SubscribersPage {
+    pub subscribers: Vec<SubscriberSummary>,
+    /// Pass back as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteSubscriberQuery {
+    /// Keep the subscriber's transactions for revenue reporting and scrub its
+    /// identity instead of deleting everything.
+    #[serde(default)]
+    pub anonymize: bool,
+}
+
+/// Rows removed by a subscriber deletion.
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct DeletedSubscriber {
+    pub subscriber_id: String,
+    pub anonymized: bool,
+    pub transactions: u64,
+    pub events: u64,
+    pub webhook_deliveries: u64,
+    pub aliases: u64,
+    pub attributes: u64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct CreateSubscriber {
+    pub app_user_id: String,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct AliasSubscriber {
+    pub new_app_user_id: String,
+}
+
+/// Find an app's subscriber by app_user_id, following aliases left behind by merges.
+pub(crate) async fn find_subscriber(pool: &DbPool, app_id: &str, app_user_id: &str) -> Result<Option<Subscriber>, sqlx::Error> {
     let subscriber = sqlx::query_as::<_, Subscriber>(
-        "SELECT * FROM subscribers WHERE app_user_id = ?"
+        "SELECT * FROM subscribers WHERE app_id = $1 AND app_user_id = $2"
     )
-    .bind(&app_user_id)
-    .fetch_optional(&state.pool)
+    .bind(app_id)
+    .bind(app_user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if subscriber.is_some() {
+        return Ok(subscriber);
+    }
+
+    sqlx::query_as::<_, Subscriber>(
+        "SELECT s.* FROM subscribers s
+         JOIN subscriber_aliases sa ON sa.subscriber_id = s.id
+         WHERE sa.app_id = $1 AND sa.alias = $2"
+    )
+    .bind(app_id)
+    .bind(app_user_id)
+    .fetch_optional(pool)
     .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-    .ok_or((StatusCode::NOT_FOUND, "Subscriber not found".to_string()))?;
+}
+
+/// Find an app's subscriber like [`find_subscriber`], creating it if there is
+/// none. The flag is whether it was created.
+pub(crate) async fn find_or_create_subscriber(pool: &DbPool, app_id: &str, app_user_id: &str) -> Result<(Subscriber, bool), sqlx::Error> {
+    if let Some(subscriber) = find_subscriber(pool, app_id, app_user_id).await? {
+        return Ok((subscriber, false));
+    }
 
+    let created = sqlx::query(
+        "INSERT INTO subscribers (id, app_id, app_user_id, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(app_id)
+    .bind(app_user_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?
+    .rows_affected() > 0;
+
+    let subscriber = sqlx::query_as::<_, Subscriber>("SELECT * FROM subscribers WHERE app_id = $1 AND app_user_id = $2")
+        .bind(app_id)
+        .bind(app_user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok((subscriber, created))
+}
+
+pub(crate) async fn subscriber_info(pool: &DbPool, subscriber: Subscriber) -> Result<SubscriberInfo, (StatusCode, String)> {
     let transactions = sqlx::query_as::<_, Transaction>(
-        "SELECT * FROM transactions WHERE subscriber_id = ? ORDER BY purchase_date DESC"
+        "SELECT t.* FROM transactions t
+         JOIN products p ON p.id = t.product_id
+         WHERE t.subscriber_id = $1 AND p.app_id = $2
+         ORDER BY t.purchase_date DESC"
     )
     .bind(&subscriber.id)
-    .fetch_all(&state.pool)
+    .bind(&subscriber.app_id)
+    .fetch_all(pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let active_entitlements = sqlx::query_as::<_, Entitlement>(
-        "SELECT DISTINCT e.* FROM entitlements e
+    let now = chrono::Utc::now().to_rfc3339();
+
+    // A NULL expiration_date (lifetime purchase) wins over any dated one.
+    let active_entitlements = sqlx::query_as::<_, ActiveEntitlement>(&format!(
+        "SELECT e.*,
+                CASE WHEN COUNT(t.expiration_date) < COUNT(*) THEN NULL
+                     ELSE MAX(t.expiration_date) END AS expires_at
+         FROM entitlements e
          JOIN product_entitlements pe ON e.id = pe.entitlement_id
          JOIN transactions t ON pe.product_id = t.product_id
-         WHERE t.subscriber_id = ? AND t.status = 'active'"
+         WHERE t.subscriber_id = $1 AND e.app_id = $2 AND {}
+         GROUP BY e.id",
+        entitling("$3")
+    ))
+    .bind(&subscriber.id)
+    .bind(&subscriber.app_id)
+    .bind(&now)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let granting = sqlx::query_as::<_, GrantingTransaction>(
+        "SELECT e.name AS entitlement, p.store_product_id, t.store, t.status, t.expiration_date, t.period_type,
+                t.auto_renew, t.grace_period_expires_date, t.auto_resume_time
+         FROM entitlements e
+         JOIN product_entitlements pe ON e.id = pe.entitlement_id
+         JOIN products p ON p.id = pe.product_id
+         JOIN transactions t ON t.product_id = pe.product_id
+         WHERE t.subscriber_id = $1 AND e.app_id = $2 AND t.status != 'refunded'"
     )
     .bind(&subscriber.id)
-    .fetch_all(&state.pool)
+    .bind(&subscriber.app_id)
+    .fetch_all(pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(SubscriberInfo {
+    let attributes = load_attributes(pool, &subscriber.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(SubscriberInfo {
         subscriber,
         active_entitlements,
         transactions,
+        subscription_status: subscription_status(granting, &now),
+        attributes,
+    })
+}
+
+/// A non-refunded transaction and an entitlement it grants.
+#[derive(sqlx::FromRow)]
+struct GrantingTransaction {
+    entitlement: String,
+    store_product_id: String,
+    store: String,
+    status: String,
+    expiration_date: Option<String>,
+    period_type: String,
+    auto_renew: Option<i32>,
+    grace_period_expires_date: Option<String>,
+    auto_resume_time: Option<String>,
+}
+
+/// SQL condition on `t` that holds while the transaction grants its
+/// entitlements at `now`: active and unexpired, or in a billing grace period
+/// that hasn't ended. Mirrors [`GrantingTransaction::is_active`].
+fn entitling(now: &str) -> String {
+    format!(
+        "((t.status = 'active' AND (t.expiration_date IS NULL OR t.expiration_date > {now}))
+          OR (t.status = 'grace_period' AND (t.grace_period_expires_date IS NULL OR t.grace_period_expires_date > {now})))"
+    )
+}
+
+impl GrantingTransaction {
+    /// Whether this transaction grants its entitlement at `now`; the same
+    /// rule as [`entitling`].
+    fn is_active(&self, now: &str) -> bool {
+        match self.status.as_str() {
+            "active" => self.expiration_date.as_deref().is_none_or(|e| e > now),
+            "grace_period" => self.grace_period_expires_date.as_deref().is_none_or(|e| e > now),
+            _ => false,
+        }
+    }
+
+    /// Transactions still granting access outrank lapsing ones, then the one
+    /// that runs longest wins.
+    fn precedence(&self, now: &str) -> (u8, bool, Option<&str>) {
+        let standing = match self.status.as_str() {
+            "active" if self.is_active(now) => 4,
+            "grace_period" => 3,
+            "billing_retry" => 2,
+            "paused" => 1,
+            _ => 0,
+        };
+        (standing, self.expiration_date.is_none(), self.expiration_date.as_deref())
+    }
+}
+
+/// Summarize each entitlement from the transaction that best describes it.
+fn subscription_status(granting: Vec<GrantingTransaction>, now: &str) -> BTreeMap<String, EntitlementStatus> {
+    let mut by_entitlement: BTreeMap<String, Vec<GrantingTransaction>> = BTreeMap::new();
+    for transaction in granting {
+        by_entitlement.entry(transaction.entitlement.clone()).or_default().push(transaction);
+    }
+
+    by_entitlement.into_iter()
+        .filter_map(|(entitlement, transactions)| {
+            let expires_at = if transactions.iter().any(|t| t.expiration_date.is_none()) {
+                None
+            } else {
+                transactions.iter().filter_map(|t| t.expiration_date.clone()).max()
+            };
+            let current = transactions.into_iter().max_by(|a, b| a.precedence(now).cmp(&b.precedence(now)))?;
+
+            Some((entitlement, EntitlementStatus {
+                is_active: current.is_active(now),
+                expires_at,
+                in_grace_period: current.status == "grace_period" && current.is_active(now),
+                in_billing_retry: current.status == "billing_retry",
+                product_id: current.store_product_id,
+                store: current.store,
+                period_type: current.period_type,
+                will_renew: current.auto_renew.map(|renew| renew != 0),
+                grace_period_expires_at: current.grace_period_expires_date,
+                is_paused: current.status == "paused",
+                auto_resume_time: current.auto_resume_time,
+            }))
+        })
+        .collect()
+}
+
+/// A subscriber's attributes that currently have a value.
+async fn load_attributes(pool: &DbPool, subscriber_id: &str) -> Result<BTreeMap<String, SubscriberAttribute>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, String, i64)>(
+        "SELECT key, value, updated_at_ms FROM subscriber_attributes WHERE subscriber_id = $1 AND value IS NOT NULL"
+    )
+    .bind(subscriber_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter()
+        .map(|(key, value, updated_at_ms)| (key, SubscriberAttribute { value, updated_at_ms }))
+        .collect())
+}
+
+/// Up to `limit` of an app's subscribers after `after`, oldest first in
+/// `(created_at, id)` order, each with how many entitlements it has active.
+pub(crate) async fn list_subscribers_after(
+    pool: &DbPool,
+    app_id: &str,
+    after: Option<(String, String)>,
+    limit: i64,
+) -> Result<Vec<SubscriberSummary>, sqlx::Error> {
+    let position = if after.is_some() { "AND (s.created_at, s.id) > ($3, $4)" } else { "" };
+    let sql = format!(
+        "SELECT s.*,
+                (SELECT COUNT(DISTINCT pe.entitlement_id)
+                 FROM transactions t
+                 JOIN product_entitlements pe ON pe.product_id = t.product_id
+                 WHERE t.subscriber_id = s.id AND {}) AS active_entitlement_count
+         FROM subscribers s
+         WHERE s.app_id = $1 {position}
+         ORDER BY s.created_at ASC, s.id ASC
+         LIMIT {limit}",
+        entitling("$2")
+    );
+
+    let mut query = sqlx::query_as::<_, SubscriberSummary>(&sql)
+        .bind(app_id)
+        .bind(chrono::Utc::now().to_rfc3339());
+    if let Some((created_at, id)) = after {
+        query = query.bind(created_at).bind(id);
+    }
+    query.fetch_all(pool).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/subscribers",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        SubscribersQuery,
+    ),
+    responses(
+        (status = 200, body = SubscribersPage),
+        (status = 400, description = "Invalid cursor"),
+    ),
+)]
+pub async fn list_subscribers(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Query(query): Query<SubscribersQuery>,
+) -> Result<Json<SubscribersPage>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let after = query.cursor.as_deref()
+        .map(|cursor| decode_cursor(cursor).ok_or((StatusCode::BAD_REQUEST, "Invalid cursor".to_string())))
+        .transpose()?;
+
+    // One extra row tells us whether another page follows
+    let mut subscribers = list_subscribers_after(&state.pool, &app_id, after, limit + 1)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|s| encode_cursor(&s.subscriber.created_at, &s.subscriber.id))
+    } else {
+        None
+    };
+
+    Ok(Json(SubscribersPage { subscribers, next_cursor }))
+}
+
+/// Subscribers fetched per query while exporting.
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+const EXPORT_HEADER: &str = "app_user_id,created_at,active_entitlements,latest_expiration\n";
+
+/// Quote a CSV field if it contains anything that would break the row.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// CSV rows for one batch of subscribers, with their active entitlement
+/// names (`;`-separated) and latest expiration across all transactions.
+async fn export_rows(pool: &DbPool, subscribers: &[Subscriber]) -> Result<String, sqlx::Error> {
+    // `$first, $first+1, ...`, one per subscriber
+    let placeholders = |first: usize| -> String {
+        (first..first + subscribers.len()).map(|i| format!("${i}")).collect::<Vec<_>>().join(", ")
+    };
+
+    let sql = format!(
+        "SELECT DISTINCT t.subscriber_id, e.name
+         FROM transactions t
+         JOIN product_entitlements pe ON pe.product_id = t.product_id
+         JOIN entitlements e ON e.id = pe.entitlement_id
+         WHERE t.subscriber_id IN ({}) AND {}
+         ORDER BY e.name",
+        placeholders(2),
+        entitling("$1")
+    );
+    let mut query = sqlx::query_as::<_, (String, String)>(&sql).bind(chrono::Utc::now().to_rfc3339());
+    for subscriber in subscribers {
+        query = query.bind(&subscriber.id);
+    }
+    let mut entitlements: HashMap<String, Vec<String>> = HashMap::new();
+    for (subscriber_id, name) in query.fetch_all(pool).await? {
+        entitlements.entry(subscriber_id).or_default().push(name);
+    }
+
+    let sql = format!(
+        "SELECT subscriber_id, MAX(expiration_date) FROM transactions WHERE subscriber_id IN ({}) GROUP BY subscriber_id",
+        placeholders(1)
+    );
+    let mut query = sqlx::query_as::<_, (String, Option<String>)>(&sql);
+    for subscriber in subscribers {
+        query = query.bind(&subscriber.id);
+    }
+    let expirations: HashMap<String, Option<String>> = query.fetch_all(pool).await?.into_iter().collect();
+
+    let mut rows = String::new();
+    for subscriber in subscribers {
+        let names = entitlements.get(&subscriber.id).map(|names| names.join(";")).unwrap_or_default();
+        let expiration = expirations.get(&subscriber.id).cloned().flatten().unwrap_or_default();
+        rows.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&subscriber.app_user_id),
+            csv_field(&subscriber.created_at),
+            csv_field(&names),
+            csv_field(&expiration),
+        ));
+    }
+    Ok(rows)
+}
+
+/// Stream every subscriber of the app as CSV, a batch at a time, so large
+/// exports never sit in memory.
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/subscribers/export.csv",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    responses(
+        (status = 200, description = "One row per subscriber", content_type = "text/csv", body = String),
+    ),
+)]
+pub async fn export_subscribers(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    // The cursor is the last exported subscriber; `None` once done
+    let batches = stream::unfold(Some(None::<(String, String)>), move |cursor| {
+        let pool = state.pool.clone();
+        let app_id = app_id.clone();
+        async move {
+            let after = cursor?;
+            let position = if after.is_some() { "AND (created_at, id) > ($2, $3)" } else { "" };
+            let sql = format!(
+                "SELECT * FROM subscribers WHERE app_id = $1 {position} ORDER BY created_at ASC, id ASC LIMIT {EXPORT_BATCH_SIZE}"
+            );
+            let mut query = sqlx::query_as::<_, Subscriber>(&sql).bind(&app_id);
+            if let Some((created_at, id)) = after {
+                query = query.bind(created_at).bind(id);
+            }
+
+            let batch = match query.fetch_all(&pool).await {
+                Ok(batch) if batch.is_empty() => return None,
+                Ok(batch) => batch,
+                Err(e) => return Some((Err(e), None)),
+            };
+            // A short batch means there's nothing after it
+            let next = if batch.len() as i64 == EXPORT_BATCH_SIZE {
+                batch.last().map(|s| Some((s.created_at.clone(), s.id.clone())))
+            } else {
+                None
+            };
+            Some((export_rows(&pool, &batch).await, next))
+        }
+    });
+    let body = stream::once(async { Ok(EXPORT_HEADER.to_string()) }).chain(batches);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"subscribers.csv\""),
+        ],
+        Body::from_stream(body),
+    ))
+}
+
+/// Register a subscriber before it has purchased anything, e.g. on first
+/// launch. Answers 201 when it was created and 200 when it already existed.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/subscribers",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+    ),
+    request_body = CreateSubscriber,
+    responses(
+        (status = 201, body = SubscriberInfo),
+        (status = 400, description = "Invalid app user ID"),
+    ),
+)]
+pub async fn create_subscriber(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Json(input): Json<CreateSubscriber>,
+) -> Result<(StatusCode, Json<SubscriberInfo>), (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    if input.app_user_id.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "app_user_id must not be empty".to_string()));
+    }
+
+    let (subscriber, created) = find_or_create_subscriber(&state.pool, &app_id, &input.app_user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+
+    Ok((status, Json(subscriber_info(&state.pool, subscriber).await?)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/subscribers/{app_user_id}",
+    tag = "subscribers",
+    params(
+        ("app_user_id" = String, Path, description = "The app's own ID for the user"),
+    ),
+    responses(
+        (status = 200, body = SubscriberInfo),
+        (status = 404, description = "Subscriber not found"),
+    ),
+)]
+pub async fn get_subscriber(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_user_id): Path<String>,
+) -> Result<Json<SubscriberInfo>, (StatusCode, String)> {
+    let subscriber = find_subscriber(&state.pool, &auth.app_id, &app_user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Subscriber not found".to_string()))?;
+
+    Ok(Json(subscriber_info(&state.pool, subscriber).await?))
+}
+
+/// Merge attributes into a subscriber, creating the subscriber if it doesn't
+/// exist yet. Each value only replaces what's stored if its timestamp is at
+/// least as new, so out-of-order writes from several devices settle on the
+/// latest one. Values without a timestamp are stamped with the current time.
+#[utoipa::path(
+    post,
+    path = "/v1/apps/{app_id}/subscribers/{app_user_id}/attributes",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("app_user_id" = String, Path, description = "The app's own ID for the user"),
+    ),
+    request_body = BTreeMap<String, AttributeUpdate>,
+    responses(
+        (status = 200, description = "All of the subscriber's attributes", body = BTreeMap<String, SubscriberAttribute>),
+        (status = 400, description = "Invalid attribute"),
+    ),
+)]
+pub async fn set_attributes(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, app_user_id)): Path<(String, String)>,
+    Json(input): Json<BTreeMap<String, AttributeUpdate>>,
+) -> Result<Json<BTreeMap<String, SubscriberAttribute>>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    if input.keys().any(|key| key.is_empty()) {
+        return Err((StatusCode::BAD_REQUEST, "Attribute keys must not be empty".to_string()));
+    }
+
+    let (subscriber, _) = find_or_create_subscriber(&state.pool, &app_id, &app_user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for (key, update) in input {
+        let (value, updated_at_ms) = match update {
+            AttributeUpdate::Timestamped { value, updated_at_ms } => (value, updated_at_ms),
+            AttributeUpdate::Value(value) => (value, now_ms),
+        };
+
+        sqlx::query(
+            "INSERT INTO subscriber_attributes (subscriber_id, key, value, updated_at_ms) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (subscriber_id, key) DO UPDATE SET value = excluded.value, updated_at_ms = excluded.updated_at_ms \
+             WHERE excluded.updated_at_ms >= subscriber_attributes.updated_at_ms"
+        )
+        .bind(&subscriber.id)
+        .bind(&key)
+        .bind(value)
+        .bind(updated_at_ms)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let attributes = load_attributes(&state.pool, &subscriber.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(attributes))
+}
+
+/// Remove a subscriber and everything recorded about them, for data deletion
+/// requests. `app_user_id` may also be one of the subscriber's aliases.
+///
+/// With `anonymize=true` the subscriber's transactions stay so revenue figures
+/// don't change, but its app_user_id is replaced, its aliases, attributes and
+/// events are removed, and stored receipts are cleared.
+#[utoipa::path(
+    delete,
+    path = "/v1/apps/{app_id}/subscribers/{app_user_id}",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("app_user_id" = String, Path, description = "The app's own ID for the user"),
+        DeleteSubscriberQuery,
+    ),
+    responses(
+        (status = 200, description = "Rows deleted", body = DeletedSubscriber),
+        (status = 404, description = "Subscriber not found"),
+    ),
+)]
+pub async fn delete_subscriber(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, app_user_id)): Path<(String, String)>,
+    Query(query): Query<DeleteSubscriberQuery>,
+) -> Result<Json<DeletedSubscriber>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let subscriber = find_subscriber(&state.pool, &app_id, &app_user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Subscriber not found".to_string()))?;
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Deleted explicitly rather than left to ON DELETE CASCADE so the counts can be reported
+    let webhook_deliveries = execute_for_subscriber(
+        &mut tx,
+        "DELETE FROM webhook_deliveries WHERE event_id IN (SELECT id FROM events WHERE subscriber_id = $1)",
+        &subscriber.id,
+    ).await?;
+    let events = execute_for_subscriber(&mut tx, "DELETE FROM events WHERE subscriber_id = $1", &subscriber.id).await?;
+    let aliases = execute_for_subscriber(&mut tx, "DELETE FROM subscriber_aliases WHERE subscriber_id = $1", &subscriber.id).await?;
+    let attributes = execute_for_subscriber(&mut tx, "DELETE FROM subscriber_attributes WHERE subscriber_id = $1", &subscriber.id).await?;
+
+    let transactions = if query.anonymize {
+        execute_for_subscriber(&mut tx, "UPDATE transactions SET raw_receipt = NULL WHERE subscriber_id = $1", &subscriber.id).await?;
+        0
+    } else {
+        execute_for_subscriber(&mut tx, "DELETE FROM transactions WHERE subscriber_id = $1", &subscriber.id).await?
+    };
+
+    if query.anonymize {
+        sqlx::query("UPDATE subscribers SET app_user_id = $1 WHERE id = $2")
+            .bind(format!("$anonymized:{}", uuid::Uuid::new_v4().simple()))
+            .bind(&subscriber.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    } else {
+        sqlx::query("DELETE FROM subscribers WHERE id = $1")
+            .bind(&subscriber.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!(
+        "Deleted subscriber {} of app {} (anonymized: {})",
+        subscriber.id, app_id, query.anonymize
+    );
+
+    Ok(Json(DeletedSubscriber {
+        subscriber_id: subscriber.id,
+        anonymized: query.anonymize,
+        transactions,
+        events,
+        webhook_deliveries,
+        aliases,
+        attributes,
     }))
 }
+
+async fn execute_for_subscriber(
+    conn: &mut AnyConnection,
+    statement: &str,
+    subscriber_id: &str,
+) -> Result<u64, (StatusCode, String)> {
+    sqlx::query(statement)
+        .bind(subscriber_id)
+        .execute(conn)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Merge the subscriber identified by `app_user_id` (typically anonymous) into
+/// `new_app_user_id`, moving its transactions and events across. Both may
+/// already have purchases; the target ends up with all of them.
+#[utoipa::path(
+    post,
+    path = "/v1/subscribers/{app_user_id}/alias",
+    tag = "subscribers",
+    params(
+        ("app_user_id" = String, Path, description = "The app's own ID for the user"),
+    ),
+    request_body = AliasSubscriber,
+    responses(
+        (status = 200, body = SubscriberInfo),
+        (status = 400, description = "Aliasing a subscriber to itself"),
+        (status = 404, description = "Subscriber not found"),
+    ),
+)]
+pub async fn alias_subscriber(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_user_id): Path<String>,
+    Json(input): Json<AliasSubscriber>,
+) -> Result<Json<SubscriberInfo>, (StatusCode, String)> {
+    if input.new_app_user_id == app_user_id {
+        return Err((StatusCode::BAD_REQUEST, "Cannot alias a subscriber to itself".to_string()));
+    }
+
+    let old = sqlx::query_as::<_, Subscriber>("SELECT * FROM subscribers WHERE app_id = $1 AND app_user_id = $2")
+        .bind(&auth.app_id)
+        .bind(&app_user_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Subscriber not found".to_string()))?;
+
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut tx = state.pool.begin().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query(
+        "INSERT INTO subscribers (id, app_id, app_user_id, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(&old.app_id)
+    .bind(&input.new_app_user_id)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let target = sqlx::query_as::<_, Subscriber>(
+        "SELECT * FROM subscribers WHERE app_id = $1 AND app_user_id = $2"
+    )
+    .bind(&old.app_id)
+    .bind(&input.new_app_user_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for statement in [
+        "UPDATE transactions SET subscriber_id = $1 WHERE subscriber_id = $2",
+        "UPDATE events SET subscriber_id = $1 WHERE subscriber_id = $2",
+        "UPDATE subscriber_aliases SET subscriber_id = $1 WHERE subscriber_id = $2",
+        "INSERT INTO subscriber_attributes (subscriber_id, key, value, updated_at_ms) \
+         SELECT $1, key, value, updated_at_ms FROM subscriber_attributes WHERE subscriber_id = $2 \
+         ON CONFLICT (subscriber_id, key) DO UPDATE SET value = excluded.value, updated_at_ms = excluded.updated_at_ms \
+         WHERE excluded.updated_at_ms > subscriber_attributes.updated_at_ms",
+    ] {
+        sqlx::query(statement)
+            .bind(&target.id)
+            .bind(&old.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    sqlx::query(
+        "INSERT INTO subscriber_aliases (app_id, alias, subscriber_id, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (app_id, alias) DO UPDATE SET subscriber_id = excluded.subscriber_id, created_at = excluded.created_at"
+    )
+    .bind(&old.app_id)
+    .bind(&old.app_user_id)
+    .bind(&target.id)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("DELETE FROM subscribers WHERE id = $1")
+        .bind(&old.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(subscriber_info(&state.pool, target).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::test_support::{create_test_app, test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /// Create a "com.test.pro" product granting a "pro" entitlement.
+    async fn create_test_product(state: &AppState, app_id: &str, api_key: &str) -> String {
+        let app = crate::api::router(state.clone());
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/entitlements"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"name":"pro","description":"Pro access"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        let ent_id = v["id"].as_str().unwrap().to_string();
+
+        let app = crate::api::router(state.clone());
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/products"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"store_product_id":"com.test.pro","product_type":"subscription","entitlement_ids":["{ent_id}"]}}"#
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        v["id"].as_str().unwrap().to_string()
+    }
+
+    /// Insert a subscriber with one active transaction, bypassing store verification.
+    async fn seed_subscriber(state: &AppState, app_id: &str, product_id: &str, app_user_id: &str) {
+        seed_subscriber_expiring(state, app_id, product_id, app_user_id, None).await;
+    }
+
+    async fn seed_subscriber_expiring(
+        state: &AppState,
+        app_id: &str,
+        product_id: &str,
+        app_user_id: &str,
+        expiration_date: Option<&str>,
+    ) {
+        let subscriber_id = uuid::Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ($1, $2, $3)")
+            .bind(&subscriber_id)
+            .bind(app_id)
+            .bind(app_user_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, expiration_date, status)
+             VALUES ($1, $2, $3, 'apple', $4, '2026-01-01T00:00:00Z', $5, 'active')"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&subscriber_id)
+        .bind(product_id)
+        .bind(format!("tx_{app_user_id}"))
+        .bind(expiration_date)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_subscriber_without_purchase() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let app = crate::api::router(state);
+
+        let create = || {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/v1/apps/{app_id}/subscribers"))
+                .header("authorization", format!("Bearer {api_key}"))
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"app_user_id":"first_launch"}"#))
+                .unwrap()
+        };
+        let response = app.clone().oneshot(create()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let created: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["subscriber"]["app_user_id"], "first_launch");
+
+        // Identifying again returns the same record
+        let response = app.clone().oneshot(create()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let again: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(again["subscriber"]["id"], created["subscriber"]["id"]);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/subscribers/first_launch")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["subscriber"]["id"], created["subscriber"]["id"]);
+        assert!(v["transactions"].as_array().unwrap().is_empty());
+        assert!(v["active_entitlements"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_alias_merges_transactions() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber(&state, &app_id, &product_id, "anon_1").await;
+        seed_subscriber(&state, &app_id, &product_id, "user_1").await;
+
+        let app = crate::api::router(state);
+
+        let response = app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/subscribers/anon_1/alias")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"new_app_user_id":"user_1"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for lookup in ["user_1", "anon_1"] {
+            let response = app.clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/v1/subscribers/{lookup}"))
+                        .header("authorization", format!("Bearer {api_key}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let v: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(v["subscriber"]["app_user_id"], "user_1");
+            assert_eq!(v["transactions"].as_array().unwrap().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_lookup_is_scoped_to_app() {
+        let state = test_state(test_pool().await);
+        let (app_a, key_a) = create_test_app(&state, "com.test.a").await;
+        let (app_b, key_b) = create_test_app(&state, "com.test.b").await;
+        let product_a = create_test_product(&state, &app_a, &key_a).await;
+        let product_b = create_test_product(&state, &app_b, &key_b).await;
+        seed_subscriber(&state, &app_a, &product_a, "user123").await;
+        seed_subscriber(&state, &app_b, &product_b, "user123").await;
+
+        let app = crate::api::router(state);
+
+        for (app_id, key) in [(&app_a, &key_a), (&app_b, &key_b)] {
+            let response = app.clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/v1/subscribers/user123")
+                        .header("authorization", format!("Bearer {key}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let v: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(v["subscriber"]["app_id"], app_id.as_str());
+            assert_eq!(v["transactions"].as_array().unwrap().len(), 1);
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/subscribers/user123")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_expired_transaction_grants_no_entitlement() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber_expiring(&state, &app_id, &product_id, "lapsed", Some("2020-01-01T00:00:00+00:00")).await;
+        seed_subscriber_expiring(&state, &app_id, &product_id, "current", Some("2999-01-01T00:00:00+00:00")).await;
+
+        let app = crate::api::router(state);
+
+        let mut entitlements = Vec::new();
+        for lookup in ["lapsed", "current"] {
+            let response = app.clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/v1/subscribers/{lookup}"))
+                        .header("authorization", format!("Bearer {api_key}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let v: Value = serde_json::from_slice(&body).unwrap();
+            entitlements.push(v["active_entitlements"].clone());
+        }
+
+        assert!(entitlements[0].as_array().unwrap().is_empty());
+        assert_eq!(entitlements[1][0]["name"], "pro");
+        assert_eq!(entitlements[1][0]["expires_at"], "2999-01-01T00:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_paused_transaction_grants_no_entitlement() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber_expiring(&state, &app_id, &product_id, "user_1", Some("2999-01-01T00:00:00Z")).await;
+        sqlx::query("UPDATE transactions SET status = 'paused', auto_resume_time = '2999-02-01T00:00:00Z'")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/subscribers/user_1")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let info: Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(info["active_entitlements"].as_array().unwrap().is_empty());
+        let pro = &info["subscription_status"]["pro"];
+        assert_eq!(pro["is_active"], false);
+        assert_eq!(pro["is_paused"], true);
+        assert_eq!(pro["auto_resume_time"], "2999-02-01T00:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_grace_period_transaction_keeps_entitlement() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber_expiring(&state, &app_id, &product_id, "user_1", Some("2020-01-01T00:00:00Z")).await;
+        sqlx::query("UPDATE transactions SET status = 'grace_period', grace_period_expires_date = '2999-01-01T00:00:00Z'")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let get = |uri: String| {
+            let app = crate::api::router(state.clone());
+            let api_key = api_key.clone();
+            async move {
+                let resp = app
+                    .oneshot(
+                        Request::builder()
+                            .uri(uri)
+                            .header("authorization", format!("Bearer {api_key}"))
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap()
+            }
+        };
+
+        let info: Value = serde_json::from_slice(&get("/v1/subscribers/user_1".to_string()).await).unwrap();
+        assert_eq!(info["active_entitlements"][0]["name"], "pro");
+        assert_eq!(info["subscription_status"]["pro"]["is_active"], true);
+        assert_eq!(info["subscription_status"]["pro"]["in_grace_period"], true);
+
+        let page: Value = serde_json::from_slice(&get(format!("/v1/apps/{app_id}/subscribers")).await).unwrap();
+        assert_eq!(page["subscribers"][0]["active_entitlement_count"], 1);
+
+        let csv = String::from_utf8(get(format!("/v1/apps/{app_id}/subscribers/export.csv")).await.to_vec()).unwrap();
+        assert!(csv.lines().nth(1).unwrap().contains(",pro,"));
+
+        // Once the grace period ends the entitlement goes with it.
+        sqlx::query("UPDATE transactions SET grace_period_expires_date = '2020-02-01T00:00:00Z'")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let info: Value = serde_json::from_slice(&get("/v1/subscribers/user_1".to_string()).await).unwrap();
+        assert!(info["active_entitlements"].as_array().unwrap().is_empty());
+        assert_eq!(info["subscription_status"]["pro"]["is_active"], false);
+        assert_eq!(info["subscription_status"]["pro"]["in_grace_period"], false);
+    }
+
+    #[tokio::test]
+    async fn test_list_subscribers_pages_through_all() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber(&state, &app_id, &product_id, "paying").await;
+        for i in 0..4 {
+            sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ($1, $2, $3)")
+                .bind(uuid::Uuid::new_v4().to_string())
+                .bind(&app_id)
+                .bind(format!("free_{i}"))
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+        let (other_app_id, _) = create_test_app(&state, "com.other").await;
+        seed_subscriber(&state, &other_app_id, &product_id, "elsewhere").await;
+
+        let mut seen = Vec::new();
+        let mut cursor: Option<String> = None;
+        let mut pages = 0;
+        loop {
+            let uri = match &cursor {
+                Some(c) => format!("/v1/apps/{app_id}/subscribers?limit=2&cursor={c}"),
+                None => format!("/v1/apps/{app_id}/subscribers?limit=2"),
+            };
+            let resp = crate::api::router(state.clone())
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header("authorization", format!("Bearer {api_key}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+            let page: Value = serde_json::from_slice(&body).unwrap();
+            pages += 1;
+
+            for s in page["subscribers"].as_array().unwrap() {
+                let expected = if s["app_user_id"] == "paying" { 1 } else { 0 };
+                assert_eq!(s["active_entitlement_count"], expected);
+                seen.push(s["app_user_id"].as_str().unwrap().to_string());
+            }
+            match page["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, 3);
+        seen.sort();
+        assert_eq!(seen, vec!["free_0", "free_1", "free_2", "free_3", "paying"]);
+    }
+
+    /// Seed "user_1" with a transaction, an alias, an event and a webhook delivery for it.
+    async fn seed_subscriber_history(state: &AppState, app_id: &str, product_id: &str) -> String {
+        seed_subscriber(state, app_id, product_id, "user_1").await;
+        let subscriber_id: String = sqlx::query_scalar("SELECT id FROM subscribers WHERE app_user_id = 'user_1'")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+
+        sqlx::query("INSERT INTO subscriber_aliases (app_id, alias, subscriber_id) VALUES ($1, 'anon_1', $2)")
+            .bind(app_id)
+            .bind(&subscriber_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO events (id, subscriber_id, event_type, payload) VALUES ('evt', $1, 'INITIAL_PURCHASE', '{}')")
+            .bind(&subscriber_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ('wh', $1, 'http://localhost/hook', 'secret')")
+            .bind(app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO webhook_deliveries (id, webhook_endpoint_id, event_id, status) VALUES ('del', 'wh', 'evt', 'pending')")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        subscriber_id
+    }
+
+    async fn count(state: &AppState, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(&state.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn delete_subscriber(state: &AppState, app_id: &str, api_key: &str, query: &str) -> (StatusCode, Value) {
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/v1/apps/{app_id}/subscribers/anon_1{query}"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_delete_subscriber_leaves_no_orphans() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        let subscriber_id = seed_subscriber_history(&state, &app_id, &product_id).await;
+
+        let (status, summary) = delete_subscriber(&state, &app_id, &api_key, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(summary["subscriber_id"], subscriber_id.as_str());
+        assert_eq!(summary["transactions"], 1);
+        assert_eq!(summary["events"], 1);
+        assert_eq!(summary["webhook_deliveries"], 1);
+        assert_eq!(summary["aliases"], 1);
+
+        for table in ["subscribers", "transactions", "events", "webhook_deliveries", "subscriber_aliases"] {
+            assert_eq!(count(&state, table).await, 0, "{table} still has rows");
+        }
+
+        let (status, _) = delete_subscriber(&state, &app_id, &api_key, "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_anonymize_subscriber_keeps_transactions() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        let subscriber_id = seed_subscriber_history(&state, &app_id, &product_id).await;
+
+        let (status, summary) = delete_subscriber(&state, &app_id, &api_key, "?anonymize=true").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(summary["anonymized"], true);
+        assert_eq!(summary["transactions"], 0);
+
+        let app_user_id: String = sqlx::query_scalar("SELECT app_user_id FROM subscribers WHERE id = $1")
+            .bind(&subscriber_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert!(!app_user_id.contains("user_1"));
+        assert_eq!(count(&state, "transactions").await, 1);
+        assert_eq!(count(&state, "subscriber_aliases").await, 0);
+        assert_eq!(count(&state, "events").await, 0);
+    }
+
+    async fn post_attributes(state: &AppState, app_id: &str, api_key: &str, body: Value) -> Value {
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/v1/apps/{app_id}/subscribers/user_1/attributes"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_attributes_keep_newest_value() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+
+        post_attributes(&state, &app_id, &api_key, serde_json::json!({
+            "$email": { "value": "new@example.com", "updated_at_ms": 2000 },
+            "$displayName": "Ada",
+        })).await;
+        let attributes = post_attributes(&state, &app_id, &api_key, serde_json::json!({
+            "$email": { "value": "old@example.com", "updated_at_ms": 1000 },
+        })).await;
+        assert_eq!(attributes["$email"]["value"], "new@example.com");
+        assert_eq!(attributes["$email"]["updated_at_ms"], 2000);
+
+        let attributes = post_attributes(&state, &app_id, &api_key, serde_json::json!({
+            "$email": { "value": "newest@example.com", "updated_at_ms": 3000 },
+            "$displayName": null,
+        })).await;
+        assert_eq!(attributes["$email"]["value"], "newest@example.com");
+        assert!(attributes.get("$displayName").is_none());
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/subscribers/user_1")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let info: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(info["attributes"]["$email"]["value"], "newest@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_subscription_status_rolls_up_trial() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber_expiring(&state, &app_id, &product_id, "user_1", Some("2020-01-01T00:00:00Z")).await;
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date,
+                                       expiration_date, status, period_type, auto_renew)
+             SELECT 'trial_tx', id, $1, 'apple', 'tx_trial', '2026-01-01T00:00:00Z', '2999-01-01T00:00:00Z', 'active', 'trial', 1
+             FROM subscribers WHERE app_user_id = 'user_1'"
+        )
+        .bind(&product_id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/subscribers/user_1")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let info: Value = serde_json::from_slice(&body).unwrap();
+
+        let pro = &info["subscription_status"]["pro"];
+        assert_eq!(pro["is_active"], true);
+        assert_eq!(pro["expires_at"], "2999-01-01T00:00:00Z");
+        assert_eq!(pro["period_type"], "trial");
+        assert_eq!(pro["will_renew"], true);
+        assert_eq!(pro["in_grace_period"], false);
+        assert_eq!(pro["in_billing_retry"], false);
+        assert_eq!(pro["product_id"], "com.test.pro");
+    }
+
+    #[tokio::test]
+    async fn test_export_subscribers_csv() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let product_id = create_test_product(&state, &app_id, &api_key).await;
+        seed_subscriber_expiring(&state, &app_id, &product_id, "user,123", Some("2099-01-01T00:00:00Z")).await;
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/apps/{app_id}/subscribers/export.csv"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "text/csv; charset=utf-8");
+        assert_eq!(resp.headers()["content-disposition"], "attachment; filename=\"subscribers.csv\"");
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "app_user_id,created_at,active_entitlements,latest_expiration");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("\"user,123\","));
+        assert!(lines[1].ends_with(",pro,2099-01-01T00:00:00Z"));
+    }
+}
@@ -0,0 +1,52 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Serialize;
+use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+use crate::models::transaction::Transaction;
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct SubscriptionHistory {
+    pub original_transaction_id: String,
+    /// Every period of the subscription, oldest first.
+    pub transactions: Vec<Transaction>,
+}
+
+/// The renewal chain started by `original_transaction_id`: the original
+/// purchase and each renewal recorded since.
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/subscriptions/{original_transaction_id}",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        ("original_transaction_id" = String, Path, description = "The transaction that started the subscription"),
+    ),
+    responses(
+        (status = 200, body = SubscriptionHistory),
+        (status = 404, description = "Subscription not found"),
+    ),
+)]
+pub async fn get_subscription(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path((app_id, original_transaction_id)): Path<(String, String)>,
+) -> Result<Json<SubscriptionHistory>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+
+    let transactions = sqlx::query_as::<_, Transaction>(
+        "SELECT t.* FROM transactions t JOIN subscribers s ON s.id = t.subscriber_id \
+         WHERE s.app_id = $1 AND (t.original_transaction_id = $2 OR t.store_transaction_id = $2) \
+         ORDER BY t.purchase_date, t.created_at"
+    )
+    .bind(&app_id)
+    .bind(&original_transaction_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if transactions.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Subscription not found".to_string()));
+    }
+
+    Ok(Json(SubscriptionHistory { original_transaction_id, transactions }))
+}
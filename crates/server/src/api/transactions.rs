@@ -0,0 +1,252 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
+use crate::api::events::{decode_cursor, encode_cursor};
+use crate::models::transaction::Transaction;
+use crate::store::types::Store;
+
+const TRANSACTION_STATUSES: &[&str] = &["active", "expired", "refunded", "grace_period", "billing_retry", "paused"];
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionsQuery {
+    /// `active`, `expired`, `refunded`, `grace_period`, `billing_retry` or `paused`.
+    pub status: Option<String>,
+    /// `apple`, `google`, `amazon` or `stripe`.
+    pub store: Option<String>,
+    /// The product's ID, or its identifier in the store.
+    pub product_id: Option<String>,
+    /// Only transactions purchased at or after this RFC 3339 time.
+    pub since: Option<String>,
+    /// Only transactions purchased before this RFC 3339 time.
+    pub until: Option<String>,
+    /// Opaque `next_cursor` from a previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct AppTransaction {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub transaction: Transaction,
+    /// The subscriber the transaction belongs to.
+    pub app_user_id: String,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct TransactionsPage {
+    pub transactions: Vec<AppTransaction>,
+    /// Pass back as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Search the app's transactions, newest first, for support: each filter
+/// given narrows the results.
+#[utoipa::path(
+    get,
+    path = "/v1/apps/{app_id}/transactions",
+    tag = "subscribers",
+    params(
+        ("app_id" = String, Path, description = "The app's ID"),
+        TransactionsQuery,
+    ),
+    responses(
+        (status = 200, body = TransactionsPage),
+        (status = 400, description = "Invalid cursor, status or store"),
+    ),
+)]
+pub async fn list_transactions(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(app_id): Path<String>,
+    Query(query): Query<TransactionsQuery>,
+) -> Result<Json<TransactionsPage>, (StatusCode, String)> {
+    auth.authorize(&app_id)?;
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    // Each filter adds its own placeholders, bound below in the same order
+    let mut conditions = vec!["s.app_id = $1".to_string()];
+    let mut params = vec![app_id];
+    if let Some(status) = &query.status {
+        if !TRANSACTION_STATUSES.contains(&status.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown status: {status}")));
+        }
+        params.push(status.clone());
+        conditions.push(format!("t.status = ${}", params.len()));
+    }
+    if let Some(store) = &query.store {
+        let store = Store::parse(store).ok_or((StatusCode::BAD_REQUEST, format!("Unknown store: {store}")))?;
+        params.push(store.as_str().to_string());
+        conditions.push(format!("t.store = ${}", params.len()));
+    }
+    if let Some(product_id) = &query.product_id {
+        params.push(product_id.clone());
+        conditions.push(format!("(p.id = ${0} OR p.store_product_id = ${0})", params.len()));
+    }
+    if let Some(since) = &query.since {
+        params.push(since.clone());
+        conditions.push(format!("t.purchase_date >= ${}", params.len()));
+    }
+    if let Some(until) = &query.until {
+        params.push(until.clone());
+        conditions.push(format!("t.purchase_date < ${}", params.len()));
+    }
+    if let Some(cursor) = &query.cursor {
+        let (created_at, id) = decode_cursor(cursor)
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+        params.extend([created_at, id]);
+        conditions.push(format!("(t.created_at, t.id) < (${}, ${})", params.len() - 1, params.len()));
+    }
+
+    // One extra row tells us whether another page follows
+    let sql = format!(
+        "SELECT t.*, s.app_user_id FROM transactions t
+         JOIN subscribers s ON s.id = t.subscriber_id
+         JOIN products p ON p.id = t.product_id
+         WHERE {}
+         ORDER BY t.created_at DESC, t.id DESC
+         LIMIT {}",
+        conditions.join(" AND "),
+        limit + 1,
+    );
+    let mut transactions_query = sqlx::query_as::<_, AppTransaction>(&sql);
+    for param in params {
+        transactions_query = transactions_query.bind(param);
+    }
+    let mut transactions = transactions_query
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let next_cursor = if transactions.len() as i64 > limit {
+        transactions.truncate(limit as usize);
+        transactions.last().map(|t| encode_cursor(&t.transaction.created_at, &t.transaction.id))
+    } else {
+        None
+    };
+
+    Ok(Json(TransactionsPage { transactions, next_cursor }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::test_support::{create_test_app, test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /// Two apps, the first with transactions across stores and statuses.
+    async fn seed(state: &AppState) -> (String, String) {
+        let (app_id, api_key) = create_test_app(state, "com.test").await;
+        let (other_app_id, _) = create_test_app(state, "com.other").await;
+
+        for (app_id, suffix) in [(&app_id, "test"), (&other_app_id, "other")] {
+            sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ($1, $2, $3, 'subscription')")
+                .bind(format!("prod_{suffix}"))
+                .bind(app_id)
+                .bind(format!("com.{suffix}.pro"))
+                .execute(&state.pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ($1, $2, $3)")
+                .bind(format!("sub_{suffix}"))
+                .bind(app_id)
+                .bind(format!("user_{suffix}"))
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+
+        for (id, subscriber, store, status, purchased) in [
+            ("tx_1", "sub_test", "apple", "active", "2026-01-01T00:00:00Z"),
+            ("tx_2", "sub_test", "google", "active", "2026-02-01T00:00:00Z"),
+            ("tx_3", "sub_test", "apple", "expired", "2026-03-01T00:00:00Z"),
+            ("tx_4", "sub_test", "apple", "active", "2026-04-01T00:00:00Z"),
+            ("tx_5", "sub_other", "apple", "active", "2026-05-01T00:00:00Z"),
+        ] {
+            let product = if subscriber == "sub_test" { "prod_test" } else { "prod_other" };
+            sqlx::query(
+                "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status, created_at)
+                 VALUES ($1, $2, $3, $4, $1, $5, $6, $5)"
+            )
+            .bind(id)
+            .bind(subscriber)
+            .bind(product)
+            .bind(store)
+            .bind(purchased)
+            .bind(status)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        }
+
+        (app_id, api_key)
+    }
+
+    async fn list(state: &AppState, api_key: &str, uri: &str) -> (StatusCode, Value) {
+        let response = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn ids(page: &Value) -> Vec<&str> {
+        page["transactions"].as_array().unwrap().iter().map(|t| t["id"].as_str().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_transactions_are_filtered_by_status_and_store() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = seed(&state).await;
+        let uri = format!("/v1/apps/{app_id}/transactions");
+
+        let (status, page) = list(&state, &api_key, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&page), vec!["tx_4", "tx_3", "tx_2", "tx_1"]);
+        assert_eq!(page["transactions"][0]["app_user_id"], "user_test");
+
+        let (_, page) = list(&state, &api_key, &format!("{uri}?status=active&store=apple")).await;
+        assert_eq!(ids(&page), vec!["tx_4", "tx_1"]);
+
+        let (_, page) = list(&state, &api_key, &format!("{uri}?store=google&product_id=com.test.pro")).await;
+        assert_eq!(ids(&page), vec!["tx_2"]);
+
+        let (_, page) = list(&state, &api_key, &format!("{uri}?since=2026-02-01T00:00:00Z&until=2026-04-01T00:00:00Z")).await;
+        assert_eq!(ids(&page), vec!["tx_3", "tx_2"]);
+
+        let (status, _) = list(&state, &api_key, &format!("{uri}?store=steam")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = list(&state, &api_key, &format!("{uri}?status=active%27%20OR%201%3D1%20--")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_transactions_page_through_all() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = seed(&state).await;
+
+        let mut seen = Vec::new();
+        let mut uri = format!("/v1/apps/{app_id}/transactions?limit=3");
+        loop {
+            let (status, page) = list(&state, &api_key, &uri).await;
+            assert_eq!(status, StatusCode::OK);
+            seen.extend(ids(&page).into_iter().map(String::from));
+            let Some(cursor) = page["next_cursor"].as_str() else { break };
+            uri = format!("/v1/apps/{app_id}/transactions?limit=3&cursor={cursor}");
+        }
+        assert_eq!(seen, vec!["tx_4", "tx_3", "tx_2", "tx_1"]);
+    }
+}
@@ -1,42 +1,94 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
 use serde::{Deserialize, Serialize};
 use crate::api::AppState;
+use crate::api::auth::AuthenticatedApp;
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct WebhookEndpoint {
     pub id: String,
     pub app_id: String,
     pub url: String,
-    pub secret: String,
+    /// Signs deliveries. Only returned when the endpoint is created or its
+    /// secret rotated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
     pub active: i32,
     pub created_at: String,
+    #[sqlx(try_from = "String")]
+    pub event_types: EventTypeFilter,
 }
 
-#[derive(Debug, Deserialize)]
+/// Event types an endpoint subscribes to, stored as a JSON array; empty means all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(transparent)]
+pub struct EventTypeFilter(pub Vec<String>);
+
+impl EventTypeFilter {
+    pub fn matches(&self, event_type: &str) -> bool {
+        self.0.is_empty() || self.0.iter().any(|t| t == event_type)
+    }
+}
+
+impl TryFrom<String> for EventTypeFilter {
+    type Error = serde_json::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        serde_json::from_str(&value)
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
 pub struct CreateWebhook {
-    pub app_id: String,
+    /// Defaults to the API key's app, the only one it may add endpoints to.
+    #[serde(default)]
+    pub app_id: Option<String>,
     pub url: String,
+    /// Only deliver these event types; empty or omitted means all events.
+    #[serde(default)]
+    pub event_types: Vec<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks",
+    tag = "webhooks",
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response to a retried request"),
+    ),
+    request_body = CreateWebhook,
+    responses(
+        (status = 201, body = WebhookEndpoint),
+        (status = 403, description = "`app_id` is another app"),
+        (status = 409, description = "A request with the same Idempotency-Key is still in progress"),
+        (status = 422, description = "Idempotency key reused for a different request"),
+    ),
+)]
 pub async fn create_webhook(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
     Json(input): Json<CreateWebhook>,
 ) -> Result<(StatusCode, Json<WebhookEndpoint>), (StatusCode, String)> {
+    let app_id = input.app_id.as_deref().unwrap_or(&auth.app_id);
+    auth.authorize(app_id)?;
+
     let id = uuid::Uuid::new_v4().to_string();
     let secret = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
+    let event_types = serde_json::to_string(&input.event_types)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret, active, created_at) VALUES (?, ?, ?, ?, 1, ?)")
+    sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret, active, created_at, event_types) VALUES ($1, $2, $3, $4, 1, $5, $6)")
         .bind(&id)
-        .bind(&input.app_id)
+        .bind(app_id)
         .bind(&input.url)
         .bind(&secret)
         .bind(&now)
+        .bind(&event_types)
         .execute(&state.pool)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let webhook = sqlx::query_as::<_, WebhookEndpoint>("SELECT * FROM webhook_endpoints WHERE id = ?")
+    let webhook = sqlx::query_as::<_, WebhookEndpoint>("SELECT * FROM webhook_endpoints WHERE id = $1")
         .bind(&id)
         .fetch_one(&state.pool)
         .await
@@ -45,15 +97,700 @@ pub async fn create_webhook(
     Ok((StatusCode::CREATED, Json(webhook)))
 }
 
+/// The API key's app's endpoints, newest first, without their secrets.
+#[utoipa::path(
+    get,
+    path = "/v1/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, body = Vec<WebhookEndpoint>),
+    ),
+)]
 pub async fn list_webhooks(
     State(state): State<AppState>,
+    auth: AuthenticatedApp,
 ) -> Result<Json<Vec<WebhookEndpoint>>, (StatusCode, String)> {
-    let webhooks = sqlx::query_as::<_, WebhookEndpoint>(
-        "SELECT * FROM webhook_endpoints ORDER BY created_at DESC"
+    let mut webhooks = sqlx::query_as::<_, WebhookEndpoint>(
+        "SELECT * FROM webhook_endpoints WHERE app_id = $1 ORDER BY created_at DESC"
     )
+    .bind(&auth.app_id)
     .fetch_all(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    for webhook in &mut webhooks {
+        webhook.secret = None;
+    }
     Ok(Json(webhooks))
 }
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct UpdateWebhook {
+    pub url: Option<String>,
+    /// Inactive endpoints keep their config but receive no deliveries.
+    pub active: Option<bool>,
+}
+
+/// The caller's endpoint `endpoint_id`. Another app's endpoint is reported
+/// as not found, so its ID can't be probed for.
+async fn fetch_webhook(state: &AppState, auth: &AuthenticatedApp, endpoint_id: &str) -> Result<WebhookEndpoint, (StatusCode, String)> {
+    sqlx::query_as::<_, WebhookEndpoint>("SELECT * FROM webhook_endpoints WHERE id = $1 AND app_id = $2")
+        .bind(endpoint_id)
+        .bind(&auth.app_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Webhook endpoint not found".to_string()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/webhooks/{endpoint_id}",
+    tag = "webhooks",
+    params(
+        ("endpoint_id" = String, Path, description = "Webhook endpoint ID"),
+    ),
+    request_body = UpdateWebhook,
+    responses(
+        (status = 200, body = WebhookEndpoint),
+        (status = 404, description = "Endpoint not found"),
+    ),
+)]
+pub async fn update_webhook(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(endpoint_id): Path<String>,
+    Json(input): Json<UpdateWebhook>,
+) -> Result<Json<WebhookEndpoint>, (StatusCode, String)> {
+    fetch_webhook(&state, &auth, &endpoint_id).await?;
+
+    sqlx::query("UPDATE webhook_endpoints SET url = COALESCE($1, url), active = COALESCE($2, active) WHERE id = $3")
+        .bind(&input.url)
+        .bind(input.active.map(i32::from))
+        .bind(&endpoint_id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut webhook = fetch_webhook(&state, &auth, &endpoint_id).await?;
+    webhook.secret = None;
+    Ok(Json(webhook))
+}
+
+/// Replace an endpoint's signing secret. Deliveries from now on are signed
+/// with the new one, so receivers should be updated straight away.
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks/{endpoint_id}/rotate-secret",
+    tag = "webhooks",
+    params(
+        ("endpoint_id" = String, Path, description = "Webhook endpoint ID"),
+    ),
+    responses(
+        (status = 200, body = WebhookEndpoint),
+        (status = 404, description = "Endpoint not found"),
+    ),
+)]
+pub async fn rotate_webhook_secret(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<WebhookEndpoint>, (StatusCode, String)> {
+    fetch_webhook(&state, &auth, &endpoint_id).await?;
+
+    sqlx::query("UPDATE webhook_endpoints SET secret = $1 WHERE id = $2")
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&endpoint_id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(fetch_webhook(&state, &auth, &endpoint_id).await?))
+}
+
+/// Delete an endpoint along with its delivery history.
+#[utoipa::path(
+    delete,
+    path = "/v1/webhooks/{endpoint_id}",
+    tag = "webhooks",
+    params(
+        ("endpoint_id" = String, Path, description = "Webhook endpoint ID"),
+    ),
+    responses(
+        (status = 204, description = "Endpoint deleted"),
+        (status = 404, description = "Endpoint not found"),
+    ),
+)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(endpoint_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    fetch_webhook(&state, &auth, &endpoint_id).await?;
+
+    sqlx::query("DELETE FROM webhook_endpoints WHERE id = $1")
+        .bind(&endpoint_id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_endpoint_id: String,
+    pub event_id: String,
+    pub status: String,
+    pub attempts: i32,
+    /// Envelope version the delivery is sent in.
+    pub api_version: Option<i32>,
+    pub last_attempt_at: Option<String>,
+    pub next_retry_at: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveriesQuery {
+    /// Only list deliveries in this status, e.g. `dead_letter`.
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct RedriveResult {
+    pub requeued: u64,
+}
+
+/// List an endpoint's deliveries, newest first.
+#[utoipa::path(
+    get,
+    path = "/v1/webhooks/{endpoint_id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("endpoint_id" = String, Path, description = "Webhook endpoint ID"),
+        DeliveriesQuery,
+    ),
+    responses(
+        (status = 200, body = Vec<WebhookDelivery>),
+        (status = 404, description = "Endpoint not found"),
+    ),
+)]
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(endpoint_id): Path<String>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, (StatusCode, String)> {
+    fetch_webhook(&state, &auth, &endpoint_id).await?;
+    let limit = query.limit.unwrap_or(50).min(100);
+
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        "SELECT id, webhook_endpoint_id, event_id, status, attempts, api_version, last_attempt_at, next_retry_at, last_error, created_at
+         FROM webhook_deliveries
+         WHERE webhook_endpoint_id = $1 AND ($2 IS NULL OR status = $2)
+         ORDER BY created_at DESC, id DESC
+         LIMIT $3"
+    )
+    .bind(&endpoint_id)
+    .bind(&query.status)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(deliveries))
+}
+
+/// Put a delivery back in the queue for the worker's next pass. `attempts` is
+/// kept, so a delivery that fails again goes straight back to `dead_letter`.
+#[utoipa::path(
+    post,
+    path = "/v1/webhook-deliveries/{delivery_id}/retry",
+    tag = "webhooks",
+    params(
+        ("delivery_id" = String, Path, description = "Webhook delivery ID"),
+    ),
+    responses(
+        (status = 200, body = WebhookDelivery),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "Delivery already succeeded"),
+    ),
+)]
+pub async fn retry_delivery(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(delivery_id): Path<String>,
+) -> Result<Json<WebhookDelivery>, (StatusCode, String)> {
+    // Another app's delivery is reported as not found, like its endpoints
+    sqlx::query_scalar::<_, String>(
+        "SELECT d.id FROM webhook_deliveries d
+         JOIN webhook_endpoints e ON e.id = d.webhook_endpoint_id
+         WHERE d.id = $1 AND e.app_id = $2"
+    )
+    .bind(&delivery_id)
+    .bind(&auth.app_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Webhook delivery not found".to_string()))?;
+
+    let result = sqlx::query(
+        "UPDATE webhook_deliveries SET status = 'pending', next_retry_at = NULL WHERE id = $1 AND status != 'delivered'"
+    )
+    .bind(&delivery_id)
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let delivery = sqlx::query_as::<_, WebhookDelivery>(
+        "SELECT id, webhook_endpoint_id, event_id, status, attempts, api_version, last_attempt_at, next_retry_at, last_error, created_at
+         FROM webhook_deliveries WHERE id = $1"
+    )
+    .bind(&delivery_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Webhook delivery not found".to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::CONFLICT, "Webhook delivery already delivered".to_string()));
+    }
+
+    Ok(Json(delivery))
+}
+
+/// Requeue every dead-lettered delivery for an endpoint, e.g. once it is back up.
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks/{endpoint_id}/redrive",
+    tag = "webhooks",
+    params(
+        ("endpoint_id" = String, Path, description = "Webhook endpoint ID"),
+    ),
+    responses(
+        (status = 200, body = RedriveResult),
+        (status = 404, description = "Endpoint not found"),
+    ),
+)]
+pub async fn redrive_dead_letters(
+    State(state): State<AppState>,
+    auth: AuthenticatedApp,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<RedriveResult>, (StatusCode, String)> {
+    fetch_webhook(&state, &auth, &endpoint_id).await?;
+
+    let requeued = sqlx::query(
+        "UPDATE webhook_deliveries SET status = 'pending', next_retry_at = NULL WHERE webhook_endpoint_id = $1 AND status = 'dead_letter'"
+    )
+    .bind(&endpoint_id)
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .rows_affected();
+
+    Ok(Json(RedriveResult { requeued }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::AppState;
+    use crate::test_support::{create_test_app, test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn create_test_webhook(state: &AppState, api_key: &str, body: String) -> String {
+        let app = crate::api::router(state.clone());
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/webhooks")
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        v["id"].as_str().unwrap().to_string()
+    }
+
+    async fn insert_event(state: &AppState, event_type: &str) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO events (id, subscriber_id, event_type, payload) VALUES ($1, 'sub', $2, '{}')")
+            .bind(&id)
+            .bind(event_type)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        id
+    }
+
+    #[tokio::test]
+    async fn test_filtered_endpoint_only_receives_matching_events() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let all_events = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/all"}}"#),
+        ).await;
+        let renewals_only = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/renewals","event_types":["RENEWAL"]}}"#),
+        ).await;
+
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        for event_type in ["RENEWAL", "REFUND"] {
+            let event_id = insert_event(&state, event_type).await;
+            crate::webhooks::fanout::enqueue_deliveries(&mut state.pool.acquire().await.unwrap(), &event_id, &app_id).await.unwrap();
+        }
+
+        let count_for = |endpoint_id: String| {
+            let pool = state.pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM webhook_deliveries WHERE webhook_endpoint_id = $1")
+                    .bind(endpoint_id)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(count_for(all_events).await, 2);
+        assert_eq!(count_for(renewals_only.clone()).await, 1);
+
+        // Queued deliveries carry their body, wrapped in the current envelope
+        let (version, payload): (i32, String) = sqlx::query_as("SELECT api_version, payload FROM webhook_deliveries WHERE webhook_endpoint_id = $1")
+            .bind(&renewals_only)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        let envelope: crate::webhooks::envelope::WebhookEnvelope = serde_json::from_str(&payload).unwrap();
+        assert_eq!(version, crate::webhooks::envelope::WEBHOOK_API_VERSION);
+        assert_eq!((envelope.event_type.as_str(), envelope.app_id.as_str()), ("RENEWAL", app_id.as_str()));
+    }
+
+    async fn insert_delivery(state: &AppState, endpoint_id: &str, status: &str, attempts: i32) -> String {
+        let event_id = insert_event(state, "RENEWAL").await;
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (id, webhook_endpoint_id, event_id, status, attempts, next_retry_at, last_error)
+             VALUES ($1, $2, $3, $4, $5, '2099-01-01T00:00:00Z', 'HTTP 503')"
+        )
+        .bind(&id)
+        .bind(endpoint_id)
+        .bind(&event_id)
+        .bind(status)
+        .bind(attempts)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+        id
+    }
+
+    async fn send(state: &AppState, api_key: Option<&str>, method: &str, uri: String, body: Option<&str>) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if body.is_some() {
+            request = request.header("content-type", "application/json");
+        }
+        if let Some(api_key) = api_key {
+            request = request.header("authorization", format!("Bearer {api_key}"));
+        }
+        let resp = crate::api::router(state.clone())
+            .oneshot(request.body(body.map(|b| Body::from(b.to_string())).unwrap_or_default()).unwrap())
+            .await
+            .unwrap();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_retry_requeues_single_delivery() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/hook"}}"#),
+        ).await;
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let dead = insert_delivery(&state, &endpoint_id, "dead_letter", 10).await;
+        let delivered = insert_delivery(&state, &endpoint_id, "delivered", 1).await;
+
+        let (status, body) = send(&state, Some(&api_key), "POST", format!("/v1/webhook-deliveries/{dead}/retry"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "pending");
+        assert_eq!(body["attempts"], 10);
+        assert!(body["next_retry_at"].is_null());
+
+        let (status, _) = send(&state, Some(&api_key), "POST", format!("/v1/webhook-deliveries/{delivered}/retry"), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&state, Some(&api_key), "POST", "/v1/webhook-deliveries/missing/retry".to_string(), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let resp = crate::api::router(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/webhooks/{endpoint_id}/deliveries?status=pending"))
+                    .header("authorization", format!("Bearer {api_key}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let deliveries: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(deliveries.as_array().unwrap().len(), 1);
+        assert_eq!(deliveries[0]["id"], dead.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_redrive_requeues_only_dead_letters() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/hook"}}"#),
+        ).await;
+        let other_endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/other"}}"#),
+        ).await;
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            insert_delivery(&state, &endpoint_id, "dead_letter", 10).await;
+        }
+        let failed = insert_delivery(&state, &endpoint_id, "failed", 3).await;
+        let elsewhere = insert_delivery(&state, &other_endpoint_id, "dead_letter", 10).await;
+
+        let (status, body) = send(&state, Some(&api_key), "POST", format!("/v1/webhooks/{endpoint_id}/redrive"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["requeued"], 2);
+
+        let pending: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM webhook_deliveries WHERE webhook_endpoint_id = $1 AND status = 'pending' AND next_retry_at IS NULL AND attempts = 10"
+        )
+        .bind(&endpoint_id)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+        assert_eq!(pending, 2);
+
+        for (id, expected) in [(failed, "failed"), (elsewhere, "dead_letter")] {
+            let status: String = sqlx::query_scalar("SELECT status FROM webhook_deliveries WHERE id = $1")
+                .bind(&id)
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+            assert_eq!(status, expected);
+        }
+
+        let (status, _) = send(&state, Some(&api_key), "POST", "/v1/webhooks/missing/redrive".to_string(), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_webhook_url_and_active() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/old"}}"#),
+        ).await;
+
+        let (status, body) = send(
+            &state, Some(&api_key), "PUT", format!("/v1/webhooks/{endpoint_id}"),
+            Some(r#"{"url":"https://example.com/new","active":false}"#),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["url"], "https://example.com/new");
+        assert_eq!(body["active"], 0);
+
+        // Omitted fields are left alone
+        let (_, body) = send(&state, Some(&api_key), "PUT", format!("/v1/webhooks/{endpoint_id}"), Some(r#"{"active":true}"#)).await;
+        assert_eq!(body["url"], "https://example.com/new");
+        assert_eq!(body["active"], 1);
+
+        let (status, _) = send(&state, Some(&api_key), "PUT", "/v1/webhooks/missing".to_string(), Some("{}")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_webhook_secret() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/hook"}}"#),
+        ).await;
+        let old_secret: String = sqlx::query_scalar("SELECT secret FROM webhook_endpoints WHERE id = $1")
+            .bind(&endpoint_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+
+        let (status, body) = send(&state, Some(&api_key), "POST", format!("/v1/webhooks/{endpoint_id}/rotate-secret"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let new_secret = body["secret"].as_str().unwrap();
+        assert_ne!(new_secret, old_secret);
+
+        let stored: String = sqlx::query_scalar("SELECT secret FROM webhook_endpoints WHERE id = $1")
+            .bind(&endpoint_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, new_secret);
+    }
+
+    #[tokio::test]
+    async fn test_delete_webhook_removes_deliveries() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/hook"}}"#),
+        ).await;
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        insert_delivery(&state, &endpoint_id, "failed", 1).await;
+
+        let (status, _) = send(&state, Some(&api_key), "DELETE", format!("/v1/webhooks/{endpoint_id}"), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let deliveries: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM webhook_deliveries")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(deliveries, 0);
+
+        let (status, _) = send(&state, Some(&api_key), "DELETE", format!("/v1/webhooks/{endpoint_id}"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_changes_require_the_owning_apps_key() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let (_, other_key) = create_test_app(&state, "com.other").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/hook"}}"#),
+        ).await;
+
+        for (method, uri, body) in [
+            ("PUT", format!("/v1/webhooks/{endpoint_id}"), Some(r#"{"active":false}"#)),
+            ("POST", format!("/v1/webhooks/{endpoint_id}/rotate-secret"), None),
+            ("DELETE", format!("/v1/webhooks/{endpoint_id}"), None),
+        ] {
+            let (status, _) = send(&state, None, method, uri.clone(), body).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {uri}");
+            let (status, _) = send(&state, Some(&other_key), method, uri.clone(), body).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{method} {uri}");
+        }
+
+        let (url, active, secret): (String, i32, String) = sqlx::query_as("SELECT url, active, secret FROM webhook_endpoints WHERE id = $1")
+            .bind(&endpoint_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!((url.as_str(), active), ("https://example.com/hook", 1));
+
+        let (status, body) = send(&state, Some(&api_key), "POST", format!("/v1/webhooks/{endpoint_id}/rotate-secret"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(body["secret"], secret.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_require_the_owning_apps_key() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let (_, other_key) = create_test_app(&state, "com.other").await;
+        let endpoint_id = create_test_webhook(
+            &state,
+            &api_key,
+            format!(r#"{{"app_id":"{app_id}","url":"https://example.com/hook"}}"#),
+        ).await;
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', $1, 'user123')")
+            .bind(&app_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let dead = insert_delivery(&state, &endpoint_id, "dead_letter", 10).await;
+
+        for (method, uri) in [
+            ("GET", format!("/v1/webhooks/{endpoint_id}/deliveries")),
+            ("POST", format!("/v1/webhook-deliveries/{dead}/retry")),
+            ("POST", format!("/v1/webhooks/{endpoint_id}/redrive")),
+        ] {
+            let (status, _) = send(&state, None, method, uri.clone(), None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {uri}");
+            let (status, _) = send(&state, Some(&other_key), method, uri.clone(), None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{method} {uri}");
+        }
+
+        let status: String = sqlx::query_scalar("SELECT status FROM webhook_deliveries WHERE id = $1")
+            .bind(&dead)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "dead_letter");
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_are_created_and_listed_for_the_keys_app() {
+        let state = test_state(test_pool().await);
+        let (app_id, api_key) = create_test_app(&state, "com.test").await;
+        let (other_id, other_key) = create_test_app(&state, "com.other").await;
+        let endpoint_id = create_test_webhook(&state, &api_key, r#"{"url":"https://example.com/hook"}"#.to_string()).await;
+        create_test_webhook(&state, &other_key, r#"{"url":"https://example.com/other"}"#.to_string()).await;
+
+        let body = format!(r#"{{"app_id":"{other_id}","url":"https://example.com/hook"}}"#);
+        let (status, _) = send(&state, None, "POST", "/v1/webhooks".to_string(), Some(&body)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&state, Some(&api_key), "POST", "/v1/webhooks".to_string(), Some(&body)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send(&state, None, "GET", "/v1/webhooks".to_string(), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = send(&state, Some(&api_key), "GET", "/v1/webhooks".to_string(), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let webhooks = body.as_array().unwrap();
+        assert_eq!(webhooks.len(), 1);
+        assert_eq!((webhooks[0]["id"].as_str(), webhooks[0]["app_id"].as_str()), (Some(endpoint_id.as_str()), Some(app_id.as_str())));
+        assert!(webhooks[0].get("secret").is_none());
+    }
+}
@@ -1,11 +1,24 @@
+use std::collections::HashMap;
 use config::{Config, Environment, File};
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    #[serde(default)]
+    pub expiry: ExpiryConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub voided_purchases: VoidedPurchasesConfig,
+    #[serde(default)]
+    pub offerings: OfferingsConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -13,25 +26,252 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub secret_key: SecretString,
+    /// Origins browsers may call the API from; `https://*.example.com` allows
+    /// any subdomain. Set as a comma-separated list in `OPENCAT__SERVER__CORS_ORIGINS`.
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    /// `text` for people, `json` for log aggregators.
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Largest request body accepted, in bytes; bigger ones get 413.
+    #[serde(default = "default_server_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Seconds a request may take before it's answered with 408.
+    #[serde(default = "default_server_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+}
+
+/// Shortest `server.secret_key` accepted, in bytes.
+const MIN_SECRET_KEY_BYTES: usize = 32;
+
+/// The key `.env.example` and `docker-compose.yml` ship with.
+const PLACEHOLDER_SECRET_KEY: &str = "change-me-to-a-random-string-at-least-32-chars";
+
+impl ServerConfig {
+    /// Stored store credentials are encrypted with a key derived from
+    /// `secret_key`, so a short or well-known one protects nothing.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let secret_key = self.secret_key.expose_secret();
+        anyhow::ensure!(
+            secret_key.len() >= MIN_SECRET_KEY_BYTES,
+            "server.secret_key must be at least {MIN_SECRET_KEY_BYTES} bytes, got {}",
+            secret_key.len()
+        );
+        anyhow::ensure!(
+            secret_key != PLACEHOLDER_SECRET_KEY,
+            "server.secret_key is still the example placeholder; set it to a random string"
+        );
+        Ok(())
+    }
+}
+
+fn default_server_max_body_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_server_request_timeout_seconds() -> u64 {
+    30
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseConfig {
     pub url: String,
+    /// Most connections the pool opens at once.
+    #[serde(default = "default_database_max_connections")]
+    pub max_connections: u32,
+    /// Seconds a query waits for a free connection before failing.
+    #[serde(default = "default_database_acquire_timeout_seconds")]
+    pub acquire_timeout_seconds: u64,
+    /// SQLite only: seconds a connection waits on another's write lock
+    /// before giving up with "database is locked".
+    #[serde(default = "default_database_busy_timeout_seconds")]
+    pub busy_timeout_seconds: u64,
+}
+
+impl DatabaseConfig {
+    /// Default pool settings for `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            max_connections: default_database_max_connections(),
+            acquire_timeout_seconds: default_database_acquire_timeout_seconds(),
+            busy_timeout_seconds: default_database_busy_timeout_seconds(),
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.max_connections >= 1, "database.max_connections must be at least 1");
+        Ok(())
+    }
+}
+
+fn default_database_max_connections() -> u32 {
+    10
+}
+
+fn default_database_acquire_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_database_busy_timeout_seconds() -> u64 {
+    5
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookConfig {
+    /// Keep sending the raw secret in `X-Webhook-Secret` alongside the HMAC
+    /// signature while receivers migrate.
+    #[serde(default)]
+    pub legacy_secret_header: bool,
+    /// Most deliveries in flight at once.
+    #[serde(default = "default_webhook_concurrency")]
+    pub concurrency: usize,
+    /// Attempts before a delivery is dead-lettered.
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: i32,
+    /// Longest wait in seconds after each failed attempt; attempts beyond the
+    /// end reuse the last entry. Set as a comma-separated list in
+    /// `OPENCAT__WEBHOOKS__BACKOFF_SECONDS`.
+    #[serde(default = "default_webhook_backoff_seconds")]
+    pub backoff_seconds: Vec<u64>,
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.concurrency >= 1, "webhooks.concurrency must be at least 1");
+        anyhow::ensure!(self.max_attempts >= 1, "webhooks.max_attempts must be at least 1");
+        anyhow::ensure!(!self.backoff_seconds.is_empty(), "webhooks.backoff_seconds must not be empty");
+        Ok(())
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            legacy_secret_header: false,
+            concurrency: default_webhook_concurrency(),
+            max_attempts: default_webhook_max_attempts(),
+            backoff_seconds: default_webhook_backoff_seconds(),
+        }
+    }
+}
+
+fn default_webhook_concurrency() -> usize {
+    16
+}
+
+fn default_webhook_max_attempts() -> i32 {
+    10
+}
+
+fn default_webhook_backoff_seconds() -> Vec<u64> {
+    vec![1, 5, 30, 120, 600, 3600]
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExpiryConfig {
+    /// Seconds between scans for active subscriptions past their expiration date.
+    #[serde(default = "default_expiry_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self { interval_secs: default_expiry_interval_secs() }
+    }
+}
+
+fn default_expiry_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VoidedPurchasesConfig {
+    /// Seconds between polls of Google Play's voided purchases.
+    #[serde(default = "default_voided_purchases_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for VoidedPurchasesConfig {
+    fn default() -> Self {
+        Self { interval_secs: default_voided_purchases_interval_secs() }
+    }
+}
+
+fn default_voided_purchases_interval_secs() -> u64 {
+    3600
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OfferingsConfig {
+    /// Seconds an app's offerings are served from memory. Changes made
+    /// through the API show up at once; others (e.g. the CLI) after this.
+    #[serde(default = "default_offerings_cache_ttl_seconds")]
+    pub cache_ttl_seconds: u64,
+    /// Units of each currency a US dollar buys, e.g. `EUR = 0.92`, for
+    /// `?currency=`. Prices in currencies missing here aren't converted.
+    #[serde(default)]
+    pub fx_rates: HashMap<String, f64>,
+}
+
+impl Default for OfferingsConfig {
+    fn default() -> Self {
+        Self { cache_ttl_seconds: default_offerings_cache_ttl_seconds(), fx_rates: HashMap::new() }
+    }
+}
+
+fn default_offerings_cache_ttl_seconds() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct NotificationConfig {
+    /// Audience set on the Pub/Sub push subscription for Google notifications.
+    /// Google pushes are rejected until this is configured.
+    pub google_push_audience: Option<String>,
+    /// Service account the push subscription authenticates as, if it should be pinned.
+    pub google_push_service_account: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MetricsConfig {
+    /// Serve Prometheus metrics at `/metrics`. The endpoint is unauthenticated,
+    /// so keep it off the public network.
+    #[serde(default)]
+    pub enabled: bool,
 }
 
 impl AppConfig {
     pub fn load() -> anyhow::Result<Self> {
+        Self::load_with(Environment::with_prefix("OPENCAT"))
+    }
+
+    fn load_with(environment: Environment) -> anyhow::Result<Self> {
         let config = Config::builder()
             .add_source(File::with_name("config/default").required(false))
             .add_source(
-                Environment::with_prefix("OPENCAT")
+                environment
                     .separator("__")
-                    .try_parsing(true),
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("server.cors_origins")
+                    .with_list_parse_key("webhooks.backoff_seconds"),
             )
             .build()?;
 
-        Ok(config.try_deserialize()?)
+        let config: Self = config.try_deserialize()?;
+        config.server.validate()?;
+        config.database.validate()?;
+        config.webhooks.validate()?;
+        Ok(config)
     }
 }
 
@@ -46,5 +286,79 @@ mod tests {
         let config = AppConfig::load().unwrap();
         assert_eq!(config.server.host, "0.0.0.0");
         assert_eq!(config.server.port, 8080);
+        assert_eq!(config.server.max_body_bytes, 256 * 1024);
+        assert_eq!(config.server.request_timeout_seconds, 30);
+        assert_eq!(config.offerings.cache_ttl_seconds, 60);
+    }
+
+    #[test]
+    fn test_cors_origins_parse_from_comma_separated_env() {
+        let env = std::collections::HashMap::from([
+            ("OPENCAT__DATABASE__URL".to_string(), "sqlite://opencat.db".to_string()),
+            ("OPENCAT__SERVER__SECRET_KEY".to_string(), "test-secret-key-min-32-chars-long!!".to_string()),
+            ("OPENCAT__SERVER__CORS_ORIGINS".to_string(), "https://dashboard.example.com,https://*.example.com".to_string()),
+        ]);
+        let config = AppConfig::load_with(Environment::with_prefix("OPENCAT").source(Some(env))).unwrap();
+        assert_eq!(
+            config.server.cors_origins,
+            vec!["https://dashboard.example.com", "https://*.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_webhook_schedule_parses_and_validates() {
+        let env = |backoff: &str| std::collections::HashMap::from([
+            ("OPENCAT__DATABASE__URL".to_string(), "sqlite://opencat.db".to_string()),
+            ("OPENCAT__SERVER__SECRET_KEY".to_string(), "test-secret-key-min-32-chars-long!!".to_string()),
+            ("OPENCAT__WEBHOOKS__MAX_ATTEMPTS".to_string(), "3".to_string()),
+            ("OPENCAT__WEBHOOKS__BACKOFF_SECONDS".to_string(), backoff.to_string()),
+        ]);
+        let config = AppConfig::load_with(Environment::with_prefix("OPENCAT").source(Some(env("10,60")))).unwrap();
+        assert_eq!(config.webhooks.max_attempts, 3);
+        assert_eq!(config.webhooks.backoff_seconds, vec![10, 60]);
+
+        let invalid = WebhookConfig { max_attempts: 0, ..WebhookConfig::default() };
+        assert!(invalid.validate().is_err());
+        let invalid = WebhookConfig { backoff_seconds: vec![], ..WebhookConfig::default() };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_short_or_placeholder_secret_key_is_rejected() {
+        let env = |secret_key: &str| std::collections::HashMap::from([
+            ("OPENCAT__DATABASE__URL".to_string(), "sqlite://opencat.db".to_string()),
+            ("OPENCAT__SERVER__SECRET_KEY".to_string(), secret_key.to_string()),
+        ]);
+        let load = |secret_key: &str| AppConfig::load_with(Environment::with_prefix("OPENCAT").source(Some(env(secret_key))));
+
+        let error = load("0123456789").unwrap_err().to_string();
+        assert!(error.contains("at least 32 bytes"), "{error}");
+        assert!(!error.contains("0123456789"), "{error}");
+        assert!(load(PLACEHOLDER_SECRET_KEY).is_err());
+        assert!(load("test-secret-key-min-32-chars-long!!").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_database_pool_settings() {
+        let env = std::collections::HashMap::from([
+            ("OPENCAT__DATABASE__URL".to_string(), "sqlite::memory:".to_string()),
+            ("OPENCAT__DATABASE__MAX_CONNECTIONS".to_string(), "3".to_string()),
+            ("OPENCAT__DATABASE__ACQUIRE_TIMEOUT_SECONDS".to_string(), "7".to_string()),
+            ("OPENCAT__DATABASE__BUSY_TIMEOUT_SECONDS".to_string(), "2".to_string()),
+            ("OPENCAT__SERVER__SECRET_KEY".to_string(), "test-secret-key-min-32-chars-long!!".to_string()),
+        ]);
+        let config = AppConfig::load_with(Environment::with_prefix("OPENCAT").source(Some(env))).unwrap();
+        assert_eq!(config.database.max_connections, 3);
+        assert_eq!(config.database.acquire_timeout_seconds, 7);
+        assert_eq!(config.database.busy_timeout_seconds, 2);
+
+        let pool = crate::db::connect_with(&config.database).await.unwrap();
+        assert_eq!(pool.options().get_max_connections(), 3);
+        assert_eq!(pool.options().get_acquire_timeout(), std::time::Duration::from_secs(7));
+        let busy_timeout: i64 = sqlx::query_scalar("PRAGMA busy_timeout").fetch_one(&pool).await.unwrap();
+        assert_eq!(busy_timeout, 2000);
+
+        let invalid = DatabaseConfig { max_connections: 0, ..DatabaseConfig::new("sqlite::memory:") };
+        assert!(invalid.validate().is_err());
     }
 }
@@ -0,0 +1,98 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use crate::models::app::StoreCredentials;
+
+const NONCE_LEN: usize = 12;
+
+/// Encrypts store credentials at rest with AES-256-GCM, keyed from the server
+/// `secret_key` via HKDF-SHA256.
+///
+/// Stored values are `base64(nonce || ciphertext)`. Rows written before
+/// encryption was introduced hold plaintext JSON; those are still readable and
+/// are re-encrypted the next time the app's credentials are updated. Changing
+/// `secret_key` makes existing credentials unreadable, so they must be
+/// re-entered afterwards.
+#[derive(Clone)]
+pub struct CredentialCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialCipher {
+    pub fn new(secret_key: &str) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(None, secret_key.as_bytes());
+        let mut key = [0u8; 32];
+        hkdf.expand(b"opencat-store-credentials", &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> anyhow::Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt credentials"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(base64::engine::general_purpose::STANDARD.encode(sealed))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> anyhow::Result<String> {
+        let sealed = base64::engine::general_purpose::STANDARD.decode(stored)?;
+        if sealed.len() < NONCE_LEN {
+            anyhow::bail!("Encrypted credentials are truncated");
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt credentials; was secret_key changed?"))?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    pub fn seal_credentials(&self, creds: &StoreCredentials) -> anyhow::Result<String> {
+        self.encrypt(&serde_json::to_string(creds)?)
+    }
+
+    /// Decrypt a `store_credentials_encrypted` column value into its JSON form.
+    pub fn open_credentials_json(&self, stored: &str) -> anyhow::Result<String> {
+        if stored.trim_start().starts_with('{') {
+            // Legacy plaintext row
+            return Ok(stored.to_string());
+        }
+        self.decrypt(stored)
+    }
+
+    pub fn open_credentials(&self, stored: &str) -> anyhow::Result<StoreCredentials> {
+        Ok(serde_json::from_str(&self.open_credentials_json(stored)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = CredentialCipher::new("test-secret-key-min-32-chars-long!!");
+        let sealed = cipher.encrypt(r#"{"apple":null}"#).unwrap();
+        assert!(!sealed.contains("apple"));
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), r#"{"apple":null}"#);
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let sealed = CredentialCipher::new("test-secret-key-min-32-chars-long!!")
+            .encrypt("secret")
+            .unwrap();
+        let other = CredentialCipher::new("another-secret-key-min-32-chars-long");
+        assert!(other.decrypt(&sealed).is_err());
+    }
+}
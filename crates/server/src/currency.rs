@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// ISO 4217 currencies without minor units.
+const ZERO_DECIMAL: &[&str] = &[
+    "BIF", "CLP", "DJF", "GNF", "ISK", "JPY", "KMF", "KRW", "PYG", "RWF", "UGX", "UYI", "VND", "VUV", "XAF", "XOF", "XPF",
+];
+
+/// ISO 4217 currencies with thousandths.
+const THREE_DECIMAL: &[&str] = &["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// Digits after the decimal point `currency` is shown with.
+pub fn minor_digits(currency: &str) -> u32 {
+    let currency = currency.to_uppercase();
+    if ZERO_DECIMAL.contains(&currency.as_str()) {
+        0
+    } else if THREE_DECIMAL.contains(&currency.as_str()) {
+        3
+    } else {
+        2
+    }
+}
+
+/// The symbol English-locale formatting uses for `currency`, when it has one.
+fn symbol(currency: &str) -> Option<&'static str> {
+    Some(match currency {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" => "¥",
+        "CNY" => "CN¥",
+        "INR" => "₹",
+        "KRW" => "₩",
+        "BRL" => "R$",
+        "CAD" => "CA$",
+        "AUD" => "A$",
+        "NZD" => "NZ$",
+        "HKD" => "HK$",
+        "MXN" => "MX$",
+        "TWD" => "NT$",
+        "ILS" => "₪",
+        "VND" => "₫",
+        "PHP" => "₱",
+        _ => return None,
+    })
+}
+
+/// Format a price the English-locale way: the currency's symbol (or its code
+/// and a space), grouped thousands and the currency's usual decimals, e.g.
+/// `$1,299.99`, `¥1,200` or `CHF 9.90`.
+pub fn format_price(micros: i64, currency: &str) -> String {
+    let currency = currency.to_uppercase();
+    let digits = minor_digits(&currency);
+    let minor = round_to_minor(micros, digits);
+
+    let scale = 10_i64.pow(digits);
+    let whole = (minor.abs() / scale).to_string();
+    let mut grouped = String::with_capacity(whole.len() + whole.len() / 3);
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    if digits > 0 {
+        grouped.push_str(&format!(".{:0width$}", minor.abs() % scale, width = digits as usize));
+    }
+
+    let sign = if minor < 0 { "-" } else { "" };
+    match symbol(&currency) {
+        Some(symbol) => format!("{sign}{symbol}{grouped}"),
+        None => format!("{sign}{currency} {grouped}"),
+    }
+}
+
+/// `micros` in the currency's minor units, rounding half away from zero.
+fn round_to_minor(micros: i64, digits: u32) -> i64 {
+    let step = 10_i64.pow(6 - digits);
+    let rounded = (micros.abs() + step / 2) / step;
+    if micros < 0 { -rounded } else { rounded }
+}
+
+/// Exchange rates, as units of each currency a US dollar buys, from
+/// `offerings.fx_rates`. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct FxRates(Arc<HashMap<String, f64>>);
+
+impl FxRates {
+    pub fn new(rates: HashMap<String, f64>) -> Self {
+        Self(Arc::new(rates.into_iter().map(|(currency, rate)| (currency.to_uppercase(), rate)).collect()))
+    }
+
+    fn per_dollar(&self, currency: &str) -> Option<f64> {
+        match currency {
+            "USD" => Some(1.0),
+            other => self.0.get(other).copied().filter(|rate| rate.is_finite() && *rate > 0.0),
+        }
+    }
+
+    /// `micros` of `from` in `to`, rounded to what `to` can express, or
+    /// `None` without a rate for either.
+    pub fn convert(&self, micros: i64, from: &str, to: &str) -> Option<i64> {
+        let (from, to) = (from.to_uppercase(), to.to_uppercase());
+        if from == to {
+            return Some(micros);
+        }
+        let converted = micros as f64 / self.per_dollar(&from)? * self.per_dollar(&to)?;
+        let step = 10_f64.powi(6 - minor_digits(&to) as i32);
+        Some(((converted / step).round() * step) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_usd() {
+        assert_eq!(format_price(9_990_000, "USD"), "$9.99");
+        assert_eq!(format_price(1_299_990_000, "usd"), "$1,299.99");
+        assert_eq!(format_price(500_000, "USD"), "$0.50");
+        assert_eq!(format_price(9_995_000, "USD"), "$10.00");
+        assert_eq!(format_price(9_900_000, "CHF"), "CHF 9.90");
+        assert_eq!(format_price(4_990_000, "KWD"), "KWD 4.990");
+    }
+
+    #[test]
+    fn test_format_jpy_has_no_decimals() {
+        assert_eq!(format_price(1_200_000_000, "JPY"), "¥1,200");
+        assert_eq!(format_price(120_500_000, "JPY"), "¥121");
+        assert_eq!(format_price(0, "JPY"), "¥0");
+    }
+
+    #[test]
+    fn test_convert() {
+        let rates = FxRates::new(HashMap::from([("eur".to_string(), 0.9), ("JPY".to_string(), 150.0)]));
+
+        assert_eq!(rates.convert(10_000_000, "USD", "EUR"), Some(9_000_000));
+        assert_eq!(rates.convert(9_000_000, "EUR", "USD"), Some(10_000_000));
+        // Rounded to whole yen
+        assert_eq!(rates.convert(9_990_000, "USD", "JPY"), Some(1_499_000_000));
+        assert_eq!(rates.convert(9_990_000, "GBP", "GBP"), Some(9_990_000));
+        assert_eq!(rates.convert(9_990_000, "USD", "GBP"), None);
+    }
+}
@@ -1,25 +1,163 @@
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
-use sqlx::{Pool, Sqlite};
-use std::str::FromStr;
+use std::collections::HashSet;
+use std::time::Duration;
+use sqlx::any::AnyPoolOptions;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::{Any, Executor, Pool};
+use crate::config::DatabaseConfig;
 
-pub type DbPool = Pool<Sqlite>;
+/// Connection pool over either SQLite or PostgreSQL, chosen from the URL scheme.
+///
+/// Queries use `$N` placeholders and portable SQL so they run unchanged on both.
+pub type DbPool = Pool<Any>;
 
-pub async fn connect(database_url: &str) -> anyhow::Result<DbPool> {
-    let options = SqliteConnectOptions::from_str(database_url)?
-        .create_if_missing(true)
-        .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
-        .foreign_keys(true);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
 
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect_with(options)
-        .await?;
+impl Backend {
+    pub fn from_url(database_url: &str) -> anyhow::Result<Self> {
+        if database_url.starts_with("sqlite:") {
+            Ok(Self::Sqlite)
+        } else if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+            Ok(Self::Postgres)
+        } else {
+            anyhow::bail!("Unsupported database URL scheme: {database_url}")
+        }
+    }
+}
 
-    sqlx::migrate!("./migrations").run(&pool).await?;
+/// Connect to `database_url` with the default pool settings and migrate it.
+pub async fn connect(database_url: &str) -> anyhow::Result<DbPool> {
+    connect_with(&DatabaseConfig::new(database_url)).await
+}
 
+/// Connect with the configured pool settings and migrate the database.
+pub async fn connect_with(config: &DatabaseConfig) -> anyhow::Result<DbPool> {
+    let pool = open(config).await?;
+    migrate(&pool, Backend::from_url(&config.url)?).await?;
     Ok(pool)
 }
 
+/// Apply every pending migration.
+pub async fn migrate(pool: &DbPool, backend: Backend) -> anyhow::Result<()> {
+    let mut conn = pool.acquire().await?;
+    set_foreign_keys(&mut conn, backend, false).await?;
+    let migrated = migrator(backend).run(&mut *conn).await;
+    set_foreign_keys(&mut conn, backend, true).await?;
+    Ok(migrated?)
+}
+
+/// Migrations run with SQLite's foreign keys off, so rebuilding a referenced
+/// table doesn't cascade its `DROP TABLE` to the children. The pragma can't
+/// be changed inside the transaction each migration runs in.
+async fn set_foreign_keys(conn: &mut sqlx::AnyConnection, backend: Backend, enabled: bool) -> sqlx::Result<()> {
+    if backend == Backend::Sqlite {
+        conn.execute(if enabled { "PRAGMA foreign_keys = ON" } else { "PRAGMA foreign_keys = OFF" }).await?;
+    }
+    Ok(())
+}
+
+/// Connect without applying migrations, e.g. to inspect or revert them.
+pub async fn open(config: &DatabaseConfig) -> anyhow::Result<DbPool> {
+    sqlx::any::install_default_drivers();
+
+    let backend = Backend::from_url(&config.url)?;
+    let mut options = AnyPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_seconds));
+
+    let url = match backend {
+        Backend::Sqlite => {
+            let busy_timeout_ms = config.busy_timeout_seconds * 1000;
+            options = options.after_connect(move |conn, _meta| {
+                Box::pin(async move {
+                    conn.execute("PRAGMA foreign_keys = ON").await?;
+                    conn.execute("PRAGMA journal_mode = WAL").await?;
+                    conn.execute(format!("PRAGMA busy_timeout = {busy_timeout_ms}").as_str()).await?;
+                    Ok(())
+                })
+            });
+            sqlite_url(&config.url)
+        }
+        Backend::Postgres => config.url.clone(),
+    };
+
+    Ok(options.connect(&url).await?)
+}
+
+/// The migrations embedded for `backend`.
+pub fn migrator(backend: Backend) -> Migrator {
+    match backend {
+        Backend::Sqlite => sqlx::migrate!("./migrations/sqlite"),
+        Backend::Postgres => sqlx::migrate!("./migrations/postgres"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// Every embedded migration, oldest first, and whether `_sqlx_migrations`
+/// records it as applied.
+pub async fn migration_status(pool: &DbPool, backend: Backend) -> anyhow::Result<Vec<MigrationStatus>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: HashSet<i64> = conn.list_applied_migrations().await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+
+    Ok(migrator(backend).iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.contains(&m.version),
+        })
+        .collect())
+}
+
+/// Roll back the most recently applied migration with its `.down.sql`,
+/// returning it. `None` when nothing has been applied.
+pub async fn revert_last_migration(pool: &DbPool, backend: Backend) -> anyhow::Result<Option<MigrationStatus>> {
+    let mut applied: Vec<_> = migration_status(pool, backend).await?
+        .into_iter()
+        .filter(|m| m.applied)
+        .collect();
+    let Some(mut last) = applied.pop() else {
+        return Ok(None);
+    };
+
+    let target = applied.last().map_or(0, |m| m.version);
+    let mut conn = pool.acquire().await?;
+    set_foreign_keys(&mut conn, backend, false).await?;
+    let reverted = migrator(backend).undo(&mut *conn, target).await;
+    set_foreign_keys(&mut conn, backend, true).await?;
+    reverted?;
+    last.applied = false;
+    Ok(Some(last))
+}
+
+/// The Any driver re-parses the URL for every new connection, so an anonymous
+/// `:memory:` database would be different on each one. Give it a unique shared
+/// name instead, and create file databases on first use.
+fn sqlite_url(database_url: &str) -> String {
+    if database_url == "sqlite::memory:" {
+        format!("sqlite:file:opencat-{}?mode=memory&cache=shared", uuid::Uuid::new_v4().simple())
+    } else if database_url.contains("mode=") {
+        database_url.to_string()
+    } else if database_url.contains('?') {
+        format!("{database_url}&mode=rwc")
+    } else {
+        format!("{database_url}?mode=rwc")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -33,4 +171,77 @@ mod tests {
             .unwrap();
         assert!(result.is_some());
     }
+
+    #[tokio::test]
+    async fn test_migration_status_and_revert() {
+        let pool = connect("sqlite::memory:").await.unwrap();
+
+        let status = migration_status(&pool, Backend::Sqlite).await.unwrap();
+        let embedded = std::fs::read_dir("migrations/sqlite").unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".up.sql"))
+            .count();
+        assert_eq!(status.len(), embedded);
+        assert!(status.iter().all(|m| m.applied));
+        assert_eq!((status[0].version, status[0].description.as_str()), (1, "initial schema"));
+
+        let reverted = revert_last_migration(&pool, Backend::Sqlite).await.unwrap().unwrap();
+        let last = status.last().unwrap();
+        assert_eq!(reverted.version, last.version);
+        let status = migration_status(&pool, Backend::Sqlite).await.unwrap();
+        assert_eq!(status.iter().filter(|m| !m.applied).map(|m| m.version).collect::<Vec<_>>(), vec![last.version]);
+
+        // Every down migration runs cleanly, and the schema can be rebuilt after
+        migrator(Backend::Sqlite).undo(&pool, 0).await.unwrap();
+        assert!(migration_status(&pool, Backend::Sqlite).await.unwrap().iter().all(|m| !m.applied));
+        assert_eq!(revert_last_migration(&pool, Backend::Sqlite).await.unwrap(), None);
+        migrator(Backend::Sqlite).run(&pool).await.unwrap();
+        assert!(migration_status(&pool, Backend::Sqlite).await.unwrap().iter().all(|m| m.applied));
+    }
+
+    #[tokio::test]
+    async fn test_rebuilding_apps_keeps_dependent_rows() {
+        let pool = connect("sqlite::memory:").await.unwrap();
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('app', 'Test', 'ios', 'com.test')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO entitlements (id, app_id, name) VALUES ('ent', 'app', 'pro')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Back to before 026 rebuilt apps, and forward again
+        while migration_status(&pool, Backend::Sqlite).await.unwrap().iter().any(|m| m.applied && m.version >= 26) {
+            revert_last_migration(&pool, Backend::Sqlite).await.unwrap();
+        }
+        migrate(&pool, Backend::Sqlite).await.unwrap();
+
+        let entitlements: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entitlements WHERE app_id = 'app'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(entitlements, 1);
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('web', 'Test', 'web', 'com.test')")
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    /// Runs against a real server when `OPENCAT_TEST_POSTGRES_URL` is set.
+    #[tokio::test]
+    async fn test_postgres_connect_and_migrate() {
+        let Ok(url) = std::env::var("OPENCAT_TEST_POSTGRES_URL") else {
+            return;
+        };
+
+        let pool = connect(&url).await.unwrap();
+        let apps: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM apps")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(apps, 0);
+        migrator(Backend::Postgres).undo(&pool, 0).await.unwrap();
+        assert!(migration_status(&pool, Backend::Postgres).await.unwrap().iter().all(|m| !m.applied));
+        migrator(Backend::Postgres).run(&pool).await.unwrap();
+    }
 }
@@ -0,0 +1,73 @@
+use sqlx::AnyConnection;
+use tokio::sync::broadcast;
+use crate::db::DbPool;
+use crate::models::event::Event;
+
+/// How many events a slow live subscriber may fall behind before it starts
+/// missing them.
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Broadcasts every newly stored `Event` to live subscribers such as the SSE
+/// stream. Publishing with nobody listening is a no-op.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+}
+
+/// Store an event for `app_id` and queue its webhook deliveries on the same
+/// connection, so inside a transaction the event is never committed without
+/// them. Returns the event's ID; hand it to [`EventBus::publish_stored`] once committed.
+pub async fn record_event(
+    conn: &mut AnyConnection,
+    app_id: &str,
+    subscriber_id: Option<&str>,
+    event_type: &str,
+    payload: &str,
+) -> anyhow::Result<String> {
+    let event_id = uuid::Uuid::new_v4().to_string();
+
+    sqlx::query(
+        "INSERT INTO events (id, app_id, subscriber_id, event_type, payload, created_at) VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(&event_id)
+    .bind(app_id)
+    .bind(subscriber_id)
+    .bind(event_type)
+    .bind(payload)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(&mut *conn)
+    .await?;
+
+    crate::webhooks::fanout::enqueue_deliveries(conn, &event_id, app_id).await?;
+    Ok(event_id)
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Publish the event stored under `event_id`, if the insert produced one.
+    pub async fn publish_stored(&self, pool: &DbPool, event_id: &str) -> Result<(), sqlx::Error> {
+        let event = sqlx::query_as::<_, Event>("SELECT * FROM events WHERE id = $1")
+            .bind(event_id)
+            .fetch_optional(pool)
+            .await?;
+
+        if let Some(event) = event {
+            self.publish(event);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use crate::db::DbPool;
+use crate::events::{record_event, EventBus};
+use crate::transactions::event_payload;
+use crate::store::types::{PeriodType, Store, TransactionEvent, TransactionStatus, VerifiedTransaction};
+
+#[derive(sqlx::FromRow)]
+struct LapsedTransaction {
+    id: String,
+    app_id: String,
+    subscriber_id: String,
+    store: String,
+    store_transaction_id: String,
+    original_transaction_id: Option<String>,
+    store_product_id: String,
+    purchase_date: String,
+    expiration_date: String,
+    environment: String,
+    period_type: String,
+}
+
+/// Marks active transactions expired once their `expiration_date` passes and
+/// records an `EXPIRATION` event for each, so state stays correct when the
+/// store's own notification never arrives.
+pub struct ExpiryWorker {
+    pool: DbPool,
+    events: EventBus,
+    interval: Duration,
+}
+
+impl ExpiryWorker {
+    pub fn new(pool: DbPool, events: EventBus, interval: Duration) -> Self {
+        Self { pool, events, interval }
+    }
+
+    /// Scan for lapsed transactions every `interval` until `shutdown` is cancelled, finishing the current pass first.
+    pub async fn run(&self, shutdown: CancellationToken) {
+        while !shutdown.is_cancelled() {
+            if let Err(e) = self.expire_lapsed().await {
+                tracing::error!("Subscription expiry error: {e}");
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+        tracing::info!("Subscription expiry worker stopped");
+    }
+
+    /// Expire every active transaction past its expiration date, returning how many changed.
+    async fn expire_lapsed(&self) -> anyhow::Result<usize> {
+        let now = chrono::Utc::now().to_rfc3339();
+
+        // Dates are stored as RFC 3339 UTC strings, so they compare lexically
+        let lapsed = sqlx::query_as::<_, LapsedTransaction>(
+            "SELECT t.id, s.app_id, t.subscriber_id, t.store, t.store_transaction_id, t.original_transaction_id, p.store_product_id, t.purchase_date, t.expiration_date, t.environment, t.period_type
+             FROM transactions t
+             JOIN subscribers s ON s.id = t.subscriber_id
+             JOIN products p ON p.id = t.product_id
+             WHERE t.status = 'active' AND t.expiration_date IS NOT NULL AND t.expiration_date < $1
+             LIMIT 100"
+        )
+        .bind(&now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut expired = 0;
+        for lapsed in lapsed {
+            let Some(store) = Store::parse(&lapsed.store) else {
+                tracing::warn!("Transaction {} has unknown store {}", lapsed.id, lapsed.store);
+                continue;
+            };
+
+            let event = TransactionEvent {
+                event_type: "EXPIRATION".to_string(),
+                transaction: VerifiedTransaction {
+                    store_transaction_id: lapsed.store_transaction_id,
+                    original_transaction_id: lapsed.original_transaction_id,
+                    product_id: lapsed.store_product_id,
+                    purchase_date: lapsed.purchase_date,
+                    expiration_date: Some(lapsed.expiration_date),
+                    status: TransactionStatus::Expired,
+                    store,
+                    is_sandbox: lapsed.environment == "sandbox",
+                    period_type: PeriodType::parse(&lapsed.period_type),
+                    auto_renew: None,
+                    auto_resume_time: None,
+                },
+                renewal_info: None,
+            };
+            let mut tx = self.pool.begin().await?;
+
+            // A notification may have updated the transaction since the scan
+            let updated = sqlx::query(
+                "UPDATE transactions SET status = 'expired', updated_at = $1 WHERE id = $2 AND status = 'active'"
+            )
+            .bind(&now)
+            .bind(&lapsed.id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if updated == 0 {
+                continue;
+            }
+
+            let payload = event_payload(&event)?;
+            let event_id = record_event(&mut tx, &lapsed.app_id, Some(&lapsed.subscriber_id), &event.event_type, &payload).await?;
+
+            tx.commit().await?;
+            self.events.publish_stored(&self.pool, &event_id).await?;
+            expired += 1;
+        }
+
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    async fn seed_transaction(pool: &DbPool, id: &str, expiration_date: &str) {
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, expiration_date, status)
+             VALUES ($1, 'sub', 'prod', 'apple', $1, '2026-01-01T00:00:00Z', $2, 'active')"
+        )
+        .bind(id)
+        .bind(expiration_date)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lapsed_transaction_is_expired() {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('app', 'Test', 'ios', 'com.test')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ('prod', 'app', 'com.test.pro', 'subscription')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', 'app', 'user123')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        seed_transaction(&pool, "lapsed", "2026-01-02T00:00:00Z").await;
+        seed_transaction(&pool, "current", "2099-01-01T00:00:00Z").await;
+
+        let worker = ExpiryWorker::new(pool.clone(), EventBus::default(), Duration::from_secs(60));
+        assert_eq!(worker.expire_lapsed().await.unwrap(), 1);
+
+        let statuses: Vec<(String, String)> = sqlx::query_as("SELECT id, status FROM transactions ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(statuses, vec![
+            ("current".to_string(), "active".to_string()),
+            ("lapsed".to_string(), "expired".to_string()),
+        ]);
+
+        let (subscriber_id, event_type): (Option<String>, String) =
+            sqlx::query_as("SELECT subscriber_id, event_type FROM events")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(subscriber_id.as_deref(), Some("sub"));
+        assert_eq!(event_type, "EXPIRATION");
+
+        // Already expired, so a second tick does nothing
+        assert_eq!(worker.expire_lapsed().await.unwrap(), 0);
+    }
+}
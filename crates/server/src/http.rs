@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+/// Build the HTTP client shared by store adapters and the webhook worker.
+///
+/// `reqwest::Client` is a handle to a connection pool, so cloning it is cheap
+/// and keeps connections to the store APIs alive across requests.
+pub fn build_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(concat!("opencat-server/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(30))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(16)
+        .build()
+}
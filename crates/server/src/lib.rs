@@ -1,31 +1,107 @@
 pub mod api;
 pub mod cli;
 pub mod config;
+pub mod crypto;
+pub mod currency;
 pub mod db;
+pub mod events;
+pub mod expiry;
+pub mod http;
+pub mod metrics;
 pub mod models;
 pub mod store;
+pub mod shutdown;
+pub mod transactions;
+pub mod voided;
 pub mod webhooks;
+#[cfg(test)]
+pub(crate) mod test_support;
 
-use crate::config::AppConfig;
+use crate::config::{AppConfig, LogFormat};
+use crate::crypto::CredentialCipher;
+use crate::expiry::ExpiryWorker;
+use crate::voided::VoidedPurchaseWorker;
+use secrecy::ExposeSecret;
+use tokio_util::sync::CancellationToken;
+use crate::webhooks::delivery::WebhookDeliveryWorker;
 
 pub async fn run() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
+    let config = AppConfig::load()?;
+
+    let subscriber = tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "opencat_server=debug,tower_http=debug".parse().unwrap()),
-        )
-        .init();
+        );
+    match config.server.log_format {
+        LogFormat::Text => subscriber.init(),
+        // Span fields such as the request id become top-level keys
+        LogFormat::Json => subscriber.json().flatten_event(true).with_current_span(true).with_span_list(false).init(),
+    }
 
-    let config = AppConfig::load()?;
-    let pool = db::connect(&config.database.url).await?;
+    let pool = db::connect_with(&config.database).await?;
+    let metrics_handle = config.metrics.enabled.then(metrics::install).transpose()?;
+
+    let http = http::build_client()?;
 
-    let app = api::router(api::AppState { pool });
+    // Background workers finish their current pass once this is cancelled
+    let shutdown = CancellationToken::new();
+    let worker = WebhookDeliveryWorker::new(pool.clone(), http.clone(), config.webhooks.clone());
+    let worker_shutdown = shutdown.clone();
+    let worker_handle = tokio::spawn(async move { worker.run(worker_shutdown).await });
+
+    let events = events::EventBus::default();
+    let expiry_worker = ExpiryWorker::new(
+        pool.clone(),
+        events.clone(),
+        std::time::Duration::from_secs(config.expiry.interval_secs),
+    );
+    let expiry_shutdown = shutdown.clone();
+    let expiry_handle = tokio::spawn(async move { expiry_worker.run(expiry_shutdown).await });
+
+    let cipher = CredentialCipher::new(config.server.secret_key.expose_secret());
+    let voided_worker = VoidedPurchaseWorker::new(
+        pool.clone(),
+        events.clone(),
+        cipher.clone(),
+        http.clone(),
+        std::time::Duration::from_secs(config.voided_purchases.interval_secs),
+    );
+    let voided_shutdown = shutdown.clone();
+    let voided_handle = tokio::spawn(async move { voided_worker.run(voided_shutdown).await });
+
+    let mut app = api::router_with_limits(api::AppState {
+        pool,
+        cipher,
+        http,
+        events,
+        apple_verifier: store::apple_jws::AppleJwsVerifier::default(),
+        apple_environments: store::apple::AppleEnvironmentCache::default(),
+        google_verifier: store::google_push::GooglePushVerifier::new(
+            config.notifications.google_push_audience.clone(),
+            config.notifications.google_push_service_account.clone(),
+        ),
+        offerings_cache: api::offerings::OfferingsCache::new(
+            std::time::Duration::from_secs(config.offerings.cache_ttl_seconds),
+        ),
+        fx_rates: currency::FxRates::new(config.offerings.fx_rates.clone()),
+    }, api::RequestLimits::from_config(&config.server));
+    if let Some(handle) = metrics_handle {
+        app = app.merge(metrics::routes(handle));
+    }
+    let app = app.layer(api::cors::layer(&config.server.cors_origins));
 
     let addr = format!("{}:{}", config.server.host, config.server.port);
     tracing::info!("OpenCat server listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    shutdown::serve(listener, app, shutdown::signal()).await?;
+
+    tracing::info!("Shutting down background workers");
+    shutdown.cancel();
+    worker_handle.await?;
+    expiry_handle.await?;
+    voided_handle.await?;
 
     Ok(())
 }
@@ -0,0 +1,152 @@
+//! Prometheus metrics, served at `GET /metrics` when `[metrics] enabled = true`.
+//!
+//! Until [`install`] runs, recording a metric is a no-op.
+
+use std::time::Instant;
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+const REQUEST_DURATION: &str = "http_request_duration_seconds";
+
+const REQUEST_DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Exporter configured with our histogram buckets.
+pub fn builder() -> PrometheusBuilder {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(REQUEST_DURATION.to_string()), REQUEST_DURATION_BUCKETS)
+        .expect("bucket list is not empty")
+}
+
+/// Install the process-wide recorder, returning the handle that renders it.
+pub fn install() -> anyhow::Result<PrometheusHandle> {
+    Ok(builder().install_recorder()?)
+}
+
+/// The `/metrics` scrape endpoint.
+pub fn routes(handle: PrometheusHandle) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(handle)
+}
+
+async fn render(State(handle): State<PrometheusHandle>) -> String {
+    handle.render()
+}
+
+/// Count and time each request, labelled by route template rather than the
+/// raw path so IDs don't multiply the series.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let path = request.extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    ::metrics::counter!("http_requests_total", &labels).increment(1);
+    ::metrics::histogram!(REQUEST_DURATION, &labels).record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// A purchase verified (or not) with `store`.
+pub fn record_store_verification(store: &str, success: bool) {
+    let result = if success { "success" } else { "failure" };
+    ::metrics::counter!("store_verifications_total", "store" => store.to_string(), "result" => result).increment(1);
+}
+
+/// A webhook delivery attempt ending as `outcome`: delivered, failed or dead_letter.
+pub fn record_webhook_delivery(outcome: &'static str) {
+    ::metrics::counter!("webhook_deliveries_total", "outcome" => outcome).increment(1);
+}
+
+/// A store notification that was `result`: processed, duplicate, invalid or failed.
+pub fn record_notification(store: &str, result: &'static str) {
+    ::metrics::counter!("notifications_total", "store" => store.to_string(), "result" => result).increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_pool, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_scrape_reports_request_and_verification_counters() {
+        // Scoped to this test's thread so tests don't share a global recorder
+        let recorder = builder().build_recorder();
+        let _guard = ::metrics::set_default_local_recorder(&recorder);
+
+        // RVS rejects every receipt
+        let rvs = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&rvs)
+            .await;
+
+        let app = crate::api::router(test_state(test_pool().await)).merge(routes(recorder.handle()));
+
+        let (_, body) = send(&app, Request::builder()
+            .method("POST")
+            .uri("/v1/apps")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"name":"Test","platform":"ios","bundle_id":"com.test"}"#))
+            .unwrap()).await;
+        let created: Value = serde_json::from_str(&body).unwrap();
+        let app_id = created["id"].as_str().unwrap();
+        let api_key = created["api_key"].as_str().unwrap();
+
+        let (status, _) = send(&app, Request::builder()
+            .method("PUT")
+            .uri(format!("/v1/apps/{app_id}/credentials"))
+            .header("authorization", format!("Bearer {api_key}"))
+            .header("content-type", "application/json")
+            .body(Body::from(format!(r#"{{"amazon":{{"shared_secret":"secret","rvs_base_url":"{}"}}}}"#, rvs.uri())))
+            .unwrap()).await;
+        assert!(status.is_success());
+
+        let receipt = serde_json::json!({
+            "app_id": app_id,
+            "app_user_id": "user123",
+            "store": "amazon",
+            "receipt_data": r#"{"user_id":"amzn1","receipt_id":"r1"}"#,
+        });
+        let (status, _) = send(&app, Request::builder()
+            .method("POST")
+            .uri("/v1/receipts")
+            .header("authorization", format!("Bearer {api_key}"))
+            .header("content-type", "application/json")
+            .body(Body::from(receipt.to_string()))
+            .unwrap()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, scraped) = send(&app, Request::builder().uri("/metrics").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(scraped.contains(r#"http_requests_total{method="POST",path="/v1/receipts",status="400"} 1"#), "{scraped}");
+        assert!(scraped.contains(r#"http_request_duration_seconds_bucket{method="PUT",path="/v1/apps/{app_id}/credentials""#), "{scraped}");
+        assert!(scraped.contains(r#"store_verifications_total{store="amazon",result="failure"} 1"#), "{scraped}");
+    }
+}
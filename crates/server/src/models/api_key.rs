@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// API key metadata; the secret itself is only ever stored as a SHA-256 hash.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub app_id: String,
+    pub key_prefix: String,
+    pub permissions: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// Returned once from key creation; `key` cannot be retrieved again.
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
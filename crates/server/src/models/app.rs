@@ -1,36 +1,192 @@
 use serde::{Deserialize, Serialize};
+use crate::store::types::Store;
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct App {
     pub id: String,
     pub name: String,
-    pub platform: String,
+    #[sqlx(try_from = "String")]
+    pub platform: Platform,
     pub bundle_id: String,
     pub store_credentials_encrypted: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Returned once from app creation, with the app's first API key in plaintext.
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct CreatedApp {
+    #[serde(flatten)]
+    pub app: App,
+    pub api_key: String,
+}
+
+/// What an app runs on, stored as its lowercase name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Ios,
+    Android,
+    Amazon,
+    Web,
+    Macos,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ios => "ios",
+            Self::Android => "android",
+            Self::Amazon => "amazon",
+            Self::Web => "web",
+            Self::Macos => "macos",
+        }
+    }
+
+    /// Exact, case-sensitive match, so `"IOS"` is as unknown as `"andoid"`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ios" => Some(Self::Ios),
+            "android" => Some(Self::Android),
+            "amazon" => Some(Self::Amazon),
+            "web" => Some(Self::Web),
+            "macos" => Some(Self::Macos),
+            _ => None,
+        }
+    }
+
+    /// The store the platform's purchases go through, used when a receipt
+    /// doesn't name one.
+    pub fn store(&self) -> Store {
+        match self {
+            Self::Ios | Self::Macos => Store::Apple,
+            Self::Android => Store::Google,
+            Self::Amazon => Store::Amazon,
+            Self::Web => Store::Stripe,
+        }
+    }
+}
+
+impl TryFrom<String> for Platform {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| format!("Unknown platform: {value}"))
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
 pub struct CreateApp {
     pub name: String,
+    /// One of [`Platform`]'s names; checked by the handler so a typo is a 400.
     pub platform: String,
     pub bundle_id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
 pub struct UpdateStoreCredentials {
     pub apple: Option<AppleCredentials>,
+    pub google: Option<GoogleCredentials>,
+    pub amazon: Option<AmazonCredentials>,
+    pub stripe: Option<StripeCredentials>,
+    /// Forget Apple keys replaced by earlier updates instead of keeping them
+    /// as fallbacks; send once the new key is known to work.
+    #[serde(default)]
+    pub retire_previous_apple_keys: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct AppleCredentials {
     pub issuer_id: String,
+    /// The primary key, which everything is signed with.
     pub key_id: String,
     pub private_key: String,
+    /// Keys the primary replaced, tried in order when Apple rejects it so a
+    /// rotation doesn't break verification before the new key is live.
+    #[serde(default)]
+    pub previous_keys: Vec<AppleKey>,
+    /// App-specific shared secret, needed to verify legacy subscription receipts.
+    #[serde(default)]
+    pub shared_secret: Option<String>,
+    /// The app has its customers' consent to share consumption data with
+    /// Apple, so CONSUMPTION_REQUEST notifications get answered.
+    #[serde(default)]
+    pub consumption_consent: bool,
+    /// The app's Apple ID from App Store Connect. Production notifications
+    /// carrying a different `appAppleId` are rejected.
+    #[serde(default)]
+    pub app_apple_id: Option<i64>,
+    /// Which App Store environment the app's purchases are verified in.
+    #[serde(default)]
+    pub environment: AppleEnvironmentSetting,
+}
+
+/// The App Store environment an app's credentials are used against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AppleEnvironmentSetting {
+    /// Production, falling back to the sandbox for transactions production
+    /// doesn't know, as App Review's are.
+    #[default]
+    Auto,
+    Production,
+    /// Sandbox only, for apps distributed solely through TestFlight.
+    Sandbox,
+}
+
+impl AppleCredentials {
+    /// Keep `existing`'s keys as fallbacks behind this one's primary key,
+    /// unless they are the same key.
+    pub fn rotate_from(mut self, existing: Option<&AppleCredentials>) -> Self {
+        if let Some(existing) = existing {
+            let replaced = AppleKey { key_id: existing.key_id.clone(), private_key: existing.private_key.clone() };
+            for key in std::iter::once(replaced).chain(existing.previous_keys.iter().cloned()) {
+                if key.key_id != self.key_id && !self.previous_keys.iter().any(|k| k.key_id == key.key_id) {
+                    self.previous_keys.push(key);
+                }
+            }
+        }
+        self
+    }
+}
+
+/// An App Store Connect API key: its ID and `.p8` private key.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AppleKey {
+    pub key_id: String,
+    pub private_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct GoogleCredentials {
+    pub service_account_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AmazonCredentials {
+    /// Developer shared secret for the Receipt Verification Service.
+    pub shared_secret: String,
+    /// Override the RVS root, e.g. to verify against the App Tester sandbox.
+    #[serde(default)]
+    pub rvs_base_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct StripeCredentials {
+    /// Secret (`sk_...`) or restricted (`rk_...`) API key with read access to subscriptions.
+    pub secret_key: String,
+    /// Override the API root, e.g. to point at stripe-mock.
+    #[serde(default)]
+    pub api_base_url: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoreCredentials {
     pub apple: Option<AppleCredentials>,
+    #[serde(default)]
+    pub google: Option<GoogleCredentials>,
+    #[serde(default)]
+    pub amazon: Option<AmazonCredentials>,
+    #[serde(default)]
+    pub stripe: Option<StripeCredentials>,
 }
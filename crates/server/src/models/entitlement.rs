@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct Entitlement {
     pub id: String,
     pub app_id: String,
@@ -9,8 +9,50 @@ pub struct Entitlement {
     pub created_at: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// An entitlement a subscriber currently holds, with the latest expiration
+/// across the transactions granting it (`None` means it never expires).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct ActiveEntitlement {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub entitlement: Entitlement,
+    pub expires_at: Option<String>,
+}
+
+/// Where a subscriber stands with one entitlement, rolled up from the
+/// transactions that grant it.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct EntitlementStatus {
+    /// Whether the entitlement is granted right now, including during a grace period.
+    pub is_active: bool,
+    /// Latest expiration across those transactions (`None` means it never expires).
+    pub expires_at: Option<String>,
+    /// Store product of the transaction the rest of the status describes.
+    pub product_id: String,
+    pub store: String,
+    /// `normal`, `trial` or `intro`.
+    pub period_type: String,
+    /// `None` until the store has told us.
+    pub will_renew: Option<bool>,
+    /// Past expiration but still granted until `grace_period_expires_at`;
+    /// implies `is_active`.
+    pub in_grace_period: bool,
+    pub grace_period_expires_at: Option<String>,
+    pub in_billing_retry: bool,
+    /// Paused by the subscriber, granting nothing until it resumes.
+    pub is_paused: bool,
+    pub auto_resume_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
 pub struct CreateEntitlement {
     pub name: String,
     pub description: Option<String>,
 }
+
+/// Fields left out are unchanged.
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct UpdateEntitlement {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
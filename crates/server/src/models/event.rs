@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct Event {
     pub id: String,
-    pub subscriber_id: String,
+    pub subscriber_id: Option<String>,
+    /// `None` only for events recorded before events were tied to apps.
+    pub app_id: Option<String>,
     pub event_type: String,
     pub payload: String,
     pub created_at: String,
@@ -1,6 +1,8 @@
+pub mod api_key;
 pub mod app;
 pub mod entitlement;
 pub mod event;
+pub mod offering;
 pub mod product;
 pub mod subscriber;
 pub mod transaction;
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Offering {
+    pub id: String,
+    pub app_id: String,
+    pub identifier: String,
+    pub description: Option<String>,
+    pub is_current: i32,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Package {
+    pub id: String,
+    pub offering_id: String,
+    pub identifier: String,
+    pub package_type: String,
+    pub product_id: String,
+    pub position: i32,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct CreateOffering {
+    pub identifier: String,
+    pub description: Option<String>,
+    /// Make this the app's current offering, replacing any previous one.
+    #[serde(default)]
+    pub is_current: bool,
+    pub packages: Vec<CreatePackage>,
+}
+
+/// The offering shown when no override or experiment applies, by identifier.
+/// `null` leaves the app without one; the field can't be left out, so a
+/// malformed body doesn't clear it by accident.
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CurrentOffering {
+    #[serde(deserialize_with = "Option::deserialize")]
+    #[schema(required = true)]
+    pub current_offering_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct CreatePackage {
+    pub identifier: String,
+    pub package_type: String,
+    pub product_id: String,
+}
+
+/// Shows `offering_id` instead of the current offering to users matching
+/// every column that is set.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OfferingOverride {
+    pub id: String,
+    pub app_id: String,
+    pub offering_id: String,
+    /// Territory code, as sent in `?country=`.
+    pub country: Option<String>,
+    /// Lowest dotted app version (e.g. `2.1`) the override applies to.
+    pub min_app_version: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct CreateOfferingOverride {
+    /// Identifier of the offering to show.
+    pub offering: String,
+    pub country: Option<String>,
+    pub min_app_version: Option<String>,
+}
+
+/// An offering A/B test; see [`ExperimentVariant`].
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Experiment {
+    pub id: String,
+    pub app_id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+/// One arm of an [`Experiment`], shown to a `weight`-proportional share of users.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ExperimentVariant {
+    pub id: String,
+    pub experiment_id: String,
+    pub name: String,
+    pub offering_id: String,
+    pub weight: i32,
+    pub position: i32,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct CreateExperiment {
+    pub name: String,
+    pub variants: Vec<CreateExperimentVariant>,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct CreateExperimentVariant {
+    pub name: String,
+    /// Identifier of the offering to show.
+    pub offering: String,
+    pub weight: u32,
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct Product {
     pub id: String,
     pub app_id: String,
@@ -11,14 +11,41 @@ pub struct Product {
     pub price_micros: Option<i64>,
     pub currency: Option<String>,
     pub subscription_period: Option<String>,
+    /// Length of the introductory offer.
     pub trial_period: Option<String>,
+    pub intro_price_micros: Option<i64>,
+    pub intro_currency: Option<String>,
+    /// `free_trial`, `pay_as_you_go` or `pay_up_front`.
+    pub offer_mode: Option<String>,
     pub last_synced_at: Option<String>,
     pub created_at: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
 pub struct CreateProduct {
     pub store_product_id: String,
     pub product_type: String,
     pub entitlement_ids: Vec<String>,
 }
+
+/// Fields left out are unchanged; `entitlement_ids` replaces every existing mapping.
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct UpdateProduct {
+    pub product_type: Option<String>,
+    pub entitlement_ids: Option<Vec<String>>,
+}
+
+/// One row of a bulk import, matched to existing products by `store_product_id`.
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct ImportProduct {
+    pub store_product_id: String,
+    pub product_type: String,
+    /// Entitlement names; missing ones are created. Replaces every existing mapping.
+    pub entitlements: Vec<String>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct ImportSummary {
+    pub created: usize,
+    pub updated: usize,
+}
@@ -1,9 +1,25 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct Subscriber {
     pub id: String,
     pub app_id: String,
     pub app_user_id: String,
     pub created_at: String,
 }
+
+/// A custom attribute value and when the client set it.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct SubscriberAttribute {
+    pub value: String,
+    pub updated_at_ms: i64,
+}
+
+/// One attribute in a `POST .../attributes` body: either a bare value, or a
+/// value with the client's timestamp. `null` clears the attribute.
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(untagged)]
+pub enum AttributeUpdate {
+    Timestamped { value: Option<String>, updated_at_ms: i64 },
+    Value(Option<String>),
+}
@@ -1,16 +1,25 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 pub struct Transaction {
     pub id: String,
     pub subscriber_id: String,
     pub product_id: String,
     pub store: String,
     pub store_transaction_id: String,
+    /// Shared by every renewal of the same subscription; see
+    /// `GET /v1/apps/{app_id}/subscriptions/{original_transaction_id}`.
+    pub original_transaction_id: Option<String>,
     pub purchase_date: String,
     pub expiration_date: Option<String>,
     pub status: String,
     pub raw_receipt: Option<String>,
+    /// `production` or `sandbox`.
+    pub environment: String,
+    /// `trial`, `intro` or `normal`: the pricing phase the period was bought in.
+    pub period_type: String,
+    /// When a paused subscription resumes on its own; `None` unless paused.
+    pub auto_resume_time: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use tokio::net::TcpListener;
+
+/// Counts requests currently being handled, so shutdown can say how many it drained.
+#[derive(Debug, Clone, Default)]
+pub struct InFlightRequests(Arc<AtomicUsize>);
+
+impl InFlightRequests {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Decrements on drop, so requests whose connection goes away still count down.
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+async fn track(State(in_flight): State<InFlightRequests>, request: Request, next: Next) -> Response {
+    in_flight.0.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlightGuard(in_flight.0.clone());
+    next.run(request).await
+}
+
+/// Serve `app` until `signal` resolves, then stop accepting connections and
+/// wait for requests already in flight to finish.
+pub async fn serve<F>(listener: TcpListener, app: Router, signal: F) -> std::io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let in_flight = InFlightRequests::default();
+    let app = app.layer(middleware::from_fn_with_state(in_flight.clone(), track));
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            signal.await;
+            tracing::info!("Shutdown requested, draining {} in-flight requests", in_flight.count());
+        })
+        .await?;
+
+    tracing::info!("In-flight requests drained");
+    Ok(())
+}
+
+/// Resolves on Ctrl+C or, on Unix, SIGTERM.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests_and_stops_accepting() {
+        let app = Router::new().route("/slow", get(|| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, async {
+            let _ = signal.await;
+        }));
+
+        let slow = tokio::spawn(reqwest::Client::new().get(format!("http://{addr}/slow")).send());
+
+        // Shut down while the slow request is being handled
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        trigger.send(()).unwrap();
+
+        let response = slow.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+}
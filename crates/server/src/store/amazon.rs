@@ -0,0 +1,146 @@
+use super::{StoreAdapter, StoreError, types::*};
+use super::retry::send_with_retry;
+use reqwest::Client;
+use serde::Deserialize;
+
+const RVS_BASE: &str = "https://appstore-sdk.amazon.com";
+
+/// Verifies Amazon Appstore receipts with the Receipt Verification Service.
+pub struct AmazonAppstoreAdapter {
+    client: Client,
+    shared_secret: String,
+    api_base: String,
+}
+
+/// What clients submit as `receipt_data`: RVS needs both the receipt and the
+/// Amazon user it belongs to.
+#[derive(Deserialize)]
+struct AmazonReceipt {
+    user_id: String,
+    receipt_id: String,
+}
+
+impl AmazonAppstoreAdapter {
+    pub fn new(client: Client, shared_secret: String) -> Self {
+        Self {
+            client,
+            shared_secret,
+            api_base: RVS_BASE.to_string(),
+        }
+    }
+
+    /// Point at a different RVS root, e.g. the App Tester sandbox.
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl StoreAdapter for AmazonAppstoreAdapter {
+    async fn verify_purchase(&self, receipt_data: &str) -> Result<VerifiedTransaction, StoreError> {
+        let receipt: AmazonReceipt = serde_json::from_str(receipt_data)
+            .map_err(|e| StoreError::Invalid(format!("Expected {{\"user_id\", \"receipt_id\"}} receipt data: {e}")))?;
+
+        let url = format!(
+            "{}/version/1.0/verifyReceiptId/developer/{}/user/{}/receiptId/{}",
+            self.api_base, self.shared_secret, receipt.user_id, receipt.receipt_id
+        );
+
+        let response = send_with_retry(|| self.client.get(&url)).await?;
+
+        match response.status().as_u16() {
+            200 => {}
+            400 => return Err(StoreError::Invalid("Invalid Amazon receipt".to_string())),
+            496 => return Err(StoreError::Auth("Invalid Amazon shared secret".to_string())),
+            497 => return Err(StoreError::Invalid("Invalid Amazon user ID".to_string())),
+            _ => return Err(StoreError::from_status(response.status(), "Amazon RVS error")),
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        Ok(parse_receipt(&body))
+    }
+
+    async fn get_subscription_status(&self, receipt_data: &str) -> Result<VerifiedTransaction, StoreError> {
+        self.verify_purchase(receipt_data).await
+    }
+
+    async fn process_notification(&self, _payload: &[u8]) -> Result<Vec<TransactionEvent>, StoreError> {
+        Err(StoreError::Invalid("Amazon notifications are not supported".to_string()))
+    }
+}
+
+fn millis_to_rfc3339(value: &serde_json::Value) -> Option<String> {
+    value.as_i64()
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|d| d.to_rfc3339())
+}
+
+fn parse_receipt(body: &serde_json::Value) -> VerifiedTransaction {
+    let now = chrono::Utc::now();
+    let cancelled_at = body["cancelDate"].as_i64().and_then(chrono::DateTime::from_timestamp_millis);
+    let grace_period_ends = body["gracePeriodEndDate"].as_i64().and_then(chrono::DateTime::from_timestamp_millis);
+
+    // cancelReason 0 is a customer refund; anything else ended the purchase some other way
+    let status = match cancelled_at {
+        Some(_) if body["cancelReason"].as_i64() == Some(0) => TransactionStatus::Refunded,
+        Some(at) if at <= now => TransactionStatus::Expired,
+        _ if grace_period_ends.is_some_and(|end| end > now) => TransactionStatus::GracePeriod,
+        _ => TransactionStatus::Active,
+    };
+
+    VerifiedTransaction {
+        store_transaction_id: body["receiptId"].as_str().unwrap_or_default().to_string(),
+        original_transaction_id: None,
+        product_id: body["termSku"].as_str()
+            .or(body["productId"].as_str())
+            .unwrap_or_default()
+            .to_string(),
+        purchase_date: millis_to_rfc3339(&body["purchaseDate"]).unwrap_or_default(),
+        expiration_date: millis_to_rfc3339(&body["cancelDate"]).or_else(|| millis_to_rfc3339(&body["renewalDate"])),
+        status,
+        store: Store::Amazon,
+        is_sandbox: body["testTransaction"].as_bool().unwrap_or(false),
+        period_type: match body["freeTrialEndDate"].as_i64().and_then(chrono::DateTime::from_timestamp_millis) {
+            Some(end) if end > now => PeriodType::Trial,
+            _ => PeriodType::Normal,
+        },
+        auto_renew: None,
+        auto_resume_time: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rvs_subscription_receipt() {
+        let body = serde_json::json!({
+            "autoRenewing": true,
+            "betaProduct": false,
+            "cancelDate": null,
+            "cancelReason": null,
+            "freeTrialEndDate": null,
+            "gracePeriodEndDate": null,
+            "parentProductId": "com.test.pro",
+            "productId": "com.test.pro",
+            "productType": "SUBSCRIPTION",
+            "purchaseDate": 1767225600000_i64,
+            "quantity": null,
+            "receiptId": "q1YqVbJSyjH28DGAQN6D2ogA",
+            "renewalDate": 4102444800000_i64,
+            "term": "1 Month",
+            "termSku": "com.test.pro.monthly",
+            "testTransaction": false
+        });
+
+        let tx = parse_receipt(&body);
+        assert_eq!(tx.store_transaction_id, "q1YqVbJSyjH28DGAQN6D2ogA");
+        assert_eq!(tx.product_id, "com.test.pro.monthly");
+        assert_eq!(tx.purchase_date, "2026-01-01T00:00:00+00:00");
+        assert_eq!(tx.expiration_date.as_deref(), Some("2100-01-01T00:00:00+00:00"));
+        assert!(matches!(tx.status, TransactionStatus::Active));
+        assert!(matches!(tx.store, Store::Amazon));
+    }
+}
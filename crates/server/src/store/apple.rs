@@ -1,23 +1,246 @@
-use super::{StoreAdapter, types::*};
+use super::{StoreAdapter, StoreError, apple_jws::AppleJwsVerifier, types::*};
+use super::retry::send_with_retry;
+use super::token_cache::TokenCache;
+use crate::models::app::AppleKey;
+use crate::models::subscriber::Subscriber;
 use reqwest::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// App Store Server API tokens may be valid for at most an hour.
+const JWT_TTL_SECS: i64 = 3600;
+
+const API_PRODUCTION_URL: &str = "https://api.storekit.itunes.apple.com";
+const API_SANDBOX_URL: &str = "https://api.storekit-sandbox.itunes.apple.com";
+
+/// Forget remembered environments past this many transactions rather than grow unbounded.
+const ENVIRONMENT_CACHE_CAPACITY: usize = 10_000;
+
+const VERIFY_RECEIPT_PRODUCTION_URL: &str = "https://buy.itunes.apple.com/verifyReceipt";
+const VERIFY_RECEIPT_SANDBOX_URL: &str = "https://sandbox.itunes.apple.com/verifyReceipt";
+
+/// Separates the fields of a promotional offer signature payload.
+const OFFER_PAYLOAD_SEPARATOR: char = '\u{2063}';
+
+/// `verifyReceipt` status for a sandbox receipt sent to production.
+const STATUS_SANDBOX_RECEIPT: i64 = 21007;
+/// `verifyReceipt` status for a production receipt sent to the sandbox.
+const STATUS_PRODUCTION_RECEIPT: i64 = 21008;
+/// `verifyReceipt` status when the shared secret doesn't match.
+const STATUS_SHARED_SECRET_MISMATCH: i64 = 21004;
+/// `verifyReceipt` status when Apple can't serve the request; 21100-21199 are
+/// internal errors too.
+const STATUS_SERVICE_UNAVAILABLE: i64 = 21005;
 
 pub struct AppleStoreAdapter {
     client: Client,
     issuer_id: String,
     key_id: String,
     private_key: String,
+    previous_keys: Vec<AppleKey>,
     bundle_id: String,
     environment: AppleEnvironment,
+    environment_fallback: bool,
+    verifier: AppleJwsVerifier,
+    token_cache: TokenCache,
+    shared_secret: Option<String>,
+    api_urls: (String, String),
+    verify_receipt_urls: (String, String),
+    environment_cache: AppleEnvironmentCache,
+    consumption_consent: bool,
+    app_apple_id: Option<i64>,
+}
+
+/// A purchase's transactions from the App Store Server API's history.
+#[derive(Debug)]
+pub struct TransactionHistory {
+    pub transactions: Vec<VerifiedTransaction>,
+    /// Pass back to [`AppleStoreAdapter::get_transaction_history`] to fetch
+    /// only later changes.
+    pub revision: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AppleEnvironment {
     Production,
     Sandbox,
 }
 
+impl AppleEnvironment {
+    fn other(self) -> Self {
+        match self {
+            Self::Production => Self::Sandbox,
+            Self::Sandbox => Self::Production,
+        }
+    }
+}
+
+/// Remembers which environment each transaction was found in, so sandbox
+/// purchases (including App Review's) skip the failed production lookup next time.
+#[derive(Debug, Clone, Default)]
+pub struct AppleEnvironmentCache {
+    environments: Arc<Mutex<HashMap<String, AppleEnvironment>>>,
+}
+
+impl AppleEnvironmentCache {
+    fn get(&self, transaction_id: &str) -> Option<AppleEnvironment> {
+        let environments = self.environments.lock().unwrap_or_else(|e| e.into_inner());
+        environments.get(transaction_id).copied()
+    }
+
+    fn insert(&self, transaction_id: &str, environment: AppleEnvironment) {
+        let mut environments = self.environments.lock().unwrap_or_else(|e| e.into_inner());
+        if environments.len() >= ENVIRONMENT_CACHE_CAPACITY {
+            environments.clear();
+        }
+        environments.insert(transaction_id.to_string(), environment);
+    }
+}
+
+/// Body of a consumption-information response to a CONSUMPTION_REQUEST
+/// notification, which Apple weighs when deciding a customer's refund request.
+///
+/// Enumerated fields use Apple's codes; 0 always means "undeclared".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumptionRequest {
+    pub customer_consented: bool,
+    pub consumption_status: u8,
+    pub platform: u8,
+    pub sample_content_provided: bool,
+    pub delivery_status: u8,
+    pub app_account_token: String,
+    pub account_tenure: u8,
+    pub play_time: u8,
+    pub lifetime_dollars_refunded: u8,
+    pub lifetime_dollars_purchased: u8,
+    pub user_status: u8,
+    pub refund_preference: u8,
+}
+
+/// What we know about a subscriber's purchase history, in USD micros.
+#[derive(Debug, Clone, Default)]
+pub struct ConsumptionUsage {
+    pub purchased_micros: i64,
+    pub refunded_micros: i64,
+    pub has_active_subscription: bool,
+}
+
+impl ConsumptionRequest {
+    /// Map a subscriber record and their purchase history onto Apple's buckets.
+    /// We don't track usage, so consumption and play time stay undeclared.
+    pub fn for_subscriber(subscriber: &Subscriber, usage: &ConsumptionUsage, now: chrono::DateTime<chrono::Utc>) -> Self {
+        let account_tenure = chrono::DateTime::parse_from_rfc3339(&subscriber.created_at)
+            .map(|created| tenure_bucket((now - created.with_timezone(&chrono::Utc)).num_days()))
+            .unwrap_or(0);
+
+        Self {
+            customer_consented: true,
+            consumption_status: 0,
+            // The purchase was made through the App Store
+            platform: 1,
+            sample_content_provided: false,
+            // Delivered and working
+            delivery_status: 0,
+            app_account_token: String::new(),
+            account_tenure,
+            play_time: 0,
+            lifetime_dollars_refunded: dollars_bucket(usage.refunded_micros),
+            lifetime_dollars_purchased: dollars_bucket(usage.purchased_micros),
+            // 1 = active, 0 = undeclared
+            user_status: u8::from(usage.has_active_subscription),
+            refund_preference: 0,
+        }
+    }
+}
+
+fn tenure_bucket(days: i64) -> u8 {
+    match days {
+        ..0 => 0,
+        0..3 => 1,
+        3..10 => 2,
+        10..30 => 3,
+        30..90 => 4,
+        90..180 => 5,
+        180..365 => 6,
+        _ => 7,
+    }
+}
+
+fn dollars_bucket(micros: i64) -> u8 {
+    match micros {
+        ..0 => 0,
+        0 => 1,
+        1..50_000_000 => 2,
+        50_000_000..100_000_000 => 3,
+        100_000_000..500_000_000 => 4,
+        500_000_000..1_000_000_000 => 5,
+        1_000_000_000..2_000_000_000 => 6,
+        _ => 7,
+    }
+}
+
+/// A promotional offer signed for StoreKit, which passes these fields through
+/// unchanged when the customer redeems the offer.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedOffer {
+    pub key_identifier: String,
+    pub nonce: String,
+    pub timestamp: i64,
+    /// Base64 DER-encoded ECDSA P-256 signature.
+    pub signature: String,
+}
+
+/// The string Apple expects promotional offer signatures over: the fields
+/// joined by U+2063 in this exact order, with the nonce and app account token
+/// in lowercase.
+fn promotional_offer_payload(
+    bundle_id: &str,
+    key_id: &str,
+    product_id: &str,
+    offer_id: &str,
+    app_account_token: &str,
+    nonce: &str,
+    timestamp: i64,
+) -> String {
+    [
+        bundle_id,
+        key_id,
+        product_id,
+        offer_id,
+        &app_account_token.to_lowercase(),
+        &nonce.to_lowercase(),
+        &timestamp.to_string(),
+    ]
+    .join(&OFFER_PAYLOAD_SEPARATOR.to_string())
+}
+
+/// Convert a fixed-width `r || s` ECDSA signature, as JWS uses, to the DER
+/// `SEQUENCE { r INTEGER, s INTEGER }` StoreKit expects.
+fn ecdsa_der(raw: &[u8]) -> Vec<u8> {
+    let integer = |bytes: &[u8]| {
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len() - 1);
+        let mut value = bytes[start..].to_vec();
+        if value[0] & 0x80 != 0 {
+            value.insert(0, 0);
+        }
+        let mut encoded = vec![0x02, value.len() as u8];
+        encoded.extend(value);
+        encoded
+    };
+
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let body = [integer(r), integer(s)].concat();
+    let mut der = vec![0x30, body.len() as u8];
+    der.extend(body);
+    der
+}
+
 impl AppleStoreAdapter {
     pub fn new(
+        client: Client,
         issuer_id: String,
         key_id: String,
         private_key: String,
@@ -25,139 +248,1256 @@ impl AppleStoreAdapter {
         environment: AppleEnvironment,
     ) -> Self {
         Self {
-            client: Client::new(),
+            client,
             issuer_id,
             key_id,
             private_key,
+            previous_keys: Vec::new(),
             bundle_id,
             environment,
+            environment_fallback: true,
+            verifier: AppleJwsVerifier::default(),
+            token_cache: TokenCache::default(),
+            shared_secret: None,
+            api_urls: (API_PRODUCTION_URL.to_string(), API_SANDBOX_URL.to_string()),
+            verify_receipt_urls: (
+                VERIFY_RECEIPT_PRODUCTION_URL.to_string(),
+                VERIFY_RECEIPT_SANDBOX_URL.to_string(),
+            ),
+            environment_cache: AppleEnvironmentCache::default(),
+            consumption_consent: false,
+            app_apple_id: None,
+        }
+    }
+
+    /// Keys to fall back on, in order, when Apple rejects the primary one
+    /// with 401, as it can mid-rotation. Signing always uses the primary key.
+    pub fn with_previous_keys(mut self, keys: Vec<AppleKey>) -> Self {
+        self.previous_keys = keys;
+        self
+    }
+
+    /// Share remembered transaction environments with other adapters.
+    pub fn with_environment_cache(mut self, cache: AppleEnvironmentCache) -> Self {
+        self.environment_cache = cache;
+        self
+    }
+
+    /// Whether to retry in the other environment when a transaction isn't
+    /// found in the first. Off pins the adapter to its environment.
+    pub fn with_environment_fallback(mut self, fallback: bool) -> Self {
+        self.environment_fallback = fallback;
+        self
+    }
+
+    /// Use other App Store Server API roots, e.g. a mock server in tests.
+    pub fn with_api_urls(mut self, production: impl Into<String>, sandbox: impl Into<String>) -> Self {
+        self.api_urls = (production.into(), sandbox.into());
+        self
+    }
+
+    /// App-specific shared secret, which `verifyReceipt` requires for
+    /// auto-renewable subscriptions.
+    pub fn with_shared_secret(mut self, shared_secret: Option<String>) -> Self {
+        self.shared_secret = shared_secret;
+        self
+    }
+
+    /// Send legacy receipts to other `verifyReceipt` endpoints, e.g. a mock server in tests.
+    pub fn with_verify_receipt_urls(mut self, production: impl Into<String>, sandbox: impl Into<String>) -> Self {
+        self.verify_receipt_urls = (production.into(), sandbox.into());
+        self
+    }
+
+    /// Whether customers agreed to their consumption data being shared with Apple.
+    pub fn with_consumption_consent(mut self, consented: bool) -> Self {
+        self.consumption_consent = consented;
+        self
+    }
+
+    /// The app's Apple ID, checked against notifications' `appAppleId`.
+    pub fn with_app_apple_id(mut self, app_apple_id: Option<i64>) -> Self {
+        self.app_apple_id = app_apple_id;
+        self
+    }
+
+    /// Reject a verified payload Apple signed for some other app, so one
+    /// app's genuine notifications can't be replayed against another. Fields
+    /// Apple left out aren't held against it: sandbox payloads carry no
+    /// `appAppleId`.
+    fn check_app(&self, decoded: &serde_json::Value) -> Result<(), StoreError> {
+        if let Some(bundle_id) = decoded["bundleId"].as_str().filter(|b| *b != self.bundle_id) {
+            return Err(StoreError::WrongApp(format!("signed for bundle {bundle_id}, not {}", self.bundle_id)));
         }
+        if let (Some(expected), Some(app_apple_id)) = (self.app_apple_id, decoded["appAppleId"].as_i64()) {
+            if app_apple_id != expected {
+                return Err(StoreError::WrongApp(format!("signed for Apple ID {app_apple_id}, not {expected}")));
+            }
+        }
+        Ok(())
     }
 
-    fn base_url(&self) -> &str {
-        match self.environment {
-            AppleEnvironment::Production => "https://api.storekit.itunes.apple.com",
-            AppleEnvironment::Sandbox => "https://api.storekit-sandbox.itunes.apple.com",
+    pub fn has_consumption_consent(&self) -> bool {
+        self.consumption_consent
+    }
+
+    /// Override the trust anchor used to verify signed payloads.
+    pub fn with_verifier(mut self, verifier: AppleJwsVerifier) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    fn base_url(&self, environment: AppleEnvironment) -> &str {
+        match environment {
+            AppleEnvironment::Production => &self.api_urls.0,
+            AppleEnvironment::Sandbox => &self.api_urls.1,
         }
     }
 
+    /// The App Store Server API root tried first.
+    pub fn api_base_url(&self) -> &str {
+        self.base_url(self.environment)
+    }
+
+    /// Environments to look `transaction_id` up in, in order: the one it was
+    /// last found in first, then the other unless the adapter is pinned.
+    fn environments(&self, transaction_id: &str) -> Vec<AppleEnvironment> {
+        if !self.environment_fallback {
+            return vec![self.environment];
+        }
+        let first = self.environment_cache.get(transaction_id).unwrap_or(self.environment);
+        vec![first, first.other()]
+    }
+
+    /// Look a transaction up in one environment; `None` if that environment doesn't know it.
+    async fn fetch_transaction(
+        &self,
+        environment: AppleEnvironment,
+        transaction_id: &str,
+    ) -> Result<Option<VerifiedTransaction>, StoreError> {
+        let url = format!("{}/inApps/v1/transactions/{}", self.base_url(environment), transaction_id);
+        let response = self.send_authorized(|| self.client.get(&url)).await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(StoreError::from_status(response.status(), "Apple API error"));
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        let signed_transaction = body["signedTransactionInfo"]
+            .as_str()
+            .ok_or_else(|| StoreError::Upstream("Missing signedTransactionInfo".to_string()))?;
+
+        let decoded = self.verifier.decode(signed_transaction)
+            .map_err(|e| StoreError::Upstream(e.to_string()))?;
+
+        let mut transaction = parse_transaction(&decoded);
+        transaction.is_sandbox = environment == AppleEnvironment::Sandbox;
+        Ok(Some(transaction))
+    }
+
+    /// The latest transaction of every subscription group the customer behind
+    /// `original_transaction_id` has, with its status as Apple reports it
+    /// rather than inferred from the transaction's dates.
+    pub async fn get_all_subscription_statuses(
+        &self,
+        original_transaction_id: &str,
+    ) -> Result<Vec<VerifiedTransaction>, StoreError> {
+        for environment in self.environments(original_transaction_id) {
+            if let Some(statuses) = self.fetch_statuses(environment, original_transaction_id).await? {
+                self.environment_cache.insert(original_transaction_id, environment);
+                return Ok(statuses);
+            }
+        }
+
+        Err(StoreError::NotFound(format!("Apple transaction {original_transaction_id} not found")))
+    }
+
+    /// Subscription statuses from one environment; `None` if that environment
+    /// doesn't know the transaction.
+    async fn fetch_statuses(
+        &self,
+        environment: AppleEnvironment,
+        original_transaction_id: &str,
+    ) -> Result<Option<Vec<VerifiedTransaction>>, StoreError> {
+        let url = format!("{}/inApps/v1/subscriptions/{}", self.base_url(environment), original_transaction_id);
+        let response = self.send_authorized(|| self.client.get(&url)).await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(StoreError::from_status(response.status(), "Apple API error"));
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        let mut statuses = Vec::new();
+        for group in body["data"].as_array().into_iter().flatten() {
+            for last in group["lastTransactions"].as_array().into_iter().flatten() {
+                let signed_transaction = last["signedTransactionInfo"]
+                    .as_str()
+                    .ok_or_else(|| StoreError::Upstream("Missing signedTransactionInfo".to_string()))?;
+                let decoded = self.verifier.decode(signed_transaction)
+                    .map_err(|e| StoreError::Upstream(e.to_string()))?;
+
+                let mut transaction = parse_transaction(&decoded);
+                transaction.is_sandbox = environment == AppleEnvironment::Sandbox;
+                if let Some(status) = last["status"].as_i64().and_then(subscription_status) {
+                    transaction.status = status;
+                }
+                if let Some(signed_renewal) = last["signedRenewalInfo"].as_str() {
+                    let renewal = self.verifier.decode(signed_renewal)
+                        .map_err(|e| StoreError::Upstream(e.to_string()))?;
+                    transaction.auto_renew = parse_renewal_info(&renewal).auto_renew_status;
+                }
+                statuses.push(transaction);
+            }
+        }
+
+        Ok(Some(statuses))
+    }
+
+    /// Every transaction in the history of the purchase `original_transaction_id`,
+    /// following Apple's pages. With the `revision` a previous call returned,
+    /// only what changed since then.
+    pub async fn get_transaction_history(
+        &self,
+        original_transaction_id: &str,
+        revision: Option<String>,
+    ) -> Result<TransactionHistory, StoreError> {
+        for environment in self.environments(original_transaction_id) {
+            if let Some(history) = self.fetch_history(environment, original_transaction_id, revision.clone()).await? {
+                self.environment_cache.insert(original_transaction_id, environment);
+                return Ok(history);
+            }
+        }
+
+        Err(StoreError::NotFound(format!("Apple transaction {original_transaction_id} not found")))
+    }
+
+    /// Page through a purchase's history in one environment; `None` if that
+    /// environment doesn't know it.
+    async fn fetch_history(
+        &self,
+        environment: AppleEnvironment,
+        original_transaction_id: &str,
+        mut revision: Option<String>,
+    ) -> Result<Option<TransactionHistory>, StoreError> {
+        let url = format!("{}/inApps/v1/history/{}", self.base_url(environment), original_transaction_id);
+        let mut transactions = Vec::new();
+
+        for page in 0.. {
+            let response = self.send_authorized(|| {
+                let request = self.client.get(&url);
+                match &revision {
+                    Some(revision) => request.query(&[("revision", revision)]),
+                    None => request,
+                }
+            }).await?;
+
+            if page == 0 && response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !response.status().is_success() {
+                return Err(StoreError::from_status(response.status(), "Apple API error"));
+            }
+
+            let body: serde_json::Value = response.json().await?;
+            for signed_transaction in body["signedTransactions"].as_array().into_iter().flatten() {
+                let signed_transaction = signed_transaction
+                    .as_str()
+                    .ok_or_else(|| StoreError::Upstream("Malformed signedTransactions".to_string()))?;
+                let decoded = self.verifier.decode(signed_transaction)
+                    .map_err(|e| StoreError::Upstream(e.to_string()))?;
+                let mut transaction = parse_transaction(&decoded);
+                transaction.is_sandbox = environment == AppleEnvironment::Sandbox;
+                transactions.push(transaction);
+            }
+
+            revision = body["revision"].as_str().map(String::from).or(revision);
+            if !body["hasMore"].as_bool().unwrap_or(false) {
+                break;
+            }
+        }
+
+        Ok(Some(TransactionHistory { transactions, revision }))
+    }
+
+    /// Answer a CONSUMPTION_REQUEST for the purchase `transaction_id` belongs to.
+    pub async fn send_consumption_info(&self, transaction_id: &str, request: &ConsumptionRequest) -> anyhow::Result<()> {
+        for environment in self.environments(transaction_id) {
+            let url = format!("{}/inApps/v1/transactions/consumption/{}", self.base_url(environment), transaction_id);
+            let response = self.send_authorized(|| self.client.put(&url).json(request)).await?;
+
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !response.status().is_success() {
+                anyhow::bail!("Apple API error: {}", response.status());
+            }
+            self.environment_cache.insert(transaction_id, environment);
+            return Ok(());
+        }
+
+        anyhow::bail!("Apple transaction {transaction_id} not found")
+    }
+
+    /// Sign a promotional offer so the app can present it with StoreKit.
+    /// `app_account_token` is the UUID the purchase will carry, or empty.
+    pub fn sign_promotional_offer(
+        &self,
+        product_id: &str,
+        offer_id: &str,
+        app_account_token: &str,
+    ) -> anyhow::Result<SignedOffer> {
+        use base64::Engine;
+
+        let nonce = uuid::Uuid::new_v4().to_string();
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let payload = promotional_offer_payload(
+            &self.bundle_id, &self.key_id, product_id, offer_id, app_account_token, &nonce, timestamp,
+        );
+
+        let raw = jsonwebtoken::crypto::sign(
+            payload.as_bytes(),
+            &jsonwebtoken::EncodingKey::from_ec_pem(self.private_key.as_bytes())?,
+            jsonwebtoken::Algorithm::ES256,
+        )?;
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(raw)?;
+
+        Ok(SignedOffer {
+            key_identifier: self.key_id.clone(),
+            nonce,
+            timestamp,
+            signature: base64::engine::general_purpose::STANDARD.encode(ecdsa_der(&raw)),
+        })
+    }
+
+    /// App Store Server API bearer token, reused until shortly before it expires.
     fn generate_jwt(&self) -> anyhow::Result<String> {
+        self.token_cache.get_or_refresh(JWT_TTL_SECS, |now, exp| self.sign_jwt(now, exp))
+    }
+
+    /// Send `request` with the primary key's token, retrying with each
+    /// previous key while Apple answers 401.
+    async fn send_authorized(
+        &self,
+        request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, StoreError> {
+        let jwt = self.generate_jwt().map_err(|e| StoreError::Auth(e.to_string()))?;
+        let mut response = send_with_retry(|| request().bearer_auth(&jwt)).await?;
+
+        for key in &self.previous_keys {
+            if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+                break;
+            }
+            let now = chrono::Utc::now().timestamp();
+            let jwt = self.sign_jwt_with(&key.key_id, &key.private_key, now, now + JWT_TTL_SECS)
+                .map_err(|e| StoreError::Auth(e.to_string()))?;
+            response = send_with_retry(|| request().bearer_auth(&jwt)).await?;
+            if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+                tracing::warn!("Apple rejected the primary key; previous key {} was accepted", key.key_id);
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn sign_jwt(&self, now: i64, exp: i64) -> anyhow::Result<String> {
+        self.sign_jwt_with(&self.key_id, &self.private_key, now, exp)
+    }
+
+    fn sign_jwt_with(&self, key_id: &str, private_key: &str, now: i64, exp: i64) -> anyhow::Result<String> {
         use jsonwebtoken::{encode, EncodingKey, Header, Algorithm};
 
-        let now = chrono::Utc::now().timestamp();
         let claims = serde_json::json!({
             "iss": self.issuer_id,
             "iat": now,
-            "exp": now + 3600,
+            "exp": exp,
             "aud": "appstoreconnect-v1",
             "bid": self.bundle_id,
         });
 
         let mut header = Header::new(Algorithm::ES256);
-        header.kid = Some(self.key_id.clone());
+        header.kid = Some(key_id.to_string());
 
         let token = encode(
             &header,
             &claims,
-            &EncodingKey::from_ec_pem(self.private_key.as_bytes())?,
+            &EncodingKey::from_ec_pem(private_key.as_bytes())?,
         )?;
 
         Ok(token)
     }
+
+    /// Verify a StoreKit 1 base64 app receipt, returning its newest transaction.
+    pub async fn verify_legacy_receipt(&self, receipt_data: &str) -> Result<VerifiedTransaction, StoreError> {
+        let (receipts, is_sandbox) = self.legacy_receipt_transactions(receipt_data).await?;
+        let mut transaction = receipts
+            .iter()
+            .max_by_key(|r| string_millis(&r["purchase_date_ms"]).unwrap_or(0))
+            .map(parse_legacy_transaction)
+            .ok_or_else(|| StoreError::Invalid("Receipt contains no transactions".to_string()))?;
+        transaction.is_sandbox = is_sandbox;
+        Ok(transaction)
+    }
+
+    /// Verify a StoreKit 1 base64 app receipt, returning the newest
+    /// transaction for each product in it.
+    pub async fn verify_legacy_receipt_products(&self, receipt_data: &str) -> Result<Vec<VerifiedTransaction>, StoreError> {
+        let (mut receipts, is_sandbox) = self.legacy_receipt_transactions(receipt_data).await?;
+        receipts.sort_by_key(|r| std::cmp::Reverse(string_millis(&r["purchase_date_ms"]).unwrap_or(0)));
+
+        let mut transactions: Vec<VerifiedTransaction> = Vec::new();
+        for receipt in &receipts {
+            let mut transaction = parse_legacy_transaction(receipt);
+            if transactions.iter().any(|t| t.product_id == transaction.product_id) {
+                continue;
+            }
+            transaction.is_sandbox = is_sandbox;
+            transactions.push(transaction);
+        }
+        Ok(transactions)
+    }
+
+    /// The transactions in a verified app receipt, and whether it came from the sandbox.
+    async fn legacy_receipt_transactions(&self, receipt_data: &str) -> Result<(Vec<serde_json::Value>, bool), StoreError> {
+        let (production, sandbox) = &self.verify_receipt_urls;
+        let (first, second) = match self.environment {
+            AppleEnvironment::Production => (production, sandbox),
+            AppleEnvironment::Sandbox => (sandbox, production),
+        };
+
+        let mut body = self.post_receipt(first, receipt_data).await?;
+        let status = body["status"].as_i64().unwrap_or(-1);
+        if self.environment_fallback && (status == STATUS_SANDBOX_RECEIPT || status == STATUS_PRODUCTION_RECEIPT) {
+            body = self.post_receipt(second, receipt_data).await?;
+        }
+
+        match body["status"].as_i64() {
+            Some(0) => {}
+            Some(STATUS_SHARED_SECRET_MISMATCH) => {
+                return Err(StoreError::Auth("Apple rejected the app's shared secret".to_string()));
+            }
+            Some(status @ (STATUS_SERVICE_UNAVAILABLE | 21100..=21199)) => {
+                return Err(StoreError::Upstream(format!("Apple verifyReceipt status {status}")));
+            }
+            Some(status) => return Err(StoreError::Invalid(format!("Apple verifyReceipt status {status}"))),
+            None => return Err(StoreError::Upstream("Apple verifyReceipt response missing status".to_string())),
+        }
+
+        let receipts = body["latest_receipt_info"]
+            .as_array()
+            .or_else(|| body["receipt"]["in_app"].as_array())
+            .filter(|receipts| !receipts.is_empty())
+            .ok_or_else(|| StoreError::Invalid("Receipt contains no transactions".to_string()))?
+            .clone();
+        Ok((receipts, body["environment"].as_str() == Some("Sandbox")))
+    }
+
+    async fn post_receipt(&self, url: &str, receipt_data: &str) -> Result<serde_json::Value, StoreError> {
+        let mut request = serde_json::json!({
+            "receipt-data": receipt_data,
+            "exclude-old-transactions": true,
+        });
+        if let Some(secret) = &self.shared_secret {
+            request["password"] = serde_json::json!(secret);
+        }
+
+        let response = send_with_retry(|| self.client.post(url).json(&request)).await?;
+        if !response.status().is_success() {
+            return Err(StoreError::from_status(response.status(), "Apple verifyReceipt error"));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+/// StoreKit 2 transaction IDs are numeric; anything else is a legacy app receipt.
+fn is_transaction_id(receipt_data: &str) -> bool {
+    !receipt_data.is_empty() && receipt_data.bytes().all(|b| b.is_ascii_digit())
 }
 
 #[async_trait::async_trait]
 impl StoreAdapter for AppleStoreAdapter {
-    async fn verify_purchase(&self, transaction_id: &str) -> anyhow::Result<VerifiedTransaction> {
-        let jwt = self.generate_jwt()?;
-        let url = format!("{}/inApps/v1/transactions/{}", self.base_url(), transaction_id);
-
-        let response = self.client
-            .get(&url)
-            .bearer_auth(&jwt)
-            .send()
-            .await?;
+    async fn verify_purchase(&self, transaction_id: &str) -> Result<VerifiedTransaction, StoreError> {
+        if !is_transaction_id(transaction_id) {
+            return self.verify_legacy_receipt(transaction_id).await;
+        }
 
-        if !response.status().is_success() {
-            anyhow::bail!("Apple API error: {}", response.status());
+        // App Review buys in the sandbox against production builds, so try the
+        // other environment whenever the first one has never heard of the transaction
+        for environment in self.environments(transaction_id) {
+            if let Some(transaction) = self.fetch_transaction(environment, transaction_id).await? {
+                self.environment_cache.insert(transaction_id, environment);
+                return Ok(transaction);
+            }
         }
 
-        let body: serde_json::Value = response.json().await?;
-        let signed_transaction = body["signedTransactionInfo"]
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Missing signedTransactionInfo"))?;
+        Err(StoreError::NotFound(format!("Apple transaction {transaction_id} not found")))
+    }
 
-        let parts: Vec<&str> = signed_transaction.split('.').collect();
-        if parts.len() != 3 {
-            anyhow::bail!("Invalid JWS format");
+    /// A StoreKit 1 app receipt holds every product the user bought; a
+    /// StoreKit 2 transaction ID only its own purchase.
+    async fn verify_all_purchases(&self, receipt_data: &str) -> Result<Vec<VerifiedTransaction>, StoreError> {
+        if is_transaction_id(receipt_data) {
+            return Ok(vec![self.verify_purchase(receipt_data).await?]);
         }
-        use base64::Engine;
-        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(parts[1])?;
-        let decoded: serde_json::Value = serde_json::from_slice(&payload)?;
-
-        Ok(VerifiedTransaction {
-            store_transaction_id: decoded["transactionId"].as_str().unwrap_or_default().to_string(),
-            product_id: decoded["productId"].as_str().unwrap_or_default().to_string(),
-            purchase_date: decoded["purchaseDate"].as_str().unwrap_or_default().to_string(),
-            expiration_date: decoded["expiresDate"].as_str().map(String::from),
-            status: TransactionStatus::Active,
-            store: Store::Apple,
-        })
+        self.verify_legacy_receipt_products(receipt_data).await
     }
 
-    async fn get_subscription_status(&self, transaction_id: &str) -> anyhow::Result<VerifiedTransaction> {
-        self.verify_purchase(transaction_id).await
+    async fn get_subscription_status(&self, transaction_id: &str) -> Result<VerifiedTransaction, StoreError> {
+        let mut statuses = self.get_all_subscription_statuses(transaction_id).await?;
+        if let Some(i) = statuses.iter().position(|t| {
+            t.store_transaction_id == transaction_id || t.original_transaction_id.as_deref() == Some(transaction_id)
+        }) {
+            return Ok(statuses.swap_remove(i));
+        }
+
+        // A renewal's ID matches neither, but its purchase's original ID does
+        let original = self.verify_purchase(transaction_id).await?.original_transaction_id;
+        statuses.into_iter()
+            .find(|t| original.is_some() && t.original_transaction_id == original)
+            .ok_or_else(|| StoreError::NotFound(format!("No subscription status for Apple transaction {transaction_id}")))
     }
 
-    async fn process_notification(&self, payload: &[u8]) -> anyhow::Result<Vec<TransactionEvent>> {
-        let body: serde_json::Value = serde_json::from_slice(payload)?;
+    async fn process_notification(&self, payload: &[u8]) -> Result<Vec<TransactionEvent>, StoreError> {
+        let body: serde_json::Value = serde_json::from_slice(payload)
+            .map_err(|e| StoreError::Invalid(e.to_string()))?;
         let signed_payload = body["signedPayload"]
             .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Missing signedPayload"))?;
+            .ok_or_else(|| StoreError::Invalid("Missing signedPayload".to_string()))?;
+
+        let decode = |jws: &str| self.verifier.decode(jws).map_err(|e| StoreError::Invalid(e.to_string()));
+        let decoded = decode(signed_payload)?;
+        self.check_app(&decoded["data"])?;
 
-        let parts: Vec<&str> = signed_payload.split('.').collect();
-        if parts.len() != 3 {
-            anyhow::bail!("Invalid JWS format");
+        // Sent on request from App Store Connect to check the URL works
+        if decoded["notificationType"] == "TEST" {
+            tracing::info!(
+                "Received App Store test notification {} (version {})",
+                decoded["notificationUUID"].as_str().unwrap_or_default(),
+                decoded["version"].as_str().unwrap_or("unknown"),
+            );
+            return Ok(Vec::new());
         }
-        use base64::Engine;
-        let payload_bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(parts[1])?;
-        let decoded: serde_json::Value = serde_json::from_slice(&payload_bytes)?;
 
-        let notification_type = decoded["notificationType"]
-            .as_str()
-            .unwrap_or("UNKNOWN")
-            .to_string();
-
-        let event_type = match notification_type.as_str() {
-            "DID_RENEW" => "RENEWAL",
-            "EXPIRED" => "EXPIRATION",
-            "DID_FAIL_TO_RENEW" => "BILLING_ISSUE_DETECTED",
-            "REFUND" => "REFUND",
-            "SUBSCRIBED" | "INITIAL_BUY" => "INITIAL_PURCHASE",
-            "DID_CHANGE_RENEWAL_STATUS" => "CANCELLATION",
-            other => other,
-        };
+        let event_type = notification_event_type(
+            decoded["notificationType"].as_str().unwrap_or("UNKNOWN"),
+            decoded["subtype"].as_str(),
+        );
 
         if let Some(signed_tx) = decoded["data"]["signedTransactionInfo"].as_str() {
-            let tx_parts: Vec<&str> = signed_tx.split('.').collect();
-            if tx_parts.len() == 3 {
-                let tx_payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(tx_parts[1])?;
-                let tx_decoded: serde_json::Value = serde_json::from_slice(&tx_payload)?;
+            let tx_decoded = decode(signed_tx)?;
+            self.check_app(&tx_decoded)?;
 
-                return Ok(vec![TransactionEvent {
-                    event_type: event_type.to_string(),
-                    transaction: VerifiedTransaction {
-                        store_transaction_id: tx_decoded["transactionId"].as_str().unwrap_or_default().to_string(),
-                        product_id: tx_decoded["productId"].as_str().unwrap_or_default().to_string(),
-                        purchase_date: tx_decoded["purchaseDate"].as_str().unwrap_or_default().to_string(),
-                        expiration_date: tx_decoded["expiresDate"].as_str().map(String::from),
-                        status: TransactionStatus::Active,
-                        store: Store::Apple,
-                    },
-                }]);
-            }
+            let renewal_info = match decoded["data"]["signedRenewalInfo"].as_str() {
+                Some(signed_renewal) => Some(parse_renewal_info(&decode(signed_renewal)?)),
+                None => None,
+            };
+
+            return Ok(vec![TransactionEvent {
+                event_type: event_type.to_string(),
+                transaction: parse_transaction(&tx_decoded),
+                renewal_info,
+            }]);
         }
 
         Ok(vec![])
     }
 }
+
+/// Our event type for an App Store Server Notification, taking its subtype
+/// into account. Types we don't interpret pass through unchanged.
+fn notification_event_type<'a>(notification_type: &'a str, subtype: Option<&str>) -> &'a str {
+    match (notification_type, subtype) {
+        ("SUBSCRIBED", Some("RESUBSCRIBE")) => "RESTARTED",
+        ("SUBSCRIBED", _) | ("INITIAL_BUY", _) => "INITIAL_PURCHASE",
+        ("DID_RENEW", Some("BILLING_RECOVERY")) => "SUBSCRIPTION_RECOVERED",
+        ("DID_RENEW", _) => "RENEWAL",
+        ("DID_FAIL_TO_RENEW", Some("GRACE_PERIOD")) => "GRACE_PERIOD",
+        ("DID_FAIL_TO_RENEW", _) => "BILLING_ISSUE_DETECTED",
+        // Grace ran out but Apple keeps retrying the charge
+        ("GRACE_PERIOD_EXPIRED", _) => "BILLING_ISSUE_DETECTED",
+        ("DID_CHANGE_RENEWAL_STATUS", Some("AUTO_RENEW_ENABLED")) => "UNCANCELLATION",
+        ("DID_CHANGE_RENEWAL_STATUS", _) => "CANCELLATION",
+        ("DID_CHANGE_RENEWAL_PREF", _) => "PRODUCT_CHANGE",
+        ("EXPIRED", _) => "EXPIRATION",
+        ("REFUND", _) => "REFUND",
+        ("REFUND_REVERSED", _) => "REFUND_REVERSED",
+        ("PRICE_INCREASE", Some("ACCEPTED")) => "PRICE_INCREASE_ACCEPTED",
+        ("PRICE_INCREASE", _) => "PRICE_INCREASE_PENDING",
+        ("ONE_TIME_CHARGE", _) => "NON_RENEWING_PURCHASE",
+        (other, _) => other,
+    }
+}
+
+/// Decode a notification's `signedPayload` without verifying it, to find the
+/// owning app and notification UUID; that app's adapter then verifies the payload.
+pub fn peek_notification(payload: &[u8]) -> anyhow::Result<serde_json::Value> {
+    use base64::Engine;
+
+    let body: serde_json::Value = serde_json::from_slice(payload)?;
+    let signed_payload = body["signedPayload"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Missing signedPayload"))?;
+    let claims = signed_payload
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("Malformed signedPayload"))?;
+
+    Ok(serde_json::from_slice(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(claims)?,
+    )?)
+}
+
+/// Apple encodes dates as milliseconds since the epoch; we store RFC 3339 strings.
+fn millis_to_rfc3339(value: &serde_json::Value) -> Option<String> {
+    value.as_i64()
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|d| d.to_rfc3339())
+}
+
+fn parse_transaction(decoded: &serde_json::Value) -> VerifiedTransaction {
+    let expires_at = decoded["expiresDate"].as_i64().and_then(chrono::DateTime::from_timestamp_millis);
+
+    let status = if decoded["revocationDate"].is_number() {
+        TransactionStatus::Refunded
+    } else if expires_at.is_some_and(|e| e <= chrono::Utc::now()) {
+        TransactionStatus::Expired
+    } else {
+        TransactionStatus::Active
+    };
+
+    VerifiedTransaction {
+        store_transaction_id: decoded["transactionId"].as_str().unwrap_or_default().to_string(),
+        original_transaction_id: decoded["originalTransactionId"].as_str().map(String::from),
+        product_id: decoded["productId"].as_str().unwrap_or_default().to_string(),
+        purchase_date: millis_to_rfc3339(&decoded["purchaseDate"]).unwrap_or_default(),
+        expiration_date: expires_at.map(|d| d.to_rfc3339()),
+        status,
+        store: Store::Apple,
+        is_sandbox: decoded["environment"].as_str() == Some("Sandbox"),
+        period_type: match (decoded["offerType"].as_i64(), decoded["offerDiscountType"].as_str()) {
+            (Some(1), Some("FREE_TRIAL")) => PeriodType::Trial,
+            (Some(1), _) => PeriodType::Intro,
+            _ => PeriodType::Normal,
+        },
+        auto_renew: None,
+        auto_resume_time: None,
+    }
+}
+
+/// The status endpoint's subscription status.
+fn subscription_status(status: i64) -> Option<TransactionStatus> {
+    match status {
+        1 => Some(TransactionStatus::Active),
+        2 => Some(TransactionStatus::Expired),
+        3 => Some(TransactionStatus::BillingRetry),
+        4 => Some(TransactionStatus::GracePeriod),
+        5 => Some(TransactionStatus::Refunded),
+        _ => None,
+    }
+}
+
+/// `verifyReceipt` encodes millisecond timestamps as strings.
+fn string_millis(value: &serde_json::Value) -> Option<i64> {
+    value.as_str().and_then(|s| s.parse().ok())
+}
+
+fn parse_legacy_transaction(receipt: &serde_json::Value) -> VerifiedTransaction {
+    let date = |value: &serde_json::Value| {
+        string_millis(value).and_then(chrono::DateTime::from_timestamp_millis)
+    };
+    let expires_at = date(&receipt["expires_date_ms"]);
+
+    let status = if receipt["cancellation_date_ms"].is_string() {
+        TransactionStatus::Refunded
+    } else if expires_at.is_some_and(|e| e <= chrono::Utc::now()) {
+        TransactionStatus::Expired
+    } else {
+        TransactionStatus::Active
+    };
+
+    VerifiedTransaction {
+        store_transaction_id: receipt["transaction_id"].as_str().unwrap_or_default().to_string(),
+        original_transaction_id: receipt["original_transaction_id"].as_str().map(String::from),
+        product_id: receipt["product_id"].as_str().unwrap_or_default().to_string(),
+        purchase_date: date(&receipt["purchase_date_ms"]).map(|d| d.to_rfc3339()).unwrap_or_default(),
+        expiration_date: expires_at.map(|d| d.to_rfc3339()),
+        status,
+        store: Store::Apple,
+        is_sandbox: false,
+        period_type: if receipt["is_trial_period"].as_str() == Some("true") {
+            PeriodType::Trial
+        } else if receipt["is_in_intro_offer_period"].as_str() == Some("true") {
+            PeriodType::Intro
+        } else {
+            PeriodType::Normal
+        },
+        auto_renew: None,
+        auto_resume_time: None,
+    }
+}
+
+fn parse_renewal_info(decoded: &serde_json::Value) -> RenewalInfo {
+    let expiration_reason = decoded["expirationIntent"].as_i64().map(|intent| {
+        match intent {
+            1 => "customer_cancelled",
+            2 => "billing_error",
+            3 => "price_increase_declined",
+            4 => "product_unavailable",
+            _ => "unknown",
+        }
+        .to_string()
+    });
+
+    let offer_type = decoded["offerType"].as_i64().map(|offer| {
+        match offer {
+            1 => "introductory",
+            2 => "promotional",
+            3 => "offer_code",
+            4 => "win_back",
+            _ => "unknown",
+        }
+        .to_string()
+    });
+
+    RenewalInfo {
+        auto_renew_status: decoded["autoRenewStatus"].as_i64().map(|status| status == 1),
+        expiration_reason,
+        grace_period_expires_date: millis_to_rfc3339(&decoded["gracePeriodExpiresDate"]),
+        offer_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::apple_jws::tests::{sign_test_jws, test_leaf_public_key, test_verifier, TEST_LEAF_KEY};
+
+    fn test_adapter() -> AppleStoreAdapter {
+        AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            String::new(),
+            "com.test".to_string(),
+            AppleEnvironment::Sandbox,
+        )
+    }
+
+    #[test]
+    fn test_jwt_is_reused_until_expiry() {
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Sandbox,
+        );
+        let first = adapter.generate_jwt().unwrap();
+        let second = adapter.generate_jwt().unwrap();
+        assert_eq!(first, second);
+    }
+
+    fn rotated_adapter() -> AppleStoreAdapter {
+        AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "NEWKEY".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        )
+        .with_previous_keys(vec![AppleKey { key_id: "OLDKEY".to_string(), private_key: TEST_LEAF_KEY.to_string() }])
+    }
+
+    #[test]
+    fn test_signing_uses_the_primary_key() {
+        let adapter = rotated_adapter();
+
+        let header = jsonwebtoken::decode_header(&adapter.generate_jwt().unwrap()).unwrap();
+        assert_eq!(header.kid.as_deref(), Some("NEWKEY"));
+
+        let offer = adapter.sign_promotional_offer("com.test.pro", "offer", "").unwrap();
+        assert_eq!(offer.key_identifier, "NEWKEY");
+    }
+
+    #[tokio::test]
+    async fn test_previous_key_is_tried_when_primary_is_rejected() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/production/inApps/v1/transactions/1000"))
+            .respond_with(ResponseTemplate::new(401))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        let signed_tx = sign_test_jws(&serde_json::json!({
+            "transactionId": "1000",
+            "productId": "com.test.pro",
+            "environment": "Production",
+        }));
+        Mock::given(method("GET"))
+            .and(path("/production/inApps/v1/transactions/1000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "signedTransactionInfo": signed_tx })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let adapter = rotated_adapter()
+            .with_verifier(test_verifier())
+            .with_api_urls(format!("{}/production", server.uri()), format!("{}/sandbox", server.uri()));
+        let tx = adapter.verify_purchase("1000").await.unwrap();
+        assert_eq!(tx.product_id, "com.test.pro");
+
+        let requests = server.received_requests().await.unwrap();
+        let kid = |i: usize| {
+            let token = requests[i].headers[&wiremock::http::HeaderName::from("authorization")].as_str();
+            jsonwebtoken::decode_header(token.trim_start_matches("Bearer ")).unwrap().kid.unwrap()
+        };
+        assert_eq!((kid(0), kid(1)), ("NEWKEY".to_string(), "OLDKEY".to_string()));
+    }
+
+    #[test]
+    fn test_notification_subtypes_map_to_event_types() {
+        for (notification_type, subtype, expected) in [
+            ("SUBSCRIBED", Some("INITIAL_BUY"), "INITIAL_PURCHASE"),
+            ("SUBSCRIBED", Some("RESUBSCRIBE"), "RESTARTED"),
+            ("DID_RENEW", None, "RENEWAL"),
+            ("DID_RENEW", Some("BILLING_RECOVERY"), "SUBSCRIPTION_RECOVERED"),
+            ("DID_FAIL_TO_RENEW", None, "BILLING_ISSUE_DETECTED"),
+            ("DID_FAIL_TO_RENEW", Some("GRACE_PERIOD"), "GRACE_PERIOD"),
+            ("DID_CHANGE_RENEWAL_STATUS", Some("AUTO_RENEW_DISABLED"), "CANCELLATION"),
+            ("DID_CHANGE_RENEWAL_STATUS", Some("AUTO_RENEW_ENABLED"), "UNCANCELLATION"),
+            ("DID_CHANGE_RENEWAL_PREF", Some("DOWNGRADE"), "PRODUCT_CHANGE"),
+            ("EXPIRED", Some("VOLUNTARY"), "EXPIRATION"),
+            ("EXPIRED", Some("BILLING_RETRY"), "EXPIRATION"),
+            ("PRICE_INCREASE", Some("PENDING"), "PRICE_INCREASE_PENDING"),
+            ("PRICE_INCREASE", Some("ACCEPTED"), "PRICE_INCREASE_ACCEPTED"),
+            ("REFUND_DECLINED", None, "REFUND_DECLINED"),
+        ] {
+            assert_eq!(
+                notification_event_type(notification_type, subtype),
+                expected,
+                "{notification_type}/{subtype:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notification_with_untrusted_signature_is_rejected() {
+        let signed_payload = sign_test_jws(&serde_json::json!({
+            "notificationType": "REFUND",
+            "data": {},
+        }));
+        let body = serde_json::json!({ "signedPayload": signed_payload });
+
+        let result = test_adapter()
+            .process_notification(body.to_string().as_bytes())
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_test_notification_produces_no_events() {
+        let signed_payload = sign_test_jws(&serde_json::json!({
+            "notificationType": "TEST",
+            "notificationUUID": "3838df56-31ab-4e2b-9535-ed4e4a0c4a1e",
+            "version": "2.0",
+            "data": { "bundleId": "com.test", "environment": "Sandbox" },
+        }));
+        let body = serde_json::json!({ "signedPayload": signed_payload });
+
+        let events = test_adapter()
+            .with_verifier(test_verifier())
+            .process_notification(body.to_string().as_bytes())
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_renewal_status_change_propagates_auto_renew_flag() {
+        let signed_tx = sign_test_jws(&serde_json::json!({
+            "transactionId": "2000000000000001",
+            "productId": "com.test.pro",
+        }));
+        let signed_renewal = sign_test_jws(&serde_json::json!({
+            "autoRenewStatus": 0,
+            "expirationIntent": 1,
+        }));
+        let signed_payload = sign_test_jws(&serde_json::json!({
+            "notificationType": "DID_CHANGE_RENEWAL_STATUS",
+            "subtype": "AUTO_RENEW_DISABLED",
+            "data": {
+                "signedTransactionInfo": signed_tx,
+                "signedRenewalInfo": signed_renewal,
+            },
+        }));
+        let body = serde_json::json!({ "signedPayload": signed_payload });
+
+        let events = test_adapter()
+            .with_verifier(test_verifier())
+            .process_notification(body.to_string().as_bytes())
+            .await
+            .unwrap();
+
+        assert_eq!(events.len(), 1);
+        let renewal = events[0].renewal_info.as_ref().unwrap();
+        assert_eq!(renewal.auto_renew_status, Some(false));
+        assert_eq!(renewal.expiration_reason.as_deref(), Some("customer_cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_receipt_is_retried_against_sandbox() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/production/verifyReceipt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "status": 21007 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/sandbox/verifyReceipt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": 0,
+                "latest_receipt_info": [
+                    { "transaction_id": "1000", "product_id": "com.test.pro", "purchase_date_ms": "1767225600000", "expires_date_ms": "4102444800000" },
+                    { "transaction_id": "1001", "product_id": "com.test.pro", "purchase_date_ms": "1769904000000", "expires_date_ms": "4102444800000" },
+                ],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            String::new(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        )
+        .with_verify_receipt_urls(
+            format!("{}/production/verifyReceipt", server.uri()),
+            format!("{}/sandbox/verifyReceipt", server.uri()),
+        );
+
+        let tx = adapter.verify_purchase("MIIT0wYJKoZIhvcNAQcCoIITxDCCE8ACAQExCzAJ").await.unwrap();
+        assert_eq!(tx.store_transaction_id, "1001");
+        assert!(matches!(tx.status, TransactionStatus::Active));
+    }
+
+    #[tokio::test]
+    async fn test_app_receipt_restores_newest_transaction_per_product() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/sandbox/verifyReceipt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": 0,
+                "latest_receipt_info": [
+                    { "transaction_id": "1000", "product_id": "com.test.pro", "purchase_date_ms": "1767225600000", "expires_date_ms": "1769904000000" },
+                    { "transaction_id": "1001", "product_id": "com.test.pro", "purchase_date_ms": "1769904000000", "expires_date_ms": "4102444800000" },
+                    { "transaction_id": "2000", "product_id": "com.test.lifetime", "purchase_date_ms": "1767225600000" },
+                ],
+            })))
+            .mount(&server)
+            .await;
+
+        let adapter = test_adapter().with_verify_receipt_urls(
+            format!("{}/production/verifyReceipt", server.uri()),
+            format!("{}/sandbox/verifyReceipt", server.uri()),
+        );
+
+        let transactions = adapter.verify_all_purchases("MIIT0wYJKoZIhvcNAQcCoIITxDCCE8ACAQExCzAJ").await.unwrap();
+        let ids: Vec<_> = transactions.iter().map(|t| t.store_transaction_id.as_str()).collect();
+        assert_eq!(ids, vec!["1001", "2000"]);
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_transaction_falls_back_and_is_remembered() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/production/inApps/v1/transactions/1000"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({ "errorCode": 4040010 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let signed_tx = sign_test_jws(&serde_json::json!({
+            "transactionId": "1000",
+            "productId": "com.test.pro",
+            "environment": "Sandbox",
+        }));
+        Mock::given(method("GET"))
+            .and(path("/sandbox/inApps/v1/transactions/1000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "signedTransactionInfo": signed_tx })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        )
+        .with_verifier(test_verifier())
+        .with_api_urls(format!("{}/production", server.uri()), format!("{}/sandbox", server.uri()));
+
+        let tx = adapter.verify_purchase("1000").await.unwrap();
+        assert!(tx.is_sandbox);
+
+        // The second lookup goes straight to the sandbox
+        adapter.verify_purchase("1000").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transaction_history_accumulates_across_pages() {
+        use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let signed = |transaction_id: &str| sign_test_jws(&serde_json::json!({
+            "transactionId": transaction_id,
+            "originalTransactionId": "1000",
+            "productId": "com.test.pro",
+        }));
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/production/inApps/v1/history/1000"))
+            .and(query_param_is_missing("revision"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "revision": "rev-1",
+                "hasMore": true,
+                "signedTransactions": [signed("1000"), signed("1001")],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/production/inApps/v1/history/1000"))
+            .and(query_param("revision", "rev-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "revision": "rev-2",
+                "hasMore": false,
+                "signedTransactions": [signed("1002")],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        )
+        .with_verifier(test_verifier())
+        .with_api_urls(format!("{}/production", server.uri()), format!("{}/sandbox", server.uri()));
+
+        let history = adapter.get_transaction_history("1000", None).await.unwrap();
+        let ids: Vec<_> = history.transactions.iter().map(|t| t.store_transaction_id.as_str()).collect();
+        assert_eq!(ids, vec!["1000", "1001", "1002"]);
+        assert!(history.transactions.iter().all(|t| t.original_transaction_id.as_deref() == Some("1000")));
+        assert_eq!(history.revision.as_deref(), Some("rev-2"));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_statuses_map_apple_status_codes() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // One group per status, each with a transaction that hasn't expired
+        let expires = chrono::Utc::now().timestamp_millis() + 86_400_000;
+        let groups: Vec<_> = (1..=5)
+            .map(|status| serde_json::json!({
+                "subscriptionGroupIdentifier": format!("group-{status}"),
+                "lastTransactions": [{
+                    "originalTransactionId": format!("100{status}"),
+                    "status": status,
+                    "signedTransactionInfo": sign_test_jws(&serde_json::json!({
+                        "transactionId": format!("200{status}"),
+                        "originalTransactionId": format!("100{status}"),
+                        "productId": "com.test.pro",
+                        "expiresDate": expires,
+                    })),
+                    "signedRenewalInfo": sign_test_jws(&serde_json::json!({ "autoRenewStatus": 1 })),
+                }],
+            }))
+            .collect();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/production/inApps/v1/subscriptions/1003"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "data": groups })))
+            .mount(&server)
+            .await;
+
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        )
+        .with_verifier(test_verifier())
+        .with_api_urls(format!("{}/production", server.uri()), format!("{}/sandbox", server.uri()));
+
+        let statuses = adapter.get_all_subscription_statuses("1003").await.unwrap();
+        let mapped: Vec<_> = statuses.iter().map(|t| (t.store_transaction_id.as_str(), t.status.as_str())).collect();
+        assert_eq!(mapped, vec![
+            ("2001", TransactionStatus::Active.as_str()),
+            ("2002", TransactionStatus::Expired.as_str()),
+            ("2003", TransactionStatus::BillingRetry.as_str()),
+            ("2004", TransactionStatus::GracePeriod.as_str()),
+            ("2005", TransactionStatus::Refunded.as_str()),
+        ]);
+        assert!(statuses.iter().all(|t| t.auto_renew == Some(true)));
+
+        let status = adapter.get_subscription_status("1003").await.unwrap();
+        assert_eq!(status.store_transaction_id, "2003");
+        assert_eq!(status.status.as_str(), "billing_retry");
+    }
+
+    #[test]
+    fn test_consumption_request_from_subscriber_record() {
+        let now = chrono::Utc::now();
+        let subscriber = Subscriber {
+            id: "sub".to_string(),
+            app_id: "app".to_string(),
+            app_user_id: "user123".to_string(),
+            created_at: (now - chrono::Duration::days(45)).to_rfc3339(),
+        };
+        let usage = ConsumptionUsage {
+            purchased_micros: 59_980_000,
+            refunded_micros: 9_990_000,
+            has_active_subscription: true,
+        };
+
+        let request = ConsumptionRequest::for_subscriber(&subscriber, &usage, now);
+        assert_eq!(request.account_tenure, 4);
+        assert_eq!(request.lifetime_dollars_purchased, 3);
+        assert_eq!(request.lifetime_dollars_refunded, 2);
+        assert_eq!(request.user_status, 1);
+        assert_eq!(request.consumption_status, 0);
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["customerConsented"], true);
+        assert_eq!(body["accountTenure"], 4);
+        assert_eq!(body["appAccountToken"], "");
+    }
+
+    #[tokio::test]
+    async fn test_consumption_info_is_put_for_the_transaction() {
+        use wiremock::matchers::{body_partial_json, header_exists, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/production/inApps/v1/transactions/consumption/1000"))
+            .and(header_exists("authorization"))
+            .and(body_partial_json(serde_json::json!({ "customerConsented": true, "platform": 1 })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "key".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        )
+        .with_api_urls(format!("{}/production", server.uri()), format!("{}/sandbox", server.uri()));
+
+        let subscriber = Subscriber {
+            id: "sub".to_string(),
+            app_id: "app".to_string(),
+            app_user_id: "user123".to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let request = ConsumptionRequest::for_subscriber(&subscriber, &ConsumptionUsage::default(), chrono::Utc::now());
+        adapter.send_consumption_info("1000", &request).await.unwrap();
+    }
+
+    /// Back from DER to the fixed-width `r || s` form jsonwebtoken verifies.
+    fn der_to_raw(der: &[u8]) -> Vec<u8> {
+        assert_eq!(der[0], 0x30);
+        let mut raw = Vec::new();
+        let mut rest = &der[2..];
+        for _ in 0..2 {
+            assert_eq!(rest[0], 0x02);
+            let len = rest[1] as usize;
+            let value = &rest[2..2 + len];
+            let value = &value[value.len().saturating_sub(32)..];
+            raw.extend(std::iter::repeat_n(0, 32 - value.len()));
+            raw.extend_from_slice(value);
+            rest = &rest[2 + len..];
+        }
+        raw
+    }
+
+    #[test]
+    fn test_promotional_offer_signature_covers_fields_in_order() {
+        use base64::Engine;
+
+        let adapter = AppleStoreAdapter::new(
+            Client::new(),
+            "issuer".to_string(),
+            "KEY123".to_string(),
+            TEST_LEAF_KEY.to_string(),
+            "com.test".to_string(),
+            AppleEnvironment::Production,
+        );
+        let offer = adapter
+            .sign_promotional_offer("com.test.pro", "winback50", "9F2C0D4E-1B6A-4F3E-8C7D-2A5B6C7D8E9F")
+            .unwrap();
+        assert_eq!(offer.key_identifier, "KEY123");
+        assert_eq!(offer.nonce, offer.nonce.to_lowercase());
+
+        let expected = format!(
+            "com.test\u{2063}KEY123\u{2063}com.test.pro\u{2063}winback50\u{2063}9f2c0d4e-1b6a-4f3e-8c7d-2a5b6c7d8e9f\u{2063}{}\u{2063}{}",
+            offer.nonce, offer.timestamp
+        );
+        let der = base64::engine::general_purpose::STANDARD.decode(&offer.signature).unwrap();
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(der_to_raw(&der));
+        let key = jsonwebtoken::DecodingKey::from_ec_der(&test_leaf_public_key());
+        let verify = |message: &str| {
+            jsonwebtoken::crypto::verify(&signature, message.as_bytes(), &key, jsonwebtoken::Algorithm::ES256).unwrap()
+        };
+
+        assert!(verify(&expected));
+        assert!(!verify(&expected.replace("com.test.pro\u{2063}winback50", "winback50\u{2063}com.test.pro")));
+    }
+
+    #[test]
+    fn test_ecdsa_der_pads_high_bit_integers() {
+        let mut raw = vec![0u8; 64];
+        raw[1] = 0x01;
+        raw[32] = 0x80;
+        let der = ecdsa_der(&raw);
+        assert_eq!(&der[..5], &[0x30, 0x44, 0x02, 0x1f, 0x01]);
+        assert_eq!(&der[35..38], &[0x02, 0x21, 0x00]);
+    }
+}
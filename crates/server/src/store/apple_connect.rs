@@ -1,10 +1,19 @@
 use reqwest::Client;
 use crate::models::app::AppleCredentials;
+use super::retry::send_with_retry;
+use super::token_cache::TokenCache;
+
+/// App Store Connect API tokens may be valid for at most 20 minutes.
+const JWT_TTL_SECS: i64 = 1200;
+
+const API_BASE: &str = "https://api.appstoreconnect.apple.com";
 
 pub struct AppleConnectClient {
     client: Client,
     credentials: AppleCredentials,
     bundle_id: String,
+    token_cache: TokenCache,
+    api_base: String,
 }
 
 /// Combined product info ready for our database
@@ -16,32 +25,90 @@ pub struct SyncedProduct {
     pub price_micros: i64,
     pub currency: String,
     pub subscription_period: Option<String>,
+    /// Length of the introductory offer, if there is one.
     pub trial_period: Option<String>,
+    pub intro_price_micros: Option<i64>,
+    pub intro_currency: Option<String>,
+    /// `free_trial`, `pay_as_you_go` or `pay_up_front`.
+    pub offer_mode: Option<String>,
     pub product_type: String,
+    /// Current price in each territory the product is sold in.
+    pub prices: Vec<TerritoryPrice>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerritoryPrice {
+    /// App Store territory code, e.g. `USA`.
+    pub territory: String,
+    pub price_micros: i64,
+    pub currency: String,
+}
+
+/// Territory whose price stands in for the product's single legacy price.
+const DEFAULT_TERRITORY: &str = "USA";
+
+#[derive(Debug, Clone, PartialEq)]
 struct AppleIntroOffer {
     period: String,
+    offer_mode: String,
+    price_micros: i64,
+    currency: String,
 }
 
 impl AppleConnectClient {
-    pub fn new(credentials: AppleCredentials, bundle_id: String) -> Self {
+    pub fn new(client: Client, credentials: AppleCredentials, bundle_id: String) -> Self {
         Self {
-            client: Client::new(),
+            client,
             credentials,
             bundle_id,
+            token_cache: TokenCache::default(),
+            api_base: API_BASE.to_string(),
+        }
+    }
+
+    /// Point at a different App Store Connect API root, e.g. a mock server in tests.
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    /// Fetch every page of a collection, following `links.next` until it runs out.
+    async fn fetch_pages(&self, jwt: &str, url: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+        let mut pages = Vec::new();
+        let mut next = Some(url.to_string());
+
+        while let Some(url) = next {
+            let resp: serde_json::Value = send_with_retry(|| self.client.get(&url).bearer_auth(jwt)).await?.json().await?;
+            next = resp["links"]["next"].as_str().map(String::from);
+            pages.push(resp);
         }
+
+        Ok(pages)
+    }
+
+    /// The `data` items across every page of a collection.
+    async fn fetch_all(&self, jwt: &str, url: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+        let pages = self.fetch_pages(jwt, url).await?;
+        Ok(pages
+            .iter()
+            .filter_map(|page| page["data"].as_array())
+            .flatten()
+            .cloned()
+            .collect())
     }
 
+    /// App Store Connect API bearer token, reused until shortly before it expires.
     fn generate_jwt(&self) -> anyhow::Result<String> {
+        self.token_cache.get_or_refresh(JWT_TTL_SECS, |now, exp| self.sign_jwt(now, exp))
+    }
+
+    fn sign_jwt(&self, now: i64, exp: i64) -> anyhow::Result<String> {
         use jsonwebtoken::{encode, EncodingKey, Header, Algorithm};
 
-        let now = chrono::Utc::now().timestamp();
         let claims = serde_json::json!({
             "iss": self.credentials.issuer_id,
             "iat": now,
-            "exp": now + 1200,
+            "exp": exp,
             "aud": "appstoreconnect-v1",
         });
 
@@ -77,13 +144,10 @@ impl AppleConnectClient {
 
     async fn find_app_id(&self, jwt: &str) -> anyhow::Result<String> {
         let url = format!(
-            "https://api.appstoreconnect.apple.com/v1/apps?filter[bundleId]={}",
-            self.bundle_id
+            "{}/v1/apps?filter[bundleId]={}",
+            self.api_base, self.bundle_id
         );
-        let resp: serde_json::Value = self.client
-            .get(&url)
-            .bearer_auth(jwt)
-            .send()
+        let resp: serde_json::Value = send_with_retry(|| self.client.get(&url).bearer_auth(jwt))
             .await?
             .json()
             .await?;
@@ -98,42 +162,22 @@ impl AppleConnectClient {
         let mut products = Vec::new();
 
         let groups_url = format!(
-            "https://api.appstoreconnect.apple.com/v1/apps/{}/subscriptionGroups",
-            app_id
+            "{}/v1/apps/{}/subscriptionGroups",
+            self.api_base, app_id
         );
-        let groups_resp: serde_json::Value = self.client
-            .get(&groups_url)
-            .bearer_auth(jwt)
-            .send()
-            .await?
-            .json()
-            .await?;
-
-        tracing::info!("Subscription groups response: {}", serde_json::to_string(&groups_resp).unwrap_or_default());
-
-        let empty = vec![];
-        let groups = groups_resp["data"].as_array().unwrap_or(&empty);
+        let groups = self.fetch_all(jwt, &groups_url).await?;
         tracing::info!("Found {} subscription groups", groups.len());
 
-        for group in groups {
+        for group in &groups {
             let group_id = group["id"].as_str().unwrap_or_default();
 
             let subs_url = format!(
-                "https://api.appstoreconnect.apple.com/v1/subscriptionGroups/{}/subscriptions",
-                group_id
+                "{}/v1/subscriptionGroups/{}/subscriptions",
+                self.api_base, group_id
             );
-            let subs_resp: serde_json::Value = self.client
-                .get(&subs_url)
-                .bearer_auth(jwt)
-                .send()
-                .await?
-                .json()
-                .await?;
-
-            let empty_subs = vec![];
-            let subs = subs_resp["data"].as_array().unwrap_or(&empty_subs);
-
-            for sub in subs {
+            let subs = self.fetch_all(jwt, &subs_url).await?;
+
+            for sub in &subs {
                 let sub_id = sub["id"].as_str().unwrap_or_default();
                 let attrs = &sub["attributes"];
                 let product_id = attrs["productId"].as_str().unwrap_or_default();
@@ -142,12 +186,17 @@ impl AppleConnectClient {
                 let (display_name, description) = self.fetch_subscription_localization(jwt, sub_id).await
                     .unwrap_or((name.to_string(), None));
 
-                let (price_micros, currency) = self.fetch_subscription_price(jwt, sub_id).await
+                let prices = self.fetch_subscription_prices(jwt, sub_id).await
+                    .unwrap_or_default();
+                let (price_micros, currency) = prices.iter()
+                    .find(|p| p.territory == DEFAULT_TERRITORY)
+                    .or_else(|| prices.first())
+                    .map(|p| (p.price_micros, p.currency.clone()))
                     .unwrap_or((0, "USD".to_string()));
 
                 let period = self.fetch_subscription_period(jwt, sub_id).await.ok();
 
-                let trial = self.fetch_introductory_offer(jwt, sub_id).await.ok().flatten();
+                let intro = self.fetch_introductory_offer(jwt, sub_id).await.ok().flatten();
 
                 products.push(SyncedProduct {
                     store_product_id: product_id.to_string(),
@@ -156,8 +205,12 @@ impl AppleConnectClient {
                     price_micros,
                     currency,
                     subscription_period: period,
-                    trial_period: trial.map(|t| t.period),
+                    trial_period: intro.as_ref().map(|i| i.period.clone()),
+                    intro_price_micros: intro.as_ref().map(|i| i.price_micros),
+                    intro_currency: intro.as_ref().map(|i| i.currency.clone()),
+                    offer_mode: intro.map(|i| i.offer_mode),
                     product_type: "subscription".to_string(),
+                    prices,
                 });
             }
         }
@@ -167,10 +220,10 @@ impl AppleConnectClient {
 
     async fn fetch_subscription_localization(&self, jwt: &str, sub_id: &str) -> anyhow::Result<(String, Option<String>)> {
         let url = format!(
-            "https://api.appstoreconnect.apple.com/v1/subscriptions/{}/subscriptionLocalizations",
-            sub_id
+            "{}/v1/subscriptions/{}/subscriptionLocalizations",
+            self.api_base, sub_id
         );
-        let resp: serde_json::Value = self.client.get(&url).bearer_auth(jwt).send().await?.json().await?;
+        let resp: serde_json::Value = send_with_retry(|| self.client.get(&url).bearer_auth(jwt)).await?.json().await?;
         let empty = vec![];
         let localizations = resp["data"].as_array().unwrap_or(&empty);
 
@@ -187,47 +240,33 @@ impl AppleConnectClient {
         }
     }
 
-    async fn fetch_subscription_price(&self, jwt: &str, sub_id: &str) -> anyhow::Result<(i64, String)> {
+    async fn fetch_subscription_prices(&self, jwt: &str, sub_id: &str) -> anyhow::Result<Vec<TerritoryPrice>> {
         let url = format!(
-            "https://api.appstoreconnect.apple.com/v1/subscriptions/{}/prices",
-            sub_id
+            "{}/v1/subscriptions/{}/prices?include=subscriptionPricePoint,territory&limit=200",
+            self.api_base, sub_id
         );
-        let resp: serde_json::Value = self.client.get(&url).bearer_auth(jwt).send().await?.json().await?;
-        let empty = vec![];
-        let prices = resp["data"].as_array().unwrap_or(&empty);
-
-        if let Some(price) = prices.first() {
-            let price_point_url = price["relationships"]["subscriptionPricePoint"]["links"]["related"]
-                .as_str()
-                .ok_or_else(|| anyhow::anyhow!("No price point link"))?;
-
-            let pp_resp: serde_json::Value = self.client.get(price_point_url).bearer_auth(jwt).send().await?.json().await?;
-            let amount_str = pp_resp["data"]["attributes"]["customerPrice"].as_str().unwrap_or("0");
-            let amount: f64 = amount_str.parse().unwrap_or(0.0);
-            let price_micros = (amount * 1_000_000.0) as i64;
-
-            let territory_url = pp_resp["data"]["relationships"]["territory"]["links"]["related"]
-                .as_str()
-                .unwrap_or("");
-            let currency = if !territory_url.is_empty() {
-                let t_resp: serde_json::Value = self.client.get(territory_url).bearer_auth(jwt).send().await?.json().await?;
-                t_resp["data"]["attributes"]["currency"].as_str().unwrap_or("USD").to_string()
-            } else {
-                "USD".to_string()
-            };
+        let pages = self.fetch_pages(jwt, &url).await?;
+
+        // A territory's scheduled prices can straddle pages, so resolve them all at once
+        let combine = |key: &str| {
+            pages.iter()
+                .filter_map(|page| page[key].as_array())
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let combined = serde_json::json!({ "data": combine("data"), "included": combine("included") });
 
-            Ok((price_micros, currency))
-        } else {
-            anyhow::bail!("No prices found")
-        }
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        Ok(parse_price_page(&combined, &today))
     }
 
     async fn fetch_subscription_period(&self, jwt: &str, sub_id: &str) -> anyhow::Result<String> {
         let url = format!(
-            "https://api.appstoreconnect.apple.com/v1/subscriptions/{}",
-            sub_id
+            "{}/v1/subscriptions/{}",
+            self.api_base, sub_id
         );
-        let resp: serde_json::Value = self.client.get(&url).bearer_auth(jwt).send().await?.json().await?;
+        let resp: serde_json::Value = send_with_retry(|| self.client.get(&url).bearer_auth(jwt)).await?.json().await?;
 
         let period = resp["data"]["attributes"]["subscriptionPeriod"]
             .as_str()
@@ -248,48 +287,22 @@ impl AppleConnectClient {
 
     async fn fetch_introductory_offer(&self, jwt: &str, sub_id: &str) -> anyhow::Result<Option<AppleIntroOffer>> {
         let url = format!(
-            "https://api.appstoreconnect.apple.com/v1/subscriptions/{}/introductoryOffers",
-            sub_id
+            "{}/v1/subscriptions/{}/introductoryOffers?include=subscriptionPricePoint,territory",
+            self.api_base, sub_id
         );
-        let resp: serde_json::Value = self.client.get(&url).bearer_auth(jwt).send().await?.json().await?;
-        let empty = vec![];
-        let offers = resp["data"].as_array().unwrap_or(&empty);
-
-        if let Some(offer) = offers.first() {
-            let attrs = &offer["attributes"];
-            let duration = attrs["duration"].as_str().unwrap_or("P1W");
-
-            let iso_period = match duration {
-                "THREE_DAYS" => "P3D",
-                "ONE_WEEK" => "P1W",
-                "TWO_WEEKS" => "P2W",
-                "ONE_MONTH" => "P1M",
-                "TWO_MONTHS" => "P2M",
-                "THREE_MONTHS" => "P3M",
-                "SIX_MONTHS" => "P6M",
-                "ONE_YEAR" => "P1Y",
-                other => other,
-            };
-
-            Ok(Some(AppleIntroOffer {
-                period: iso_period.to_string(),
-            }))
-        } else {
-            Ok(None)
-        }
+        let resp: serde_json::Value = send_with_retry(|| self.client.get(&url).bearer_auth(jwt)).await?.json().await?;
+        Ok(parse_intro_offer(&resp))
     }
 
     async fn fetch_in_app_purchases(&self, jwt: &str, app_id: &str) -> anyhow::Result<Vec<SyncedProduct>> {
         let url = format!(
-            "https://api.appstoreconnect.apple.com/v2/apps/{}/inAppPurchasesV2",
-            app_id
+            "{}/v2/apps/{}/inAppPurchasesV2",
+            self.api_base, app_id
         );
-        let resp: serde_json::Value = self.client.get(&url).bearer_auth(jwt).send().await?.json().await?;
-        let empty = vec![];
-        let iaps = resp["data"].as_array().unwrap_or(&empty);
+        let iaps = self.fetch_all(jwt, &url).await?;
         let mut products = Vec::new();
 
-        for iap in iaps {
+        for iap in &iaps {
             let attrs = &iap["attributes"];
             let product_id = attrs["productId"].as_str().unwrap_or_default();
             let name = attrs["name"].as_str().unwrap_or(product_id);
@@ -309,10 +322,273 @@ impl AppleConnectClient {
                 currency: "USD".to_string(),
                 subscription_period: None,
                 trial_period: None,
+                intro_price_micros: None,
+                intro_currency: None,
+                offer_mode: None,
                 product_type: product_type.to_string(),
+                prices: Vec::new(),
             });
         }
 
         Ok(products)
     }
 }
+
+/// Current price per territory in a `subscriptions/{id}/prices` response,
+/// resolving price points and currencies from the `included` resources.
+///
+/// Scheduled changes show up as extra entries with a later `startDate`; the
+/// latest one already in effect on `today` wins.
+fn parse_price_page(page: &serde_json::Value, today: &str) -> Vec<TerritoryPrice> {
+    let empty = vec![];
+    let included = page["included"].as_array().unwrap_or(&empty);
+    let find_included = |kind: &str, id: &str| {
+        included.iter().find(|i| i["type"].as_str() == Some(kind) && i["id"].as_str() == Some(id))
+    };
+
+    let mut current: Vec<(String, TerritoryPrice)> = Vec::new();
+    for price in page["data"].as_array().unwrap_or(&empty) {
+        let start = price["attributes"]["startDate"].as_str().unwrap_or("").to_string();
+        if start.as_str() > today {
+            continue;
+        }
+
+        let Some(territory) = price["relationships"]["territory"]["data"]["id"].as_str() else {
+            continue;
+        };
+        let Some(point_id) = price["relationships"]["subscriptionPricePoint"]["data"]["id"].as_str() else {
+            continue;
+        };
+        let Some(point) = find_included("subscriptionPricePoints", point_id) else {
+            continue;
+        };
+
+        let amount: f64 = point["attributes"]["customerPrice"].as_str().unwrap_or("0").parse().unwrap_or(0.0);
+        let currency = find_included("territories", territory)
+            .and_then(|t| t["attributes"]["currency"].as_str())
+            .unwrap_or("USD");
+
+        let entry = TerritoryPrice {
+            territory: territory.to_string(),
+            price_micros: (amount * 1_000_000.0).round() as i64,
+            currency: currency.to_string(),
+        };
+        match current.iter_mut().find(|(_, p)| p.territory == entry.territory) {
+            Some((existing_start, existing)) if *existing_start <= start => {
+                *existing_start = start;
+                *existing = entry;
+            }
+            Some(_) => {}
+            None => current.push((start, entry)),
+        }
+    }
+
+    current.into_iter().map(|(_, price)| price).collect()
+}
+
+/// The introductory offer in a `subscriptions/{id}/introductoryOffers`
+/// response, preferring the default territory's. Free trials have no price
+/// point and are priced at 0 in the territory's currency.
+fn parse_intro_offer(page: &serde_json::Value) -> Option<AppleIntroOffer> {
+    let empty = vec![];
+    let included = page["included"].as_array().unwrap_or(&empty);
+    let find_included = |kind: &str, id: &str| {
+        included.iter().find(|i| i["type"].as_str() == Some(kind) && i["id"].as_str() == Some(id))
+    };
+
+    let offers = page["data"].as_array()?;
+    fn territory_of(offer: &serde_json::Value) -> Option<&str> {
+        offer["relationships"]["territory"]["data"]["id"].as_str()
+    }
+    let offer = offers.iter()
+        .find(|o| territory_of(o) == Some(DEFAULT_TERRITORY))
+        .or_else(|| offers.first())?;
+
+    let attrs = &offer["attributes"];
+    let duration = attrs["duration"].as_str().unwrap_or("P1W");
+    let period = match duration {
+        "THREE_DAYS" => "P3D",
+        "ONE_WEEK" => "P1W",
+        "TWO_WEEKS" => "P2W",
+        "ONE_MONTH" => "P1M",
+        "TWO_MONTHS" => "P2M",
+        "THREE_MONTHS" => "P3M",
+        "SIX_MONTHS" => "P6M",
+        "ONE_YEAR" => "P1Y",
+        other => other,
+    };
+
+    let offer_mode = match attrs["offerMode"].as_str().unwrap_or("FREE_TRIAL") {
+        "FREE_TRIAL" => "free_trial",
+        "PAY_AS_YOU_GO" => "pay_as_you_go",
+        "PAY_UP_FRONT" => "pay_up_front",
+        other => other,
+    };
+
+    let amount: f64 = offer["relationships"]["subscriptionPricePoint"]["data"]["id"].as_str()
+        .and_then(|id| find_included("subscriptionPricePoints", id))
+        .and_then(|point| point["attributes"]["customerPrice"].as_str())
+        .and_then(|price| price.parse().ok())
+        .unwrap_or(0.0);
+    let currency = territory_of(offer)
+        .and_then(|territory| find_included("territories", territory))
+        .and_then(|t| t["attributes"]["currency"].as_str())
+        .unwrap_or("USD");
+
+    Some(AppleIntroOffer {
+        period: period.to_string(),
+        offer_mode: offer_mode.to_string(),
+        price_micros: (amount * 1_000_000.0).round() as i64,
+        currency: currency.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn iap(product_id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "attributes": { "productId": product_id, "name": product_id, "inAppPurchaseType": "NON_CONSUMABLE" },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_in_app_purchases_follow_pagination() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/apps/app/inAppPurchasesV2"))
+            .and(query_param("cursor", "page2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [iap("com.test.gems")],
+                "links": { "self": "ignored" },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/apps/app/inAppPurchasesV2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [iap("com.test.lifetime")],
+                "links": { "next": format!("{}/v2/apps/app/inAppPurchasesV2?cursor=page2", server.uri()) },
+            })))
+            .mount(&server)
+            .await;
+
+        let credentials = AppleCredentials {
+            issuer_id: "issuer".to_string(),
+            key_id: "key".to_string(),
+            private_key: String::new(),
+            previous_keys: Vec::new(),
+            shared_secret: None,
+            consumption_consent: false,
+            app_apple_id: None,
+            environment: Default::default(),
+        };
+        let client = AppleConnectClient::new(Client::new(), credentials, "com.test".to_string())
+            .with_api_base(server.uri());
+
+        let products = client.fetch_in_app_purchases("jwt", "app").await.unwrap();
+        let ids: Vec<_> = products.iter().map(|p| p.store_product_id.as_str()).collect();
+        assert_eq!(ids, vec!["com.test.lifetime", "com.test.gems"]);
+    }
+
+    #[test]
+    fn test_parse_multi_territory_prices() {
+        let page = serde_json::json!({
+            "data": [
+                {
+                    "type": "subscriptionPrices", "id": "p1",
+                    "attributes": { "startDate": null },
+                    "relationships": {
+                        "subscriptionPricePoint": { "data": { "type": "subscriptionPricePoints", "id": "pp-usa" } },
+                        "territory": { "data": { "type": "territories", "id": "USA" } },
+                    },
+                },
+                {
+                    "type": "subscriptionPrices", "id": "p2",
+                    "attributes": { "startDate": null },
+                    "relationships": {
+                        "subscriptionPricePoint": { "data": { "type": "subscriptionPricePoints", "id": "pp-gbr" } },
+                        "territory": { "data": { "type": "territories", "id": "GBR" } },
+                    },
+                },
+                {
+                    "type": "subscriptionPrices", "id": "p3",
+                    "attributes": { "startDate": "2099-01-01" },
+                    "relationships": {
+                        "subscriptionPricePoint": { "data": { "type": "subscriptionPricePoints", "id": "pp-gbr-future" } },
+                        "territory": { "data": { "type": "territories", "id": "GBR" } },
+                    },
+                },
+            ],
+            "included": [
+                { "type": "subscriptionPricePoints", "id": "pp-usa", "attributes": { "customerPrice": "9.99" } },
+                { "type": "subscriptionPricePoints", "id": "pp-gbr", "attributes": { "customerPrice": "8.99" } },
+                { "type": "subscriptionPricePoints", "id": "pp-gbr-future", "attributes": { "customerPrice": "10.99" } },
+                { "type": "territories", "id": "USA", "attributes": { "currency": "USD" } },
+                { "type": "territories", "id": "GBR", "attributes": { "currency": "GBP" } },
+            ],
+        });
+
+        let prices = parse_price_page(&page, "2026-10-15");
+        assert_eq!(prices, vec![
+            TerritoryPrice { territory: "USA".to_string(), price_micros: 9_990_000, currency: "USD".to_string() },
+            TerritoryPrice { territory: "GBR".to_string(), price_micros: 8_990_000, currency: "GBP".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_intro_offers() {
+        let free_trial = serde_json::json!({
+            "data": [{
+                "type": "subscriptionIntroductoryOffers", "id": "o1",
+                "attributes": { "duration": "ONE_WEEK", "offerMode": "FREE_TRIAL", "numberOfPeriods": 1 },
+                "relationships": { "territory": { "data": { "type": "territories", "id": "USA" } } },
+            }],
+            "included": [{ "type": "territories", "id": "USA", "attributes": { "currency": "USD" } }],
+        });
+        assert_eq!(parse_intro_offer(&free_trial), Some(AppleIntroOffer {
+            period: "P1W".to_string(),
+            offer_mode: "free_trial".to_string(),
+            price_micros: 0,
+            currency: "USD".to_string(),
+        }));
+
+        let paid = serde_json::json!({
+            "data": [
+                {
+                    "type": "subscriptionIntroductoryOffers", "id": "o-gbr",
+                    "attributes": { "duration": "THREE_MONTHS", "offerMode": "PAY_UP_FRONT", "numberOfPeriods": 1 },
+                    "relationships": {
+                        "subscriptionPricePoint": { "data": { "type": "subscriptionPricePoints", "id": "pp-gbr" } },
+                        "territory": { "data": { "type": "territories", "id": "GBR" } },
+                    },
+                },
+                {
+                    "type": "subscriptionIntroductoryOffers", "id": "o-usa",
+                    "attributes": { "duration": "ONE_MONTH", "offerMode": "PAY_AS_YOU_GO", "numberOfPeriods": 3 },
+                    "relationships": {
+                        "subscriptionPricePoint": { "data": { "type": "subscriptionPricePoints", "id": "pp-usa" } },
+                        "territory": { "data": { "type": "territories", "id": "USA" } },
+                    },
+                },
+            ],
+            "included": [
+                { "type": "subscriptionPricePoints", "id": "pp-gbr", "attributes": { "customerPrice": "4.99" } },
+                { "type": "subscriptionPricePoints", "id": "pp-usa", "attributes": { "customerPrice": "1.99" } },
+                { "type": "territories", "id": "GBR", "attributes": { "currency": "GBP" } },
+                { "type": "territories", "id": "USA", "attributes": { "currency": "USD" } },
+            ],
+        });
+        assert_eq!(parse_intro_offer(&paid), Some(AppleIntroOffer {
+            period: "P1M".to_string(),
+            offer_mode: "pay_as_you_go".to_string(),
+            price_micros: 1_990_000,
+            currency: "USD".to_string(),
+        }));
+
+        assert_eq!(parse_intro_offer(&serde_json::json!({ "data": [] })), None);
+    }
+}
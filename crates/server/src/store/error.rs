@@ -0,0 +1,73 @@
+/// Why a store couldn't verify a purchase or notification, so callers can
+/// tell the client's mistakes apart from ours and the store's.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// The store has no record of the purchase.
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// The receipt or notification is malformed or fails verification.
+    #[error("invalid: {0}")]
+    Invalid(String),
+    /// The store failed or couldn't be reached.
+    #[error("store unavailable: {0}")]
+    Upstream(String),
+    /// The store rejected our credentials for the app.
+    #[error("store credentials rejected: {0}")]
+    Auth(String),
+    /// The store is throttling us.
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+    /// Genuinely signed by the store, but for a different app.
+    #[error("wrong app: {0}")]
+    WrongApp(String),
+}
+
+impl StoreError {
+    /// Classify an unsuccessful response from a store API.
+    pub fn from_status(status: reqwest::StatusCode, context: &str) -> Self {
+        let message = format!("{context}: {status}");
+        match status.as_u16() {
+            401 | 403 => Self::Auth(message),
+            404 => Self::NotFound(message),
+            429 => Self::RateLimited(message),
+            400..=499 => Self::Invalid(message),
+            _ => Self::Upstream(message),
+        }
+    }
+}
+
+/// Transport failures and unreadable responses are the store's problem.
+impl From<reqwest::Error> for StoreError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Upstream(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        for (status, expected) in [
+            (400, "invalid"),
+            (401, "auth"),
+            (403, "auth"),
+            (404, "not_found"),
+            (429, "rate_limited"),
+            (500, "upstream"),
+            (503, "upstream"),
+        ] {
+            let error = StoreError::from_status(reqwest::StatusCode::from_u16(status).unwrap(), "Store API error");
+            let kind = match error {
+                StoreError::NotFound(_) => "not_found",
+                StoreError::Invalid(_) => "invalid",
+                StoreError::Upstream(_) => "upstream",
+                StoreError::Auth(_) => "auth",
+                StoreError::RateLimited(_) => "rate_limited",
+                StoreError::WrongApp(_) => "wrong_app",
+            };
+            assert_eq!(kind, expected, "{status}");
+        }
+    }
+}
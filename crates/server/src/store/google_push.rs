@@ -0,0 +1,206 @@
+use std::sync::Arc;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use super::retry::send_with_retry;
+use tokio::sync::Mutex;
+
+/// Where Google publishes the keys that sign its OIDC tokens.
+const GOOGLE_CERTS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+
+/// Google rotates signing keys daily and publishes new ones well ahead of use.
+const CERTS_TTL_SECS: i64 = 3600;
+
+/// Don't let tokens with made-up key IDs trigger a fetch on every request.
+const UNKNOWN_KID_REFETCH_SECS: i64 = 60;
+
+const GOOGLE_ISSUERS: &[&str] = &["https://accounts.google.com", "accounts.google.com"];
+
+#[derive(Deserialize)]
+struct PushClaims {
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+/// Verifies the OIDC bearer token Pub/Sub attaches to authenticated push
+/// requests, so only Google can deliver Real-time Developer Notifications.
+///
+/// Without a configured audience every push is rejected.
+#[derive(Clone)]
+pub struct GooglePushVerifier {
+    audience: Option<String>,
+    service_account_email: Option<String>,
+    certs_url: String,
+    certs: Arc<Mutex<Option<(JwkSet, i64)>>>,
+}
+
+impl Default for GooglePushVerifier {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl GooglePushVerifier {
+    /// `audience` is the one set on the push subscription; `service_account_email`,
+    /// when given, must match the account the subscription pushes as.
+    pub fn new(audience: Option<String>, service_account_email: Option<String>) -> Self {
+        Self {
+            audience,
+            service_account_email,
+            certs_url: GOOGLE_CERTS_URL.to_string(),
+            certs: Arc::default(),
+        }
+    }
+
+    /// Fetch signing keys from somewhere else, e.g. a mock server in tests.
+    pub fn with_certs_url(mut self, certs_url: impl Into<String>) -> Self {
+        self.certs_url = certs_url.into();
+        self
+    }
+
+    /// Check the `Authorization` header of a push request.
+    pub async fn verify(&self, client: &reqwest::Client, authorization: Option<&str>) -> anyhow::Result<()> {
+        let audience = self.audience.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No Google push audience configured"))?;
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| anyhow::anyhow!("Missing bearer token"))?;
+
+        let header = jsonwebtoken::decode_header(token)?;
+        if header.alg != Algorithm::RS256 {
+            anyhow::bail!("Unexpected token algorithm: {:?}", header.alg);
+        }
+        let kid = header.kid.ok_or_else(|| anyhow::anyhow!("Missing kid header"))?;
+        let key = self.decoding_key(client, &kid).await?;
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[audience]);
+        validation.set_issuer(GOOGLE_ISSUERS);
+
+        let claims = jsonwebtoken::decode::<PushClaims>(token, &key, &validation)?.claims;
+
+        if let Some(expected) = &self.service_account_email {
+            if claims.email.as_ref() != Some(expected) || claims.email_verified != Some(true) {
+                anyhow::bail!("Token was not issued to the expected service account");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the key for `kid`, refetching when the cached set is stale or doesn't have it.
+    async fn decoding_key(&self, client: &reqwest::Client, kid: &str) -> anyhow::Result<DecodingKey> {
+        let now = chrono::Utc::now().timestamp();
+        let mut certs = self.certs.lock().await;
+
+        let stale = match certs.as_ref() {
+            None => true,
+            Some((set, fetched_at)) => {
+                let age = now - fetched_at;
+                age >= CERTS_TTL_SECS || (set.find(kid).is_none() && age >= UNKNOWN_KID_REFETCH_SECS)
+            }
+        };
+        if stale {
+            let set: JwkSet = send_with_retry(|| client.get(&self.certs_url))
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            *certs = Some((set, now));
+        }
+
+        let jwk = certs.as_ref()
+            .and_then(|(set, _)| set.find(kid))
+            .ok_or_else(|| anyhow::anyhow!("Unknown signing key {kid}"))?;
+        Ok(DecodingKey::from_jwk(jwk)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::google::tests::TEST_RSA_KEY;
+    use jsonwebtoken::{EncodingKey, Header};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const AUDIENCE: &str = "https://opencat.example/v1/notifications/google";
+    const SERVICE_ACCOUNT: &str = "pubsub-push@opencat.iam.gserviceaccount.com";
+    const TEST_KID: &str = "test-kid";
+
+    /// Modulus of `TEST_RSA_KEY`, base64url-encoded as in a JWK.
+    const TEST_RSA_MODULUS: &str = "quMSJQsDiUq5EDJbBkVCcNm8af4ygzHuLtalUJ2-uIQg7EJ9C5--Uc66XJxKoXjbDnmCi2Z4EtHeiJZWfeOTMHi1vmA47P3SirM9KBKR22d1CP14KXSm7n0URJvPU6OwVaur7pQRDSGumFNkqo_EnOv0WIINmNy9-QIBqmKGmcEcx_HAyJHmFso-MGyYDOK77T-5qY5goHLFv3gcW0LzQemMl-CUEQIYY7hBsKxETGZFlYYx06dUJRQvTzpbtC4zRNjIwhsOwchzxhYBhIbp2C1cSubUlY2Mr84MhVm-jZW2dUTHeewOleinF5dT7ZS6LsjsJupWI_7C7seI7jlwWw";
+
+    /// Serves `TEST_RSA_KEY` as the only signing key.
+    async fn mock_certs() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/certs"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "keys": [{ "kty": "RSA", "alg": "RS256", "use": "sig", "kid": TEST_KID, "n": TEST_RSA_MODULUS, "e": "AQAB" }],
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn verifier(server: &MockServer) -> GooglePushVerifier {
+        GooglePushVerifier::new(Some(AUDIENCE.to_string()), Some(SERVICE_ACCOUNT.to_string()))
+            .with_certs_url(format!("{}/certs", server.uri()))
+    }
+
+    /// Claims of a valid push token, to tamper with.
+    fn push_claims() -> serde_json::Value {
+        let now = chrono::Utc::now().timestamp();
+        serde_json::json!({
+            "iss": "https://accounts.google.com",
+            "aud": AUDIENCE,
+            "email": SERVICE_ACCOUNT,
+            "email_verified": true,
+            "iat": now,
+            "exp": now + 3600,
+        })
+    }
+
+    fn bearer(kid: &str, claims: &serde_json::Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let key = EncodingKey::from_rsa_pem(TEST_RSA_KEY.as_bytes()).unwrap();
+        format!("Bearer {}", jsonwebtoken::encode(&header, claims, &key).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_unconfigured_verifier_rejects_everything() {
+        let verifier = GooglePushVerifier::default();
+        assert!(verifier.verify(&reqwest::Client::new(), Some("Bearer anything")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_signed_by_google_is_accepted() {
+        let server = mock_certs().await;
+        let authorization = bearer(TEST_KID, &push_claims());
+        verifier(&server).verify(&reqwest::Client::new(), Some(&authorization)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_token_with_bad_claims_or_key_is_rejected() {
+        let server = mock_certs().await;
+        let verifier = verifier(&server);
+        let client = reqwest::Client::new();
+        let with = |claim: &str, value: serde_json::Value| {
+            let mut claims = push_claims();
+            claims[claim] = value;
+            bearer(TEST_KID, &claims)
+        };
+
+        for (case, authorization) in [
+            ("wrong audience", with("aud", serde_json::json!("https://elsewhere.example/push"))),
+            ("wrong issuer", with("iss", serde_json::json!("https://evil.example"))),
+            ("expired", with("exp", serde_json::json!(chrono::Utc::now().timestamp() - 3600))),
+            ("other service account", with("email", serde_json::json!("someone@example.com"))),
+            ("unverified email", with("email_verified", serde_json::json!(false))),
+            ("unknown kid", bearer("other-kid", &push_claims())),
+        ] {
+            assert!(verifier.verify(&client, Some(&authorization)).await.is_err(), "accepted token with {case}");
+        }
+    }
+}
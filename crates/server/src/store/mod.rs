@@ -1,13 +1,196 @@
+pub mod amazon;
 pub mod apple;
 pub mod apple_connect;
+pub mod apple_jws;
+pub mod error;
 pub mod google;
+pub mod google_push;
+pub mod retry;
+pub mod stripe;
+pub mod token_cache;
 pub mod types;
 
+use crate::crypto::CredentialCipher;
+use crate::models::app::{App, AppleEnvironmentSetting};
 use types::{TransactionEvent, VerifiedTransaction};
 
+pub use error::StoreError;
+
 #[async_trait::async_trait]
 pub trait StoreAdapter: Send + Sync {
-    async fn verify_purchase(&self, receipt_data: &str) -> anyhow::Result<VerifiedTransaction>;
-    async fn get_subscription_status(&self, store_transaction_id: &str) -> anyhow::Result<VerifiedTransaction>;
-    async fn process_notification(&self, payload: &[u8]) -> anyhow::Result<Vec<TransactionEvent>>;
+    async fn verify_purchase(&self, receipt_data: &str) -> Result<VerifiedTransaction, StoreError>;
+    /// Every purchase `receipt_data` proves, for restoring a user's purchases.
+    /// Most stores' receipts cover a single purchase.
+    async fn verify_all_purchases(&self, receipt_data: &str) -> Result<Vec<VerifiedTransaction>, StoreError> {
+        Ok(vec![self.verify_purchase(receipt_data).await?])
+    }
+    async fn get_subscription_status(&self, store_transaction_id: &str) -> Result<VerifiedTransaction, StoreError>;
+    async fn process_notification(&self, payload: &[u8]) -> Result<Vec<TransactionEvent>, StoreError>;
+}
+
+/// Build the adapter for `store` ("apple", "google", "amazon" or "stripe") from an app's configured credentials.
+pub fn adapter_for_app(
+    app: &App,
+    store: &str,
+    cipher: &CredentialCipher,
+    client: &reqwest::Client,
+    apple_verifier: &apple_jws::AppleJwsVerifier,
+    apple_environments: &apple::AppleEnvironmentCache,
+) -> anyhow::Result<Box<dyn StoreAdapter>> {
+    if store == "apple" {
+        return Ok(Box::new(apple_adapter_for_app(app, cipher, client, apple_verifier, apple_environments)?));
+    }
+
+    let sealed = app.store_credentials_encrypted.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No store credentials configured"))?;
+    let creds = cipher.open_credentials(sealed)?;
+
+    match store {
+        "google" => {
+            let google = creds.google
+                .ok_or_else(|| anyhow::anyhow!("No Google credentials configured"))?;
+            Ok(Box::new(google::GooglePlayAdapter::new(
+                client.clone(),
+                google.service_account_key,
+                app.bundle_id.clone(),
+            )))
+        }
+        "amazon" => {
+            let amazon = creds.amazon
+                .ok_or_else(|| anyhow::anyhow!("No Amazon credentials configured"))?;
+            let adapter = amazon::AmazonAppstoreAdapter::new(client.clone(), amazon.shared_secret);
+            Ok(Box::new(match amazon.rvs_base_url {
+                Some(base) => adapter.with_api_base(base),
+                None => adapter,
+            }))
+        }
+        "stripe" => {
+            let stripe = creds.stripe
+                .ok_or_else(|| anyhow::anyhow!("No Stripe credentials configured"))?;
+            let adapter = stripe::StripeAdapter::new(client.clone(), stripe.secret_key);
+            Ok(Box::new(match stripe.api_base_url {
+                Some(base) => adapter.with_api_base(base),
+                None => adapter,
+            }))
+        }
+        other => anyhow::bail!("Unsupported store: {other}"),
+    }
+}
+
+/// Build the App Store adapter itself, for Apple-only calls such as consumption info.
+pub fn apple_adapter_for_app(
+    app: &App,
+    cipher: &CredentialCipher,
+    client: &reqwest::Client,
+    apple_verifier: &apple_jws::AppleJwsVerifier,
+    apple_environments: &apple::AppleEnvironmentCache,
+) -> anyhow::Result<apple::AppleStoreAdapter> {
+    let sealed = app.store_credentials_encrypted.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No store credentials configured"))?;
+    let apple = cipher.open_credentials(sealed)?.apple
+        .ok_or_else(|| anyhow::anyhow!("No Apple credentials configured"))?;
+    let (environment, fallback) = match apple.environment {
+        AppleEnvironmentSetting::Auto => (apple::AppleEnvironment::Production, true),
+        AppleEnvironmentSetting::Production => (apple::AppleEnvironment::Production, false),
+        AppleEnvironmentSetting::Sandbox => (apple::AppleEnvironment::Sandbox, false),
+    };
+
+    Ok(apple::AppleStoreAdapter::new(
+        client.clone(),
+        apple.issuer_id,
+        apple.key_id,
+        apple.private_key,
+        app.bundle_id.clone(),
+        environment,
+    )
+    .with_environment_fallback(fallback)
+    .with_previous_keys(apple.previous_keys)
+    .with_verifier(apple_verifier.clone())
+    .with_environment_cache(apple_environments.clone())
+    .with_shared_secret(apple.shared_secret)
+    .with_consumption_consent(apple.consumption_consent)
+    .with_app_apple_id(apple.app_apple_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::app::{Platform, StoreCredentials};
+    use crate::test_support::TEST_CIPHER_SECRET;
+
+    fn app_with_credentials(cipher: &CredentialCipher, platform: Platform, credentials: serde_json::Value) -> App {
+        let creds: StoreCredentials = serde_json::from_value(credentials).unwrap();
+        App {
+            id: "app_1".to_string(),
+            name: "Test".to_string(),
+            platform,
+            bundle_id: "com.test".to_string(),
+            store_credentials_encrypted: Some(cipher.seal_credentials(&creds).unwrap()),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_sandbox_app_builds_sandbox_adapter() {
+        let cipher = CredentialCipher::new(TEST_CIPHER_SECRET);
+        let app_with = |environment: &str| {
+            app_with_credentials(&cipher, Platform::Ios, serde_json::json!({
+                "apple": { "issuer_id": "issuer", "key_id": "key", "private_key": "", "environment": environment },
+            }))
+        };
+        let base_url = |environment: &str| {
+            apple_adapter_for_app(
+                &app_with(environment),
+                &cipher,
+                &reqwest::Client::new(),
+                &apple_jws::AppleJwsVerifier::default(),
+                &apple::AppleEnvironmentCache::default(),
+            )
+            .unwrap()
+            .api_base_url()
+            .to_string()
+        };
+
+        assert_eq!(base_url("sandbox"), "https://api.storekit-sandbox.itunes.apple.com");
+        assert_eq!(base_url("production"), "https://api.storekit.itunes.apple.com");
+        assert_eq!(base_url("auto"), "https://api.storekit.itunes.apple.com");
+    }
+
+    #[tokio::test]
+    async fn test_amazon_credentials_can_override_rvs_base() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/version/1.0/verifyReceiptId/developer/secret/user/amzn1/receiptId/r1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "receiptId": "r1",
+                "productId": "com.test.pro",
+                "purchaseDate": 1767225600000_i64,
+                "cancelDate": null,
+                "testTransaction": true,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cipher = CredentialCipher::new(TEST_CIPHER_SECRET);
+        let app = app_with_credentials(&cipher, Platform::Amazon, serde_json::json!({
+            "amazon": { "shared_secret": "secret", "rvs_base_url": server.uri() },
+        }));
+        let adapter = adapter_for_app(
+            &app,
+            "amazon",
+            &cipher,
+            &reqwest::Client::new(),
+            &apple_jws::AppleJwsVerifier::default(),
+            &apple::AppleEnvironmentCache::default(),
+        )
+        .unwrap();
+
+        let verified = adapter.verify_purchase(r#"{"user_id":"amzn1","receipt_id":"r1"}"#).await.unwrap();
+        assert_eq!(verified.store_transaction_id, "r1");
+    }
 }
@@ -0,0 +1,116 @@
+use std::time::Duration;
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+
+/// Attempts per call, including the first.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry; doubled before each one after.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest `Retry-After` waited out. A store asking for more is answered
+/// with its response rather than holding up the caller.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Send the request `request` builds, retrying with exponential backoff while
+/// the store answers 429 or 5xx, up to [`MAX_ATTEMPTS`] times. A `Retry-After`
+/// header replaces the backoff. The last response is returned whatever its
+/// status, for the caller to classify; transport errors aren't retried.
+pub async fn send_with_retry(request: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+    let mut attempt = 1;
+    loop {
+        let response = request().send().await?;
+        let status = response.status();
+        if attempt >= MAX_ATTEMPTS || !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()) {
+            return Ok(response);
+        }
+
+        let delay = match retry_after(&response) {
+            Some(delay) if delay > MAX_RETRY_AFTER => return Ok(response),
+            Some(delay) => delay,
+            None => BASE_DELAY * 2u32.pow(attempt - 1),
+        };
+        // Only the host: some stores put secrets in the path
+        tracing::warn!(
+            %status,
+            attempt,
+            host = response.url().host_str().unwrap_or_default(),
+            "Store API call failed; retrying in {delay:?}"
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// `Retry-After` as either delay-seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried_until_success() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let url = format!("{}/flaky", server.uri());
+        let started = std::time::Instant::now();
+        let response = send_with_retry(|| client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "ok");
+        // Backed off 500ms, then 1s
+        assert!(started.elapsed() >= BASE_DELAY * 3);
+    }
+
+    #[tokio::test]
+    async fn test_only_transient_failures_are_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/bad"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/throttled"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "0"))
+            .expect(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/throttled-for-long"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "120"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        for (route, status) in [("bad", 400), ("throttled", 429), ("throttled-for-long", 429)] {
+            let url = format!("{}/{route}", server.uri());
+            let response = send_with_retry(|| client.get(&url)).await.unwrap();
+            assert_eq!(response.status().as_u16(), status, "{route}");
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use super::{StoreAdapter, StoreError, types::*};
+use super::retry::send_with_retry;
+use reqwest::Client;
+
+const API_BASE: &str = "https://api.stripe.com";
+
+/// Verifies Stripe subscriptions sold on the web, using the app's secret key.
+///
+/// Receipts are subscription IDs (`sub_...`), and a subscription's price ID is
+/// what products are matched on.
+pub struct StripeAdapter {
+    client: Client,
+    secret_key: String,
+    api_base: String,
+}
+
+impl StripeAdapter {
+    pub fn new(client: Client, secret_key: String) -> Self {
+        Self {
+            client,
+            secret_key,
+            api_base: API_BASE.to_string(),
+        }
+    }
+
+    /// Point at a different API root, e.g. stripe-mock.
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl StoreAdapter for StripeAdapter {
+    async fn verify_purchase(&self, subscription_id: &str) -> Result<VerifiedTransaction, StoreError> {
+        if !subscription_id.starts_with("sub_") || !subscription_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(StoreError::Invalid("Expected a Stripe subscription ID (sub_...)".to_string()));
+        }
+
+        let response = send_with_retry(|| {
+            self.client
+                .get(format!("{}/v1/subscriptions/{}", self.api_base, subscription_id))
+                .bearer_auth(&self.secret_key)
+        })
+        .await?;
+
+        match response.status().as_u16() {
+            200 => {}
+            401 => return Err(StoreError::Auth("Invalid Stripe secret key".to_string())),
+            404 => return Err(StoreError::NotFound("Stripe subscription not found".to_string())),
+            _ => return Err(StoreError::from_status(response.status(), "Stripe API error")),
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        parse_subscription(&body).map_err(|e| StoreError::Upstream(e.to_string()))
+    }
+
+    async fn get_subscription_status(&self, subscription_id: &str) -> Result<VerifiedTransaction, StoreError> {
+        self.verify_purchase(subscription_id).await
+    }
+
+    /// Events carry the whole subscription, so no API call is needed. Events
+    /// that aren't about a subscription produce nothing.
+    async fn process_notification(&self, payload: &[u8]) -> Result<Vec<TransactionEvent>, StoreError> {
+        let body: serde_json::Value = serde_json::from_slice(payload)
+            .map_err(|e| StoreError::Invalid(e.to_string()))?;
+        let object = &body["data"]["object"];
+        let previous = &body["data"]["previous_attributes"];
+
+        let event_type = match body["type"].as_str().unwrap_or_default() {
+            "customer.subscription.created" => "INITIAL_PURCHASE",
+            "customer.subscription.deleted" => "EXPIRATION",
+            "customer.subscription.updated" => updated_event_type(object, previous),
+            _ => return Ok(vec![]),
+        };
+
+        Ok(vec![TransactionEvent {
+            event_type: event_type.to_string(),
+            transaction: parse_subscription(object).map_err(|e| StoreError::Invalid(e.to_string()))?,
+            renewal_info: None,
+        }])
+    }
+}
+
+/// Work out what a `customer.subscription.updated` event means from the
+/// fields that changed.
+fn updated_event_type(object: &serde_json::Value, previous: &serde_json::Value) -> &'static str {
+    let status = object["status"].as_str().unwrap_or_default();
+
+    if matches!(status, "past_due" | "unpaid" | "incomplete") {
+        "BILLING_ISSUE_DETECTED"
+    } else if matches!(previous["status"].as_str(), Some("past_due" | "unpaid" | "incomplete")) {
+        "SUBSCRIPTION_RECOVERED"
+    } else if previous.get("current_period_end").is_some() || previous["items"]["data"][0].get("current_period_end").is_some() {
+        "RENEWAL"
+    } else if object["cancel_at_period_end"].as_bool() == Some(true) && previous.get("cancel_at_period_end").is_some() {
+        "CANCELLATION"
+    } else {
+        "UNKNOWN"
+    }
+}
+
+fn seconds_to_rfc3339(value: &serde_json::Value) -> Option<String> {
+    value.as_i64()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|d| d.to_rfc3339())
+}
+
+fn parse_subscription(body: &serde_json::Value) -> anyhow::Result<VerifiedTransaction> {
+    let id = body["id"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("Stripe subscription has no id"))?;
+    let item = &body["items"]["data"][0];
+    let price_id = item["price"]["id"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("Stripe subscription {id} has no price"))?;
+
+    let status = match body["status"].as_str().unwrap_or_default() {
+        "active" | "trialing" => TransactionStatus::Active,
+        "past_due" | "incomplete" => TransactionStatus::BillingRetry,
+        "canceled" | "unpaid" | "incomplete_expired" | "paused" => TransactionStatus::Expired,
+        other => anyhow::bail!("Unknown Stripe subscription status: {other}"),
+    };
+
+    // Newer API versions moved the billing period onto subscription items
+    let period_end = if body["current_period_end"].is_i64() {
+        &body["current_period_end"]
+    } else {
+        &item["current_period_end"]
+    };
+
+    Ok(VerifiedTransaction {
+        store_transaction_id: id.to_string(),
+        original_transaction_id: None,
+        product_id: price_id.to_string(),
+        purchase_date: seconds_to_rfc3339(&body["start_date"]).unwrap_or_default(),
+        expiration_date: seconds_to_rfc3339(&body["ended_at"]).or_else(|| seconds_to_rfc3339(period_end)),
+        status,
+        store: Store::Stripe,
+        is_sandbox: !body["livemode"].as_bool().unwrap_or(true),
+        period_type: if body["status"].as_str() == Some("trialing") { PeriodType::Trial } else { PeriodType::Normal },
+        auto_renew: None,
+        auto_resume_time: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn subscription(status: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "sub_1QxYzAbCdEfGhIjK",
+            "object": "subscription",
+            "cancel_at_period_end": false,
+            "canceled_at": null,
+            "current_period_end": 1769904000,
+            "current_period_start": 1767225600,
+            "customer": "cus_RaBcDeFgHiJkLm",
+            "ended_at": null,
+            "items": {
+                "object": "list",
+                "data": [{
+                    "id": "si_RaBcDeFgHiJkLm",
+                    "object": "subscription_item",
+                    "price": {
+                        "id": "price_1QxYzMonthlyPro",
+                        "object": "price",
+                        "product": "prod_RaBcDeFgHiJkLm",
+                        "recurring": { "interval": "month", "interval_count": 1 },
+                        "unit_amount": 999,
+                        "currency": "usd"
+                    },
+                    "quantity": 1
+                }]
+            },
+            "livemode": false,
+            "start_date": 1767225600,
+            "status": status,
+            "trial_end": null
+        })
+    }
+
+    #[test]
+    fn test_parse_subscription() {
+        let tx = parse_subscription(&subscription("active")).unwrap();
+        assert_eq!(tx.store_transaction_id, "sub_1QxYzAbCdEfGhIjK");
+        assert_eq!(tx.product_id, "price_1QxYzMonthlyPro");
+        assert_eq!(tx.purchase_date, "2026-01-01T00:00:00+00:00");
+        assert_eq!(tx.expiration_date.as_deref(), Some("2026-02-01T00:00:00+00:00"));
+        assert!(matches!(tx.status, TransactionStatus::Active));
+        assert!(matches!(tx.store, Store::Stripe));
+        assert!(tx.is_sandbox);
+
+        for (status, expected) in [("trialing", "active"), ("past_due", "billing_retry"), ("canceled", "expired")] {
+            assert_eq!(parse_subscription(&subscription(status)).unwrap().status.as_str(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_purchase_fetches_subscription() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/subscriptions/sub_1QxYzAbCdEfGhIjK"))
+            .and(header("authorization", "Bearer sk_test_123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(subscription("past_due")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let adapter = StripeAdapter::new(Client::new(), "sk_test_123".to_string()).with_api_base(server.uri());
+        let tx = adapter.verify_purchase("sub_1QxYzAbCdEfGhIjK").await.unwrap();
+        assert!(matches!(tx.status, TransactionStatus::BillingRetry));
+
+        assert!(adapter.verify_purchase("../v1/customers").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_process_subscription_updated_event() {
+        let event = serde_json::json!({
+            "id": "evt_1QxYzAbCdEfGhIjK",
+            "object": "event",
+            "type": "customer.subscription.updated",
+            "data": {
+                "object": subscription("active"),
+                "previous_attributes": { "status": "past_due" }
+            }
+        });
+
+        let adapter = StripeAdapter::new(Client::new(), "sk_test_123".to_string());
+        let events = adapter.process_notification(event.to_string().as_bytes()).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "SUBSCRIPTION_RECOVERED");
+        assert_eq!(events[0].transaction.store_transaction_id, "sub_1QxYzAbCdEfGhIjK");
+
+        let invoice = serde_json::json!({ "type": "invoice.paid", "data": { "object": { "id": "in_123" } } });
+        assert!(adapter.process_notification(invoice.to_string().as_bytes()).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Mutex;
+
+/// Regenerate a cached token once it is this close to expiring.
+const REFRESH_MARGIN_SECS: i64 = 60;
+
+/// Holds a signed bearer token and its `exp`, so it can be reused across API
+/// calls instead of being re-signed for every request.
+#[derive(Debug, Default)]
+pub struct TokenCache {
+    cached: Mutex<Option<(String, i64)>>,
+}
+
+impl TokenCache {
+    /// Return the cached token, or call `mint(now, exp)` for a new one valid for `ttl_secs`.
+    pub fn get_or_refresh(
+        &self,
+        ttl_secs: i64,
+        mint: impl FnOnce(i64, i64) -> anyhow::Result<String>,
+    ) -> anyhow::Result<String> {
+        let now = chrono::Utc::now().timestamp();
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some((token, exp)) = cached.as_ref() {
+            if *exp - now > REFRESH_MARGIN_SECS {
+                return Ok(token.clone());
+            }
+        }
+
+        let exp = now + ttl_secs;
+        let token = mint(now, exp)?;
+        *cached = Some((token.clone(), exp));
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_near_expiry_is_regenerated() {
+        let cache = TokenCache::default();
+        let first = cache.get_or_refresh(30, |now, _| Ok(format!("a{now}"))).unwrap();
+        let second = cache.get_or_refresh(30, |_, _| Ok("b".to_string())).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(second, "b");
+    }
+}
@@ -1,22 +1,41 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct VerifiedTransaction {
     pub store_transaction_id: String,
+    /// The transaction that started the subscription this one renews, which
+    /// Apple repeats on every renewal. `None` for stores without such chains.
+    #[serde(default)]
+    pub original_transaction_id: Option<String>,
     pub product_id: String,
     pub purchase_date: String,
     pub expiration_date: Option<String>,
     pub status: TransactionStatus,
     pub store: Store,
+    /// Bought in a store test environment rather than with real money.
+    #[serde(default)]
+    pub is_sandbox: bool,
+    /// Free trial or introductory pricing, when the store says so.
+    #[serde(default)]
+    pub period_type: PeriodType,
+    /// Whether the subscription will renew, for stores that report it with
+    /// the purchase rather than only in notifications.
+    #[serde(default)]
+    pub auto_renew: Option<bool>,
+    /// When a paused subscription resumes on its own, if the store scheduled it.
+    #[serde(default)]
+    pub auto_resume_time: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub enum TransactionStatus {
     Active,
     Expired,
     Refunded,
     GracePeriod,
     BillingRetry,
+    /// Suspended by the subscriber until a resume date; no access meanwhile.
+    Paused,
 }
 
 impl TransactionStatus {
@@ -27,27 +46,88 @@ impl TransactionStatus {
             Self::Refunded => "refunded",
             Self::GracePeriod => "grace_period",
             Self::BillingRetry => "billing_retry",
+            Self::Paused => "paused",
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The pricing phase a subscription period was bought in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodType {
+    #[default]
+    Normal,
+    Trial,
+    Intro,
+}
+
+impl PeriodType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Normal => "normal",
+            Self::Trial => "trial",
+            Self::Intro => "intro",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "trial" => Self::Trial,
+            "intro" => Self::Intro,
+            _ => Self::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub enum Store {
     Apple,
     Google,
+    Amazon,
+    Stripe,
 }
 
 impl Store {
-    pub fn as_str(&self) -> &str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             Self::Apple => "apple",
             Self::Google => "google",
+            Self::Amazon => "amazon",
+            Self::Stripe => "stripe",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "apple" => Some(Self::Apple),
+            "google" => Some(Self::Google),
+            "amazon" => Some(Self::Amazon),
+            "stripe" => Some(Self::Stripe),
+            _ => None,
         }
     }
 }
 
+impl VerifiedTransaction {
+    /// `"sandbox"` or `"production"`, as stored on transactions and events.
+    pub fn environment(&self) -> &'static str {
+        if self.is_sandbox { "sandbox" } else { "production" }
+    }
+}
+
+/// Renewal state reported alongside a transaction, used to tell voluntary churn
+/// apart from billing problems.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenewalInfo {
+    pub auto_renew_status: Option<bool>,
+    pub expiration_reason: Option<String>,
+    pub grace_period_expires_date: Option<String>,
+    pub offer_type: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionEvent {
     pub event_type: String,
     pub transaction: VerifiedTransaction,
+    pub renewal_info: Option<RenewalInfo>,
 }
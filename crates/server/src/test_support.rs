@@ -0,0 +1,48 @@
+//! Setup shared by the crate's tests.
+
+use crate::api::offerings::OfferingsCache;
+use crate::api::AppState;
+use crate::crypto::CredentialCipher;
+use crate::currency::FxRates;
+use crate::db::{self, DbPool};
+use crate::events::EventBus;
+use crate::models::app::CreateApp;
+use crate::store::apple::AppleEnvironmentCache;
+use crate::store::apple_jws::AppleJwsVerifier;
+use crate::store::google_push::GooglePushVerifier;
+
+/// Key for the credential cipher in tests.
+pub(crate) const TEST_CIPHER_SECRET: &str = "test-secret-key-min-32-chars-long!!";
+
+/// A fresh, migrated in-memory database.
+pub(crate) async fn test_pool() -> DbPool {
+    db::connect("sqlite::memory:").await.unwrap()
+}
+
+/// State around `pool` with defaults for everything else; override fields
+/// with struct update syntax where a test needs to.
+pub(crate) fn test_state(pool: DbPool) -> AppState {
+    AppState {
+        pool,
+        cipher: CredentialCipher::new(TEST_CIPHER_SECRET),
+        http: reqwest::Client::new(),
+        events: EventBus::default(),
+        apple_verifier: AppleJwsVerifier::default(),
+        apple_environments: AppleEnvironmentCache::default(),
+        google_verifier: GooglePushVerifier::default(),
+        offerings_cache: OfferingsCache::default(),
+        fx_rates: FxRates::default(),
+    }
+}
+
+/// An iOS app with `bundle_id`. Returns its ID and API key.
+pub(crate) async fn create_test_app(state: &AppState, bundle_id: &str) -> (String, String) {
+    let created = crate::api::apps::insert_app(&state.pool, &CreateApp {
+        name: "Test".to_string(),
+        platform: "ios".to_string(),
+        bundle_id: bundle_id.to_string(),
+    })
+    .await
+    .unwrap();
+    (created.app.id, created.api_key)
+}
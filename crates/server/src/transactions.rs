@@ -0,0 +1,339 @@
+use sqlx::AnyConnection;
+use crate::store::types::{TransactionEvent, TransactionStatus, VerifiedTransaction};
+
+/// Status a transaction should have after `event`, falling back to what the
+/// store reported for event types that don't imply one.
+pub fn status_after_event(event: &TransactionEvent) -> TransactionStatus {
+    match event.event_type.as_str() {
+        "INITIAL_PURCHASE" | "RENEWAL" | "SUBSCRIPTION_RECOVERED" | "RESTARTED" | "RESUME" => TransactionStatus::Active,
+        "EXPIRATION" => TransactionStatus::Expired,
+        "REFUND" => TransactionStatus::Refunded,
+        "GRACE_PERIOD" => TransactionStatus::GracePeriod,
+        "PAUSE" => TransactionStatus::Paused,
+        "BILLING_ISSUE_DETECTED" | "ACCOUNT_HOLD" => TransactionStatus::BillingRetry,
+        _ => event.transaction.status.clone(),
+    }
+}
+
+/// Serialize `event` for storage, with the transaction's environment at the
+/// top level so webhook consumers can filter out sandbox purchases.
+pub fn event_payload(event: &TransactionEvent) -> serde_json::Result<String> {
+    let mut payload = serde_json::to_value(event)?;
+    payload["environment"] = serde_json::json!(event.transaction.environment());
+    serde_json::to_string(&payload)
+}
+
+/// Whether `event` brings a transaction stored as paused back to active.
+/// Google reports a resume only as a recovery or renewal, so this is how a
+/// `RESUME` is told apart.
+pub async fn resumes_pause(
+    conn: &mut AnyConnection,
+    app_id: &str,
+    event: &TransactionEvent,
+) -> Result<bool, sqlx::Error> {
+    if !matches!(status_after_event(event), TransactionStatus::Active) {
+        return Ok(false);
+    }
+    let status = sqlx::query_scalar::<_, String>(
+        "SELECT t.status FROM transactions t
+         JOIN subscribers s ON s.id = t.subscriber_id
+         WHERE t.store = $1 AND t.store_transaction_id = $2 AND s.app_id = $3"
+    )
+    .bind(event.transaction.store.as_str())
+    .bind(&event.transaction.store_transaction_id)
+    .bind(app_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(status.as_deref() == Some("paused"))
+}
+
+/// Bring the stored transaction for `event` up to date within `app_id`.
+///
+/// A transaction we haven't seen that renews one we have, by its original
+/// transaction id, is recorded for the same subscriber. Returns the owning
+/// subscriber, or `None` when we have no record of the transaction or its
+/// subscription; without a subscriber to attach it to there is nothing to store.
+pub async fn apply_transaction_event(
+    conn: &mut AnyConnection,
+    app_id: &str,
+    event: &TransactionEvent,
+) -> Result<Option<String>, sqlx::Error> {
+    let existing = sqlx::query_as::<_, (String, String)>(
+        "SELECT t.id, t.subscriber_id FROM transactions t
+         JOIN subscribers s ON s.id = t.subscriber_id
+         WHERE t.store = $1 AND t.store_transaction_id = $2 AND s.app_id = $3"
+    )
+    .bind(event.transaction.store.as_str())
+    .bind(&event.transaction.store_transaction_id)
+    .bind(app_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((transaction_id, subscriber_id)) = existing else {
+        return record_renewal(conn, app_id, event).await;
+    };
+
+    // Renewal info only comes with some notifications; keep what we knew otherwise
+    let renewal = event.renewal_info.as_ref();
+    sqlx::query(
+        "UPDATE transactions SET status = $1, expiration_date = COALESCE($2, expiration_date), environment = $3, \
+         period_type = $4, auto_renew = COALESCE($5, auto_renew), \
+         grace_period_expires_date = CASE WHEN $6 THEN $7 ELSE grace_period_expires_date END, auto_resume_time = $8, \
+         updated_at = $9 WHERE id = $10"
+    )
+    .bind(status_after_event(event).as_str())
+    .bind(&event.transaction.expiration_date)
+    .bind(event.transaction.environment())
+    .bind(event.transaction.period_type.as_str())
+    .bind(renewal.and_then(|r| r.auto_renew_status).or(event.transaction.auto_renew).map(i32::from))
+    .bind(renewal.is_some())
+    .bind(renewal.and_then(|r| r.grace_period_expires_date.clone()))
+    .bind(&event.transaction.auto_resume_time)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&transaction_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(subscriber_id))
+}
+
+/// Record `event`'s transaction as a new period of a subscription we already
+/// have, returning its subscriber.
+async fn record_renewal(
+    conn: &mut AnyConnection,
+    app_id: &str,
+    event: &TransactionEvent,
+) -> Result<Option<String>, sqlx::Error> {
+    let transaction = &event.transaction;
+    let Some(original_transaction_id) = &transaction.original_transaction_id else {
+        return Ok(None);
+    };
+
+    let chain = sqlx::query_as::<_, (String, String)>(
+        "SELECT t.subscriber_id, t.product_id FROM transactions t
+         JOIN subscribers s ON s.id = t.subscriber_id
+         WHERE t.store = $1 AND (t.original_transaction_id = $2 OR t.store_transaction_id = $2) AND s.app_id = $3
+         ORDER BY t.purchase_date DESC
+         LIMIT 1"
+    )
+    .bind(transaction.store.as_str())
+    .bind(original_transaction_id)
+    .bind(app_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some((subscriber_id, chain_product_id)) = chain else {
+        return Ok(None);
+    };
+
+    // An upgrade or crossgrade renews into a different product
+    let product_id = sqlx::query_scalar::<_, String>("SELECT id FROM products WHERE app_id = $1 AND store_product_id = $2")
+        .bind(app_id)
+        .bind(&transaction.product_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .unwrap_or(chain_product_id);
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let renewal = event.renewal_info.as_ref();
+    sqlx::query(
+        "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, original_transaction_id, \
+         purchase_date, expiration_date, status, environment, period_type, auto_renew, grace_period_expires_date, auto_resume_time, \
+         created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)"
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(&subscriber_id)
+    .bind(&product_id)
+    .bind(transaction.store.as_str())
+    .bind(&transaction.store_transaction_id)
+    .bind(original_transaction_id)
+    .bind(&transaction.purchase_date)
+    .bind(&transaction.expiration_date)
+    .bind(status_after_event(event).as_str())
+    .bind(transaction.environment())
+    .bind(transaction.period_type.as_str())
+    .bind(renewal.and_then(|r| r.auto_renew_status).or(transaction.auto_renew).map(i32::from))
+    .bind(renewal.and_then(|r| r.grace_period_expires_date.clone()))
+    .bind(&transaction.auto_resume_time)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut *conn)
+    .await?;
+
+    supersede_earlier_periods(conn, transaction, &now).await?;
+    Ok(Some(subscriber_id))
+}
+
+/// Expire the periods of `transaction`'s subscription bought before it, so
+/// only the newest renewal in a chain still grants access. Returns how many
+/// were expired.
+pub async fn supersede_earlier_periods(
+    conn: &mut AnyConnection,
+    transaction: &VerifiedTransaction,
+    now: &str,
+) -> Result<u64, sqlx::Error> {
+    let Some(original_transaction_id) = &transaction.original_transaction_id else {
+        return Ok(0);
+    };
+
+    // Dates are stored as RFC 3339 UTC strings, so they compare lexically
+    let result = sqlx::query(
+        "UPDATE transactions SET status = 'expired', updated_at = $1 \
+         WHERE store = $2 AND (original_transaction_id = $3 OR store_transaction_id = $3) \
+         AND store_transaction_id != $4 AND purchase_date < $5 \
+         AND status IN ('active', 'grace_period', 'billing_retry')"
+    )
+    .bind(now)
+    .bind(transaction.store.as_str())
+    .bind(original_transaction_id)
+    .bind(&transaction.store_transaction_id)
+    .bind(&transaction.purchase_date)
+    .execute(&mut *conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, DbPool};
+    use crate::store::types::{PeriodType, Store, VerifiedTransaction};
+
+    async fn seed(pool: &DbPool) {
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('app', 'Test', 'ios', 'com.test')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ('prod', 'app', 'com.test.pro', 'subscription')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', 'app', 'user123')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, expiration_date, status)
+             VALUES ('tx', 'sub', 'prod', 'apple', '1000', '2026-01-01T00:00:00Z', '2026-02-01T00:00:00Z', 'active')"
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    fn event(event_type: &str, store_transaction_id: &str, expiration_date: Option<&str>) -> TransactionEvent {
+        TransactionEvent {
+            event_type: event_type.to_string(),
+            transaction: VerifiedTransaction {
+                store_transaction_id: store_transaction_id.to_string(),
+                original_transaction_id: None,
+                product_id: "com.test.pro".to_string(),
+                purchase_date: "2026-01-01T00:00:00Z".to_string(),
+                expiration_date: expiration_date.map(String::from),
+                status: TransactionStatus::Active,
+                store: Store::Apple,
+                is_sandbox: false,
+                period_type: PeriodType::Normal,
+                auto_renew: None,
+                auto_resume_time: None,
+            },
+            renewal_info: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notification_types_update_status() {
+        for (event_type, expected_status) in [
+            ("RENEWAL", "active"),
+            ("EXPIRATION", "expired"),
+            ("REFUND", "refunded"),
+            ("GRACE_PERIOD", "grace_period"),
+            ("PAUSE", "paused"),
+        ] {
+            let pool = db::connect("sqlite::memory:").await.unwrap();
+            seed(&pool).await;
+
+            let subscriber = apply_transaction_event(&mut pool.acquire().await.unwrap(), "app", &event(event_type, "1000", None))
+                .await
+                .unwrap();
+            assert_eq!(subscriber.as_deref(), Some("sub"));
+
+            let (status, expiration): (String, Option<String>) =
+                sqlx::query_as("SELECT status, expiration_date FROM transactions WHERE id = 'tx'")
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!(status, expected_status, "{event_type}");
+            assert_eq!(expiration.as_deref(), Some("2026-02-01T00:00:00Z"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recovery_of_paused_transaction_is_a_resume() {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        seed(&pool).await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let mut pause = event("PAUSE", "1000", None);
+        pause.transaction.auto_resume_time = Some("2026-03-01T00:00:00Z".to_string());
+        assert!(!resumes_pause(&mut conn, "app", &pause).await.unwrap());
+        apply_transaction_event(&mut conn, "app", &pause).await.unwrap();
+        let auto_resume_time: Option<String> = sqlx::query_scalar("SELECT auto_resume_time FROM transactions WHERE id = 'tx'")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(auto_resume_time.as_deref(), Some("2026-03-01T00:00:00Z"));
+
+        assert!(!resumes_pause(&mut conn, "app", &event("EXPIRATION", "1000", None)).await.unwrap());
+        assert!(resumes_pause(&mut conn, "app", &event("SUBSCRIPTION_RECOVERED", "1000", None)).await.unwrap());
+        assert!(!resumes_pause(&mut conn, "other-app", &event("SUBSCRIPTION_RECOVERED", "1000", None)).await.unwrap());
+
+        apply_transaction_event(&mut conn, "app", &event("RESUME", "1000", None)).await.unwrap();
+        let (status, auto_resume_time): (String, Option<String>) =
+            sqlx::query_as("SELECT status, auto_resume_time FROM transactions WHERE id = 'tx'")
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(status, "active");
+        assert_eq!(auto_resume_time, None);
+    }
+
+    #[tokio::test]
+    async fn test_renewal_extends_expiration() {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        seed(&pool).await;
+        sqlx::query("UPDATE transactions SET status = 'expired'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        apply_transaction_event(&mut pool.acquire().await.unwrap(), "app", &event("RENEWAL", "1000", Some("2026-03-01T00:00:00Z")))
+            .await
+            .unwrap();
+
+        let (status, expiration): (String, Option<String>) =
+            sqlx::query_as("SELECT status, expiration_date FROM transactions WHERE id = 'tx'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "active");
+        assert_eq!(expiration.as_deref(), Some("2026-03-01T00:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_transaction_is_ignored() {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        seed(&pool).await;
+
+        let subscriber = apply_transaction_event(&mut pool.acquire().await.unwrap(), "app", &event("REFUND", "9999", None))
+            .await
+            .unwrap();
+        assert_eq!(subscriber, None);
+
+        // Scoped to the app, too
+        let subscriber = apply_transaction_event(&mut pool.acquire().await.unwrap(), "other-app", &event("REFUND", "1000", None))
+            .await
+            .unwrap();
+        assert_eq!(subscriber, None);
+    }
+}
@@ -0,0 +1,299 @@
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use crate::crypto::CredentialCipher;
+use crate::db::DbPool;
+use crate::events::{record_event, EventBus};
+use crate::models::app::App;
+use crate::store::google::{GooglePlayAdapter, VoidedPurchase};
+use crate::store::types::{PeriodType, Store, TransactionEvent, TransactionStatus, VerifiedTransaction};
+use crate::transactions::event_payload;
+
+/// How far back the first poll for an app reaches; Google keeps 30 days.
+const INITIAL_LOOKBACK_DAYS: i64 = 30;
+
+#[derive(sqlx::FromRow)]
+struct VoidedTransaction {
+    id: String,
+    subscriber_id: String,
+    store_transaction_id: String,
+    original_transaction_id: Option<String>,
+    store_product_id: String,
+    purchase_date: String,
+    expiration_date: Option<String>,
+    environment: String,
+    period_type: String,
+}
+
+/// Polls Google Play's voided purchases for every app with Google
+/// credentials, marking matching transactions refunded and recording a
+/// `REFUND` event, since refunds issued by Play support don't always arrive
+/// as real-time notifications.
+pub struct VoidedPurchaseWorker {
+    pool: DbPool,
+    events: EventBus,
+    cipher: CredentialCipher,
+    http: reqwest::Client,
+    interval: Duration,
+    api_base: Option<String>,
+}
+
+impl VoidedPurchaseWorker {
+    pub fn new(pool: DbPool, events: EventBus, cipher: CredentialCipher, http: reqwest::Client, interval: Duration) -> Self {
+        Self { pool, events, cipher, http, interval, api_base: None }
+    }
+
+    /// Point adapters at a different Android Publisher API root, e.g. a mock server in tests.
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = Some(api_base.into());
+        self
+    }
+
+    /// Poll every `interval` until `shutdown` is cancelled, finishing the current pass first.
+    pub async fn run(&self, shutdown: CancellationToken) {
+        while !shutdown.is_cancelled() {
+            if let Err(e) = self.poll_all().await {
+                tracing::error!("Voided purchases poll error: {e}");
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+        tracing::info!("Voided purchases worker stopped");
+    }
+
+    /// Poll each app in turn, returning how many transactions were refunded.
+    async fn poll_all(&self) -> anyhow::Result<usize> {
+        let apps = sqlx::query_as::<_, App>("SELECT * FROM apps WHERE store_credentials_encrypted IS NOT NULL")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut refunded = 0;
+        for app in apps {
+            // One app's broken credentials shouldn't hold up the rest
+            match self.poll_app(&app).await {
+                Ok(count) => refunded += count,
+                Err(e) => tracing::warn!("Failed to poll voided purchases for app {}: {e}", app.id),
+            }
+        }
+        Ok(refunded)
+    }
+
+    async fn poll_app(&self, app: &App) -> anyhow::Result<usize> {
+        let Some(sealed) = app.store_credentials_encrypted.as_deref() else {
+            return Ok(0);
+        };
+        let Some(google) = self.cipher.open_credentials(sealed)?.google else {
+            return Ok(0);
+        };
+
+        let mut adapter = GooglePlayAdapter::new(self.http.clone(), google.service_account_key, app.bundle_id.clone());
+        if let Some(api_base) = &self.api_base {
+            adapter = adapter.with_api_base(api_base.clone());
+        }
+
+        let last_polled = sqlx::query_scalar::<_, i64>(
+            "SELECT last_voided_time_millis FROM google_voided_purchase_polls WHERE app_id = $1"
+        )
+        .bind(&app.id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let start = last_polled.unwrap_or_else(|| {
+            (chrono::Utc::now() - chrono::Duration::days(INITIAL_LOOKBACK_DAYS)).timestamp_millis()
+        });
+
+        let voided = adapter.list_voided_purchases(start).await?;
+
+        let mut refunded = 0;
+        for purchase in &voided {
+            if refund_google_purchase(&self.pool, &self.events, &app.id, &purchase.purchase_token).await? {
+                refunded += 1;
+            }
+        }
+
+        // startTime is inclusive, so the newest purchase comes back next time;
+        // it is already refunded by then and gets skipped
+        let newest = voided.iter().map(VoidedPurchase::voided_at_millis).fold(start, i64::max);
+        sqlx::query(
+            "INSERT INTO google_voided_purchase_polls (app_id, last_voided_time_millis, polled_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (app_id) DO UPDATE SET last_voided_time_millis = excluded.last_voided_time_millis, polled_at = excluded.polled_at"
+        )
+        .bind(&app.id)
+        .bind(newest)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(refunded)
+    }
+}
+
+/// Refund the app's Google transaction for `purchase_token`, if we have one
+/// that isn't already refunded, recording a `REFUND` event.
+pub async fn refund_google_purchase(
+    pool: &DbPool,
+    events: &EventBus,
+    app_id: &str,
+    purchase_token: &str,
+) -> anyhow::Result<bool> {
+    let transaction = sqlx::query_as::<_, VoidedTransaction>(
+        "SELECT t.id, t.subscriber_id, t.store_transaction_id, t.original_transaction_id, p.store_product_id, t.purchase_date, t.expiration_date, t.environment, t.period_type
+         FROM transactions t
+         JOIN subscribers s ON s.id = t.subscriber_id
+         JOIN products p ON p.id = t.product_id
+         WHERE t.store = 'google' AND t.store_transaction_id = $1 AND s.app_id = $2 AND t.status != 'refunded'"
+    )
+    .bind(purchase_token)
+    .bind(app_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(transaction) = transaction else {
+        return Ok(false);
+    };
+
+    let event = TransactionEvent {
+        event_type: "REFUND".to_string(),
+        transaction: VerifiedTransaction {
+            store_transaction_id: transaction.store_transaction_id,
+            original_transaction_id: transaction.original_transaction_id,
+            product_id: transaction.store_product_id,
+            purchase_date: transaction.purchase_date,
+            expiration_date: transaction.expiration_date,
+            status: TransactionStatus::Refunded,
+            store: Store::Google,
+            is_sandbox: transaction.environment == "sandbox",
+            period_type: PeriodType::parse(&transaction.period_type),
+            auto_renew: None,
+            auto_resume_time: None,
+        },
+        renewal_info: None,
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut tx = pool.begin().await?;
+
+    // A concurrent poll or notification may have refunded it since the lookup
+    let updated = sqlx::query(
+        "UPDATE transactions SET status = 'refunded', updated_at = $1 WHERE id = $2 AND status != 'refunded'"
+    )
+    .bind(&now)
+    .bind(&transaction.id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    let payload = event_payload(&event)?;
+    let event_id = record_event(&mut tx, app_id, Some(&transaction.subscriber_id), &event.event_type, &payload).await?;
+
+    tx.commit().await?;
+    events.publish_stored(pool, &event_id).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::app::{GoogleCredentials, StoreCredentials};
+    use crate::store::google::tests::TEST_RSA_KEY;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const TEST_SECRET: &str = "test-secret-key-min-32-chars-long!!";
+
+    async fn seed(pool: &DbPool, token_uri: &str) {
+        let key = serde_json::json!({
+            "client_email": "test@example.iam.gserviceaccount.com",
+            "private_key": TEST_RSA_KEY,
+            "token_uri": token_uri,
+        });
+        let sealed = CredentialCipher::new(TEST_SECRET)
+            .seal_credentials(&StoreCredentials {
+                apple: None,
+                google: Some(GoogleCredentials { service_account_key: key.to_string() }),
+                amazon: None,
+                stripe: None,
+            })
+            .unwrap();
+
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id, store_credentials_encrypted) VALUES ('app', 'Test', 'android', 'com.test', $1)")
+            .bind(&sealed)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO products (id, app_id, store_product_id, product_type) VALUES ('prod', 'app', 'com.test.pro', 'subscription')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', 'app', 'user123')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (id, subscriber_id, product_id, store, store_transaction_id, purchase_date, status)
+             VALUES ('tx', 'sub', 'prod', 'google', 'voided-token', '2026-01-01T00:00:00Z', 'active')"
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_voided_token_refunds_transaction_once() {
+        let voided_at = (chrono::Utc::now() - chrono::Duration::days(1)).timestamp_millis();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "access_token": "access" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/applications/com.test/purchases/voidedpurchases"))
+            .and(query_param("type", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "voidedPurchases": [
+                    { "purchaseToken": "voided-token", "orderId": "GPA.1", "voidedTimeMillis": voided_at.to_string(), "voidedReason": 1 },
+                    { "purchaseToken": "someone-elses-token", "voidedTimeMillis": (voided_at - 1000).to_string() },
+                ],
+            })))
+            .mount(&server)
+            .await;
+
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        seed(&pool, &format!("{}/token", server.uri())).await;
+
+        let worker = VoidedPurchaseWorker::new(
+            pool.clone(),
+            EventBus::default(),
+            CredentialCipher::new(TEST_SECRET),
+            reqwest::Client::new(),
+            Duration::from_secs(3600),
+        )
+        .with_api_base(server.uri());
+
+        assert_eq!(worker.poll_all().await.unwrap(), 1);
+
+        let status: String = sqlx::query_scalar("SELECT status FROM transactions WHERE id = 'tx'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "refunded");
+
+        let last: i64 = sqlx::query_scalar("SELECT last_voided_time_millis FROM google_voided_purchase_polls WHERE app_id = 'app'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(last, voided_at);
+
+        // Seeing the same purchase again doesn't record a second refund
+        assert_eq!(worker.poll_all().await.unwrap(), 0);
+        let refunds: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events WHERE event_type = 'REFUND'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(refunds, 1);
+    }
+}
@@ -1,73 +1,150 @@
+use std::sync::Mutex;
+use futures::stream::{self, StreamExt};
+use hmac::{Hmac, Mac};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use reqwest::Client;
+use sha2::Sha256;
+use tokio_util::sync::CancellationToken;
+use crate::config::WebhookConfig;
 use crate::db::DbPool;
 
+/// Delivers pending events to registered webhook endpoints.
+///
+/// Each request carries two headers so receivers can authenticate it without
+/// the endpoint secret ever going over the wire:
+///
+/// - `X-Webhook-Timestamp`: Unix timestamp (seconds) at which the request was signed.
+/// - `X-Webhook-Signature`: `sha256=<hex>`, the HMAC-SHA256 of `"{timestamp}.{body}"`
+///   keyed with the endpoint secret.
+///
+/// The body is a [`WebhookEnvelope`](super::envelope::WebhookEnvelope), and
+/// `X-Webhook-Version` carries its `api_version`.
+///
+/// Receivers should recompute the signature over the raw body, compare it in
+/// constant time, and reject timestamps outside a small tolerance window.
 pub struct WebhookDeliveryWorker {
     pool: DbPool,
     client: Client,
+    config: WebhookConfig,
+    /// Spreads out retries; see [`next_retry_delay`].
+    rng: Mutex<StdRng>,
+}
+
+/// A due delivery along with what's needed to send it.
+#[derive(sqlx::FromRow)]
+struct DueDelivery {
+    id: String,
+    app_id: String,
+    url: String,
+    secret: String,
+    payload: String,
+    /// `None` for deliveries queued before envelopes, sent as the bare event payload.
+    api_version: Option<i32>,
+    attempts: i32,
 }
 
 impl WebhookDeliveryWorker {
-    pub fn new(pool: DbPool) -> Self {
+    /// `config` should already have passed [`WebhookConfig::validate`].
+    pub fn new(pool: DbPool, client: Client, config: WebhookConfig) -> Self {
         Self {
             pool,
-            client: Client::new(),
+            client,
+            config,
+            rng: Mutex::new(StdRng::from_entropy()),
         }
     }
 
-    pub async fn run(&self) {
-        loop {
+    /// Seed the retry jitter, so delays are reproducible in tests.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Poll for due deliveries until `shutdown` is cancelled, finishing the current pass first.
+    pub async fn run(&self, shutdown: CancellationToken) {
+        while !shutdown.is_cancelled() {
             if let Err(e) = self.process_pending().await {
                 tracing::error!("Webhook delivery error: {e}");
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {}
+                _ = shutdown.cancelled() => {}
+            }
         }
+        tracing::info!("Webhook delivery worker stopped");
     }
 
     async fn process_pending(&self) -> anyhow::Result<()> {
         let now = chrono::Utc::now().to_rfc3339();
 
-        let deliveries = sqlx::query_as::<_, (String, String, String, String, i32)>(
-            "SELECT wd.id, we.url, we.secret, e.payload, wd.attempts
+        let deliveries = sqlx::query_as::<_, DueDelivery>(
+            "SELECT wd.id, wd.app_id, we.url, we.secret, COALESCE(wd.payload, e.payload) AS payload, wd.api_version, wd.attempts
              FROM webhook_deliveries wd
-             JOIN webhook_endpoints we ON wd.webhook_endpoint_id = we.id
+             JOIN webhook_endpoints we ON wd.webhook_endpoint_id = we.id AND we.app_id = wd.app_id
              JOIN events e ON wd.event_id = e.id
              WHERE wd.status IN ('pending', 'failed')
-             AND (wd.next_retry_at IS NULL OR wd.next_retry_at <= ?)
+             AND (wd.next_retry_at IS NULL OR wd.next_retry_at <= $1)
              AND we.active = 1
-             LIMIT 10"
+             LIMIT $2"
         )
         .bind(&now)
+        .bind(self.config.concurrency as i64)
         .fetch_all(&self.pool)
         .await?;
 
-        for (delivery_id, url, secret, payload, attempts) in deliveries {
-            let result = self.client
-                .post(&url)
-                .header("X-Webhook-Secret", &secret)
-                .header("Content-Type", "application/json")
-                .body(payload)
-                .timeout(std::time::Duration::from_secs(10))
-                .send()
-                .await;
-
-            let now = chrono::Utc::now().to_rfc3339();
-
-            match result {
-                Ok(resp) if resp.status().is_success() => {
-                    sqlx::query("UPDATE webhook_deliveries SET status = 'delivered', last_attempt_at = ?, attempts = ? WHERE id = ?")
-                        .bind(&now)
-                        .bind(attempts + 1)
-                        .bind(&delivery_id)
-                        .execute(&self.pool)
-                        .await?;
-                }
-                Ok(resp) => {
-                    let error = format!("HTTP {}", resp.status());
-                    self.mark_failed(&delivery_id, &error, attempts + 1, &now).await?;
-                }
-                Err(e) => {
-                    self.mark_failed(&delivery_id, &e.to_string(), attempts + 1, &now).await?;
-                }
+        // Let every delivery finish before reporting a failure, so none is
+        // left sent but unrecorded
+        let results: Vec<anyhow::Result<()>> = stream::iter(deliveries)
+            .map(|delivery| self.deliver(delivery))
+            .buffer_unordered(self.config.concurrency)
+            .collect()
+            .await;
+
+        results.into_iter().collect()
+    }
+
+    #[tracing::instrument(skip_all, fields(delivery_id = %delivery.id, app_id = %delivery.app_id))]
+    async fn deliver(&self, delivery: DueDelivery) -> anyhow::Result<()> {
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = sign_payload(&delivery.secret, timestamp, &delivery.payload);
+
+        let mut request = self.client
+            .post(&delivery.url)
+            .header("X-Webhook-Timestamp", timestamp.to_string())
+            .header("X-Webhook-Signature", format!("sha256={signature}"))
+            .header("Content-Type", "application/json");
+        if let Some(version) = delivery.api_version {
+            request = request.header("X-Webhook-Version", version.to_string());
+        }
+        if self.config.legacy_secret_header {
+            request = request.header("X-Webhook-Secret", &delivery.secret);
+        }
+
+        let result = request
+            .body(delivery.payload)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await;
+
+        let now = chrono::Utc::now().to_rfc3339();
+
+        match result {
+            Ok(resp) if resp.status().is_success() => {
+                sqlx::query("UPDATE webhook_deliveries SET status = 'delivered', last_attempt_at = $1, attempts = $2 WHERE id = $3")
+                    .bind(&now)
+                    .bind(delivery.attempts + 1)
+                    .bind(&delivery.id)
+                    .execute(&self.pool)
+                    .await?;
+                crate::metrics::record_webhook_delivery("delivered");
+            }
+            Ok(resp) => {
+                let error = format!("HTTP {}", resp.status());
+                self.mark_failed(&delivery.id, &error, delivery.attempts + 1, &now).await?;
+            }
+            Err(e) => {
+                self.mark_failed(&delivery.id, &e.to_string(), delivery.attempts + 1, &now).await?;
             }
         }
 
@@ -75,16 +152,17 @@ impl WebhookDeliveryWorker {
     }
 
     async fn mark_failed(&self, delivery_id: &str, error: &str, attempts: i32, now: &str) -> anyhow::Result<()> {
-        let status = if attempts >= 10 { "dead_letter" } else { "failed" };
+        let status = if attempts >= self.config.max_attempts { "dead_letter" } else { "failed" };
+        crate::metrics::record_webhook_delivery(status);
         let next_retry = if status == "failed" {
-            let delay = next_retry_delay(attempts);
-            Some(chrono::Utc::now() + chrono::Duration::seconds(delay.as_secs() as i64))
+            let delay = self.retry_delay(attempts);
+            Some(chrono::Utc::now() + chrono::Duration::from_std(delay)?)
         } else {
             None
         };
 
         sqlx::query(
-            "UPDATE webhook_deliveries SET status = ?, attempts = ?, last_attempt_at = ?, last_error = ?, next_retry_at = ? WHERE id = ?"
+            "UPDATE webhook_deliveries SET status = $1, attempts = $2, last_attempt_at = $3, last_error = $4, next_retry_at = $5 WHERE id = $6"
         )
         .bind(status)
         .bind(attempts)
@@ -97,10 +175,301 @@ impl WebhookDeliveryWorker {
 
         Ok(())
     }
+
+    fn retry_delay(&self, attempts: i32) -> std::time::Duration {
+        next_retry_delay(&self.config.backoff_seconds, attempts, &mut *self.rng.lock().unwrap())
+    }
+}
+
+/// Hex-encoded HMAC-SHA256 of `"{timestamp}.{body}"` keyed with `secret`.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// How long to wait after `attempts` failed attempts: a random delay up to
+/// the schedule's step for that attempt ("full jitter"), so deliveries that
+/// failed together during an outage don't all retry together once it ends.
+/// Attempts past the end of the schedule reuse its last step.
+fn next_retry_delay(backoff_seconds: &[u64], attempts: i32, rng: &mut impl Rng) -> std::time::Duration {
+    let index = (attempts.max(1) as usize - 1).min(backoff_seconds.len() - 1);
+    let max = std::time::Duration::from_secs(backoff_seconds[index]);
+    max.mul_f64(rng.gen_range(0.0..=1.0))
 }
 
-fn next_retry_delay(attempts: i32) -> std::time::Duration {
-    let delays = [1, 5, 30, 120, 600, 3600];
-    let index = (attempts as usize).min(delays.len() - 1);
-    std::time::Duration::from_secs(delays[index])
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Seed an app, subscriber, event and endpoint, returning the pending delivery id.
+    async fn seed_delivery(pool: &DbPool, url: &str) -> String {
+        sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ('app', 'Test', 'ios', 'com.test')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ('sub', 'app', 'user123')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO events (id, subscriber_id, event_type, payload) VALUES ('evt', 'sub', 'RENEWAL', '{}')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ('wh', 'app', $1, 'secret')")
+            .bind(url)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO webhook_deliveries (id, app_id, webhook_endpoint_id, event_id, status) VALUES ('del', 'app', 'wh', 'evt', 'pending')")
+            .execute(pool)
+            .await
+            .unwrap();
+        "del".to_string()
+    }
+
+    #[tokio::test]
+    async fn test_pending_delivery_is_delivered() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header_exists("X-Webhook-Signature"))
+            .and(header_exists("X-Webhook-Timestamp"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        let delivery_id = seed_delivery(&pool, &server.uri()).await;
+
+        let shutdown = CancellationToken::new();
+        let worker = WebhookDeliveryWorker::new(pool.clone(), Client::new(), WebhookConfig::default());
+        let worker_shutdown = shutdown.clone();
+        let handle = tokio::spawn(async move { worker.run(worker_shutdown).await });
+
+        let mut status = String::new();
+        for _ in 0..50 {
+            status = sqlx::query_scalar("SELECT status FROM webhook_deliveries WHERE id = $1")
+                .bind(&delivery_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            if status == "delivered" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(status, "delivered");
+
+        shutdown.cancel();
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_retry_delay_is_jittered_within_schedule() {
+        let schedule = WebhookConfig::default().backoff_seconds;
+        let mut rng = StdRng::seed_from_u64(42);
+        let delays: Vec<_> = (0..100).map(|_| next_retry_delay(&schedule, 3, &mut rng)).collect();
+        assert!(delays.iter().all(|d| *d <= std::time::Duration::from_secs(schedule[2])));
+        assert!(delays.iter().any(|d| *d != delays[0]), "delays should vary");
+
+        // Past the end of the schedule the cap still applies
+        let cap = std::time::Duration::from_secs(*schedule.last().unwrap());
+        assert!((0..100).all(|_| next_retry_delay(&schedule, 50, &mut rng) <= cap));
+
+        // The same seed gives the same delays
+        let mut again = StdRng::seed_from_u64(42);
+        assert_eq!(next_retry_delay(&schedule, 3, &mut again), delays[0]);
+    }
+
+    #[tokio::test]
+    async fn test_custom_retry_schedule() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        let delivery_id = seed_delivery(&pool, &server.uri()).await;
+
+        let config = WebhookConfig { max_attempts: 2, backoff_seconds: vec![7, 90], ..WebhookConfig::default() };
+        let worker = WebhookDeliveryWorker::new(pool.clone(), Client::new(), config).with_rng_seed(7);
+        for _ in 0..20 {
+            assert!(worker.retry_delay(1) <= std::time::Duration::from_secs(7));
+            assert!(worker.retry_delay(5) <= std::time::Duration::from_secs(90));
+        }
+        assert!((0..20).any(|_| worker.retry_delay(5) > std::time::Duration::from_secs(7)));
+
+        // First failure is retried, the second hits max_attempts
+        worker.process_pending().await.unwrap();
+        sqlx::query("UPDATE webhook_deliveries SET next_retry_at = NULL WHERE id = $1")
+            .bind(&delivery_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        worker.process_pending().await.unwrap();
+
+        let (status, attempts): (String, i32) = sqlx::query_as("SELECT status, attempts FROM webhook_deliveries WHERE id = $1")
+            .bind(&delivery_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!((status.as_str(), attempts), ("dead_letter", 2));
+    }
+
+    #[test]
+    fn test_sign_payload_known_vectors() {
+        assert_eq!(
+            sign_payload("whsec_test", 1700000000, r#"{"event_type":"RENEWAL"}"#),
+            "42f74ca17c69892567d1e243d7f42812ef350dd2acc6f625b1a69fa0804e85cf"
+        );
+        assert_eq!(
+            sign_payload("secret", 0, ""),
+            "3445798a051818ef95def46c2eb62b43d377ce6e3c29b4d0aec3da0e59577f79"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_secret_header_is_opt_in() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("X-Webhook-Secret", "secret"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        seed_delivery(&pool, &server.uri()).await;
+
+        let worker = WebhookDeliveryWorker::new(
+            pool.clone(),
+            Client::new(),
+            WebhookConfig { legacy_secret_header: true, ..WebhookConfig::default() },
+        );
+        worker.process_pending().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slow_endpoint_does_not_block_others() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/slow"))
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(2)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/fast"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        seed_delivery(&pool, &format!("{}/slow", server.uri())).await;
+        sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ('wh-fast', 'app', $1, 'secret')")
+            .bind(format!("{}/fast", server.uri()))
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO webhook_deliveries (id, app_id, webhook_endpoint_id, event_id, status) VALUES ('del-fast', 'app', 'wh-fast', 'evt', 'pending')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let worker = WebhookDeliveryWorker::new(pool.clone(), Client::new(), WebhookConfig::default());
+        let handle = tokio::spawn(async move { worker.process_pending().await });
+
+        let status_of = |id: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, String>("SELECT status FROM webhook_deliveries WHERE id = $1")
+                    .bind(id)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let mut fast_status = String::new();
+        for _ in 0..50 {
+            fast_status = status_of("del-fast").await;
+            if fast_status == "delivered" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(fast_status, "delivered");
+        assert_eq!(status_of("del").await, "pending", "slow delivery should still be in flight");
+
+        handle.await.unwrap().unwrap();
+        assert_eq!(status_of("del").await, "delivered");
+    }
+
+    #[tokio::test]
+    async fn test_events_are_only_delivered_to_their_own_app() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/a"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/b"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        for app in ["a", "b"] {
+            sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ($1, 'Test', 'ios', $2)")
+                .bind(app)
+                .bind(format!("com.test.{app}"))
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO subscribers (id, app_id, app_user_id) VALUES ($1, $1, 'user123')")
+                .bind(app)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ($1, $1, $2, 'secret')")
+                .bind(app)
+                .bind(format!("{}/{app}", server.uri()))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        // Naming the wrong app doesn't send app a's event to app b
+        let mut conn = pool.acquire().await.unwrap();
+        let event_id = crate::events::record_event(&mut conn, "a", Some("a"), "RENEWAL", "{}").await.unwrap();
+        crate::webhooks::fanout::enqueue_deliveries(&mut conn, &event_id, "b").await.unwrap();
+        drop(conn);
+
+        // Nor does a delivery row pairing the event with the other app's endpoint
+        sqlx::query("INSERT INTO webhook_deliveries (id, app_id, webhook_endpoint_id, event_id, status) VALUES ('cross', 'a', 'b', $1, 'pending')")
+            .bind(&event_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let worker = WebhookDeliveryWorker::new(pool.clone(), Client::new(), WebhookConfig::default());
+        worker.process_pending().await.unwrap();
+
+        let statuses: Vec<(String, String)> = sqlx::query_as("SELECT webhook_endpoint_id, status FROM webhook_deliveries ORDER BY webhook_endpoint_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(statuses, vec![("a".to_string(), "delivered".to_string()), ("b".to_string(), "pending".to_string())]);
+    }
 }
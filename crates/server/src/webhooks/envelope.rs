@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use crate::models::event::Event;
+
+/// Envelope version deliveries are queued in. Bump it, and keep serving the
+/// old shape to deliveries that recorded it, whenever a field is removed,
+/// renamed or changes meaning; adding a field doesn't need a new version.
+pub const WEBHOOK_API_VERSION: i32 = 1;
+
+/// The JSON body of every webhook request. Version 1 looks like:
+///
+/// ```json
+/// {
+///   "api_version": 1,
+///   "event_id": "4f1c…",
+///   "event_type": "RENEWAL",
+///   "created_at": "2026-01-01T00:00:00+00:00",
+///   "app_id": "9b2e…",
+///   "data": { "transaction_id": "…", "product_id": "…", "status": "active" }
+/// }
+/// ```
+///
+/// `data` is the event's own payload, whose fields depend on `event_type`.
+/// The version is also sent in the `X-Webhook-Version` header, and is covered
+/// by the signature as part of the body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookEnvelope {
+    pub api_version: i32,
+    pub event_id: String,
+    pub event_type: String,
+    pub created_at: String,
+    pub app_id: String,
+    pub data: serde_json::Value,
+}
+
+impl WebhookEnvelope {
+    /// Wrap `event`, which belongs to `app_id`, in the current version.
+    pub fn new(event: &Event, app_id: &str) -> Self {
+        Self {
+            api_version: WEBHOOK_API_VERSION,
+            event_id: event.id.clone(),
+            event_type: event.event_type.clone(),
+            created_at: event.created_at.clone(),
+            app_id: app_id.to_string(),
+            // Payloads are written as JSON; anything else is passed on as a string
+            data: serde_json::from_str(&event.payload)
+                .unwrap_or_else(|_| serde_json::Value::String(event.payload.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_round_trips_with_api_version() {
+        let event = Event {
+            id: "evt".to_string(),
+            subscriber_id: Some("sub".to_string()),
+            app_id: Some("app".to_string()),
+            event_type: "RENEWAL".to_string(),
+            payload: r#"{"product_id":"com.test.pro","status":"active"}"#.to_string(),
+            created_at: "2026-01-01T00:00:00+00:00".to_string(),
+        };
+        let envelope = WebhookEnvelope::new(&event, "app");
+
+        let body = serde_json::to_string(&envelope).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["api_version"], WEBHOOK_API_VERSION);
+        assert_eq!(json["event_id"], "evt");
+        assert_eq!(json["app_id"], "app");
+        assert_eq!(json["data"]["product_id"], "com.test.pro");
+
+        assert_eq!(serde_json::from_str::<WebhookEnvelope>(&body).unwrap(), envelope);
+    }
+}
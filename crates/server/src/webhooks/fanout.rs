@@ -0,0 +1,105 @@
+use sqlx::AnyConnection;
+use crate::api::webhooks::EventTypeFilter;
+use crate::models::event::Event;
+use super::envelope::WebhookEnvelope;
+
+/// Queue a pending delivery of `event_id` for every active endpoint of
+/// `app_id`, the app the event belongs to, whose `event_types` filter matches
+/// (an empty filter matches all). The body is serialized into a
+/// [`WebhookEnvelope`] now, so the delivery is sent in the version it was
+/// queued with.
+pub async fn enqueue_deliveries(conn: &mut AnyConnection, event_id: &str, app_id: &str) -> anyhow::Result<usize> {
+    let event = sqlx::query_as::<_, Event>("SELECT * FROM events WHERE id = $1")
+        .bind(event_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    // An event with a subscriber belongs to the subscriber's app, whatever the caller says
+    let endpoints = sqlx::query_as::<_, (String, String)>(
+        "SELECT we.id, we.event_types FROM webhook_endpoints we
+         WHERE we.app_id = $1 AND we.active = 1
+         AND we.app_id = COALESCE(
+             (SELECT s.app_id FROM events e JOIN subscribers s ON s.id = e.subscriber_id WHERE e.id = $2),
+             $1
+         )"
+    )
+    .bind(app_id)
+    .bind(event_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut endpoint_ids = Vec::new();
+    for (id, event_types) in endpoints {
+        if EventTypeFilter::try_from(event_types)?.matches(&event.event_type) {
+            endpoint_ids.push(id);
+        }
+    }
+    if endpoint_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let envelope = WebhookEnvelope::new(&event, app_id);
+    let payload = serde_json::to_string(&envelope)?;
+
+    let now = chrono::Utc::now().to_rfc3339();
+    for endpoint_id in &endpoint_ids {
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (id, app_id, webhook_endpoint_id, event_id, status, attempts, api_version, payload, created_at)
+             VALUES ($1, $2, $3, $4, 'pending', 0, $5, $6, $7)"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(app_id)
+        .bind(endpoint_id)
+        .bind(event_id)
+        .bind(envelope.api_version)
+        .bind(&payload)
+        .bind(&now)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(endpoint_ids.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db;
+
+    #[tokio::test]
+    async fn test_event_is_queued_for_each_endpoint_of_its_app() {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        for (app, endpoints) in [("app", ["wh-1", "wh-2"]), ("other", ["wh-other", "wh-other-2"])] {
+            sqlx::query("INSERT INTO apps (id, name, platform, bundle_id) VALUES ($1, 'Test', 'ios', $2)")
+                .bind(app)
+                .bind(format!("com.test.{app}"))
+                .execute(&pool)
+                .await
+                .unwrap();
+            for endpoint in endpoints {
+                sqlx::query("INSERT INTO webhook_endpoints (id, app_id, url, secret) VALUES ($1, $2, 'https://example.com/hook', 'secret')")
+                    .bind(endpoint)
+                    .bind(app)
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+        }
+        sqlx::query("UPDATE webhook_endpoints SET active = 0 WHERE id = 'wh-other-2'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Notifications for transactions we don't know have no subscriber, but still fan out
+        let mut conn = pool.acquire().await.unwrap();
+        let event_id = crate::events::record_event(&mut conn, "app", None, "RENEWAL", "{}").await.unwrap();
+
+        let endpoints: Vec<String> = sqlx::query_scalar(
+            "SELECT webhook_endpoint_id FROM webhook_deliveries WHERE event_id = $1 AND status = 'pending' ORDER BY webhook_endpoint_id"
+        )
+        .bind(&event_id)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(endpoints, vec!["wh-1", "wh-2"]);
+    }
+}
@@ -1 +1,3 @@
 pub mod delivery;
+pub mod envelope;
+pub mod fanout;
@@ -8,7 +8,7 @@ export default function EventsPage() {
   const [error, setError] = useState<string | null>(null);
 
   useEffect(() => {
-    api.listEvents(undefined, 50).then(setEvents).catch((e) => setError(e.message));
+    api.listEvents(undefined, 50).then((page) => setEvents(page.events)).catch((e) => setError(e.message));
   }, []);
 
   return (
@@ -27,7 +27,7 @@ export default function ProductsPage() {
 
   useEffect(() => {
     if (selectedApp) {
-      api.listProducts(selectedApp).then(setProducts);
+      api.listProducts(selectedApp).then((page) => setProducts(page.products));
     }
   }, [selectedApp]);
 
@@ -37,7 +37,7 @@ export default function ProductsPage() {
     try {
       const result = await api.syncProducts(selectedApp);
       setSyncStatus(`Synced ${result.synced} products`);
-      api.listProducts(selectedApp).then(setProducts);
+      api.listProducts(selectedApp).then((page) => setProducts(page.products));
     } catch (e) {
       setSyncStatus(`Error: ${e instanceof Error ? e.message : String(e)}`);
     }
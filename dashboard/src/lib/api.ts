@@ -1,10 +1,12 @@
 const API_BASE = process.env.NEXT_PUBLIC_API_URL || "http://localhost:8080";
+const API_KEY = process.env.NEXT_PUBLIC_API_KEY;
 
 async function request<T>(path: string, options?: RequestInit): Promise<T> {
   const res = await fetch(`${API_BASE}${path}`, {
     ...options,
     headers: {
       "Content-Type": "application/json",
+      ...(API_KEY ? { Authorization: `Bearer ${API_KEY}` } : {}),
       ...options?.headers,
     },
   });
@@ -72,11 +74,35 @@ export interface Event {
   created_at: string;
 }
 
+export interface EventsPage {
+  events: Event[];
+  next_cursor: string | null;
+}
+
+export interface MetricsOverview {
+  active_subscribers: number;
+  active_subscriptions: number;
+  active_subscriptions_by_product: Record<string, number>;
+  active_trials: number;
+  mrr_micros: Record<string, number>;
+}
+
+export interface ProductsPage {
+  products: Product[];
+  next_cursor: string | null;
+}
+
+export interface EntitlementsPage {
+  entitlements: Entitlement[];
+  next_cursor: string | null;
+}
+
 export interface WebhookEndpoint {
   id: string;
   app_id: string;
   url: string;
-  secret: string;
+  /** Only returned when the endpoint is created or its secret rotated. */
+  secret?: string;
   active: number;
   created_at: string;
 }
@@ -85,6 +111,7 @@ export interface SubscriberInfo {
   subscriber: Subscriber;
   active_entitlements: Entitlement[];
   transactions: Transaction[];
+  attributes: Record<string, { value: string; updated_at_ms: number }>;
 }
 
 export const api = {
@@ -93,19 +120,22 @@ export const api = {
     request<App>("/v1/apps", { method: "POST", body: JSON.stringify(data) }),
 
   listEntitlements: (appId: string) =>
-    request<Entitlement[]>(`/v1/apps/${appId}/entitlements`),
+    request<EntitlementsPage>(`/v1/apps/${appId}/entitlements?limit=100`),
 
   listProducts: (appId: string) =>
-    request<Product[]>(`/v1/apps/${appId}/products`),
+    request<ProductsPage>(`/v1/apps/${appId}/products?limit=100`),
+
+  getMetricsOverview: (appId: string) =>
+    request<MetricsOverview>(`/v1/apps/${appId}/metrics/overview`),
 
   getSubscriber: (appUserId: string) =>
     request<SubscriberInfo>(`/v1/subscribers/${appUserId}`),
 
-  listEvents: (since?: string, limit?: number) => {
+  listEvents: (cursor?: string, limit?: number) => {
     const params = new URLSearchParams();
-    if (since) params.set("since", since);
+    if (cursor) params.set("cursor", cursor);
     if (limit) params.set("limit", String(limit));
-    return request<Event[]>(`/v1/events?${params}`);
+    return request<EventsPage>(`/v1/events?${params}`);
   },
 
   updateCredentials: async (appId: string, data: { apple?: { issuer_id: string; key_id: string; private_key: string } }) => {
@@ -81,11 +81,13 @@ if [ -z "$APP_ID" ]; then
     warn "Could not parse app_id, using response as-is: $APP_RESPONSE"
     APP_ID="1"
 fi
+API_KEY=$(echo "$APP_RESPONSE" | python3 -c "import sys,json; print(json.load(sys.stdin)['api_key'])" 2>/dev/null || echo "")
+AUTH="Authorization: Bearer $API_KEY"
 log "✓ App registered with ID: $APP_ID"
 
 # Create entitlement
 log "Creating 'pro' entitlement..."
-ENT_RESPONSE=$(curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/entitlements" \
+ENT_RESPONSE=$(curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/entitlements" -H "$AUTH" \
     -H "Content-Type: application/json" \
     -d '{"name": "pro", "description": "Premium access"}')
 ENT_ID=$(echo "$ENT_RESPONSE" | python3 -c "import sys,json; print(json.load(sys.stdin)['id'])" 2>/dev/null || echo "")
@@ -93,7 +95,7 @@ log "✓ Entitlement 'pro' created (ID: $ENT_ID)"
 
 # Create products
 log "Creating products..."
-curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/products" \
+curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/products" -H "$AUTH" \
     -H "Content-Type: application/json" \
     -d "{
         \"store_product_id\": \"com.rushday.premium.annual\",
@@ -102,7 +104,7 @@ curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/products" \
     }" > /dev/null
 log "✓ Annual product created"
 
-curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/products" \
+curl -sf -X POST "http://localhost:8080/v1/apps/$APP_ID/products" -H "$AUTH" \
     -H "Content-Type: application/json" \
     -d "{
         \"store_product_id\": \"com.rushday.premium.monthly\",
@@ -113,7 +115,7 @@ log "✓ Monthly product created"
 
 # ─── Step 4: Save Mock Credentials ───
 log "Saving mock store credentials..."
-CRED_RESPONSE=$(curl -sf -X PUT "http://localhost:8080/v1/apps/$APP_ID/credentials" \
+CRED_RESPONSE=$(curl -sf -X PUT "http://localhost:8080/v1/apps/$APP_ID/credentials" -H "$AUTH" \
     -H "Content-Type: application/json" \
     -d '{
         "apple": {
@@ -125,7 +127,7 @@ CRED_RESPONSE=$(curl -sf -X PUT "http://localhost:8080/v1/apps/$APP_ID/credentia
 log "✓ Credentials saved"
 
 # Verify credentials are masked on read
-CRED_GET=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/credentials")
+CRED_GET=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/credentials" -H "$AUTH")
 echo "$CRED_GET" | python3 -c "
 import sys, json
 creds = json.load(sys.stdin)
@@ -135,7 +137,7 @@ assert pk == '***configured***', f'Expected masked key, got: {pk}'
 
 # ─── Step 5: Verify Offerings API ───
 log "Testing offerings API..."
-OFFERINGS=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/offerings")
+OFFERINGS=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/offerings?flat=true" -H "$AUTH")
 OFFERING_COUNT=$(echo "$OFFERINGS" | python3 -c "import sys,json; print(len(json.load(sys.stdin)['offerings']))")
 if [ "$OFFERING_COUNT" = "2" ]; then
     log "✓ Offerings endpoint returns 2 products"
@@ -150,13 +152,13 @@ log "✓ Offerings include entitlements: $FIRST_ENTITLEMENTS"
 # ─── Step 6: Verify Server State ───
 log "Verifying server state..."
 
-APPS=$(curl -sf http://localhost:8080/v1/apps)
+APPS=$(curl -sf -H "$AUTH" http://localhost:8080/v1/apps)
 log "Apps: $APPS"
 
-ENTITLEMENTS=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/entitlements")
+ENTITLEMENTS=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/entitlements" -H "$AUTH")
 log "Entitlements: $ENTITLEMENTS"
 
-PRODUCTS=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/products")
+PRODUCTS=$(curl -sf "http://localhost:8080/v1/apps/$APP_ID/products" -H "$AUTH")
 log "Products: $PRODUCTS"
 
 # ─── Step 5: Summary ───
@@ -168,11 +170,12 @@ echo ""
 echo "  Server:    http://localhost:8080"
 echo "  Dashboard: http://localhost:3000"
 echo "  App ID:    $APP_ID"
+echo "  API key:   $API_KEY"
 echo ""
 echo "  Next steps:"
 echo "  1. Build iOS app: cd ios-app && ./scripts/auto-build.sh full"
 echo "  2. Open paywall:  ./scripts/app-tester.sh open rushday://debug/paywall"
-echo "  3. Check subscriber: curl http://localhost:8080/v1/subscribers/{user_id}"
+echo "  3. Check subscriber: curl -H \"$AUTH\" http://localhost:8080/v1/subscribers/{user_id}"
 echo ""
 echo "  Press Ctrl+C to stop all services."
 echo ""
@@ -1,6 +1,6 @@
 enum Store { apple, google }
 
-enum TransactionStatus { active, expired, refunded, gracePeriod, billingRetry }
+enum TransactionStatus { active, expired, refunded, gracePeriod, billingRetry, paused }
 
 class EntitlementInfo {
   final String id;
@@ -93,6 +93,8 @@ class TransactionInfo {
         return TransactionStatus.gracePeriod;
       case 'billing_retry':
         return TransactionStatus.billingRetry;
+      case 'paused':
+        return TransactionStatus.paused;
       default:
         return TransactionStatus.expired;
     }
@@ -110,6 +112,8 @@ class TransactionInfo {
         return 'grace_period';
       case TransactionStatus.billingRetry:
         return 'billing_retry';
+      case TransactionStatus.paused:
+        return 'paused';
     }
   }
 }
@@ -28,9 +28,28 @@ type EntitlementInfo struct {
 }
 
 type SubscriberInfo struct {
-	Subscriber         Subscriber        `json:"subscriber"`
-	ActiveEntitlements []EntitlementInfo  `json:"active_entitlements"`
-	Transactions       []Transaction     `json:"transactions"`
+	Subscriber         Subscriber                     `json:"subscriber"`
+	ActiveEntitlements []EntitlementInfo              `json:"active_entitlements"`
+	Transactions       []Transaction                  `json:"transactions"`
+	SubscriptionStatus map[string]EntitlementStatus   `json:"subscription_status,omitempty"`
+	Attributes         map[string]SubscriberAttribute `json:"attributes,omitempty"`
+}
+
+type EntitlementStatus struct {
+	IsActive             bool    `json:"is_active"`
+	ExpiresAt            *string `json:"expires_at,omitempty"`
+	ProductID            string  `json:"product_id"`
+	Store                string  `json:"store"`
+	PeriodType           string  `json:"period_type"`
+	WillRenew            *bool   `json:"will_renew,omitempty"`
+	InGracePeriod        bool    `json:"in_grace_period"`
+	GracePeriodExpiresAt *string `json:"grace_period_expires_at,omitempty"`
+	InBillingRetry       bool    `json:"in_billing_retry"`
+}
+
+type SubscriberAttribute struct {
+	Value       string `json:"value"`
+	UpdatedAtMs int64  `json:"updated_at_ms"`
 }
 
 type Entitlement struct {
@@ -67,7 +86,7 @@ type WebhookEndpoint struct {
 	ID        string `json:"id"`
 	AppID     string `json:"app_id"`
 	URL       string `json:"url"`
-	Secret    string `json:"secret"`
+	Secret    string `json:"secret,omitempty"` // only set on create and rotate
 	Active    bool   `json:"active"`
 	CreatedAt string `json:"created_at"`
 }
@@ -79,3 +98,21 @@ type Event struct {
 	Payload      string `json:"payload"`
 	CreatedAt    string `json:"created_at"`
 }
+
+// EventsPage is one page of events; pass NextCursor back to ListEvents to keep reading.
+type EventsPage struct {
+	Events     []Event `json:"events"`
+	NextCursor *string `json:"next_cursor"`
+}
+
+// ProductsPage is one page of products; pass NextCursor back to ListProducts for the next.
+type ProductsPage struct {
+	Products   []Product `json:"products"`
+	NextCursor *string   `json:"next_cursor"`
+}
+
+// EntitlementsPage is one page of entitlements; pass NextCursor back to ListEntitlements for the next.
+type EntitlementsPage struct {
+	Entitlements []Entitlement `json:"entitlements"`
+	NextCursor   *string       `json:"next_cursor"`
+}
@@ -112,10 +112,14 @@ func (c *Client) CreateProduct(appID, storeProductID, productType string, entitl
 	return &result, err
 }
 
-func (c *Client) ListProducts(appID string) ([]Product, error) {
-	var result []Product
-	err := c.request("GET", fmt.Sprintf("/v1/apps/%s/products", appID), nil, nil, &result)
-	return result, err
+func (c *Client) ListProducts(appID, cursor string) (*ProductsPage, error) {
+	q := url.Values{}
+	if cursor != "" {
+		q.Set("cursor", cursor)
+	}
+	var result ProductsPage
+	err := c.request("GET", fmt.Sprintf("/v1/apps/%s/products", appID), nil, q, &result)
+	return &result, err
 }
 
 // -- entitlements --
@@ -130,10 +134,14 @@ func (c *Client) CreateEntitlement(appID, name string, description *string) (*En
 	return &result, err
 }
 
-func (c *Client) ListEntitlements(appID string) ([]Entitlement, error) {
-	var result []Entitlement
-	err := c.request("GET", fmt.Sprintf("/v1/apps/%s/entitlements", appID), nil, nil, &result)
-	return result, err
+func (c *Client) ListEntitlements(appID, cursor string) (*EntitlementsPage, error) {
+	q := url.Values{}
+	if cursor != "" {
+		q.Set("cursor", cursor)
+	}
+	var result EntitlementsPage
+	err := c.request("GET", fmt.Sprintf("/v1/apps/%s/entitlements", appID), nil, q, &result)
+	return &result, err
 }
 
 // -- receipts --
@@ -168,12 +176,12 @@ func (c *Client) ListWebhooks() ([]WebhookEndpoint, error) {
 
 // -- events --
 
-func (c *Client) ListEvents(cursor string) ([]Event, error) {
+func (c *Client) ListEvents(cursor string) (*EventsPage, error) {
 	q := url.Values{}
 	if cursor != "" {
-		q.Set("since", cursor)
+		q.Set("cursor", cursor)
 	}
-	var result []Event
+	var result EventsPage
 	err := c.request("GET", "/v1/events", nil, q, &result)
-	return result, err
+	return &result, err
 }
@@ -144,18 +144,28 @@ func TestCreateWebhook(t *testing.T) {
 
 func TestListEvents(t *testing.T) {
 	c, srv := setupServer(t, func(w http.ResponseWriter, r *http.Request) {
-		json.NewEncoder(w).Encode([]Event{
-			{ID: "ev1", SubscriberID: "s1", EventType: "purchase", Payload: "{}", CreatedAt: "t"},
+		if r.URL.Query().Get("cursor") != "c0" {
+			t.Errorf("expected cursor c0, got %q", r.URL.Query().Get("cursor"))
+		}
+		next := "c1"
+		json.NewEncoder(w).Encode(EventsPage{
+			Events: []Event{
+				{ID: "ev1", SubscriberID: "s1", EventType: "purchase", Payload: "{}", CreatedAt: "t"},
+			},
+			NextCursor: &next,
 		})
 	})
 	defer srv.Close()
 
-	events, err := c.ListEvents("")
+	page, err := c.ListEvents("c0")
 	if err != nil {
 		t.Fatal(err)
 	}
-	if len(events) != 1 {
-		t.Fatalf("expected 1 event, got %d", len(events))
+	if len(page.Events) != 1 {
+		t.Fatalf("expected 1 event, got %d", len(page.Events))
+	}
+	if page.NextCursor == nil || *page.NextCursor != "c1" {
+		t.Fatalf("unexpected next cursor %v", page.NextCursor)
 	}
 }
 
@@ -38,4 +38,5 @@ enum class TransactionStatus {
     @SerialName("refunded") REFUNDED,
     @SerialName("grace_period") GRACE_PERIOD,
     @SerialName("billing_retry") BILLING_RETRY,
+    @SerialName("paused") PAUSED,
 }
@@ -1,8 +1,10 @@
 import type {
   App,
   Entitlement,
-  Event,
+  EntitlementsPage,
+  EventsPage,
   Product,
+  ProductsPage,
   SubscriberInfo,
   Transaction,
   WebhookEndpoint,
@@ -79,8 +81,11 @@ export class OpenCatClient {
     });
   }
 
-  async listProducts(appId: string): Promise<Product[]> {
-    return this.request("GET", `/v1/apps/${appId}/products`);
+  /** Fetch a page of products; pass `next_cursor` back as `cursor` for the next. */
+  async listProducts(appId: string, cursor?: string): Promise<ProductsPage> {
+    const params: Record<string, string> = {};
+    if (cursor !== undefined) params.cursor = cursor;
+    return this.request("GET", `/v1/apps/${appId}/products`, undefined, params);
   }
 
   // -- entitlements --
@@ -91,8 +96,11 @@ export class OpenCatClient {
     return this.request("POST", `/v1/apps/${appId}/entitlements`, body);
   }
 
-  async listEntitlements(appId: string): Promise<Entitlement[]> {
-    return this.request("GET", `/v1/apps/${appId}/entitlements`);
+  /** Fetch a page of entitlements; pass `next_cursor` back as `cursor` for the next. */
+  async listEntitlements(appId: string, cursor?: string): Promise<EntitlementsPage> {
+    const params: Record<string, string> = {};
+    if (cursor !== undefined) params.cursor = cursor;
+    return this.request("GET", `/v1/apps/${appId}/entitlements`, undefined, params);
   }
 
   // -- receipts --
@@ -127,9 +135,10 @@ export class OpenCatClient {
 
   // -- events --
 
-  async listEvents(cursor?: string): Promise<Event[]> {
+  /** Fetch events after `cursor`; pass `next_cursor` back to keep reading. */
+  async listEvents(cursor?: string): Promise<EventsPage> {
     const params: Record<string, string> = {};
-    if (cursor !== undefined) params.since = cursor;
+    if (cursor !== undefined) params.cursor = cursor;
     return this.request("GET", "/v1/events", undefined, params);
   }
 }
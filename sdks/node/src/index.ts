@@ -3,9 +3,14 @@ export type {
   App,
   Entitlement,
   EntitlementInfo,
+  EntitlementsPage,
+  EntitlementStatus,
   Event,
+  EventsPage,
   Product,
+  ProductsPage,
   Subscriber,
+  SubscriberAttribute,
   SubscriberInfo,
   Transaction,
   WebhookEndpoint,
@@ -29,6 +29,25 @@ export interface SubscriberInfo {
   subscriber: Subscriber;
   active_entitlements: EntitlementInfo[];
   transactions: Transaction[];
+  subscription_status?: Record<string, EntitlementStatus>;
+  attributes?: Record<string, SubscriberAttribute>;
+}
+
+export interface EntitlementStatus {
+  is_active: boolean;
+  expires_at?: string | null;
+  product_id: string;
+  store: string;
+  period_type: "normal" | "trial" | "intro";
+  will_renew?: boolean | null;
+  in_grace_period: boolean;
+  grace_period_expires_at?: string | null;
+  in_billing_retry: boolean;
+}
+
+export interface SubscriberAttribute {
+  value: string;
+  updated_at_ms: number;
 }
 
 export interface Entitlement {
@@ -65,7 +84,8 @@ export interface WebhookEndpoint {
   id: string;
   app_id: string;
   url: string;
-  secret: string;
+  /** Only returned when the endpoint is created or its secret rotated. */
+  secret?: string;
   active: boolean;
   created_at: string;
 }
@@ -77,3 +97,18 @@ export interface Event {
   payload: string;
   created_at: string;
 }
+
+export interface EventsPage {
+  events: Event[];
+  next_cursor: string | null;
+}
+
+export interface ProductsPage {
+  products: Product[];
+  next_cursor: string | null;
+}
+
+export interface EntitlementsPage {
+  entitlements: Entitlement[];
+  next_cursor: string | null;
+}
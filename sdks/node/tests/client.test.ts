@@ -90,11 +90,14 @@ test("createWebhook", async () => {
 });
 
 test("listEvents", async () => {
-  fetchMock.mockResolvedValue(mockResponse(200, [
-    { id: "ev1", subscriber_id: "s1", event_type: "purchase", payload: "{}", created_at: "t" },
-  ]));
-  const events = await client().listEvents();
-  expect(events).toHaveLength(1);
+  fetchMock.mockResolvedValue(mockResponse(200, {
+    events: [{ id: "ev1", subscriber_id: "s1", event_type: "purchase", payload: "{}", created_at: "t" }],
+    next_cursor: "c1",
+  }));
+  const page = await client().listEvents("c0");
+  expect(page.events).toHaveLength(1);
+  expect(page.next_cursor).toBe("c1");
+  expect(fetchMock.mock.calls[0][0]).toContain("cursor=c0");
 });
 
 test("error handling", async () => {
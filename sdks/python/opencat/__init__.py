@@ -3,9 +3,14 @@ from .models import (
     App,
     Entitlement,
     EntitlementInfo,
+    EntitlementsPage,
+    EntitlementStatus,
     Event,
+    EventsPage,
     Product,
+    ProductsPage,
     Subscriber,
+    SubscriberAttribute,
     SubscriberInfo,
     Transaction,
     WebhookEndpoint,
@@ -17,9 +22,14 @@ __all__ = [
     "App",
     "Entitlement",
     "EntitlementInfo",
+    "EntitlementsPage",
+    "EntitlementStatus",
     "Event",
+    "EventsPage",
     "Product",
+    "ProductsPage",
     "Subscriber",
+    "SubscriberAttribute",
     "SubscriberInfo",
     "Transaction",
     "WebhookEndpoint",
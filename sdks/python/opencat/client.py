@@ -7,11 +7,16 @@ import httpx
 from .models import (
     App,
     Entitlement,
+    EntitlementsPage,
     Event,
+    EventsPage,
     Product,
+    ProductsPage,
+    SubscriberAttribute,
     SubscriberInfo,
     Subscriber,
     EntitlementInfo,
+    EntitlementStatus,
     Transaction,
     WebhookEndpoint,
 )
@@ -73,7 +78,15 @@ class OpenCatClient:
         sub = Subscriber(**data["subscriber"])
         entitlements = [EntitlementInfo(**e) for e in data.get("active_entitlements", [])]
         transactions = [Transaction(**t) for t in data.get("transactions", [])]
-        return SubscriberInfo(subscriber=sub, active_entitlements=entitlements, transactions=transactions)
+        status = {k: EntitlementStatus(**v) for k, v in data.get("subscription_status", {}).items()}
+        attributes = {k: SubscriberAttribute(**v) for k, v in data.get("attributes", {}).items()}
+        return SubscriberInfo(
+            subscriber=sub,
+            active_entitlements=entitlements,
+            transactions=transactions,
+            subscription_status=status,
+            attributes=attributes,
+        )
 
     # -- products --
 
@@ -91,9 +104,16 @@ class OpenCatClient:
         })
         return Product(**data)
 
-    def list_products(self, app_id: str) -> list[Product]:
-        data = self._request("GET", f"/v1/apps/{app_id}/products")
-        return [Product(**p) for p in data]
+    def list_products(self, app_id: str, cursor: Optional[str] = None) -> ProductsPage:
+        """Fetch a page of products; pass ``next_cursor`` back as ``cursor`` for the next."""
+        params: dict[str, str] = {}
+        if cursor is not None:
+            params["cursor"] = cursor
+        data = self._request("GET", f"/v1/apps/{app_id}/products", params=params)
+        return ProductsPage(
+            products=[Product(**p) for p in data["products"]],
+            next_cursor=data.get("next_cursor"),
+        )
 
     # -- entitlements --
 
@@ -106,9 +126,16 @@ class OpenCatClient:
         data = self._request("POST", f"/v1/apps/{app_id}/entitlements", json=body)
         return Entitlement(**data)
 
-    def list_entitlements(self, app_id: str) -> list[Entitlement]:
-        data = self._request("GET", f"/v1/apps/{app_id}/entitlements")
-        return [Entitlement(**e) for e in data]
+    def list_entitlements(self, app_id: str, cursor: Optional[str] = None) -> EntitlementsPage:
+        """Fetch a page of entitlements; pass ``next_cursor`` back as ``cursor`` for the next."""
+        params: dict[str, str] = {}
+        if cursor is not None:
+            params["cursor"] = cursor
+        data = self._request("GET", f"/v1/apps/{app_id}/entitlements", params=params)
+        return EntitlementsPage(
+            entitlements=[Entitlement(**e) for e in data["entitlements"]],
+            next_cursor=data.get("next_cursor"),
+        )
 
     # -- receipts --
 
@@ -144,9 +171,13 @@ class OpenCatClient:
 
     # -- events --
 
-    def list_events(self, cursor: Optional[str] = None) -> list[Event]:
+    def list_events(self, cursor: Optional[str] = None) -> EventsPage:
+        """Fetch events after ``cursor``; pass ``next_cursor`` back to keep reading."""
         params: dict[str, str] = {}
         if cursor is not None:
-            params["since"] = cursor
+            params["cursor"] = cursor
         data = self._request("GET", "/v1/events", params=params)
-        return [Event(**e) for e in data]
+        return EventsPage(
+            events=[Event(**e) for e in data["events"]],
+            next_cursor=data.get("next_cursor"),
+        )
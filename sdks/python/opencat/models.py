@@ -33,11 +33,32 @@ class EntitlementInfo:
     purchase_date: Optional[str] = None
 
 
+@dataclass
+class EntitlementStatus:
+    is_active: bool
+    product_id: str
+    store: str
+    period_type: str
+    in_grace_period: bool
+    in_billing_retry: bool
+    expires_at: Optional[str] = None
+    will_renew: Optional[bool] = None
+    grace_period_expires_at: Optional[str] = None
+
+
+@dataclass
+class SubscriberAttribute:
+    value: str
+    updated_at_ms: int
+
+
 @dataclass
 class SubscriberInfo:
     subscriber: Subscriber
     active_entitlements: list[EntitlementInfo] = field(default_factory=list)
     transactions: list[Transaction] = field(default_factory=list)
+    subscription_status: dict[str, EntitlementStatus] = field(default_factory=dict)
+    attributes: dict[str, SubscriberAttribute] = field(default_factory=dict)
 
 
 @dataclass
@@ -78,9 +99,10 @@ class WebhookEndpoint:
     id: str
     app_id: str
     url: str
-    secret: str
     active: bool
     created_at: str
+    # Only returned when the endpoint is created or its secret rotated
+    secret: Optional[str] = None
 
 
 @dataclass
@@ -90,3 +112,21 @@ class Event:
     event_type: str
     payload: str
     created_at: str
+
+
+@dataclass
+class EventsPage:
+    events: list[Event]
+    next_cursor: Optional[str]
+
+
+@dataclass
+class ProductsPage:
+    products: list[Product]
+    next_cursor: Optional[str]
+
+
+@dataclass
+class EntitlementsPage:
+    entitlements: list[Entitlement]
+    next_cursor: Optional[str]
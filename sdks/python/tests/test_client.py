@@ -93,12 +93,17 @@ def test_create_webhook(client):
 
 @respx.mock
 def test_list_events(client):
-    respx.get(f"{BASE}/v1/events").mock(return_value=httpx.Response(200, json=[
-        {"id": "ev1", "subscriber_id": "s1", "event_type": "purchase",
-         "payload": "{}", "created_at": "t"},
-    ]))
-    events = client.list_events()
-    assert len(events) == 1
+    route = respx.get(f"{BASE}/v1/events").mock(return_value=httpx.Response(200, json={
+        "events": [
+            {"id": "ev1", "subscriber_id": "s1", "event_type": "purchase",
+             "payload": "{}", "created_at": "t"},
+        ],
+        "next_cursor": "c1",
+    }))
+    page = client.list_events("c0")
+    assert len(page.events) == 1
+    assert page.next_cursor == "c1"
+    assert route.calls[0].request.url.params["cursor"] == "c0"
 
 
 @respx.mock